@@ -0,0 +1,81 @@
+//! Embeds build metadata for `paping version` (see src/version.rs).
+//! Everything falls back to "unknown" outside a git checkout or without git.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // Rebuild on commits and checkouts (HEAD, refs) and on staged changes
+    for file in [".git/HEAD", ".git/index", ".git/refs", ".git/packed-refs"] {
+        if Path::new(file).exists() {
+            println!("cargo:rerun-if-changed={}", file);
+        }
+    }
+
+    let commit = git(&["rev-parse", "--short=10", "HEAD"]);
+    let dirty = match git(&["status", "--porcelain", "--untracked-files=no"]) {
+        Some(status) => (!status.is_empty()).to_string(),
+        None => String::new(),
+    };
+    println!(
+        "cargo:rustc-env=PAPING_GIT_COMMIT={}",
+        commit.unwrap_or_default()
+    );
+    println!("cargo:rustc-env=PAPING_GIT_DIRTY={}", dirty);
+    println!("cargo:rustc-env=PAPING_BUILD_DATE={}", build_date());
+    println!(
+        "cargo:rustc-env=PAPING_TARGET={}",
+        std::env::var("TARGET").unwrap_or_default()
+    );
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(name, _)| {
+            let feature = name.strip_prefix("CARGO_FEATURE_")?;
+            // Stands for others, which are listed themselves
+            if feature == "DEFAULT" {
+                return None;
+            }
+            Some(feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=PAPING_FEATURES={}", features.join(","));
+}
+
+/// Output of a git command, or `None` when git or the checkout is missing.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
+
+/// UTC date of the build (YYYY-MM-DD), or of SOURCE_DATE_EPOCH for
+/// reproducible builds.
+fn build_date() -> String {
+    let secs = match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => match epoch.trim().parse::<u64>() {
+            Ok(secs) => secs,
+            Err(_) => return String::new(),
+        },
+        Err(_) => match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs(),
+            Err(_) => return String::new(),
+        },
+    };
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
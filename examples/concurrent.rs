@@ -0,0 +1,54 @@
+//! Probes many targets at the same time with the async API.
+//!
+//!     cargo run --example concurrent --features async -- example.com:443 1.1.1.1:53 [::1]:22
+
+use std::time::Duration;
+
+use paping::async_pinger::AsyncPinger;
+use paping::target::{join_host_port, split_host_port};
+
+const COUNT: u32 = 4;
+
+#[tokio::main]
+async fn main() {
+    let mut tasks = Vec::new();
+    for arg in std::env::args().skip(1) {
+        let (host, port) = match split_host_port(&arg) {
+            Ok((host, Some(port))) => (host, port),
+            _ => {
+                eprintln!("Error: expected host:port, got '{}'", arg);
+                std::process::exit(1);
+            }
+        };
+
+        // One task per target; all of them share the runtime's threads.
+        tasks.push(tokio::spawn(async move {
+            let name = join_host_port(&host, port);
+            let pinger = AsyncPinger::new(host, port, Duration::from_millis(1000), None, None);
+            let mut results = pinger.into_stream(COUNT);
+            while let Some(result) = results.next().await {
+                match result.time_ms() {
+                    Some(ms) => println!("{}: {:.2}ms", name, ms),
+                    None => println!("{}: failed", name),
+                }
+            }
+            (name, results.stats().clone())
+        }));
+    }
+
+    println!();
+    for task in tasks {
+        let (name, stats) = task.await.expect("probe task panicked");
+        println!(
+            "{}: {}/{} connected ({:.1}% loss), avg {}",
+            name,
+            stats.connected,
+            stats.attempted,
+            stats.loss_percent(),
+            match stats.average() {
+                Some(avg) => format!("{:.2}ms", avg),
+                None => "-".to_string(),
+            }
+        );
+    }
+}
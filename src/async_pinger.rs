@@ -0,0 +1,331 @@
+//! Async (tokio) probing, behind the `async` feature.
+//!
+//! [`AsyncPinger`] measures the same thing as the blocking
+//! [`Pinger`](crate::pinger::Pinger) and produces the same [`PingResult`]
+//! and [`PingStats`], but connects with tokio, so hundreds of targets can be
+//! probed from a handful of threads.
+//!
+//! ```no_run
+//! # async fn demo() {
+//! use paping::async_pinger::AsyncPinger;
+//! use std::time::Duration;
+//!
+//! let pinger = AsyncPinger::new(
+//!     "example.com".to_string(),
+//!     443,
+//!     Duration::from_millis(1000),
+//!     None,
+//!     None,
+//! );
+//! let mut results = pinger.into_stream(5);
+//! while let Some(result) = results.next().await {
+//!     println!("{:?}", result.time_ms());
+//! }
+//! println!("{:.1}% loss", results.stats().loss_percent());
+//! # }
+//! ```
+
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use tokio::net::{TcpSocket, TcpStream};
+
+pub use crate::result::{Connection, Failure, FailureKind, PingResult, PingStats};
+use crate::socks5::Socks5Proxy;
+
+/// Pause between two probes of a stream, as in the CLI.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Async TCP port pinger. See the [module docs](self).
+pub struct AsyncPinger {
+    target: Arc<Target>,
+    stats: PingStats,
+}
+
+/// What to probe and how. Shared with the in-flight probe futures so they
+/// don't borrow the pinger.
+struct Target {
+    address: String,
+    port: u16,
+    timeout: Duration,
+    proxy: Option<Socks5Proxy>,
+    bind_addr: Option<IpAddr>,
+}
+
+impl AsyncPinger {
+    /// Same parameters as [`Pinger::new`](crate::pinger::Pinger::new).
+    /// socks5+tls:// proxies are not supported: probes through them fail.
+    pub fn new(
+        address: String,
+        port: u16,
+        timeout: Duration,
+        proxy: Option<Socks5Proxy>,
+        bind_addr: Option<IpAddr>,
+    ) -> Self {
+        Self {
+            target: Arc::new(Target {
+                address,
+                port,
+                timeout,
+                proxy,
+                bind_addr,
+            }),
+            stats: PingStats::default(),
+        }
+    }
+
+    /// Makes one connection attempt, through the proxy if one is set.
+    /// Nothing is recorded: see `record`, or `ping` for both.
+    pub async fn probe(&self) -> PingResult {
+        self.target.probe().await
+    }
+
+    /// Applies a probe result to the statistics.
+    pub fn record(&mut self, result: &PingResult) {
+        self.stats.record(result);
+    }
+
+    /// Probes once and records the result.
+    pub async fn ping(&mut self) -> PingResult {
+        let result = self.probe().await;
+        self.record(&result);
+        result
+    }
+
+    /// Statistics gathered so far.
+    pub fn stats(&self) -> &PingStats {
+        &self.stats
+    }
+
+    /// Turns the pinger into a stream of `count` results (0 = endless),
+    /// one second apart. Results are recorded as they are yielded.
+    pub fn into_stream(self, count: u32) -> PingStream {
+        PingStream {
+            pinger: self,
+            remaining: (count > 0).then_some(count),
+            started: false,
+            pending: None,
+        }
+    }
+}
+
+impl Target {
+    async fn probe(&self) -> PingResult {
+        match self.proxy {
+            Some(ref proxy) => self.probe_proxied(proxy).await,
+            None => self.probe_direct().await,
+        }
+    }
+
+    /// Direct connection, no proxy. The time includes the DNS lookup.
+    async fn probe_direct(&self) -> PingResult {
+        let start = Instant::now();
+        let addr = match tokio::net::lookup_host((self.address.as_str(), self.port)).await {
+            Ok(mut addrs) => addrs.next(),
+            Err(_) => None,
+        };
+        let addr = match addr {
+            Some(a) => a,
+            None => {
+                return PingResult::Failed(Failure {
+                    kind: FailureKind::Resolve,
+                    message: "could not resolve address".to_string(),
+                })
+            }
+        };
+        let connected = tokio::time::timeout(self.timeout, self.connect_with_bind(addr))
+            .await
+            .unwrap_or_else(|_| {
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "connection timed out",
+                ))
+            });
+        match connected {
+            Ok(_conn) => PingResult::Connected(Connection {
+                time: start.elapsed(),
+                addr: Some(addr),
+                proxy: None,
+                exchange: None,
+                transfer: None,
+                tls: None,
+            }),
+            Err(e) => PingResult::Failed(Failure::from_io(&e, false)),
+        }
+    }
+
+    /// Binds to the -i source IP first when one is set.
+    async fn connect_with_bind(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        match self.bind_addr {
+            Some(local_ip) => {
+                let socket = if addr.is_ipv4() {
+                    TcpSocket::new_v4()?
+                } else {
+                    TcpSocket::new_v6()?
+                };
+                socket.bind(SocketAddr::new(local_ip, 0))?;
+                socket.connect(addr).await
+            }
+            None => TcpStream::connect(addr).await,
+        }
+    }
+
+    /// Route through the SOCKS5 proxy to reach the target.
+    /// The time covers the whole tunnel setup.
+    async fn probe_proxied(&self, proxy: &Socks5Proxy) -> PingResult {
+        let start = Instant::now();
+        match proxy
+            .connect_async(&self.address, self.port, self.timeout)
+            .await
+        {
+            Ok(_conn) => PingResult::Connected(Connection {
+                time: start.elapsed(),
+                addr: None,
+                proxy: Some(proxy.transport()),
+                exchange: None,
+                transfer: None,
+                tls: None,
+            }),
+            Err(e) => PingResult::Failed(Failure::from_io(&e, true)),
+        }
+    }
+}
+
+type ProbeFuture = Pin<Box<dyn Future<Output = PingResult> + Send>>;
+
+/// Stream of probe results, from [`AsyncPinger::into_stream`].
+///
+/// Implements `futures_core::Stream`; [`PingStream::next`] allows
+/// `while let Some(result) = stream.next().await` without any extra crate.
+pub struct PingStream {
+    pinger: AsyncPinger,
+    /// Probes left, `None` when endless
+    remaining: Option<u32>,
+    started: bool,
+    pending: Option<ProbeFuture>,
+}
+
+impl PingStream {
+    /// Waits for the next result; `None` once `count` probes were made.
+    pub async fn next(&mut self) -> Option<PingResult> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_result(cx)).await
+    }
+
+    /// Statistics of the results yielded so far.
+    pub fn stats(&self) -> &PingStats {
+        self.pinger.stats()
+    }
+
+    /// Gives the pinger back, with its statistics.
+    pub fn into_inner(self) -> AsyncPinger {
+        self.pinger
+    }
+
+    fn poll_result(&mut self, cx: &mut Context<'_>) -> Poll<Option<PingResult>> {
+        if self.pending.is_none() {
+            match self.remaining {
+                Some(0) => return Poll::Ready(None),
+                Some(ref mut n) => *n -= 1,
+                None => {}
+            }
+            let target = self.pinger.target.clone();
+            let delay = self.started.then_some(INTERVAL);
+            self.started = true;
+            self.pending = Some(Box::pin(async move {
+                if let Some(delay) = delay {
+                    tokio::time::sleep(delay).await;
+                }
+                target.probe().await
+            }));
+        }
+
+        let pending = self.pending.as_mut().expect("probe in flight");
+        let result = match pending.as_mut().poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.pending = None;
+        self.pinger.record(&result);
+        Poll::Ready(Some(result))
+    }
+}
+
+impl futures_core::Stream for PingStream {
+    type Item = PingResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<PingResult>> {
+        self.poll_result(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[tokio::test]
+    async fn stream_yields_count_results() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((_conn, _)) = listener.accept().await {}
+        });
+
+        let pinger = AsyncPinger::new(
+            "127.0.0.1".to_string(),
+            port,
+            Duration::from_millis(1000),
+            None,
+            None,
+        );
+        let mut stream = pinger.into_stream(2);
+        let mut results = Vec::new();
+        while let Some(result) = stream.next().await {
+            results.push(result);
+        }
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(PingResult::is_success));
+        assert_eq!(stream.stats().attempted, 2);
+        assert_eq!(stream.stats().connected, 2);
+    }
+
+    #[tokio::test]
+    async fn probe_through_mock_proxy() {
+        // Minimal no-auth SOCKS5 server answering every CONNECT with success
+        let proxy = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut c, _) = proxy.accept().unwrap();
+            let mut greeting = [0u8; 3];
+            c.read_exact(&mut greeting).unwrap();
+            assert_eq!(greeting, [0x05, 0x01, 0x00]);
+            c.write_all(&[0x05, 0x00]).unwrap();
+            let mut request = [0u8; 5 + 9 + 2];
+            c.read_exact(&mut request).unwrap();
+            assert_eq!(&request[..5], &[0x05, 0x01, 0x00, 0x03, 9]);
+            assert_eq!(&request[5..14], b"localhost");
+            c.write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0, 80])
+                .unwrap();
+        });
+
+        let proxy = Socks5Proxy::parse(&format!("socks5://{}", proxy_addr)).unwrap();
+        let pinger = AsyncPinger::new(
+            "localhost".to_string(),
+            80,
+            Duration::from_millis(1000),
+            Some(proxy),
+            None,
+        );
+        let result = pinger.probe().await;
+        server.join().unwrap();
+        match result {
+            PingResult::Connected(c) => assert_eq!(c.proxy, Some("SOCKS5")),
+            PingResult::Failed(f) => panic!("probe failed: {}", f.message),
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Bulk transfer through a SOCKS5 proxy (`paping bench-proxy`), to weigh
+//! what the proxy costs beyond latency against the same transfer made
+//! directly.
+//!
+//! The target is either `paping listen --discard` and/or `--source`,
+//! spoken to as with --throughput (a direction the listener doesn't offer
+//! is reported as such), or an echo server: the data is then sent and read
+//! back at once, so both directions carry the same bytes.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::payload::Timeouts;
+use crate::proxy::{ProxyStream, Socks5Proxy};
+use crate::result::{Direction, Transfer};
+use crate::throughput::{Limit, Refusal, Throughput};
+
+/// Data is written to an echo server in chunks of this size...
+const ECHO_CHUNK: usize = 16 * 1024;
+/// ...with at most this much not echoed back yet, which the buffers on the
+/// way hold without anyone blocking.
+const ECHO_WINDOW: u64 = 128 * 1024;
+
+/// How to reach the target.
+#[derive(Clone, Copy)]
+pub enum Route<'a> {
+    Direct,
+    Proxy(&'a Socks5Proxy),
+}
+
+/// What a route carried, each direction measured or why not.
+#[derive(Debug)]
+pub struct Report {
+    /// The target echoed: both directions were measured at once
+    pub echo: bool,
+    pub upload: Result<Transfer, String>,
+    pub download: Result<Transfer, String>,
+}
+
+/// A transfer of `limit` each way to `host:port`.
+pub struct Bench {
+    pub host: String,
+    pub port: u16,
+    pub limit: Limit,
+    /// Bounds connecting, and each read and write
+    pub timeout: Duration,
+}
+
+impl Bench {
+    pub fn new(host: &str, port: u16, limit: Limit, timeout: Duration) -> Self {
+        Bench {
+            host: host.to_string(),
+            port,
+            limit,
+            timeout,
+        }
+    }
+
+    /// Measures both directions over `route`, on a connection each. Fails
+    /// only when the target can't be reached at all.
+    pub fn run(&self, route: Route) -> Result<Report, String> {
+        let upload = Throughput::new(self.limit, Direction::Upload)
+            .try_measure(&mut self.connect(route)?, self.timeout);
+        let upload = match upload {
+            Ok(transfer) => Ok(transfer),
+            Err(Refusal::Echo) => return self.echo(route),
+            Err(Refusal::Failed(failure)) => Err(failure.message),
+        };
+        let download = Throughput::new(self.limit, Direction::Download)
+            .measure(&mut self.connect(route)?, self.timeout)
+            .map_err(|failure| failure.message);
+        Ok(Report {
+            echo: false,
+            upload,
+            download,
+        })
+    }
+
+    fn connect(&self, route: Route) -> Result<ProxyStream, String> {
+        match route {
+            Route::Proxy(proxy) => proxy
+                .connect(&self.host, self.port, self.timeout)
+                .map_err(|e| format!("connecting through the proxy failed: {}", e)),
+            Route::Direct => {
+                let addrs = (self.host.as_str(), self.port)
+                    .to_socket_addrs()
+                    .map_err(|e| format!("resolving {} failed: {}", self.host, e))?;
+                let mut last = None;
+                for addr in addrs {
+                    match TcpStream::connect_timeout(&addr, self.timeout) {
+                        Ok(stream) => return Ok(ProxyStream::Plain(stream)),
+                        Err(e) => last = Some(e),
+                    }
+                }
+                Err(match last {
+                    Some(e) => format!("connecting failed: {}", e),
+                    None => format!("{} has no address", self.host),
+                })
+            }
+        }
+    }
+
+    /// Sends the data to an echo server while reading it back.
+    fn echo(&self, route: Route) -> Result<Report, String> {
+        let mut stream = self.connect(route)?;
+        let outcome = echo(&mut stream, self.limit, self.timeout);
+        let _ = stream.shutdown();
+        let (upload, download) = match outcome {
+            Ok((upload, download)) => (Ok(upload), Ok(download)),
+            Err(e) => (Err(e.clone()), Err(e)),
+        };
+        Ok(Report {
+            echo: true,
+            upload,
+            download,
+        })
+    }
+}
+
+/// Writes `limit` of data to `stream`, keeping at most `ECHO_WINDOW` of it
+/// in flight, until all of it came back. The upload ends with the last
+/// write, the download with the last byte read back.
+fn echo<S: Read + Write + Timeouts>(
+    stream: &mut S,
+    limit: Limit,
+    timeout: Duration,
+) -> Result<(Transfer, Transfer), String> {
+    stream
+        .set_timeouts(timeout, timeout)
+        .map_err(|e| format!("could not set timeouts: {}", e))?;
+    let data = [0x5au8; ECHO_CHUNK];
+    let mut buf = [0u8; ECHO_CHUNK];
+    let start = Instant::now();
+    let (mut sent, mut received) = (0u64, 0u64);
+    let mut sending = true;
+    let mut upload_time = Duration::ZERO;
+    while sending || received < sent {
+        while sending && sent - received < ECHO_WINDOW {
+            let size = match limit {
+                Limit::Bytes(total) => (total - sent).min(ECHO_CHUNK as u64) as usize,
+                Limit::Duration(d) if start.elapsed() < d => ECHO_CHUNK,
+                Limit::Duration(_) => 0,
+            };
+            if size == 0 {
+                sending = false;
+                break;
+            }
+            stream
+                .write_all(&data[..size])
+                .and_then(|_| stream.flush())
+                .map_err(|e| format!("sending data failed: {}", e))?;
+            sent += size as u64;
+            upload_time = start.elapsed();
+        }
+        if received == sent {
+            continue;
+        }
+        match stream.read(&mut buf) {
+            Ok(0) => {
+                return Err(format!(
+                    "the target closed the connection after echoing {} of {} bytes",
+                    received, sent
+                ))
+            }
+            Ok(n) => received += n as u64,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(format!("reading the echo failed: {}", e)),
+        }
+    }
+    Ok((
+        Transfer {
+            direction: Direction::Upload,
+            bytes: sent,
+            time: upload_time,
+        },
+        Transfer {
+            direction: Direction::Download,
+            bytes: received,
+            time: start.elapsed(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listener::Listener;
+    use crate::stop::StopToken;
+
+    /// A bench against a listener serving `connections` connections.
+    fn against(listener: Listener, connections: u32) -> (Bench, std::thread::JoinHandle<()>) {
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            listener.serve(connections, &StopToken::never(), |_| {});
+        });
+        let bench = Bench::new(
+            &addr.ip().to_string(),
+            addr.port(),
+            Limit::Bytes(1_000_000),
+            Duration::from_secs(5),
+        );
+        (bench, server)
+    }
+
+    #[test]
+    fn measures_both_directions_with_a_paping_listener() {
+        let listener = Listener::bind("127.0.0.1:0".parse().unwrap())
+            .unwrap()
+            .with_discard(true);
+        let (bench, server) = against(listener, 2);
+        let report = bench.run(Route::Direct).unwrap();
+        server.join().unwrap();
+        assert!(!report.echo);
+        assert_eq!(report.upload.unwrap().bytes, 1_000_000);
+        // Only --discard: no downloads
+        assert!(report.download.unwrap_err().contains("--source"));
+    }
+
+    #[test]
+    fn measures_echo_servers_both_ways_at_once() {
+        let listener = Listener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        // The throughput request comes back, then the echoed data
+        let (bench, server) = against(listener, 2);
+        let report = bench.run(Route::Direct).unwrap();
+        server.join().unwrap();
+        assert!(report.echo);
+        let (upload, download) = (report.upload.unwrap(), report.download.unwrap());
+        assert_eq!(upload.bytes, 1_000_000);
+        assert_eq!(download.bytes, 1_000_000);
+        assert!(upload.time <= download.time);
+    }
+}
@@ -0,0 +1,839 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::payload::Payload;
+use crate::pinger::{Pinger, Ramp};
+use crate::proxy_protocol::ProxyHeader;
+use crate::socks5::Socks5Proxy;
+use crate::template::Template;
+use crate::throughput::Throughput;
+use crate::tls::TlsProbe;
+
+/// Step-by-step configuration of a [`Pinger`], from [`Pinger::builder`].
+///
+/// ```
+/// use paping::pinger::Pinger;
+/// use std::time::Duration;
+///
+/// let pinger = Pinger::builder("example.com", 443)
+///     .timeout(Duration::from_millis(500))
+///     .interval(Duration::from_millis(200))
+///     .silent(true)
+///     .build()
+///     .unwrap();
+/// # let _ = pinger;
+///
+/// // Comparing with direct connections needs a proxy to compare against
+/// assert!(Pinger::builder("example.com", 443).compare_direct(false).build().is_err());
+/// ```
+#[derive(Clone)]
+pub struct PingerBuilder {
+    address: String,
+    port: u16,
+    timeout: Duration,
+    interval: Option<Duration>,
+    ramp: Option<Ramp>,
+    min_interval: Option<Duration>,
+    strict_interval: bool,
+    proxy: Option<Socks5Proxy>,
+    bind_addr: Option<IpAddr>,
+    sources: Vec<IpAddr>,
+    compare_direct: Option<bool>,
+    dual_stack: bool,
+    happy_eyeballs: bool,
+    sctp: bool,
+    tls: Option<TlsProbe>,
+    monitor: Option<Option<Duration>>,
+    confirm: Option<u32>,
+    max_outages: Option<usize>,
+    stop_after_success: Option<u32>,
+    stop_after_failure: Option<u32>,
+    max_failures: Option<u32>,
+    loss_thresholds: Option<(f64, f64)>,
+    timing_detail: bool,
+    port_label: Option<String>,
+    label: Option<String>,
+    progress: bool,
+    format: Option<Template>,
+    flood: bool,
+    payload: Option<Payload>,
+    proxy_header: Option<ProxyHeader>,
+    throughput: Option<Throughput>,
+    dns_retries: u32,
+    dns_cache: Option<Duration>,
+    verbose: bool,
+    deadline: Option<Duration>,
+    startup_delay: Option<Duration>,
+    stats_request: Option<Arc<AtomicBool>>,
+    heartbeat: Option<(Duration, Arc<dyn Fn() + Send + Sync>)>,
+    max_latency: Option<Duration>,
+    read_timeout: Option<Duration>,
+    silent: bool,
+}
+
+/// Why [`PingerBuilder::build`] refused a configuration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// The connection timeout is zero
+    ZeroTimeout,
+    /// The interval between pings is zero
+    ZeroInterval,
+    /// Both a fixed interval and a ramp were given
+    IntervalWithRamp,
+    /// Comparing with direct connections without a proxy
+    CompareWithoutProxy,
+    /// Dual-stack mode makes direct connections only
+    DualStackWithProxy,
+    /// Happy Eyeballs races direct connections only
+    HappyEyeballsWithProxy,
+    /// Dual-stack and Happy Eyeballs are two different ways of using both families
+    DualStackWithHappyEyeballs,
+    /// A stop-after-success/failure or max-failures limit of zero
+    ZeroStopAfter,
+    /// Loss thresholds outside 0-100%, or warning above critical
+    InvalidLossThresholds,
+    /// Certificate checks disabled on a proxy that doesn't use TLS
+    InsecureWithoutTls,
+    /// Per-step timing of proxied connections without a proxy
+    TimingDetailWithoutProxy,
+    /// The deadline for each probe is zero
+    ZeroDeadline,
+    /// The latency limit is zero: nothing could pass
+    ZeroMaxLatency,
+    /// The read timeout is zero
+    ZeroReadTimeout,
+    /// A read timeout without a payload reading the reply
+    ReadTimeoutWithoutReply,
+    /// The deadline bounds lookups and connects the proxy makes itself
+    DeadlineWithProxy,
+    /// Flooding probes back to back: there is no interval to set
+    FloodWithInterval,
+    /// Flooding through a proxy would flood the proxy
+    FloodWithProxy,
+    /// Source rotation binds direct connections only
+    RotationWithProxy,
+    /// Source rotation, dual-stack and Happy Eyeballs each pick the
+    /// connection of a round their own way
+    RotationWithDualStack,
+    /// Caching lookups for no time at all
+    ZeroDnsCache,
+    /// The proxy resolves the target itself: there are no lookups to cache
+    DnsCacheWithProxy,
+    /// Requiring authentication from a proxy given no credentials
+    RequireAuthWithoutCredentials,
+    /// A PROXY protocol header names the address connected to, which the
+    /// SOCKS5 proxy keeps to itself
+    ProxyHeaderWithProxy,
+    /// A payload would come before the throughput request, which the
+    /// listener expects first
+    ThroughputWithPayload,
+    /// SOCKS5 proxies relay TCP only
+    SctpWithProxy,
+    /// No SCTP here: not Linux, or no SCTP in the kernel
+    SctpUnsupported,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            BuildError::ZeroTimeout => "timeout must be greater than 0",
+            BuildError::ZeroInterval => "interval must be greater than 0",
+            BuildError::ZeroStopAfter => {
+                "stop-after and max-failures limits must be greater than 0"
+            }
+            BuildError::InvalidLossThresholds => {
+                "loss thresholds must be between 0 and 100, warning not above critical"
+            }
+            BuildError::IntervalWithRamp => "a fixed interval and a ramp can't be combined",
+            BuildError::CompareWithoutProxy => "comparing with direct connections requires a proxy",
+            BuildError::DualStackWithProxy => "dual-stack mode can't be used through a proxy",
+            BuildError::HappyEyeballsWithProxy => {
+                "Happy Eyeballs mode can't be used through a proxy"
+            }
+            BuildError::DualStackWithHappyEyeballs => {
+                "dual-stack and Happy Eyeballs modes can't be combined"
+            }
+            BuildError::InsecureWithoutTls => {
+                "skipping certificate checks only applies to socks5+tls:// proxies"
+            }
+            BuildError::TimingDetailWithoutProxy => "timing details require a proxy",
+            BuildError::ZeroDeadline => "deadline must be greater than 0",
+            BuildError::ZeroMaxLatency => "maximum latency must be greater than 0",
+            BuildError::ZeroReadTimeout => "read timeout must be greater than 0",
+            BuildError::ReadTimeoutWithoutReply => {
+                "a read timeout requires a payload reading the reply"
+            }
+            BuildError::DeadlineWithProxy => "a deadline can't be used through a proxy",
+            BuildError::FloodWithInterval => "flood mode can't have an interval or ramp",
+            BuildError::FloodWithProxy => "flood mode can't be used through a proxy",
+            BuildError::RotationWithProxy => "rotating source IPs only applies without a proxy",
+            BuildError::RotationWithDualStack => {
+                "rotating source IPs can't be combined with dual-stack or Happy Eyeballs modes"
+            }
+            BuildError::ZeroDnsCache => "the DNS cache time must be greater than 0",
+            BuildError::DnsCacheWithProxy => "a proxy resolves the target itself: no DNS to cache",
+            BuildError::RequireAuthWithoutCredentials => {
+                "requiring proxy authentication needs credentials in the proxy URL"
+            }
+            BuildError::ProxyHeaderWithProxy => {
+                "a PROXY protocol header can't be sent through a SOCKS5 proxy"
+            }
+            BuildError::ThroughputWithPayload => {
+                "measuring throughput can't be combined with sending a payload"
+            }
+            BuildError::SctpWithProxy => "SCTP can't go through a SOCKS5 proxy, which relays TCP only",
+            BuildError::SctpUnsupported => {
+                "SCTP isn't supported here (Linux only, with SCTP in the kernel)"
+            }
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl PingerBuilder {
+    pub(crate) fn new(address: String, port: u16) -> Self {
+        Self {
+            address,
+            port,
+            timeout: Duration::from_millis(1000),
+            interval: None,
+            ramp: None,
+            min_interval: None,
+            strict_interval: false,
+            proxy: None,
+            bind_addr: None,
+            sources: Vec::new(),
+            compare_direct: None,
+            dual_stack: false,
+            happy_eyeballs: false,
+            sctp: false,
+            tls: None,
+            monitor: None,
+            confirm: None,
+            max_outages: None,
+            stop_after_success: None,
+            stop_after_failure: None,
+            max_failures: None,
+            loss_thresholds: None,
+            timing_detail: false,
+            port_label: None,
+            label: None,
+            progress: false,
+            format: None,
+            flood: false,
+            payload: None,
+            proxy_header: None,
+            throughput: None,
+            dns_retries: 0,
+            dns_cache: None,
+            verbose: false,
+            deadline: None,
+            startup_delay: None,
+            stats_request: None,
+            heartbeat: None,
+            max_latency: None,
+            read_timeout: None,
+            silent: false,
+        }
+    }
+
+    /// Points the builder at another host and port, keeping everything
+    /// else: configure once, then build a pinger per target.
+    pub fn target(mut self, address: impl Into<String>, port: u16) -> Self {
+        self.address = address.into();
+        self.port = port;
+        self
+    }
+
+    /// Maximum wait for each connection (default 1s).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Pause between two pings (default 1s).
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Speed up following `ramp` instead of a fixed interval.
+    pub fn ramp(mut self, ramp: Ramp) -> Self {
+        self.ramp = Some(ramp);
+        self
+    }
+
+    /// Connect through this SOCKS5 proxy.
+    pub fn proxy(mut self, proxy: impl Into<Option<Socks5Proxy>>) -> Self {
+        self.proxy = proxy.into();
+        self
+    }
+
+    /// Local source IP to connect from.
+    pub fn bind_addr(mut self, bind_addr: impl Into<Option<IpAddr>>) -> Self {
+        self.bind_addr = bind_addr.into();
+        self
+    }
+
+    /// Local source IPs to connect from in turn, see
+    /// [`Pinger::with_source_rotation`]. Replaces `bind_addr`.
+    pub fn source_rotation(mut self, sources: Vec<IpAddr>) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Also connect directly every round, see [`Pinger::with_compare_direct`].
+    pub fn compare_direct(mut self, concurrent: bool) -> Self {
+        self.compare_direct = Some(concurrent);
+        self
+    }
+
+    /// See [`Pinger::with_dual_stack`].
+    pub fn dual_stack(mut self, dual_stack: bool) -> Self {
+        self.dual_stack = dual_stack;
+        self
+    }
+
+    /// See [`Pinger::with_happy_eyeballs`].
+    pub fn happy_eyeballs(mut self, happy_eyeballs: bool) -> Self {
+        self.happy_eyeballs = happy_eyeballs;
+        self
+    }
+
+    /// See [`Pinger::with_sctp`].
+    pub fn sctp(mut self, sctp: bool) -> Self {
+        self.sctp = sctp;
+        self
+    }
+
+    /// See [`Pinger::with_tls`].
+    pub fn tls(mut self, probe: TlsProbe) -> Self {
+        self.tls = Some(probe);
+        self
+    }
+
+    /// See [`Pinger::with_monitor`].
+    pub fn monitor(mut self, heartbeat: Option<Duration>) -> Self {
+        self.monitor = Some(heartbeat);
+        self
+    }
+
+    /// See [`Pinger::with_confirm`].
+    pub fn confirm(mut self, confirm: u32) -> Self {
+        self.confirm = Some(confirm);
+        self
+    }
+
+    /// See [`Pinger::with_max_outages`].
+    pub fn max_outages(mut self, limit: usize) -> Self {
+        self.max_outages = Some(limit);
+        self
+    }
+
+    /// See [`Pinger::with_stop_after_success`].
+    pub fn stop_after_success(mut self, n: u32) -> Self {
+        self.stop_after_success = Some(n);
+        self
+    }
+
+    /// See [`Pinger::with_stop_after_failure`].
+    pub fn stop_after_failure(mut self, n: u32) -> Self {
+        self.stop_after_failure = Some(n);
+        self
+    }
+
+    /// See [`Pinger::with_max_failures`].
+    pub fn max_failures(mut self, n: u32) -> Self {
+        self.max_failures = Some(n);
+        self
+    }
+
+    /// See [`Pinger::with_loss_thresholds`].
+    pub fn loss_thresholds(mut self, warn: f64, crit: f64) -> Self {
+        self.loss_thresholds = Some((warn, crit));
+        self
+    }
+
+    /// See [`Pinger::with_timing_detail`].
+    pub fn timing_detail(mut self, timing_detail: bool) -> Self {
+        self.timing_detail = timing_detail;
+        self
+    }
+
+    /// See [`Pinger::with_port_label`].
+    pub fn port_label(mut self, label: impl Into<String>) -> Self {
+        self.port_label = Some(label.into());
+        self
+    }
+
+    /// See [`Pinger::with_label`].
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// See [`Pinger::with_progress`].
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// See [`Pinger::with_flood`].
+    pub fn flood(mut self, flood: bool) -> Self {
+        self.flood = flood;
+        self
+    }
+
+    /// See [`Pinger::with_format`].
+    pub fn format(mut self, template: Template) -> Self {
+        self.format = Some(template);
+        self
+    }
+
+    /// See [`Pinger::with_payload`].
+    pub fn payload(mut self, payload: Payload) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    /// See [`Pinger::with_proxy_header`].
+    pub fn proxy_header(mut self, header: ProxyHeader) -> Self {
+        self.proxy_header = Some(header);
+        self
+    }
+
+    /// See [`Pinger::with_throughput`].
+    pub fn throughput(mut self, throughput: Throughput) -> Self {
+        self.throughput = Some(throughput);
+        self
+    }
+
+    /// See [`Pinger::with_min_interval`].
+    pub fn min_interval(mut self, floor: Duration) -> Self {
+        self.min_interval = Some(floor);
+        self
+    }
+
+    /// See [`Pinger::with_strict_interval`].
+    pub fn strict_interval(mut self, strict: bool) -> Self {
+        self.strict_interval = strict;
+        self
+    }
+
+    /// See [`Pinger::with_dns_retries`].
+    pub fn dns_retries(mut self, n: u32) -> Self {
+        self.dns_retries = n;
+        self
+    }
+
+    /// See [`Pinger::with_dns_cache`].
+    pub fn dns_cache(mut self, ttl: Duration) -> Self {
+        self.dns_cache = Some(ttl);
+        self
+    }
+
+    /// See [`Pinger::with_verbose`].
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// See [`Pinger::with_deadline`].
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// See [`Pinger::with_startup_delay`].
+    pub fn startup_delay(mut self, delay: Duration) -> Self {
+        self.startup_delay = Some(delay);
+        self
+    }
+
+    /// See [`Pinger::with_stats_request`].
+    pub fn stats_request(mut self, request: Arc<AtomicBool>) -> Self {
+        self.stats_request = Some(request);
+        self
+    }
+
+    /// See [`Pinger::with_heartbeat`]. The pingers built from clones of
+    /// the builder share `beat`.
+    pub fn heartbeat(mut self, every: Duration, beat: Arc<dyn Fn() + Send + Sync>) -> Self {
+        self.heartbeat = Some((every, beat));
+        self
+    }
+
+    /// See [`Pinger::with_max_latency`].
+    pub fn max_latency(mut self, max: Duration) -> Self {
+        self.max_latency = Some(max);
+        self
+    }
+
+    /// See [`Pinger::with_read_timeout`].
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// See [`Pinger::with_silent`].
+    pub fn silent(mut self, silent: bool) -> Self {
+        self.silent = silent;
+        self
+    }
+
+    /// Checks the configuration and creates the pinger.
+    pub fn build(self) -> Result<Pinger, BuildError> {
+        if self.timeout.is_zero() {
+            return Err(BuildError::ZeroTimeout);
+        }
+        let limits = [
+            self.stop_after_success,
+            self.stop_after_failure,
+            self.max_failures,
+        ];
+        if limits.contains(&Some(0)) {
+            return Err(BuildError::ZeroStopAfter);
+        }
+        if let Some((warn, crit)) = self.loss_thresholds {
+            let percent = 0.0..=100.0;
+            if !percent.contains(&warn) || !percent.contains(&crit) || warn > crit {
+                return Err(BuildError::InvalidLossThresholds);
+            }
+        }
+        if self.deadline.is_some_and(|d| d.is_zero()) {
+            return Err(BuildError::ZeroDeadline);
+        }
+        if self.dns_cache.is_some_and(|d| d.is_zero()) {
+            return Err(BuildError::ZeroDnsCache);
+        }
+        if self.max_latency.is_some_and(|d| d.is_zero()) {
+            return Err(BuildError::ZeroMaxLatency);
+        }
+        if let Some(timeout) = self.read_timeout {
+            if timeout.is_zero() {
+                return Err(BuildError::ZeroReadTimeout);
+            }
+            if !self.payload.as_ref().is_some_and(|p| p.read_reply) {
+                return Err(BuildError::ReadTimeoutWithoutReply);
+            }
+        }
+        if self.throughput.is_some() && self.payload.is_some() {
+            return Err(BuildError::ThroughputWithPayload);
+        }
+        match (self.interval, self.ramp) {
+            _ if self.flood && (self.interval.is_some() || self.ramp.is_some()) => {
+                return Err(BuildError::FloodWithInterval)
+            }
+            (Some(_), Some(_)) => return Err(BuildError::IntervalWithRamp),
+            (Some(interval), None) if interval.is_zero() => return Err(BuildError::ZeroInterval),
+            _ => {}
+        }
+        if let Some(ref proxy) = self.proxy {
+            if proxy.tls_insecure && !proxy.tls {
+                return Err(BuildError::InsecureWithoutTls);
+            }
+            if proxy.require_auth && proxy.username.is_none() {
+                return Err(BuildError::RequireAuthWithoutCredentials);
+            }
+            if self.dual_stack {
+                return Err(BuildError::DualStackWithProxy);
+            }
+            if self.happy_eyeballs {
+                return Err(BuildError::HappyEyeballsWithProxy);
+            }
+            if self.deadline.is_some() {
+                return Err(BuildError::DeadlineWithProxy);
+            }
+            if self.flood {
+                return Err(BuildError::FloodWithProxy);
+            }
+            if self.proxy_header.is_some() {
+                return Err(BuildError::ProxyHeaderWithProxy);
+            }
+            if self.dns_cache.is_some() {
+                return Err(BuildError::DnsCacheWithProxy);
+            }
+        } else if self.compare_direct.is_some() {
+            return Err(BuildError::CompareWithoutProxy);
+        } else if self.timing_detail {
+            return Err(BuildError::TimingDetailWithoutProxy);
+        }
+        if self.dual_stack && self.happy_eyeballs {
+            return Err(BuildError::DualStackWithHappyEyeballs);
+        }
+        if self.sctp {
+            if self.proxy.is_some() {
+                return Err(BuildError::SctpWithProxy);
+            }
+            if !crate::pinger::sctp_supported() {
+                return Err(BuildError::SctpUnsupported);
+            }
+        }
+        if self.sources.len() > 1 {
+            if self.proxy.is_some() {
+                return Err(BuildError::RotationWithProxy);
+            }
+            if self.dual_stack || self.happy_eyeballs {
+                return Err(BuildError::RotationWithDualStack);
+            }
+        }
+
+        let mut pinger = Pinger::new(
+            self.address,
+            self.port,
+            self.timeout,
+            self.proxy,
+            self.bind_addr,
+        )
+        .with_silent(self.silent);
+        if let Some(interval) = self.interval {
+            pinger = pinger.with_interval(interval);
+        }
+        if let Some(ramp) = self.ramp {
+            pinger = pinger.with_ramp(ramp);
+        }
+        if let Some(floor) = self.min_interval {
+            pinger = pinger.with_min_interval(floor);
+        }
+        if self.strict_interval {
+            pinger = pinger.with_strict_interval();
+        }
+        if let Some(concurrent) = self.compare_direct {
+            pinger = pinger.with_compare_direct(concurrent);
+        }
+        if !self.sources.is_empty() {
+            pinger = pinger.with_source_rotation(self.sources);
+        }
+        if self.dual_stack {
+            pinger = pinger.with_dual_stack();
+        }
+        if self.sctp {
+            pinger = pinger.with_sctp();
+        }
+        if let Some(probe) = self.tls {
+            pinger = pinger.with_tls(probe);
+        }
+        if let Some(heartbeat) = self.monitor {
+            pinger = pinger.with_monitor(heartbeat);
+        }
+        if let Some(confirm) = self.confirm {
+            pinger = pinger.with_confirm(confirm);
+        }
+        if let Some(limit) = self.max_outages {
+            pinger = pinger.with_max_outages(limit);
+        }
+        if self.happy_eyeballs {
+            pinger = pinger.with_happy_eyeballs();
+        }
+        if let Some(n) = self.stop_after_success {
+            pinger = pinger.with_stop_after_success(n);
+        }
+        if let Some(n) = self.stop_after_failure {
+            pinger = pinger.with_stop_after_failure(n);
+        }
+        if let Some(n) = self.max_failures {
+            pinger = pinger.with_max_failures(n);
+        }
+        if let Some((warn, crit)) = self.loss_thresholds {
+            pinger = pinger.with_loss_thresholds(warn, crit);
+        }
+        if self.timing_detail {
+            pinger = pinger.with_timing_detail();
+        }
+        if let Some(label) = self.port_label {
+            pinger = pinger.with_port_label(label);
+        }
+        if let Some(payload) = self.payload {
+            pinger = pinger.with_payload(payload);
+        }
+        if let Some(header) = self.proxy_header {
+            pinger = pinger.with_proxy_header(header);
+        }
+        if let Some(throughput) = self.throughput {
+            pinger = pinger.with_throughput(throughput);
+        }
+        if let Some(label) = self.label {
+            pinger = pinger.with_label(label);
+        }
+        if self.progress {
+            pinger = pinger.with_progress();
+        }
+        if let Some(template) = self.format {
+            pinger = pinger.with_format(template);
+        }
+        if self.flood {
+            pinger = pinger.with_flood();
+        }
+        if self.dns_retries > 0 {
+            pinger = pinger.with_dns_retries(self.dns_retries);
+        }
+        if let Some(ttl) = self.dns_cache {
+            pinger = pinger.with_dns_cache(ttl);
+        }
+        if self.verbose {
+            pinger = pinger.with_verbose();
+        }
+        if let Some(deadline) = self.deadline {
+            pinger = pinger.with_deadline(deadline);
+        }
+        if let Some(delay) = self.startup_delay {
+            pinger = pinger.with_startup_delay(delay);
+        }
+        if let Some(request) = self.stats_request {
+            pinger = pinger.with_stats_request(request);
+        }
+        if let Some((every, beat)) = self.heartbeat {
+            pinger = pinger.with_heartbeat(every, beat);
+        }
+        if let Some(max) = self.max_latency {
+            pinger = pinger.with_max_latency(max);
+        }
+        if let Some(timeout) = self.read_timeout {
+            pinger = pinger.with_read_timeout(timeout);
+        }
+        Ok(pinger)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(url: &str) -> Socks5Proxy {
+        Socks5Proxy::parse(url).unwrap()
+    }
+
+    #[test]
+    fn rejects_nonsensical_combinations() {
+        let b = || Pinger::builder("example.com", 443);
+        let err = |b: PingerBuilder| b.build().err().unwrap();
+
+        assert_eq!(err(b().timeout(Duration::ZERO)), BuildError::ZeroTimeout);
+        assert_eq!(err(b().interval(Duration::ZERO)), BuildError::ZeroInterval);
+        assert_eq!(err(b().stop_after_failure(0)), BuildError::ZeroStopAfter);
+        assert_eq!(err(b().max_failures(0)), BuildError::ZeroStopAfter);
+        assert_eq!(
+            err(b().loss_thresholds(50.0, 10.0)),
+            BuildError::InvalidLossThresholds
+        );
+        assert_eq!(
+            err(b().loss_thresholds(5.0, 150.0)),
+            BuildError::InvalidLossThresholds
+        );
+        assert_eq!(
+            err(b()
+                .interval(Duration::from_secs(1))
+                .ramp(Ramp::parse("1000:50:10").unwrap())),
+            BuildError::IntervalWithRamp
+        );
+        assert_eq!(
+            err(b().compare_direct(true)),
+            BuildError::CompareWithoutProxy
+        );
+        assert_eq!(
+            err(b().timing_detail(true)),
+            BuildError::TimingDetailWithoutProxy
+        );
+        assert_eq!(
+            err(b().proxy(proxy("socks5://127.0.0.1:1080")).dual_stack(true)),
+            BuildError::DualStackWithProxy
+        );
+        assert_eq!(
+            err(b().proxy(proxy("socks5://127.0.0.1:1080")).happy_eyeballs(true)),
+            BuildError::HappyEyeballsWithProxy
+        );
+        assert_eq!(
+            err(b().proxy(proxy("socks5://127.0.0.1:1080")).deadline(Duration::from_secs(1))),
+            BuildError::DeadlineWithProxy
+        );
+        assert_eq!(err(b().deadline(Duration::ZERO)), BuildError::ZeroDeadline);
+        assert_eq!(err(b().dns_cache(Duration::ZERO)), BuildError::ZeroDnsCache);
+        assert_eq!(
+            err(b().proxy(proxy("socks5://127.0.0.1:1080")).dns_cache(Duration::from_secs(30))),
+            BuildError::DnsCacheWithProxy
+        );
+        assert_eq!(err(b().max_latency(Duration::ZERO)), BuildError::ZeroMaxLatency);
+        assert_eq!(
+            err(b().read_timeout(Duration::from_secs(1))),
+            BuildError::ReadTimeoutWithoutReply
+        );
+        assert_eq!(
+            err(b().payload(Payload::banner()).read_timeout(Duration::ZERO)),
+            BuildError::ZeroReadTimeout
+        );
+        assert_eq!(
+            err(b().flood(true).interval(Duration::from_millis(10))),
+            BuildError::FloodWithInterval
+        );
+        assert_eq!(
+            err(b().proxy(proxy("socks5://127.0.0.1:1080")).flood(true)),
+            BuildError::FloodWithProxy
+        );
+        assert_eq!(
+            err(b().proxy(proxy("socks5://127.0.0.1:1080")).sctp(true)),
+            BuildError::SctpWithProxy
+        );
+        assert_eq!(
+            err(b().dual_stack(true).happy_eyeballs(true)),
+            BuildError::DualStackWithHappyEyeballs
+        );
+        let sources = || vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        assert_eq!(
+            err(b().source_rotation(sources()).proxy(proxy("socks5://127.0.0.1:1080"))),
+            BuildError::RotationWithProxy
+        );
+        assert_eq!(
+            err(b().source_rotation(sources()).happy_eyeballs(true)),
+            BuildError::RotationWithDualStack
+        );
+
+        let mut insecure = proxy("socks5://127.0.0.1:1080");
+        insecure.tls_insecure = true;
+        assert_eq!(err(b().proxy(insecure)), BuildError::InsecureWithoutTls);
+
+        let header = ProxyHeader::new(
+            crate::proxy_protocol::Version::V1,
+            "1.2.3.4:5678".parse().unwrap(),
+        );
+        assert_eq!(
+            err(b().proxy(proxy("socks5://127.0.0.1:1080")).proxy_header(header)),
+            BuildError::ProxyHeaderWithProxy
+        );
+
+        let throughput = Throughput::new(
+            crate::throughput::Limit::Bytes(1000),
+            crate::result::Direction::Upload,
+        );
+        assert_eq!(
+            err(b().payload(Payload::banner()).throughput(throughput)),
+            BuildError::ThroughputWithPayload
+        );
+
+        let mut anonymous = proxy("socks5://127.0.0.1:1080");
+        anonymous.require_auth = true;
+        assert_eq!(err(b().proxy(anonymous)), BuildError::RequireAuthWithoutCredentials);
+    }
+
+    #[test]
+    fn builds_valid_configurations() {
+        let p = Pinger::builder("example.com", 443)
+            .proxy(proxy("socks5+tls://127.0.0.1:1080"))
+            .compare_direct(false)
+            .interval(Duration::from_millis(250))
+            .silent(true)
+            .build()
+            .unwrap();
+        assert_eq!(p.stats().attempted, 0);
+
+        assert!(Pinger::builder("example.com", 443)
+            .dual_stack(true)
+            .bind_addr("127.0.0.1".parse::<IpAddr>().unwrap())
+            .build()
+            .is_ok());
+    }
+}
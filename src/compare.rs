@@ -0,0 +1,417 @@
+//! paping compare: two recorded runs side by side (before and after a
+//! change, say), from --log/--json NDJSON or --json-pretty documents.
+//!
+//! Files are read with the types that write them (`crate::json`), and the
+//! figures worked out again from the samples, so that files from runs
+//! appended to one another (--log) count as one.
+
+use colored::Colorize;
+use paping::target::join_host_port;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use crate::json::{Document, Record, Sample, SCHEMA};
+
+/// Changes of the average connection time smaller than this (in percent)
+/// count as none.
+const LATENCY_SAME_PERCENT: f64 = 5.0;
+/// Likewise for the loss, in percentage points.
+const LOSS_SAME_POINTS: f64 = 0.1;
+
+/// The samples of a recorded run, with what they're of.
+#[derive(Debug)]
+pub struct Recorded {
+    pub schema: u32,
+    /// host:port
+    pub target: String,
+    pub samples: Vec<Sample>,
+}
+
+/// Just the version of a --json-pretty document, read before the rest
+/// (which differs between versions).
+#[derive(Deserialize)]
+struct Header {
+    schema: u32,
+    /// Not a lone start record
+    #[serde(rename = "summary")]
+    _summary: IgnoredAny,
+}
+
+/// Only files of the current schema are read: samples of schema 1 had
+/// their own field names.
+fn readable(schema: u32) -> Result<(), String> {
+    match schema.cmp(&SCHEMA) {
+        std::cmp::Ordering::Equal => Ok(()),
+        std::cmp::Ordering::Greater => Err(format!(
+            "written by a newer paping (schema {}, this one reads {})",
+            schema, SCHEMA
+        )),
+        std::cmp::Ordering::Less => Err(format!(
+            "written by an older paping (schema {}, this one reads {})",
+            schema, SCHEMA
+        )),
+    }
+}
+
+/// Reads a --json-pretty document or NDJSON records (any number of runs
+/// of the same target). Errors name the line.
+pub fn parse(text: &str) -> Result<Recorded, String> {
+    let (schema, samples) = match serde_json::from_str::<Header>(text) {
+        Ok(header) => {
+            readable(header.schema)?;
+            let document: Document = serde_json::from_str(text).map_err(|e| e.to_string())?;
+            (document.schema, document.samples)
+        }
+        Err(_) => {
+            let (mut schema, mut samples) = (None, Vec::new());
+            for (n, line) in text.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // Start records read the same in every schema, and come
+                // before the samples of their run
+                match serde_json::from_str::<Record>(line) {
+                    Ok(Record::Start(start)) => {
+                        readable(start.schema)?;
+                        schema = Some(start.schema);
+                    }
+                    Ok(Record::Sample(sample)) => samples.push(sample),
+                    Ok(Record::Summary(_)) => {}
+                    Err(e) => return Err(format!("line {}: {}", n + 1, e)),
+                }
+            }
+            match schema {
+                Some(schema) => (schema, samples),
+                None => return Err("no start record, so no schema version".to_string()),
+            }
+        }
+    };
+
+    let mut targets: Vec<String> = samples
+        .iter()
+        .map(|s| join_host_port(&s.target, s.port))
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    let target = match targets.len() {
+        0 => return Err("no samples".to_string()),
+        1 => targets.remove(0),
+        _ => {
+            return Err(format!(
+                "samples of several targets ({})",
+                targets.join(", ")
+            ))
+        }
+    };
+    Ok(Recorded {
+        schema,
+        target,
+        samples,
+    })
+}
+
+/// What gets compared.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Figures {
+    pub attempted: usize,
+    pub loss_percent: f64,
+    pub min: Option<f64>,
+    pub avg: Option<f64>,
+    pub p95: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl Figures {
+    pub fn of(samples: &[Sample]) -> Self {
+        let mut times: Vec<f64> = samples.iter().filter_map(|s| s.result.time_ms()).collect();
+        times.sort_by(f64::total_cmp);
+        let attempted = samples.len();
+        let failed = attempted - times.len();
+        Figures {
+            attempted,
+            loss_percent: if attempted == 0 {
+                0.0
+            } else {
+                failed as f64 * 100.0 / attempted as f64
+            },
+            min: times.first().copied(),
+            avg: (!times.is_empty()).then(|| times.iter().sum::<f64>() / times.len() as f64),
+            p95: percentile(&times, 95.0),
+            max: times.last().copied(),
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Relative change from `before` to `after`, in percent.
+fn change_percent(before: f64, after: f64) -> Option<f64> {
+    (before > 0.0).then(|| (after - before) * 100.0 / before)
+}
+
+/// Sums it all up: "latency improved 23%, loss unchanged".
+pub fn verdict(before: &Figures, after: &Figures) -> String {
+    let latency = match (before.avg, after.avg) {
+        (Some(b), Some(a)) => match change_percent(b, a) {
+            Some(c) if c <= -LATENCY_SAME_PERCENT => format!("latency improved {:.0}%", -c),
+            Some(c) if c >= LATENCY_SAME_PERCENT => format!("latency worsened {:.0}%", c),
+            _ => "latency unchanged".to_string(),
+        },
+        _ => "latency not comparable (no connections on one side)".to_string(),
+    };
+    let points = after.loss_percent - before.loss_percent;
+    let loss = if points.abs() < LOSS_SAME_POINTS {
+        "loss unchanged".to_string()
+    } else if points < 0.0 {
+        format!("loss improved {:.1} points", -points)
+    } else {
+        format!("loss worsened {:.1} points", points)
+    };
+    format!("{}, {}", latency, loss)
+}
+
+/// Prints the two runs side by side, with the change of each figure, then
+/// the verdict.
+pub fn print(before_name: &str, before: &Recorded, after_name: &str, after: &Recorded) {
+    let (b, a) = (Figures::of(&before.samples), Figures::of(&after.samples));
+    println!();
+    println!(
+        "Comparing  {}  ({} pings)  with  {}  ({} pings)  for  {}",
+        before_name.green(),
+        b.attempted,
+        after_name.green(),
+        a.attempted,
+        before.target.green()
+    );
+    println!();
+
+    let ms = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.2}ms", v));
+    let mut rows = vec![[
+        String::new(),
+        "Before".to_string(),
+        "After".to_string(),
+        "Change".to_string(),
+    ]];
+    // Lower is better for every figure: the sign of the change tells
+    let mut signs = vec![0.0];
+    let points = a.loss_percent - b.loss_percent;
+    rows.push([
+        "Loss".to_string(),
+        format!("{:.1}%", b.loss_percent),
+        format!("{:.1}%", a.loss_percent),
+        format!("{:+.1} points", points),
+    ]);
+    signs.push(if points.abs() < LOSS_SAME_POINTS {
+        0.0
+    } else {
+        points
+    });
+    for (name, before, after) in [
+        ("Minimum", b.min, a.min),
+        ("Average", b.avg, a.avg),
+        ("p95", b.p95, a.p95),
+        ("Maximum", b.max, a.max),
+    ] {
+        let (change, sign) = match (before, after) {
+            (Some(x), Some(y)) => {
+                let percent =
+                    change_percent(x, y).map_or(String::new(), |c| format!(" ({:+.1}%)", c));
+                (format!("{:+.2}ms{}", y - x, percent), y - x)
+            }
+            _ => ("-".to_string(), 0.0),
+        };
+        rows.push([name.to_string(), ms(before), ms(after), change]);
+        signs.push(sign);
+    }
+
+    // The last column isn't padded
+    let widths: Vec<usize> = (0..3)
+        .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap_or(0))
+        .chain([0])
+        .collect();
+    for (row, sign) in rows.iter().zip(signs) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        let change = match sign {
+            s if s < 0.0 => cells[3].green(),
+            s if s > 0.0 => cells[3].red(),
+            _ => cells[3].normal(),
+        };
+        println!("  {}  {}  {}  {}", cells[0], cells[1], cells[2], change);
+    }
+    println!();
+    let verdict = verdict(&b, &a);
+    let mut chars = verdict.chars();
+    let verdict: String = chars
+        .next()
+        .map(|c| c.to_uppercase().chain(chars).collect())
+        .unwrap_or_default();
+    println!("{}", verdict);
+}
+
+/// paping compare: reads both files, checks they're comparable, prints.
+pub fn run(before_path: &std::path::Path, after_path: &std::path::Path) -> Result<(), String> {
+    let load = |path: &std::path::Path| {
+        std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parse(&text))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    };
+    let (before, after) = (load(before_path)?, load(after_path)?);
+    if before.schema != after.schema {
+        return Err(format!(
+            "the files have different schema versions: {} in {}, {} in {}",
+            before.schema,
+            before_path.display(),
+            after.schema,
+            after_path.display()
+        ));
+    }
+    if before.target != after.target {
+        return Err(format!(
+            "the runs are of different targets: {} in {}, {} in {}",
+            before.target,
+            before_path.display(),
+            after.target,
+            after_path.display()
+        ));
+    }
+    print(
+        &before_path.display().to_string(),
+        &before,
+        &after_path.display().to_string(),
+        &after,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paping::pinger::{FailureKind, PingResult};
+    use std::time::Duration;
+
+    fn sample(target: &str, seq: u32, time_ms: Option<f64>) -> String {
+        let result = match time_ms {
+            Some(ms) => PingResult::connected(Duration::from_secs_f64(ms / 1000.0)),
+            None => PingResult::failed(FailureKind::Timeout, "connection timed out"),
+        };
+        let sample = Sample {
+            ts: "2026-10-15T09:30:00.000Z".to_string(),
+            target: target.to_string(),
+            port: 443,
+            seq,
+            label: None,
+            result,
+        };
+        serde_json::to_string(&Record::Sample(sample)).unwrap()
+    }
+
+    const START: &str =
+        r#"{"type":"start","schema":2,"ts":"2026-10-15T09:30:00.000Z","target":"a","port":443}"#;
+
+    #[test]
+    fn parses_ndjson_and_documents() {
+        let ndjson = [
+            START.to_string(),
+            sample("a", 1, Some(10.0)),
+            sample("a", 2, None),
+            // A second run appended
+            START.to_string(),
+            sample("a", 1, Some(20.0)),
+        ]
+        .join("\n");
+        let recorded = parse(&ndjson).unwrap();
+        assert_eq!((recorded.schema, recorded.target.as_str()), (2, "a:443"));
+        assert_eq!(recorded.samples.len(), 3);
+
+        let document = format!(
+            r#"{{"schema": 2, "summary": {{"ts": "", "target": "a", "port": 443, "attempted": 1,
+                "connected": 1, "failed": 0, "resolve_failed": 0, "times_ms": [10.0],
+                "loss_percent": 0.0, "min_ms": 10.0, "avg_ms": 10.0, "max_ms": 10.0}},
+                "samples": [{}]}}"#,
+            sample("a", 1, Some(10.0)).replace(r#""type":"sample","#, "")
+        );
+        assert_eq!(parse(&document).unwrap().samples.len(), 1);
+    }
+
+    #[test]
+    fn rejects_what_cant_be_compared() {
+        let err = |lines: &[String]| parse(&lines.join("\n")).unwrap_err();
+        assert_eq!(
+            err(&[sample("a", 1, Some(1.0))]),
+            "no start record, so no schema version"
+        );
+        assert_eq!(
+            err(&[
+                START.to_string(),
+                sample("a", 1, None),
+                sample("b", 2, None)
+            ]),
+            "samples of several targets (a:443, b:443)"
+        );
+        assert_eq!(
+            err(&[START.replace(":2,", ":3,"), sample("a", 1, None)]),
+            "written by a newer paping (schema 3, this one reads 2)"
+        );
+        let old_sample = r#"{"type":"sample","ts":"2026-10-15T09:30:00.000Z","target":"a","port":443,"seq":1,"status":"failed","kind":"timeout","error":"connection timed out"}"#;
+        assert_eq!(
+            err(&[START.replace(":2,", ":1,"), old_sample.to_string()]),
+            "written by an older paping (schema 1, this one reads 2)"
+        );
+        assert_eq!(
+            parse(r#"{"schema": 1, "summary": {}, "samples": []}"#).unwrap_err(),
+            "written by an older paping (schema 1, this one reads 2)"
+        );
+        assert!(err(&[START.to_string(), "{oops".to_string()]).starts_with("line 2: "));
+        assert_eq!(err(&[START.to_string()]), "no samples");
+    }
+
+    #[test]
+    fn figures_and_verdict() {
+        let samples = |times: &[Option<f64>]| -> Vec<Sample> {
+            let lines: Vec<String> = std::iter::once(START.to_string())
+                .chain(
+                    times
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &t)| sample("a", i as u32 + 1, t)),
+                )
+                .collect();
+            parse(&lines.join("\n")).unwrap().samples
+        };
+        let times: Vec<Option<f64>> = (1..=20).map(|ms| Some(ms as f64)).collect();
+        let before = Figures::of(&samples(&times));
+        assert_eq!(before.p95, Some(19.0));
+        assert_eq!(before.avg, Some(10.5));
+        assert_eq!(before.loss_percent, 0.0);
+
+        let faster: Vec<Option<f64>> = times.iter().map(|t| t.map(|ms| ms * 0.77)).collect();
+        let after = Figures::of(&samples(&faster));
+        assert_eq!(
+            verdict(&before, &after),
+            "latency improved 23%, loss unchanged"
+        );
+
+        let lossy = Figures::of(&samples(&[Some(10.6), None, Some(10.4), Some(10.5)]));
+        assert_eq!(lossy.loss_percent, 25.0);
+        assert_eq!(
+            verdict(&before, &lossy),
+            "latency unchanged, loss worsened 25.0 points"
+        );
+        assert_eq!(
+            verdict(&lossy, &Figures::of(&samples(&[None]))),
+            "latency not comparable (no connections on one side), loss worsened 75.0 points"
+        );
+    }
+}
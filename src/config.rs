@@ -0,0 +1,621 @@
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use paping::proxy::Socks5Proxy;
+use paping::target::split_host_port;
+
+/// Defaults read from `config.toml`. Every key is optional and the matching
+/// command-line flag always wins. `[profile.<name>]` tables hold the same
+/// keys and, when selected with `--profile`, override the global ones.
+///
+/// ```toml
+/// timeout = 500        # ms
+/// interval = 2000      # ms
+/// color = "never"      # auto, always, never
+/// proxy = "socks5://127.0.0.1:1080"
+/// interface = "192.168.1.10"
+/// warn = 1.0           # loss %, yellow above
+/// crit = 10.0          # loss %, red above
+///
+/// [profile.vpn]
+/// interface = "10.8.0.2"
+///
+/// [targets]            # paping @db, paping @api
+/// db = "10.1.2.3:5432"
+/// api = { target = "https://api.internal/health", timeout = 300 }
+/// ```
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub timeout: Option<u64>,
+    pub interval: Option<u64>,
+    pub color: Option<ColorMode>,
+    pub proxy: Option<String>,
+    pub interface: Option<String>,
+    pub warn: Option<f64>,
+    pub crit: Option<f64>,
+    #[serde(default)]
+    pub profile: BTreeMap<String, Settings>,
+    #[serde(default)]
+    pub targets: BTreeMap<String, Alias>,
+}
+
+/// A `[targets]` entry, run as `paping @name`: either just the target
+/// (`db = "10.1.2.3:5432"`), or a table with the target and settings of its
+/// own (`[targets.db]`, `target = "..."`, `timeout = 300`...). The target
+/// takes any form the command line does: host:port, URL...
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alias {
+    pub target: String,
+    pub settings: Settings,
+}
+
+impl<'de> Deserialize<'de> for Alias {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        match toml::Value::deserialize(d)? {
+            toml::Value::String(target) => Ok(Alias {
+                target,
+                settings: Settings::default(),
+            }),
+            toml::Value::Table(mut table) => {
+                let target = match table.remove("target") {
+                    Some(toml::Value::String(target)) => target,
+                    Some(other) => {
+                        return Err(D::Error::custom(format!(
+                            "`target` must be a string, not {}",
+                            other.type_str()
+                        )))
+                    }
+                    None => return Err(D::Error::missing_field("target")),
+                };
+                let settings =
+                    Settings::deserialize(toml::Value::Table(table)).map_err(D::Error::custom)?;
+                Ok(Alias { target, settings })
+            }
+            other => Err(D::Error::custom(format!(
+                "expected a target string or a table, not {}",
+                other.type_str()
+            ))),
+        }
+    }
+}
+
+/// One layer of settings: the global section, a profile or the command
+/// line. `None` means "not set here", so the layer below shows through.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub timeout: Option<u64>,
+    pub interval: Option<u64>,
+    pub color: Option<ColorMode>,
+    pub proxy: Option<String>,
+    pub interface: Option<String>,
+    pub warn: Option<f64>,
+    pub crit: Option<f64>,
+}
+
+impl Settings {
+    /// `self` with every value set in `over` replaced by it.
+    pub fn merge(self, over: Settings) -> Settings {
+        Settings {
+            timeout: over.timeout.or(self.timeout),
+            interval: over.interval.or(self.interval),
+            color: over.color.or(self.color),
+            proxy: over.proxy.or(self.proxy),
+            interface: over.interface.or(self.interface),
+            warn: over.warn.or(self.warn),
+            crit: over.crit.or(self.crit),
+        }
+    }
+}
+
+impl Settings {
+    /// Settings from the `PAPING_TIMEOUT`, `PAPING_INTERVAL`, `PAPING_PROXY`,
+    /// `PAPING_INTERFACE` and `PAPING_COLOR` environment variables. Empty
+    /// variables count as unset; invalid ones are an error naming them.
+    pub fn from_env() -> Result<Settings, String> {
+        Self::from_vars(|name| std::env::var_os(name))
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<OsString>) -> Result<Settings, String> {
+        let get = |name: &str| match var(name) {
+            Some(value) if value.is_empty() => Ok(None),
+            Some(value) => match value.into_string() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(format!("{name} is not valid UTF-8")),
+            },
+            None => Ok(None),
+        };
+        let millis = |name: &str| -> Result<Option<u64>, String> {
+            get(name)?
+                .map(|v| {
+                    v.parse()
+                        .map_err(|_| format!("{name}: invalid value '{v}' (expected milliseconds)"))
+                })
+                .transpose()
+        };
+
+        let proxy = get("PAPING_PROXY")?;
+        if let Some(ref url) = proxy {
+            Socks5Proxy::parse(url).map_err(|e| format!("PAPING_PROXY: invalid proxy: {e}"))?;
+        }
+        let interface = get("PAPING_INTERFACE")?;
+        if let Some(ref ips) = interface {
+            paping::target::parse_source_ips(ips).map_err(|e| format!("PAPING_INTERFACE: {e}"))?;
+        }
+        let color = match get("PAPING_COLOR")? {
+            Some(v) => Some(<ColorMode as clap::ValueEnum>::from_str(&v, true).map_err(|_| {
+                format!("PAPING_COLOR: invalid value '{v}' (expected auto, always or never)")
+            })?),
+            None => None,
+        };
+
+        Ok(Settings {
+            timeout: millis("PAPING_TIMEOUT")?,
+            interval: millis("PAPING_INTERVAL")?,
+            color,
+            proxy,
+            interface,
+            warn: None,
+            crit: None,
+        })
+    }
+}
+
+/// Proxy URL from the variables other tools use (`ALL_PROXY`, then
+/// `SOCKS_PROXY`, upper or lower case), unless `NO_PROXY` lists the target.
+/// Only a fallback for when no proxy is configured any other way.
+/// Non-SOCKS values (`http://...`) are meant for other tools and skipped.
+pub fn standard_proxy(host: &str, port: u16) -> Result<Option<String>, String> {
+    standard_proxy_from(host, port, |name| std::env::var(name).ok())
+}
+
+fn standard_proxy_from(
+    host: &str,
+    port: u16,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<Option<String>, String> {
+    let first = |names: &[&'static str]| {
+        names
+            .iter()
+            .find_map(|&name| var(name).filter(|v| !v.is_empty()).map(|v| (name, v)))
+    };
+    if let Some((_, list)) = first(&["NO_PROXY", "no_proxy"]) {
+        if no_proxy_matches(&list, host, port) {
+            return Ok(None);
+        }
+    }
+    for names in [["ALL_PROXY", "all_proxy"], ["SOCKS_PROXY", "socks_proxy"]] {
+        let Some((name, url)) = first(&names) else {
+            continue;
+        };
+        let socks = match url.split_once("://") {
+            Some((scheme, _)) => scheme.to_ascii_lowercase().starts_with("socks"),
+            None => true,
+        };
+        if !socks {
+            continue;
+        }
+        Socks5Proxy::parse(&url).map_err(|e| format!("{name}: invalid proxy: {e}"))?;
+        return Ok(Some(url));
+    }
+    Ok(None)
+}
+
+/// Whether a `NO_PROXY` list covers `host:port`. Entries are separated by
+/// commas: `*` (everything), a domain (also matching its subdomains, with or
+/// without a leading `.` or `*.`) or an IP, optionally followed by `:port`.
+fn no_proxy_matches(list: &str, host: &str, port: u16) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    list.split(',').map(str::trim).any(|entry| {
+        if entry == "*" {
+            return true;
+        }
+        let (name, entry_port) = match split_host_port(entry) {
+            Ok(parts) => parts,
+            Err(_) => return false,
+        };
+        if entry_port.is_some_and(|p| p != port) {
+            return false;
+        }
+        let name = name
+            .trim_start_matches("*.")
+            .trim_start_matches('.')
+            .to_ascii_lowercase();
+        !name.is_empty()
+            && (host == name
+                || host
+                    .strip_suffix(name.as_str())
+                    .is_some_and(|rest| rest.ends_with('.')))
+    })
+}
+
+impl Config {
+    /// The global settings, with `profile`'s merged over them.
+    pub fn settings(self, profile: Option<&str>) -> Result<Settings, String> {
+        let global = Settings {
+            timeout: self.timeout,
+            interval: self.interval,
+            color: self.color,
+            proxy: self.proxy,
+            interface: self.interface,
+            warn: self.warn,
+            crit: self.crit,
+        };
+        let Some(name) = profile else {
+            return Ok(global);
+        };
+        let mut profiles = self.profile;
+        match profiles.remove(name) {
+            Some(profile) => Ok(global.merge(profile)),
+            None if profiles.is_empty() => Err(format!(
+                "unknown profile '{name}': the config file defines none"
+            )),
+            None => Err(format!(
+                "unknown profile '{name}' (available: {})",
+                profiles.into_keys().collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+}
+
+impl Config {
+    /// The `[targets]` entry called `name` (written `@name`). A miss lists
+    /// the closest names, or all of them when none is close.
+    pub fn alias(&self, name: &str) -> Result<Alias, String> {
+        if let Some(alias) = self.targets.get(name) {
+            return Ok(alias.clone());
+        }
+        if self.targets.is_empty() {
+            return Err(format!(
+                "unknown target alias '@{name}': the config file defines no [targets]"
+            ));
+        }
+        let mut close: Vec<(usize, &String)> = self
+            .targets
+            .keys()
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|&(distance, candidate)| {
+                distance <= 2 || candidate.starts_with(name) || name.starts_with(candidate.as_str())
+            })
+            .collect();
+        close.sort();
+        let names = |names: Vec<&String>| {
+            names
+                .iter()
+                .map(|n| format!("@{n}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if close.is_empty() {
+            Err(format!(
+                "unknown target alias '@{name}' (available: {})",
+                names(self.targets.keys().collect())
+            ))
+        } else {
+            Err(format!(
+                "unknown target alias '@{name}' (did you mean {}?)",
+                names(close.into_iter().map(|(_, n)| n).collect())
+            ))
+        }
+    }
+}
+
+/// Levenshtein distance, to suggest alias names.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn apply(self) {
+        match self {
+            ColorMode::Auto => {}
+            ColorMode::Always => colored::control::set_override(true),
+            ColorMode::Never => colored::control::set_override(false),
+        }
+    }
+}
+
+/// Where the config file is looked up: `$XDG_CONFIG_HOME/paping/config.toml`
+/// (`~/.config/...` by default), or `%APPDATA%\paping\config.toml` on Windows.
+pub fn path() -> Result<PathBuf, String> {
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA")
+            .map(PathBuf::from)
+            .ok_or("APPDATA not set".to_string())?
+    } else {
+        match std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let home = std::env::var_os("HOME").ok_or("HOME not set".to_string())?;
+                PathBuf::from(home).join(".config")
+            }
+        }
+    };
+    Ok(dir.join("paping").join("config.toml"))
+}
+
+/// Reads the config file. A missing file is the same as an empty one.
+pub fn load() -> Result<Config, String> {
+    let path = path()?;
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("cannot read '{}': {e}", path.display())),
+    };
+    parse(&text).map_err(|e| format!("invalid config file '{}':\n{e}", path.display()))
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    // toml's errors already point at the offending line and column
+    toml::from_str(text).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_all_keys() {
+        let config = parse(
+            r#"
+            timeout = 500
+            interval = 2000
+            color = "never"
+            proxy = "socks5://127.0.0.1:1080"
+            interface = "192.168.1.10"
+            warn = 1.0
+            crit = 10.0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.timeout, Some(500));
+        assert_eq!(config.interval, Some(2000));
+        assert_eq!(config.color, Some(ColorMode::Never));
+        assert_eq!(config.proxy.as_deref(), Some("socks5://127.0.0.1:1080"));
+        assert_eq!(config.interface.as_deref(), Some("192.168.1.10"));
+        assert_eq!((config.warn, config.crit), (Some(1.0), Some(10.0)));
+    }
+
+    const LAYERED: &str = r#"
+        timeout = 500
+        proxy = "socks5://127.0.0.1:1080"
+        warn = 1.0
+
+        [profile.vpn]
+        interface = "10.8.0.2"
+        proxy = "socks5://10.8.0.1:1080"
+
+        [profile.slow]
+        timeout = 5000
+        "#;
+
+    #[test]
+    fn profile_merges_over_global_and_cli_over_both() {
+        let settings = parse(LAYERED).unwrap().settings(Some("vpn")).unwrap();
+        assert_eq!(settings.timeout, Some(500));
+        assert_eq!(settings.proxy.as_deref(), Some("socks5://10.8.0.1:1080"));
+        assert_eq!(settings.interface.as_deref(), Some("10.8.0.2"));
+        assert_eq!(settings.crit, None);
+
+        let cli = Settings {
+            timeout: Some(100),
+            crit: Some(50.0),
+            ..Settings::default()
+        };
+        let settings = settings.merge(cli);
+        assert_eq!(settings.timeout, Some(100));
+        assert_eq!(settings.proxy.as_deref(), Some("socks5://10.8.0.1:1080"));
+        assert_eq!((settings.warn, settings.crit), (Some(1.0), Some(50.0)));
+    }
+
+    #[test]
+    fn no_profile_selected_uses_global_only() {
+        let settings = parse(LAYERED).unwrap().settings(None).unwrap();
+        assert_eq!(settings.timeout, Some(500));
+        assert_eq!(settings.interface, None);
+    }
+
+    #[test]
+    fn unknown_profile_lists_available_names() {
+        let err = parse(LAYERED).unwrap().settings(Some("vnp")).unwrap_err();
+        assert_eq!(err, "unknown profile 'vnp' (available: slow, vpn)");
+        let err = Config::default().settings(Some("vpn")).unwrap_err();
+        assert!(err.contains("defines none"), "{err}");
+    }
+
+    const ALIASES: &str = r#"
+        [targets]
+        db = "10.1.2.3:5432"
+        web = "https://intranet.example/"
+
+        [targets.api]
+        target = "api.internal:8443"
+        timeout = 300
+        proxy = "socks5://10.8.0.1:1080"
+        "#;
+
+    #[test]
+    fn aliases_are_strings_or_tables() {
+        let config = parse(ALIASES).unwrap();
+        let db = config.alias("db").unwrap();
+        assert_eq!(db.target, "10.1.2.3:5432");
+        assert_eq!(db.settings, Settings::default());
+        let api = config.alias("api").unwrap();
+        assert_eq!(api.target, "api.internal:8443");
+        assert_eq!(api.settings.timeout, Some(300));
+        assert_eq!(api.settings.proxy.as_deref(), Some("socks5://10.8.0.1:1080"));
+
+        let err = parse("[targets.x]\ntimeout = 5").unwrap_err();
+        assert!(err.contains("missing field `target`"), "{err}");
+        let err = parse("[targets.x]\ntarget = \"h:1\"\ntimout = 5").unwrap_err();
+        assert!(err.contains("unknown field `timout`"), "{err}");
+        let err = parse("[targets]\nx = 5").unwrap_err();
+        assert!(err.contains("expected a target string or a table"), "{err}");
+    }
+
+    #[test]
+    fn unknown_alias_suggests_close_names() {
+        let config = parse(ALIASES).unwrap();
+        assert_eq!(
+            config.alias("bd").unwrap_err(),
+            "unknown target alias '@bd' (did you mean @db?)"
+        );
+        assert_eq!(
+            config.alias("ap").unwrap_err(),
+            "unknown target alias '@ap' (did you mean @api, @db?)"
+        );
+        assert_eq!(
+            config.alias("intranet").unwrap_err(),
+            "unknown target alias '@intranet' (available: @api, @db, @web)"
+        );
+        let err = Config::default().alias("db").unwrap_err();
+        assert!(err.contains("defines no [targets]"), "{err}");
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> Result<Settings, String> {
+        let pairs: BTreeMap<String, OsString> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), OsString::from(v)))
+            .collect();
+        Settings::from_vars(|name| pairs.get(name).cloned())
+    }
+
+    #[test]
+    fn env_sits_between_file_and_cli() {
+        let file = Settings {
+            timeout: Some(500),
+            interval: Some(2000),
+            proxy: Some("socks5://127.0.0.1:1080".to_string()),
+            ..Settings::default()
+        };
+        let env = vars(&[
+            ("PAPING_TIMEOUT", "300"),
+            ("PAPING_PROXY", "socks5://10.0.0.1:1080"),
+            ("PAPING_COLOR", "never"),
+            ("PAPING_INTERFACE", ""),
+        ])
+        .unwrap();
+        let cli = Settings {
+            timeout: Some(100),
+            ..Settings::default()
+        };
+        let settings = file.merge(env).merge(cli);
+        assert_eq!(settings.timeout, Some(100));
+        assert_eq!(settings.interval, Some(2000));
+        assert_eq!(settings.proxy.as_deref(), Some("socks5://10.0.0.1:1080"));
+        assert_eq!(settings.color, Some(ColorMode::Never));
+        assert_eq!(settings.interface, None);
+    }
+
+    #[test]
+    fn invalid_env_values_name_the_variable() {
+        let err = vars(&[("PAPING_TIMEOUT", "1s")]).unwrap_err();
+        assert!(err.starts_with("PAPING_TIMEOUT: "), "{err}");
+        let err = vars(&[("PAPING_PROXY", "http://proxy:8080")]).unwrap_err();
+        assert!(err.starts_with("PAPING_PROXY: "), "{err}");
+        let err = vars(&[("PAPING_INTERFACE", "eth0")]).unwrap_err();
+        assert!(err.starts_with("PAPING_INTERFACE: "), "{err}");
+        let err = vars(&[("PAPING_COLOR", "yes")]).unwrap_err();
+        assert!(err.starts_with("PAPING_COLOR: "), "{err}");
+    }
+
+    fn standard(host: &str, pairs: &[(&str, &str)]) -> Result<Option<String>, String> {
+        standard_proxy_from(host, 443, |name| {
+            pairs
+                .iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn standard_proxy_variables() {
+        let all = ("ALL_PROXY", "socks5://10.0.0.1:1080");
+        let socks = ("socks_proxy", "socks5://10.0.0.2:1080");
+        assert_eq!(
+            standard("example.com", &[all, socks]).unwrap().as_deref(),
+            Some("socks5://10.0.0.1:1080")
+        );
+        assert_eq!(
+            standard("example.com", &[socks]).unwrap().as_deref(),
+            Some("socks5://10.0.0.2:1080")
+        );
+        // An HTTP proxy for other tools is passed over
+        assert_eq!(
+            standard("example.com", &[("ALL_PROXY", "http://proxy:3128"), socks])
+                .unwrap()
+                .as_deref(),
+            Some("socks5://10.0.0.2:1080")
+        );
+        assert_eq!(standard("example.com", &[]).unwrap(), None);
+        let err = standard("example.com", &[("ALL_PROXY", "socks5://:1080")]).unwrap_err();
+        assert!(err.starts_with("ALL_PROXY: "), "{err}");
+    }
+
+    #[test]
+    fn no_proxy_list() {
+        let list = "localhost, .internal.example, 10.0.0.5, [::1], api.example.com:8443";
+        assert!(no_proxy_matches(list, "localhost", 443));
+        assert!(no_proxy_matches(list, "db.internal.example", 443));
+        assert!(no_proxy_matches(list, "internal.example", 443));
+        assert!(no_proxy_matches(list, "10.0.0.5", 22));
+        assert!(no_proxy_matches(list, "::1", 22));
+        assert!(no_proxy_matches(list, "API.example.com", 8443));
+        assert!(!no_proxy_matches(list, "api.example.com", 443));
+        assert!(!no_proxy_matches(list, "notinternal.example", 443));
+        assert!(!no_proxy_matches(list, "10.0.0.50", 443));
+        assert!(no_proxy_matches("*", "anything", 1));
+
+        let all = ("ALL_PROXY", "socks5://10.0.0.1:1080");
+        assert_eq!(standard("localhost", &[all, ("no_proxy", "localhost")]).unwrap(), None);
+    }
+
+    #[test]
+    fn unknown_key_in_profile_is_rejected() {
+        let err = parse("[profile.vpn]\ninterfase = \"10.8.0.2\"\n").unwrap_err();
+        assert!(err.contains("line 2"), "{err}");
+    }
+
+    #[test]
+    fn empty_file_is_all_defaults() {
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn unknown_key_points_at_its_line() {
+        let err = parse("timeout = 500\ntimeuot = 500\n").unwrap_err();
+        assert!(err.contains("line 2"), "{err}");
+        assert!(err.contains("timeuot"), "{err}");
+    }
+
+    #[test]
+    fn wrong_type_is_rejected() {
+        assert!(parse("timeout = \"fast\"").is_err());
+        assert!(parse("color = \"sometimes\"").is_err());
+    }
+}
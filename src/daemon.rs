@@ -0,0 +1,259 @@
+//! --daemon: detaching from the terminal to keep pinging in the background,
+//! and the pidfile `paping status` checks.
+//!
+//! On Unix this is the usual double fork: the first child calls setsid() to
+//! leave the terminal's session, and the second, not being a session
+//! leader, can never get a terminal back. On Windows paping starts itself
+//! again as a detached process. Either way the launching process waits for
+//! the daemon to be set up, passing on whatever it writes to stderr until
+//! then, so that `paping --daemon` with a bad setting fails like any other
+//! command.
+
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Written to stderr by the daemon once set up, followed by its pid.
+const STARTED: &str = "paping-daemon-started";
+
+/// Set in the environment of the detached process on Windows.
+#[cfg(windows)]
+const CHILD_ENV: &str = "PAPING_DAEMON_CHILD";
+
+/// The daemon process, until it reports being set up.
+pub struct Detached(());
+
+impl Detached {
+    /// Tells the launching process the daemon started, and lets go of
+    /// the terminal for good: stderr goes to the null device from now on.
+    pub fn started(self) {
+        eprintln!("{} {}", STARTED, process::id());
+        silence_stderr();
+    }
+}
+
+/// Detaches from the terminal. Returns in the daemon, with stdin and stdout
+/// on the null device; the launching process waits for it to start, then
+/// exits. Must be called before any thread is started.
+#[cfg(unix)]
+pub fn detach() -> io::Result<Detached> {
+    use std::fs::File;
+    use std::os::fd::FromRawFd;
+
+    let mut fds = [0; 2];
+    // SAFETY: plain system calls. There is no other thread yet, so the
+    // forked children don't miss any.
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let [read, write] = fds;
+        match libc::fork() {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => {}
+            _ => {
+                libc::close(write);
+                wait_for_start(File::from_raw_fd(read));
+            }
+        }
+        libc::close(read);
+        libc::setsid();
+        match libc::fork() {
+            -1 => libc::_exit(1),
+            0 => {}
+            _ => libc::_exit(0),
+        }
+        let null = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+        libc::dup2(null, libc::STDIN_FILENO);
+        libc::dup2(null, libc::STDOUT_FILENO);
+        libc::dup2(write, libc::STDERR_FILENO);
+        for fd in [null, write] {
+            if fd > libc::STDERR_FILENO {
+                libc::close(fd);
+            }
+        }
+    }
+    Ok(Detached(()))
+}
+
+/// Detaches from the console. Returns in the daemon, a copy of paping
+/// started with the same arguments, without a console and with stdin and
+/// stdout on the null device; the launching process waits for it to
+/// start, then exits.
+#[cfg(windows)]
+pub fn detach() -> io::Result<Detached> {
+    use std::os::windows::process::CommandExt;
+    use std::process::{Command, Stdio};
+    use windows_sys::Win32::System::Threading::{CREATE_NEW_PROCESS_GROUP, DETACHED_PROCESS};
+
+    if std::env::var_os(CHILD_ENV).is_some() {
+        return Ok(Detached(()));
+    }
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(std::env::args_os().skip(1))
+        .env(CHILD_ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP)
+        .spawn()?;
+    let stderr = child.stderr.take().expect("stderr is piped");
+    wait_for_start(stderr)
+}
+
+/// Passes on what the daemon writes to stderr until it reports having
+/// started (exit status 0), or quits without doing so (1).
+fn wait_for_start(stderr: impl Read) -> ! {
+    for line in BufReader::new(stderr).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        match line.strip_prefix(STARTED) {
+            Some(pid) => {
+                println!("paping running in the background, pid {}", pid.trim());
+                process::exit(0);
+            }
+            None => eprintln!("{}", line),
+        }
+    }
+    process::exit(1)
+}
+
+#[cfg(unix)]
+fn silence_stderr() {
+    // SAFETY: plain system calls on descriptors we own
+    unsafe {
+        let null = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+        if null >= 0 {
+            libc::dup2(null, libc::STDERR_FILENO);
+            libc::close(null);
+        }
+        // Not to keep the directory we were started in busy
+        libc::chdir(c"/".as_ptr());
+    }
+}
+
+#[cfg(windows)]
+fn silence_stderr() {
+    use std::os::windows::io::IntoRawHandle;
+    use windows_sys::Win32::System::Console::{SetStdHandle, STD_ERROR_HANDLE};
+
+    // The standard library looks the handle up on every write
+    if let Ok(null) = std::fs::OpenOptions::new().write(true).open("NUL") {
+        // SAFETY: the handle is valid and stays open for good
+        unsafe { SetStdHandle(STD_ERROR_HANDLE, null.into_raw_handle()) };
+    }
+}
+
+/// A file holding our pid while we run, removed when dropped.
+pub struct Pidfile {
+    path: PathBuf,
+}
+
+impl Pidfile {
+    /// Writes our pid to `path`, unless it holds the pid of a process
+    /// still running.
+    pub fn create(path: &Path) -> Result<Self, String> {
+        if let Status::Running(pid) = status(path) {
+            if pid != process::id() {
+                return Err(format!("paping is already running as pid {}", pid));
+            }
+        }
+        std::fs::write(path, format!("{}\n", process::id()))
+            .map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+        // The daemon leaves its working directory once started
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        Ok(Pidfile { path })
+    }
+}
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// What a pidfile says about its process.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Status {
+    Running(u32),
+    /// The process is gone without removing the pidfile
+    Gone(u32),
+    /// No pidfile, or not one holding a pid
+    NoPidfile,
+}
+
+pub fn status(path: &Path) -> Status {
+    let pid = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| text.trim().parse().ok());
+    match pid {
+        Some(pid) if is_running(pid) => Status::Running(pid),
+        Some(pid) => Status::Gone(pid),
+        None => Status::NoPidfile,
+    }
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // 0 and negative pids would name process groups
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // SAFETY: signal 0 only checks the process exists
+    let alive = unsafe { libc::kill(pid, 0) == 0 };
+    alive || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+fn is_running(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, GetLastError, ERROR_ACCESS_DENIED, STILL_ACTIVE,
+    };
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    // SAFETY: the handle is checked, and closed once queried
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return GetLastError() == ERROR_ACCESS_DENIED;
+        }
+        let mut code = 0;
+        let queried = GetExitCodeProcess(process, &mut code) != 0;
+        CloseHandle(process);
+        queried && code == STILL_ACTIVE as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pidfile_tells_whether_the_process_runs() {
+        let path = std::env::temp_dir().join(format!("paping-test-{}.pid", process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(status(&path), Status::NoPidfile);
+
+        let pidfile = Pidfile::create(&path).unwrap();
+        assert_eq!(status(&path), Status::Running(process::id()));
+        drop(pidfile);
+        assert!(!path.exists());
+
+        // Not a pid any system hands out
+        std::fs::write(&path, format!("{}\n", u32::MAX)).unwrap();
+        assert_eq!(status(&path), Status::Gone(u32::MAX));
+        let pidfile = Pidfile::create(&path).unwrap();
+        assert_eq!(status(&path), Status::Running(process::id()));
+        drop(pidfile);
+
+        std::fs::write(&path, "not a pid").unwrap();
+        assert_eq!(status(&path), Status::NoPidfile);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
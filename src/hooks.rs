@@ -0,0 +1,557 @@
+//! --exec-on-fail, --exec-on-recover, --exec-on-each: commands run on probe
+//! events, through the shell, with the result in their environment:
+//!
+//! ```text
+//! PAPING_EVENT    fail, recover or each
+//! PAPING_TARGET   example.com
+//! PAPING_PORT     443
+//! PAPING_SEQ      12 (the probe of the run, from 1)
+//! PAPING_RESULT   connected or failed
+//! PAPING_TIME_MS  12.50 (connected only)
+//! PAPING_ERROR    connection refused (failed only)
+//! ```
+//!
+//! "fail" is the first failure after a success (or of the run), "recover"
+//! the first success after a failure. Commands run on threads of their
+//! own, so probing never waits for them: at most `MAX_RUNNING` at a time
+//! (events beyond that are skipped), each killed once over its timeout.
+//! How they ended is told on stderr with --verbose, and never affects the
+//! run.
+//!
+//! --alert-cmd is for paging rather than logging: it runs once a number of
+//! failures in a row is reached, not on the failures after (but again
+//! every --realert-every, if given, while they go on), and once more when
+//! the target connects again, with these besides:
+//!
+//! ```text
+//! PAPING_EVENT       alert or recovered
+//! PAPING_FAILURES    5 (failures in a row so far)
+//! PAPING_OUTAGE_SECS 42.0 (since the first of them)
+//! PAPING_ALERTS      1 (alerts of this outage so far)
+//! ```
+//!
+//! It isn't raised again until it has recovered, and its runs are never
+//! skipped.
+
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use paping::pinger::PingResult;
+use paping::throughput::Limit;
+
+/// Commands running at once at most, all targets together.
+pub const MAX_RUNNING: usize = 4;
+/// How often a running command is checked on.
+const POLL: Duration = Duration::from_millis(20);
+
+/// The commands to run, and when.
+#[derive(Clone, Debug, Default)]
+pub struct Commands {
+    pub on_fail: Option<String>,
+    pub on_recover: Option<String>,
+    pub on_each: Option<String>,
+    pub alert: Option<Alert>,
+}
+
+impl Commands {
+    pub fn is_empty(&self) -> bool {
+        self.on_fail.is_none()
+            && self.on_recover.is_none()
+            && self.on_each.is_none()
+            && self.alert.is_none()
+    }
+}
+
+/// Parses --realert-every: "30s", "500ms", or a plain number of seconds.
+pub fn parse_every(spec: &str) -> Result<Duration, String> {
+    let every = match spec.trim().parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        // Durations as --throughput takes them
+        Err(_) => match Limit::parse(spec) {
+            Ok(Limit::Duration(every)) => every,
+            _ => {
+                return Err(format!(
+                    "invalid duration '{}' (e.g. 30s or 500ms, or 30 for seconds)",
+                    spec
+                ))
+            }
+        },
+    };
+    match every.is_zero() {
+        true => Err("the duration must be greater than 0".to_string()),
+        false => Ok(every),
+    }
+}
+
+/// --alert-cmd, with when to run it.
+#[derive(Clone, Debug)]
+pub struct Alert {
+    pub command: String,
+    /// Failures in a row that raise it
+    pub after: u32,
+    /// Raise it again this often while they go on
+    pub every: Option<Duration>,
+}
+
+/// What the alarm of a target has to tell, after a probe.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Alarm {
+    /// Raised (or raised again, `alerts` counting them)
+    Alert {
+        failures: u32,
+        outage: Duration,
+        alerts: u32,
+    },
+    /// Connected again after being raised
+    Recovered {
+        failures: u32,
+        outage: Duration,
+        alerts: u32,
+    },
+}
+
+/// Follows the failures in a row of a target to tell when to alert.
+#[derive(Clone, Debug)]
+struct Alarms {
+    after: u32,
+    every: Option<Duration>,
+    failures: u32,
+    /// The first of the failures
+    down_since: Option<Instant>,
+    /// When last raised, none until raised (and again once recovered)
+    raised: Option<Instant>,
+    alerts: u32,
+}
+
+impl Alarms {
+    fn new(alert: &Alert) -> Self {
+        Alarms {
+            after: alert.after.max(1),
+            every: alert.every,
+            failures: 0,
+            down_since: None,
+            raised: None,
+            alerts: 0,
+        }
+    }
+
+    fn track(&mut self, up: bool, now: Instant) -> Option<Alarm> {
+        if up {
+            let raised = self.raised.take();
+            let failures = std::mem::take(&mut self.failures);
+            let since = self.down_since.take();
+            let alerts = std::mem::take(&mut self.alerts);
+            return raised.map(|_| Alarm::Recovered {
+                failures,
+                outage: now.duration_since(since.unwrap_or(now)),
+                alerts,
+            });
+        }
+        self.failures += 1;
+        let since = *self.down_since.get_or_insert(now);
+        let due = match (self.raised, self.every) {
+            (None, _) => self.failures >= self.after,
+            (Some(last), Some(every)) => now.duration_since(last) >= every,
+            (Some(_), None) => false,
+        };
+        if !due {
+            return None;
+        }
+        self.raised = Some(now);
+        self.alerts += 1;
+        Some(Alarm::Alert {
+            failures: self.failures,
+            outage: now.duration_since(since),
+            alerts: self.alerts,
+        })
+    }
+}
+
+impl Alarm {
+    fn event(&self) -> &'static str {
+        match self {
+            Alarm::Alert { .. } => "alert",
+            Alarm::Recovered { .. } => "recovered",
+        }
+    }
+
+    fn environment(&self) -> [(&'static str, String); 3] {
+        let (Alarm::Alert {
+            failures,
+            outage,
+            alerts,
+        }
+        | Alarm::Recovered {
+            failures,
+            outage,
+            alerts,
+        }) = *self;
+        [
+            ("PAPING_FAILURES", failures.to_string()),
+            ("PAPING_OUTAGE_SECS", format!("{:.1}", outage.as_secs_f64())),
+            ("PAPING_ALERTS", alerts.to_string()),
+        ]
+    }
+}
+
+/// Runs the commands of the observers it hands out. `finish` waits for
+/// those still running.
+pub struct Hooks {
+    commands: Commands,
+    timeout: Duration,
+    verbose: bool,
+    running: Arc<AtomicUsize>,
+    threads: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+/// One command to run, with its environment.
+struct Invocation {
+    event: &'static str,
+    command: String,
+    env: Vec<(&'static str, String)>,
+}
+
+impl Hooks {
+    pub fn new(commands: Commands, timeout: Duration, verbose: bool) -> Self {
+        Hooks {
+            commands,
+            timeout,
+            verbose,
+            running: Arc::new(AtomicUsize::new(0)),
+            threads: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Runs the hook commands due on each result of `target`:`port`: the
+    /// state changes, every probe, and the alerts. Each command gets its own
+    /// thread, so a slow one never holds the pinger up.
+    pub fn observer(&self, target: &str, port: u16) -> impl FnMut(&PingResult) + Send + 'static {
+        let commands = self.commands.clone();
+        let (timeout, verbose) = (self.timeout, self.verbose);
+        let running = Arc::clone(&self.running);
+        let threads = Arc::clone(&self.threads);
+        let (target, port) = (target.to_string(), port);
+        let mut seq = 0u32;
+        let mut last_up = None;
+        let mut alarms = commands.alert.as_ref().map(Alarms::new);
+        move |result| {
+            seq += 1;
+            let up = result.is_success();
+            let changed = last_up != Some(up);
+            last_up = Some(up);
+            let events = [
+                ("fail", &commands.on_fail, changed && !up),
+                // Not on the first probe: nothing to recover from
+                ("recover", &commands.on_recover, changed && up && seq > 1),
+                ("each", &commands.on_each, true),
+            ];
+            let mut invocations: Vec<Invocation> = events
+                .into_iter()
+                .filter_map(|(event, command, due)| {
+                    Some(Invocation {
+                        event,
+                        command: command.as_ref().filter(|_| due)?.clone(),
+                        env: environment(event, &target, port, seq, result),
+                    })
+                })
+                .collect();
+            let alarm = alarms.as_mut().and_then(|a| a.track(up, Instant::now()));
+            if let (Some(alarm), Some(alert)) = (alarm, commands.alert.as_ref()) {
+                let mut env = environment(alarm.event(), &target, port, seq, result);
+                env.extend(alarm.environment());
+                invocations.push(Invocation {
+                    event: alarm.event(),
+                    command: alert.command.clone(),
+                    env,
+                });
+            }
+            for invocation in invocations {
+                let event = invocation.event;
+                // Claim a slot, or skip the event (never an alert)
+                let paging = matches!(event, "alert" | "recovered");
+                if running.fetch_add(1, Ordering::SeqCst) >= MAX_RUNNING && !paging {
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    if verbose {
+                        eprintln!(
+                            "exec: on-{} (seq {}) skipped, {} commands already running",
+                            event, seq, MAX_RUNNING
+                        );
+                    }
+                    continue;
+                }
+                let running = Arc::clone(&running);
+                let thread = std::thread::spawn(move || {
+                    invocation.run(seq, timeout, verbose);
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+                let mut threads = threads.lock().unwrap_or_else(|e| e.into_inner());
+                threads.retain(|t| !t.is_finished());
+                threads.push(thread);
+            }
+        }
+    }
+
+    /// Waits for the commands still running (or their timeout). The
+    /// pingers (holding the observers) must be dropped first.
+    pub fn finish(self) {
+        self.wait();
+    }
+
+    /// Waits for the commands started so far.
+    fn wait(&self) {
+        let threads = std::mem::take(&mut *self.threads.lock().unwrap_or_else(|e| e.into_inner()));
+        for thread in threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn environment(
+    event: &'static str,
+    target: &str,
+    port: u16,
+    seq: u32,
+    result: &PingResult,
+) -> Vec<(&'static str, String)> {
+    let mut env = vec![
+        ("PAPING_EVENT", event.to_string()),
+        ("PAPING_TARGET", target.to_string()),
+        ("PAPING_PORT", port.to_string()),
+        ("PAPING_SEQ", seq.to_string()),
+    ];
+    match result {
+        PingResult::Connected(c) => {
+            env.push(("PAPING_RESULT", "connected".to_string()));
+            env.push((
+                "PAPING_TIME_MS",
+                format!("{:.2}", c.time.as_secs_f64() * 1000.0),
+            ));
+        }
+        PingResult::Failed(f) => {
+            env.push(("PAPING_RESULT", "failed".to_string()));
+            env.push(("PAPING_ERROR", f.message.clone()));
+        }
+    }
+    env
+}
+
+impl Invocation {
+    /// Runs the command to its end or its timeout, telling how it went
+    /// when verbose. Failing to start it is always told.
+    fn run(self, seq: u32, timeout: Duration, verbose: bool) {
+        let start = Instant::now();
+        let what = match self.event {
+            "alert" | "recovered" => format!("exec: alert-cmd {} (seq {})", self.event, seq),
+            event => format!("exec: on-{} (seq {})", event, seq),
+        };
+        let mut child = match shell(&self.command)
+            .envs(self.env)
+            .stdin(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    format!("Warning: {} could not start: {}", what, e).yellow()
+                );
+                return;
+            }
+        };
+        let outcome = wait(&mut child, timeout);
+        if verbose {
+            let took = start.elapsed().as_millis();
+            match outcome {
+                Ok(Some(status)) => eprintln!("{} {} after {}ms", what, status, took),
+                Ok(None) => eprintln!("{} killed after {}ms (timeout)", what, took),
+                Err(e) => eprintln!("{} lost: {}", what, e),
+            }
+        }
+    }
+}
+
+/// The exit status, or `None` once killed for running past `timeout`.
+fn wait(child: &mut Child, timeout: Duration) -> std::io::Result<Option<std::process::ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        std::thread::sleep(POLL);
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use paping::pinger::FailureKind;
+
+    #[test]
+    fn runs_commands_on_transitions_with_the_result() {
+        let out = std::env::temp_dir().join(format!("paping-hooks-{}", std::process::id()));
+        let _ = std::fs::remove_file(&out);
+        let append = |what: &str| {
+            Some(format!(
+                "echo \"{} $PAPING_SEQ $PAPING_RESULT $PAPING_TIME_MS$PAPING_ERROR\" >> {}",
+                what,
+                out.display()
+            ))
+        };
+        let hooks = Hooks::new(
+            Commands {
+                on_fail: append("fail"),
+                on_recover: append("recover"),
+                on_each: None,
+                alert: None,
+            },
+            Duration::from_secs(5),
+            false,
+        );
+        let up = PingResult::connected(Duration::from_micros(12_500));
+        let down = PingResult::failed(FailureKind::Refused, "connection refused");
+        let mut observer = hooks.observer("example.com", 443);
+        for result in [&up, &down, &down, &up, &up] {
+            observer(result);
+            // One at a time, for the lines to come in order
+            hooks.wait();
+        }
+        drop(observer);
+        hooks.finish();
+
+        let lines = std::fs::read_to_string(&out).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert_eq!(
+            lines,
+            "fail 2 failed connection refused\nrecover 4 connected 12.50\n"
+        );
+    }
+
+    #[test]
+    fn alerts_once_per_outage_until_recovered() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut alarms = Alarms::new(&Alert {
+            command: String::new(),
+            after: 3,
+            every: Some(Duration::from_secs(60)),
+        });
+        // Not raised by fewer failures in a row, so not recovered either
+        for (secs, up) in [(0, false), (1, false), (2, true), (3, true)] {
+            assert_eq!(alarms.track(up, at(secs)), None);
+        }
+        let raised: Vec<(u64, Alarm)> = (10..=140)
+            .step_by(10)
+            .filter_map(|secs| Some((secs, alarms.track(false, at(secs))?)))
+            .collect();
+        let alert = |failures, outage, alerts| Alarm::Alert {
+            failures,
+            outage: Duration::from_secs(outage),
+            alerts,
+        };
+        // At the third failure, then a minute after each alert
+        assert_eq!(raised, [(30, alert(3, 20, 1)), (90, alert(9, 80, 2))]);
+        assert_eq!(
+            alarms.track(true, at(145)),
+            Some(Alarm::Recovered {
+                failures: 14,
+                outage: Duration::from_secs(135),
+                alerts: 2,
+            })
+        );
+        assert_eq!(alarms.track(true, at(146)), None);
+
+        // Re-armed, and without --realert-every raised once
+        alarms.every = None;
+        let raised = (200..300).filter_map(|secs| alarms.track(false, at(secs)));
+        assert_eq!(raised.collect::<Vec<_>>(), [alert(3, 2, 1)]);
+    }
+
+    #[test]
+    fn parses_realert_intervals() {
+        assert_eq!(parse_every("1800"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_every("5s"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_every("500ms"), Ok(Duration::from_millis(500)));
+        for bad in ["", "0", "0s", "10M", "5m", "-1"] {
+            assert!(parse_every(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn runs_the_alert_command_with_the_outage() {
+        let out = std::env::temp_dir().join(format!("paping-alert-{}", std::process::id()));
+        let _ = std::fs::remove_file(&out);
+        let hooks = Hooks::new(
+            Commands {
+                alert: Some(Alert {
+                    command: format!(
+                        "echo \"$PAPING_EVENT $PAPING_SEQ $PAPING_FAILURES $PAPING_ALERTS\" >> {}",
+                        out.display()
+                    ),
+                    after: 2,
+                    every: None,
+                }),
+                ..Commands::default()
+            },
+            Duration::from_secs(5),
+            false,
+        );
+        let up = PingResult::connected(Duration::from_millis(5));
+        let down = PingResult::failed(FailureKind::Refused, "connection refused");
+        let mut observer = hooks.observer("example.com", 443);
+        for result in [&down, &down, &down, &down, &up, &up] {
+            observer(result);
+            // For the lines to come in order
+            hooks.wait();
+        }
+        drop(observer);
+        hooks.finish();
+
+        let lines = std::fs::read_to_string(&out).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert_eq!(lines, "alert 2 2 1\nrecovered 5 4 1\n");
+    }
+
+    #[test]
+    fn hung_commands_are_killed_and_capped() {
+        let hooks = Hooks::new(
+            Commands {
+                on_each: Some("sleep 30".to_string()),
+                ..Commands::default()
+            },
+            Duration::from_millis(200),
+            false,
+        );
+        let mut observer = hooks.observer("example.com", 443);
+        let start = Instant::now();
+        for _ in 0..MAX_RUNNING + 3 {
+            observer(&PingResult::connected(Duration::from_millis(5)));
+        }
+        assert_eq!(hooks.running.load(Ordering::SeqCst), MAX_RUNNING);
+        drop(observer);
+        hooks.finish();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
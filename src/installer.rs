@@ -3,11 +3,19 @@ use std::path::{Path, PathBuf};
 pub enum InstallOutcome {
     /// Already running from the intended install location.
     Noop,
-    /// Installed and relaunched from the install location.
-    Relaunched,
+    /// Installed and relaunched from the install location. Carries the exit
+    /// code of the relaunched process, which the caller should exit with.
+    Relaunched(i32),
 }
 
-pub fn ensure_installed_and_relaunch_if_needed() -> Result<InstallOutcome, String> {
+/// Copies the running binary to the install location and relaunches from
+/// there. Skipped entirely when `no_install` (--no-install) is set or the
+/// session isn't interactive, see `auto_install_wanted`.
+pub fn ensure_installed_and_relaunch_if_needed(no_install: bool) -> Result<InstallOutcome, String> {
+    if no_install || !auto_install_wanted() {
+        return Ok(InstallOutcome::Noop);
+    }
+
     let current_exe = std::env::current_exe().map_err(|e| format!("current_exe failed: {e}"))?;
     let install_exe = desired_install_exe_path()?;
 
@@ -16,11 +24,6 @@ pub fn ensure_installed_and_relaunch_if_needed() -> Result<InstallOutcome, Strin
         return Ok(InstallOutcome::Noop);
     }
 
-    // Prevent loops in case something weird happens.
-    if std::env::var_os("PAPING_NO_AUTO_INSTALL").is_some() {
-        return Ok(InstallOutcome::Noop);
-    }
-
     // If something is already installed, avoid overwriting it with an older binary.
     if install_exe.exists() {
         if let (Some(installed), Some(current)) = (
@@ -33,13 +36,60 @@ pub fn ensure_installed_and_relaunch_if_needed() -> Result<InstallOutcome, Strin
         }
     }
 
+    relaunch(&install(None)?)
+}
+
+/// Whether the environment looks like an interactive user session, where
+/// copying ourselves and relaunching is welcome.
+fn auto_install_wanted() -> bool {
+    use std::io::IsTerminal;
+
+    // Prevent loops in case something weird happens.
+    if std::env::var_os("PAPING_NO_AUTO_INSTALL").is_some() {
+        return false;
+    }
+    // CI systems (GitHub Actions, GitLab, Travis...) all set CI.
+    if std::env::var_os("CI").is_some() {
+        return false;
+    }
+    // Output piped or redirected: scripts, pipelines, docker builds.
+    std::io::stdout().is_terminal()
+}
+
+/// Explicit `paping install`: copies the running binary into `prefix` (or the
+/// default install dir) and adds that directory to PATH. Unlike the implicit
+/// auto-install, this always overwrites and never relaunches; the
+/// auto-install goes through here too, once it has decided to install.
+/// Returns the path of the installed binary.
+pub fn install(prefix: Option<&Path>) -> Result<PathBuf, String> {
+    let current_exe = std::env::current_exe().map_err(|e| format!("current_exe failed: {e}"))?;
+    let install_exe = match prefix {
+        Some(dir) => dir.join(EXE_NAME),
+        None => desired_install_exe_path()?,
+    };
+
+    if !same_path(&current_exe, &install_exe) {
+        copy_executable(&current_exe, &install_exe)?;
+    }
+
+    // Best-effort: add install dir to PATH.
+    if let Some(dir) = install_exe.parent() {
+        add_to_user_path(dir);
+    }
+
+    Ok(install_exe)
+}
+
+/// Copies `current_exe` to `install_exe` (creating the directory) and makes
+/// it executable.
+fn copy_executable(current_exe: &Path, install_exe: &Path) -> Result<(), String> {
     if let Some(parent) = install_exe.parent() {
         std::fs::create_dir_all(parent)
             .map_err(|e| format!("Failed to create install dir '{}': {e}", parent.display()))?;
     }
 
     // Copy current binary to install location (overwrite / upgrade).
-    std::fs::copy(&current_exe, &install_exe).map_err(|e| {
+    std::fs::copy(current_exe, install_exe).map_err(|e| {
         format!(
             "Failed to copy '{}' to '{}': {e}",
             current_exe.display(),
@@ -51,11 +101,11 @@ pub fn ensure_installed_and_relaunch_if_needed() -> Result<InstallOutcome, Strin
     #[cfg(unix)]
     {
         use std::os::unix::fs::PermissionsExt;
-        let mut perms = std::fs::metadata(&install_exe)
+        let mut perms = std::fs::metadata(install_exe)
             .map_err(|e| format!("metadata failed for '{}': {e}", install_exe.display()))?
             .permissions();
         perms.set_mode(0o755);
-        std::fs::set_permissions(&install_exe, perms).map_err(|e| {
+        std::fs::set_permissions(install_exe, perms).map_err(|e| {
             format!(
                 "Failed to set permissions on '{}': {e}",
                 install_exe.display()
@@ -63,12 +113,7 @@ pub fn ensure_installed_and_relaunch_if_needed() -> Result<InstallOutcome, Strin
         })?;
     }
 
-    // Best-effort: add install dir to PATH.
-    if let Some(dir) = install_exe.parent() {
-        add_to_user_path(dir);
-    }
-
-    relaunch(&install_exe)
+    Ok(())
 }
 
 fn relaunch(install_exe: &Path) -> Result<InstallOutcome, String> {
@@ -79,14 +124,35 @@ fn relaunch(install_exe: &Path) -> Result<InstallOutcome, String> {
     // Disable auto-install in the child process to avoid any accidental loops.
     cmd.env("PAPING_NO_AUTO_INSTALL", "1");
 
-    cmd.spawn().map_err(|e| {
+    // Ctrl+C reaches the child too (same process group / console). Ignore it
+    // here so we keep waiting while the child prints its statistics.
+    let _ = ctrlc::set_handler(|| {});
+
+    // stdin/stdout/stderr are inherited, so the child's output streams through.
+    let status = cmd.status().map_err(|e| {
         format!(
             "Failed to relaunch from '{}': {e}",
             install_exe.display()
         )
     })?;
 
-    Ok(InstallOutcome::Relaunched)
+    Ok(InstallOutcome::Relaunched(exit_code(&status)))
+}
+
+/// Exit code to forward for the relaunched process. A child killed by a
+/// signal is reported the way shells do it: 128 + signal number.
+fn exit_code(status: &std::process::ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    1
 }
 
 fn read_exe_version(exe: &Path) -> Option<(u64, u64, u64)> {
@@ -112,10 +178,11 @@ fn parse_version(s: &str) -> Option<(u64, u64, u64)> {
     Some((major, minor, patch))
 }
 
+const EXE_NAME: &str = if cfg!(windows) { "paping.exe" } else { "paping" };
+
 fn desired_install_exe_path() -> Result<PathBuf, String> {
-    let exe_name = if cfg!(windows) { "paping.exe" } else { "paping" };
     let dir = desired_install_dir()?;
-    Ok(dir.join(exe_name))
+    Ok(dir.join(EXE_NAME))
 }
 
 fn desired_install_dir() -> Result<PathBuf, String> {
@@ -205,7 +272,41 @@ fn add_to_user_path_windows(dir: &Path) -> Result<(), String> {
         .set_value("Path", &new_path)
         .map_err(|e| format!("registry write failed: {e}"))?;
 
-    // We intentionally don't broadcast WM_SETTINGCHANGE here.
-    // A new terminal/session will pick up the updated PATH.
+    // Tell Explorer (and anything else listening) that the environment
+    // changed, so terminals opened from now on see the new PATH without a
+    // logoff. Best-effort: the registry is already updated either way.
+    if let Err(e) = broadcast_environment_change() {
+        eprintln!("Warning: failed to notify Windows of the PATH change: {e}");
+        eprintln!("Open a new session (or log off and on) to pick up the updated PATH.");
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn broadcast_environment_change() -> Result<(), String> {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        SendMessageTimeoutW, HWND_BROADCAST, SMTO_ABORTIFHUNG, WM_SETTINGCHANGE,
+    };
+
+    let area: Vec<u16> = "Environment".encode_utf16().chain(Some(0)).collect();
+    let mut result: usize = 0;
+
+    // SMTO_ABORTIFHUNG + a timeout so a hung top-level window can't block us.
+    let ok = unsafe {
+        SendMessageTimeoutW(
+            HWND_BROADCAST,
+            WM_SETTINGCHANGE,
+            0,
+            area.as_ptr() as isize,
+            SMTO_ABORTIFHUNG,
+            5000,
+            &mut result,
+        )
+    };
+
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
     Ok(())
 }
@@ -0,0 +1,562 @@
+//! JSON output. --log and --json write one record per line (NDJSON): the
+//! start of the run, a sample for every probe and a summary once it ends.
+//!
+//! ```text
+//! {"type":"start","schema":2,"ts":"2026-10-15T09:30:00.120Z","target":"example.com","port":443}
+//! {"type":"sample","ts":"2026-10-15T09:30:00.123Z","target":"example.com","port":443,"seq":1,"status":"connected","time_ms":12.5,"addr":"93.184.215.14:443","proxy":null}
+//! {"type":"sample","ts":"2026-10-15T09:30:01.124Z","target":"example.com","port":443,"seq":2,"status":"failed","kind":"timeout","message":"connection timed out"}
+//! {"type":"summary","ts":"2026-10-15T09:30:01.200Z","target":"example.com","port":443,"attempted":2,"connected":1,"failed":1,"resolve_failed":0,"times_ms":[12.5],"loss_percent":50.0,"min_ms":12.5,"avg_ms":12.5,"max_ms":12.5}
+//! ```
+//!
+//! Past `ts`, `target`, `port`, `seq` and `label`, a sample is a
+//! `PingResult` and a summary `PingStats`, in the schema of
+//! `paping::result` (which reads them back).
+//!
+//! --sink streams the same lines to a collector over TCP.
+//!
+//! --json-pretty prints a single document once the run ends instead:
+//! `{"schema": 1, "summary": {...}, "samples": [{...}, ...]}`, the summary
+//! and samples being the records above without their `type`.
+//!
+//! `schema` goes up with any change that could break a reader (a field
+//! removed or changing meaning); fields may be added within a version.
+
+use std::fs::OpenOptions;
+use std::io::{self, LineWriter, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use colored::Colorize;
+use paping::pinger::{PingResult, PingStats};
+use paping::stop::{StopHandle, StopToken};
+use paping::target::{join_host_port, split_host_port};
+use paping::template::rfc3339;
+use serde::{Deserialize, Serialize};
+
+/// Version of the output format, in the start records and documents:
+/// 2 since samples and summaries follow `paping::result`.
+pub const SCHEMA: u32 = 2;
+
+/// One line of NDJSON output.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Record {
+    Start(Start),
+    Sample(Sample),
+    Summary(Summary),
+}
+
+/// First record of a run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Start {
+    pub schema: u32,
+    pub ts: String,
+    pub target: String,
+    pub port: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// Outcome of one probe.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub ts: String,
+    pub target: String,
+    pub port: u16,
+    /// 1 for the first probe of the run
+    pub seq: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub result: PingResult,
+}
+
+/// Statistics of a whole run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Summary {
+    pub ts: String,
+    pub target: String,
+    pub port: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub stats: PingStats,
+}
+
+/// The --json-pretty document.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Document {
+    pub schema: u32,
+    pub summary: Summary,
+    pub samples: Vec<Sample>,
+}
+
+/// What every record of a run has in common.
+#[derive(Clone)]
+pub struct Run {
+    target: String,
+    port: u16,
+    label: Option<String>,
+}
+
+impl Run {
+    pub fn new(target: &str, port: u16, label: Option<&str>) -> Self {
+        Run {
+            target: target.to_string(),
+            port,
+            label: label.map(str::to_string),
+        }
+    }
+
+    fn start(&self, at: SystemTime) -> Start {
+        Start {
+            schema: SCHEMA,
+            ts: rfc3339(at),
+            target: self.target.clone(),
+            port: self.port,
+            label: self.label.clone(),
+        }
+    }
+
+    fn sample(&self, at: SystemTime, seq: u32, result: &PingResult) -> Sample {
+        Sample {
+            ts: rfc3339(at),
+            target: self.target.clone(),
+            port: self.port,
+            seq,
+            label: self.label.clone(),
+            result: result.clone(),
+        }
+    }
+
+    fn summary(&self, at: SystemTime, stats: &PingStats) -> Summary {
+        Summary {
+            ts: rfc3339(at),
+            target: self.target.clone(),
+            port: self.port,
+            label: self.label.clone(),
+            stats: stats.clone(),
+        }
+    }
+
+    /// Observer numbering the results and handing each on as a sample.
+    fn observer(
+        &self,
+        mut each: impl FnMut(Sample) + Send + 'static,
+    ) -> impl FnMut(&PingResult) + Send + 'static {
+        let run = self.clone();
+        let mut seq = 0;
+        move |result| {
+            seq += 1;
+            each(run.sample(SystemTime::now(), seq, result));
+        }
+    }
+}
+
+type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// NDJSON output, to a file (--log) or stdout (--json), a line at a time
+/// so that a run killed midway leaves every probe so far.
+pub struct Log {
+    out: Output,
+    run: Run,
+}
+
+impl Log {
+    /// Opens (or creates) the file for appending, so that a bad path is
+    /// reported before pinging starts, and writes the start record.
+    pub fn open(path: &Path, run: Run) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Self::start(Box::new(LineWriter::new(file)), run)
+    }
+
+    /// NDJSON on stdout, starting with the start record.
+    pub fn stdout(run: Run) -> io::Result<Self> {
+        Self::start(Box::new(io::stdout()), run)
+    }
+
+    fn start(out: Box<dyn Write + Send>, run: Run) -> io::Result<Self> {
+        let log = Log {
+            out: Arc::new(Mutex::new(out)),
+            run,
+        };
+        write_record(&log.out, &Record::Start(log.run.start(SystemTime::now())))?;
+        Ok(log)
+    }
+
+    /// Observer to register with `Pinger::on_result`. Write errors are
+    /// left for `finish` to report.
+    pub fn observer(&self) -> impl FnMut(&PingResult) + Send + 'static {
+        let out = Arc::clone(&self.out);
+        self.run.observer(move |sample| {
+            let _ = write_record(&out, &Record::Sample(sample));
+        })
+    }
+
+    /// Writes the summary of the run.
+    pub fn finish(&self, stats: &PingStats) -> io::Result<()> {
+        let record = Record::Summary(self.run.summary(SystemTime::now(), stats));
+        write_record(&self.out, &record)?;
+        self.out.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+fn write_record(out: &Mutex<Box<dyn Write + Send>>, record: &Record) -> io::Result<()> {
+    let line = serde_json::to_string(record)?;
+    let mut out = out.lock().unwrap_or_else(|e| e.into_inner());
+    writeln!(out, "{}", line)
+}
+
+/// Lines waiting for the --sink collector at most; beyond that, they are
+/// dropped (and counted).
+const SINK_QUEUE: usize = 1024;
+/// Bounds connecting to the collector, and each write.
+const SINK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest pause between two attempts to reconnect.
+const SINK_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// --sink: NDJSON streamed to a collector over TCP. The lines are sent
+/// from a thread, so that a slow or unreachable collector never holds up
+/// probing: meanwhile they wait in a queue, and those beyond are dropped.
+/// A lost connection is made again (with a pause doubling up to 30s), and
+/// each connection starts with the start record, so that the collector
+/// can tell which run the samples belong to.
+pub struct Sink {
+    queue: SyncSender<String>,
+    run: Run,
+    stop: StopHandle,
+    dropped: Arc<AtomicU32>,
+    thread: JoinHandle<()>,
+}
+
+impl Sink {
+    /// The collector of a `tcp://HOST:PORT` URL.
+    pub fn parse(url: &str) -> Result<(String, u16), String> {
+        let Some(address) = url.strip_prefix("tcp://") else {
+            return Err(format!("'{}' is not a tcp://HOST:PORT URL", url));
+        };
+        match split_host_port(address.trim_end_matches('/'))? {
+            (host, Some(port)) if !host.is_empty() && port > 0 => Ok((host, port)),
+            _ => Err(format!("'{}' has no host and port (tcp://HOST:PORT)", url)),
+        }
+    }
+
+    /// Starts sending to `host:port`, connecting from the thread.
+    pub fn start(host: &str, port: u16, run: Run) -> Self {
+        let (queue, lines) = mpsc::sync_channel(SINK_QUEUE);
+        let start = line(&Record::Start(run.start(SystemTime::now())));
+        let stop = StopHandle::new();
+        let dropped = Arc::new(AtomicU32::new(0));
+        let collector = Collector {
+            address: join_host_port(host, port),
+            start,
+            stop: stop.token(),
+            dropped: Arc::clone(&dropped),
+        };
+        let thread = std::thread::spawn(move || collector.forward(lines));
+        Sink {
+            queue,
+            run,
+            stop,
+            dropped,
+            thread,
+        }
+    }
+
+    /// Observer to register with `Pinger::on_result`. Never blocks.
+    pub fn observer(&self) -> impl FnMut(&PingResult) + Send + 'static {
+        let queue = self.queue.clone();
+        let dropped = Arc::clone(&self.dropped);
+        self.run.observer(move |sample| {
+            if queue.try_send(line(&Record::Sample(sample))).is_err() {
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        })
+    }
+
+    /// Sends the summary of the run and what is still queued. The pingers
+    /// (holding the observers) must be dropped first. With the collector
+    /// unreachable by then, one more attempt is made, and the rest dropped.
+    pub fn finish(self, stats: &PingStats) {
+        let summary = line(&Record::Summary(self.run.summary(SystemTime::now(), stats)));
+        if self.queue.try_send(summary).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        drop(self.queue);
+        self.stop.stop();
+        let _ = self.thread.join();
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            eprintln!(
+                "{}",
+                format!(
+                    "Warning: --sink: {} record{} dropped, the collector being unreachable or slow",
+                    dropped,
+                    if dropped == 1 { "" } else { "s" }
+                )
+                .yellow()
+            );
+        }
+    }
+}
+
+fn line(record: &Record) -> String {
+    let mut line = serde_json::to_string(record).expect("plain data serializes");
+    line.push('\n');
+    line
+}
+
+/// The --sink thread's side.
+struct Collector {
+    address: String,
+    start: String,
+    /// Stopped once the run ends: the thread stops waiting to reconnect
+    stop: StopToken,
+    dropped: Arc<AtomicU32>,
+}
+
+impl Collector {
+    /// Sends the lines as they come, until the queue is closed and empty.
+    fn forward(&self, lines: Receiver<String>) {
+        let mut backoff = Duration::from_secs(1);
+        // Right away, for the collector to see the run start
+        let mut connection = match self.connect() {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                self.warn(format_args!("cannot reach {}: {} (retrying)", self.address, e));
+                None
+            }
+        };
+        // Whether the collector was last found unreachable, to warn once
+        let mut failing = connection.is_none();
+        for line in lines.iter() {
+            loop {
+                let stream = match connection {
+                    Some(ref mut stream) => stream,
+                    None => match self.connect() {
+                        Ok(stream) => {
+                            if failing {
+                                eprintln!("--sink: connected to {} again", self.address);
+                                failing = false;
+                            }
+                            backoff = Duration::from_secs(1);
+                            connection.insert(stream)
+                        }
+                        Err(e) => {
+                            if !failing {
+                                self.warn(format_args!(
+                                    "cannot reach {}: {} (retrying)",
+                                    self.address, e
+                                ));
+                                failing = true;
+                            }
+                            if self.stop.is_stopped() {
+                                // The run is over: no waiting for the collector
+                                let left = 1 + lines.try_iter().count() as u32;
+                                self.dropped.fetch_add(left, Ordering::Relaxed);
+                                return;
+                            }
+                            self.stop.wait_timeout(backoff);
+                            backoff = (backoff * 2).min(SINK_MAX_BACKOFF);
+                            continue;
+                        }
+                    },
+                };
+                match stream.write_all(line.as_bytes()) {
+                    Ok(()) => break,
+                    Err(e) => {
+                        self.warn(format_args!(
+                            "lost the connection to {}: {} (reconnecting)",
+                            self.address, e
+                        ));
+                        failing = true;
+                        connection = None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Connects, and sends the start record.
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last = None;
+        for addr in self.address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, SINK_TIMEOUT) {
+                Ok(mut stream) => {
+                    stream.set_write_timeout(Some(SINK_TIMEOUT))?;
+                    stream.write_all(self.start.as_bytes())?;
+                    return Ok(stream);
+                }
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last.unwrap_or_else(|| io::Error::other("no address")))
+    }
+
+    fn warn(&self, message: std::fmt::Arguments) {
+        eprintln!("{}", format!("Warning: --sink: {}", message).yellow());
+    }
+}
+
+/// --json-pretty: samples kept until the run ends, for one document.
+pub struct Pretty {
+    samples: Arc<Mutex<Vec<Sample>>>,
+    run: Run,
+}
+
+impl Pretty {
+    pub fn new(run: Run) -> Self {
+        Pretty {
+            samples: Arc::new(Mutex::new(Vec::new())),
+            run,
+        }
+    }
+
+    /// Observer to register with `Pinger::on_result`.
+    pub fn observer(&self) -> impl FnMut(&PingResult) + Send + 'static {
+        let samples = Arc::clone(&self.samples);
+        self.run.observer(move |sample| {
+            samples
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(sample);
+        })
+    }
+
+    /// The document, indented, with the summary of the run.
+    pub fn render(&self, stats: &PingStats) -> String {
+        let samples = std::mem::take(&mut *self.samples.lock().unwrap_or_else(|e| e.into_inner()));
+        let document = Document {
+            schema: SCHEMA,
+            summary: self.run.summary(SystemTime::now(), stats),
+            samples,
+        };
+        serde_json::to_string_pretty(&document).expect("plain data serializes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paping::pinger::FailureKind;
+    use std::time::Duration;
+
+    fn results() -> (Vec<PingResult>, PingStats) {
+        let results = vec![
+            PingResult::connected(Duration::from_millis(12)),
+            PingResult::failed(FailureKind::Timeout, "connection timed out"),
+        ];
+        let mut stats = PingStats::default();
+        for result in &results {
+            stats.record(result);
+        }
+        (results, stats)
+    }
+
+    #[test]
+    fn appends_a_run_as_records() {
+        let path = std::env::temp_dir().join(format!("paping-test-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let log = Log::open(&path, Run::new("example.com", 443, Some("api"))).unwrap();
+        let mut observer = log.observer();
+        let (results, stats) = results();
+        results.iter().for_each(&mut observer);
+        log.finish(&stats).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let records: Vec<Record> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let [Record::Start(start), Record::Sample(first), Record::Sample(second), Record::Summary(summary)] =
+            &records[..]
+        else {
+            panic!("not start, samples, summary: {:?}", records);
+        };
+        assert_eq!((start.schema, start.port), (SCHEMA, 443));
+        assert_eq!((first.seq, &first.result), (1, &results[0]));
+        assert_eq!(first.label.as_deref(), Some("api"));
+        assert_eq!((second.seq, &second.result), (2, &results[1]));
+        assert_eq!((summary.stats.attempted, summary.stats.failed), (2, 1));
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[1].starts_with(r#"{"type":"sample","#));
+        assert!(lines[1].contains(r#""status":"connected","time_ms":12.0"#));
+        assert!(lines[2].contains(r#""kind":"timeout","message":"connection timed out""#));
+        assert!(lines[3].contains(r#""loss_percent":50.0"#));
+        // Down since the timeout
+        assert!(lines[3].contains(r#""end":null"#), "{}", lines[3]);
+    }
+
+    #[test]
+    fn parses_sink_urls() {
+        assert_eq!(
+            Sink::parse("tcp://collector:5170"),
+            Ok(("collector".to_string(), 5170))
+        );
+        assert_eq!(Sink::parse("tcp://[::1]:5170/"), Ok(("::1".to_string(), 5170)));
+        assert!(Sink::parse("collector:5170").is_err());
+        assert!(Sink::parse("udp://collector:5170").is_err());
+        assert!(Sink::parse("tcp://collector").is_err());
+    }
+
+    #[test]
+    fn streams_records_and_reconnects() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        let collector = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = collector.local_addr().unwrap().port();
+        let sink = Sink::start("127.0.0.1", port, Run::new("example.com", 443, None));
+        let mut observer = sink.observer();
+        let (results, stats) = results();
+
+        // The first connection goes away after the start record
+        let (first, _) = collector.accept().unwrap();
+        let mut lines = BufReader::new(first).lines();
+        assert!(lines.next().unwrap().unwrap().starts_with(r#"{"type":"start","#));
+        drop(lines);
+        // Until writes notice, samples go to the closed connection
+        for _ in 0..5 {
+            observer(&results[0]);
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let (second, _) = collector.accept().unwrap();
+        results.iter().for_each(&mut observer);
+        drop(observer);
+        sink.finish(&stats);
+
+        let records: Vec<Record> = BufReader::new(second)
+            .lines()
+            .map(|l| serde_json::from_str(&l.unwrap()).unwrap())
+            .collect();
+        assert!(matches!(records.first(), Some(Record::Start(_))), "{:?}", records);
+        assert!(matches!(records.last(), Some(Record::Summary(_))), "{:?}", records);
+        assert!(records.len() >= 4, "{:?}", records);
+    }
+
+    #[test]
+    fn pretty_document_wraps_the_run() {
+        let pretty = Pretty::new(Run::new("example.com", 443, None));
+        let mut observer = pretty.observer();
+        let (results, stats) = results();
+        results.iter().for_each(&mut observer);
+
+        let text = pretty.render(&stats);
+        assert!(
+            text.starts_with("{\n  \"schema\": 2,\n  \"summary\": {"),
+            "{}",
+            text
+        );
+        let document: Document = serde_json::from_str(&text).unwrap();
+        assert_eq!(document.summary.stats.attempted, 2);
+        let seqs: Vec<u32> = document.samples.iter().map(|s| s.seq).collect();
+        assert_eq!(seqs, [1, 2]);
+        assert!(!text.contains("\"type\""));
+    }
+}
@@ -0,0 +1,395 @@
+//! The other end of a ping (`paping listen`, --listen): accepts TCP
+//! connections and echoes whatever they send, so that two paping instances
+//! can test a network path end to end, `--send`/`--recv` included. It can
+//! also play a server that greets with a banner, holds connections for a
+//! while or drops them at once, and serves as a fixture in tests. As the
+//! other end of --throughput it takes or sends bulk data instead of echoing.
+//!
+//! ```no_run
+//! use paping::listener::{ListenEvent, Listener};
+//! use paping::stop::StopToken;
+//!
+//! let listener = Listener::bind("0.0.0.0:9000".parse().unwrap()).unwrap();
+//! listener.serve(0, &StopToken::never(), |event| {
+//!     if let ListenEvent::Accepted { peer, .. } = event {
+//!         println!("connection from {}", peer);
+//!     }
+//! });
+//! ```
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::result::Transfer;
+use crate::stop::StopToken;
+use crate::throughput;
+
+/// How often `serve` checks for new connections and for a stop request.
+const POLL: Duration = Duration::from_millis(50);
+/// A connection sending nothing for this long is closed.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What happened on the listener, in order for each connection.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenEvent {
+    /// A connection was accepted; `seq` counts them from 1.
+    Accepted { seq: u32, peer: SocketAddr },
+    /// The connection `seq` ended after `duration`, having echoed
+    /// `echoed` bytes, or moved `transfer` when discarding or sourcing
+    /// data. `error` tells why, when the peer didn't just close.
+    Closed {
+        seq: u32,
+        peer: SocketAddr,
+        duration: Duration,
+        echoed: u64,
+        transfer: Option<Transfer>,
+        error: Option<String>,
+    },
+}
+
+/// A TCP echo listener.
+pub struct Listener {
+    inner: TcpListener,
+    behavior: Behavior,
+}
+
+/// What is done with each accepted connection.
+#[derive(Clone, Debug, Default)]
+struct Behavior {
+    banner: Vec<u8>,
+    linger: Option<Duration>,
+    reject: bool,
+    discard: bool,
+    source: bool,
+}
+
+impl Listener {
+    /// Listens on `addr`. An unspecified IPv6 address (`[::]`) also accepts
+    /// IPv4 connections where the system allows it.
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_reuse_address(true)?;
+        if addr.is_ipv6() && addr.ip().is_unspecified() {
+            // Best effort: some systems only do IPv6 on IPv6 sockets
+            let _ = socket.set_only_v6(false);
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(128)?;
+        let inner = TcpListener::from(socket);
+        inner.set_nonblocking(true)?;
+        Ok(Listener {
+            inner,
+            behavior: Behavior::default(),
+        })
+    }
+
+    /// Sends `banner` on every connection as soon as it is accepted, before
+    /// echoing anything.
+    pub fn with_banner(mut self, banner: impl Into<Vec<u8>>) -> Self {
+        self.behavior.banner = banner.into();
+        self
+    }
+
+    /// Closes every connection `linger` after accepting it (unless the peer
+    /// closed first), however busy or idle, instead of after `IDLE_TIMEOUT`
+    /// without traffic.
+    pub fn with_linger(mut self, linger: Duration) -> Self {
+        self.behavior.linger = Some(linger);
+        self
+    }
+
+    /// Closes every connection right after accepting it (and sending the
+    /// banner, if any), as a server turning clients away would.
+    pub fn with_reject(mut self, reject: bool) -> Self {
+        self.behavior.reject = reject;
+        self
+    }
+
+    /// Reads and throws away what connections send instead of echoing it,
+    /// and takes --throughput uploads. No banner is sent then, and the
+    /// linger time doesn't apply.
+    pub fn with_discard(mut self, discard: bool) -> Self {
+        self.behavior.discard = discard;
+        self
+    }
+
+    /// Sends data to --throughput downloads. Without `with_discard`,
+    /// connections sending anything but a throughput request are closed.
+    pub fn with_source(mut self, source: bool) -> Self {
+        self.behavior.source = source;
+        self
+    }
+
+    /// The address actually listened on (with the port picked by the
+    /// system when binding to port 0).
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Accepts connections until `stop` is stopped, or until `count` were
+    /// accepted and have all closed (`count` 0 = no limit). Each connection
+    /// is echoed on a thread of its own; `on_event` is called on the calling
+    /// thread.
+    pub fn serve(&self, count: u32, stop: &StopToken, mut on_event: impl FnMut(ListenEvent)) {
+        let (tx, rx) = mpsc::channel();
+        let (mut accepted, mut open) = (0u32, 0u32);
+        loop {
+            while let Ok(event) = rx.try_recv() {
+                open -= 1;
+                on_event(event);
+            }
+            if stop.is_stopped() || (count > 0 && accepted >= count && open == 0) {
+                return;
+            }
+            if count > 0 && accepted >= count {
+                stop.wait_timeout(POLL);
+                continue;
+            }
+            match self.inner.accept() {
+                Ok((stream, peer)) => {
+                    // [::ffff:192.0.2.1]:80 from a dual-stack socket: show the IPv4
+                    let peer = SocketAddr::new(peer.ip().to_canonical(), peer.port());
+                    accepted += 1;
+                    open += 1;
+                    on_event(ListenEvent::Accepted {
+                        seq: accepted,
+                        peer,
+                    });
+                    let tx = tx.clone();
+                    let seq = accepted;
+                    let behavior = self.behavior.clone();
+                    if behavior.discard || behavior.source {
+                        std::thread::spawn(move || sink(stream, seq, peer, &behavior, tx));
+                    } else {
+                        std::thread::spawn(move || echo(stream, seq, peer, &behavior, tx));
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    stop.wait_timeout(POLL);
+                }
+                // Aborted handshakes and the like: nothing to report
+                Err(_) => {
+                    stop.wait_timeout(POLL);
+                }
+            }
+        }
+    }
+}
+
+/// Sends the banner, then everything read back, until the peer closes, goes
+/// idle or the linger time is up.
+fn echo(
+    mut stream: TcpStream,
+    seq: u32,
+    peer: SocketAddr,
+    behavior: &Behavior,
+    events: Sender<ListenEvent>,
+) {
+    let start = Instant::now();
+    let mut echoed = 0u64;
+    let mut buf = [0u8; 4096];
+    let result = (|| -> io::Result<()> {
+        // Accepted sockets inherit non-blocking mode on some systems
+        stream.set_nonblocking(false)?;
+        stream.write_all(&behavior.banner)?;
+        if behavior.reject {
+            return Ok(());
+        }
+        loop {
+            let timeout = match behavior.linger {
+                Some(linger) => match linger.checked_sub(start.elapsed()) {
+                    Some(left) if !left.is_zero() => left,
+                    _ => return Ok(()),
+                },
+                None => IDLE_TIMEOUT,
+            };
+            stream.set_read_timeout(Some(timeout))?;
+            let n = match stream.read(&mut buf) {
+                // The linger time is up (checked at the top of the loop)
+                Err(ref e)
+                    if behavior.linger.is_some()
+                        && matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                {
+                    continue
+                }
+                other => other?,
+            };
+            if n == 0 {
+                return Ok(());
+            }
+            stream.write_all(&buf[..n])?;
+            echoed += n as u64;
+        }
+    })();
+    drop(stream);
+    let _ = events.send(ListenEvent::Closed {
+        seq,
+        peer,
+        duration: start.elapsed(),
+        echoed,
+        transfer: None,
+        error: result.err().map(describe),
+    });
+}
+
+/// Takes or sends data (see [`throughput::answer`]) until the peer closes
+/// or goes idle.
+fn sink(
+    mut stream: TcpStream,
+    seq: u32,
+    peer: SocketAddr,
+    behavior: &Behavior,
+    events: Sender<ListenEvent>,
+) {
+    let start = Instant::now();
+    let result = (|| -> io::Result<Option<Transfer>> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+        stream.set_write_timeout(Some(IDLE_TIMEOUT))?;
+        throughput::answer(&mut stream, behavior.discard, behavior.source)
+    })();
+    drop(stream);
+    let (transfer, error) = match result {
+        Ok(transfer) => (transfer, None),
+        Err(e) => (None, Some(describe(e))),
+    };
+    let _ = events.send(ListenEvent::Closed {
+        seq,
+        peer,
+        duration: start.elapsed(),
+        echoed: 0,
+        transfer,
+        error,
+    });
+}
+
+fn describe(e: io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+            format!("idle for {}s", IDLE_TIMEOUT.as_secs())
+        }
+        _ => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn echoes_and_reports_each_connection() {
+        let listener = Listener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"ping").unwrap();
+            let mut reply = [0u8; 4];
+            stream.read_exact(&mut reply).unwrap();
+            reply
+        });
+
+        let mut events = Vec::new();
+        listener.serve(1, &StopToken::never(), |event| events.push(event));
+        assert_eq!(&client.join().unwrap(), b"ping");
+
+        assert!(matches!(events[0], ListenEvent::Accepted { seq: 1, .. }));
+        match events[1] {
+            ListenEvent::Closed {
+                seq: 1,
+                echoed: 4,
+                error: None,
+                ..
+            } => {}
+            ref other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn stop_ends_an_idle_listener() {
+        let listener = Listener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let handle = crate::stop::StopHandle::new();
+        let token = handle.token();
+        let stopper = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            handle.stop();
+        });
+        let start = Instant::now();
+        listener.serve(0, &token, |_| panic!("nobody connects"));
+        stopper.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn banner_then_reject_or_linger() {
+        let listener = Listener::bind("127.0.0.1:0".parse().unwrap())
+            .unwrap()
+            .with_banner("421 go away\r\n")
+            .with_reject(true);
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let mut got = String::new();
+            stream.read_to_string(&mut got).unwrap();
+            got
+        });
+        listener.serve(1, &StopToken::never(), |_| {});
+        assert_eq!(client.join().unwrap(), "421 go away\r\n");
+
+        let listener = Listener::bind("127.0.0.1:0".parse().unwrap())
+            .unwrap()
+            .with_linger(Duration::from_millis(200));
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"hi").unwrap();
+            let mut got = Vec::new();
+            // Returns once the listener closes, the echo read first
+            stream.read_to_end(&mut got).unwrap();
+            got
+        });
+        let mut events = Vec::new();
+        listener.serve(1, &StopToken::never(), |event| events.push(event));
+        assert_eq!(client.join().unwrap(), b"hi");
+        match events[1] {
+            ListenEvent::Closed {
+                duration,
+                echoed: 2,
+                error: None,
+                ..
+            } => assert!(duration >= Duration::from_millis(200), "{:?}", duration),
+            ref other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn discards_and_reports_what_was_received() {
+        let listener = Listener::bind("127.0.0.1:0".parse().unwrap())
+            .unwrap()
+            .with_discard(true);
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(&[7u8; 10_000]).unwrap();
+        });
+        let mut events = Vec::new();
+        listener.serve(1, &StopToken::never(), |event| events.push(event));
+        client.join().unwrap();
+        match events[1] {
+            ListenEvent::Closed {
+                echoed: 0,
+                transfer: Some(Transfer { bytes: 10_000, .. }),
+                error: None,
+                ..
+            } => {}
+            ref other => panic!("unexpected {:?}", other),
+        }
+    }
+}
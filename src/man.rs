@@ -0,0 +1,180 @@
+//! The paping(1) man page, for packagers (`paping generate-man > paping.1`).
+//!
+//! clap_mangen renders what clap knows about (synopsis, options,
+//! subcommands); the sections below it has no metadata for are written here.
+
+use std::io::{self, Write};
+
+use clap_mangen::roff::{bold, italic, roman, Roff};
+use clap_mangen::Man;
+
+/// Exit codes, as (code, meaning).
+const EXIT_STATUS: &[(&str, &str)] = &[
+    (
+        "0",
+        "The run finished, whatever the number of failed pings; with --wait, \
+         the port accepted a connection; with --wait-closed, it stopped accepting.",
+    ),
+    (
+        "1",
+        "Invalid target, option, config file or environment variable, \
+         --max-failures was reached, or --wait/--wait-closed gave up.",
+    ),
+    ("2", "The command line could not be parsed."),
+];
+
+/// Environment variables, as (name, meaning).
+const ENVIRONMENT: &[(&str, &str)] = &[
+    ("PAPING_TIMEOUT", "Default for --timeout, in milliseconds."),
+    (
+        "PAPING_INTERVAL",
+        "Default for --interval, in milliseconds.",
+    ),
+    ("PAPING_PROXY", "Default for --proxy."),
+    ("PAPING_INTERFACE", "Default for --interface."),
+    (
+        "PAPING_COLOR",
+        "Default for --color (auto, always or never).",
+    ),
+    (
+        "ALL_PROXY, SOCKS_PROXY",
+        "SOCKS5 proxy used when none is configured. Other schemes are ignored.",
+    ),
+    (
+        "NO_PROXY",
+        "Comma-separated hosts, domains and IPs (optionally with :port) that \
+         bypass ALL_PROXY and SOCKS_PROXY; * bypasses them for every target.",
+    ),
+    (
+        "NO_COLOR",
+        "Disables colors, unless --color always is given.",
+    ),
+    (
+        "XDG_CONFIG_HOME",
+        "Directory holding paping/config.toml (default: ~/.config).",
+    ),
+    (
+        "PAPING_NO_AUTO_INSTALL, CI",
+        "When set, paping does not install itself on first run.",
+    ),
+    (
+        "PAPING_GITHUB_TOKEN",
+        "GitHub token used by paping update, to avoid API rate limits.",
+    ),
+    ("PAPING_UPDATE_UA", "User-Agent sent by paping update."),
+];
+
+/// Examples, as (command, what it does).
+const EXAMPLES: &[(&str, &str)] = &[
+    (
+        "paping example.com -p 443",
+        "Ping port 443 until interrupted.",
+    ),
+    (
+        "paping example.com:22 -c 4",
+        "Send 4 pings, then print the statistics.",
+    ),
+    (
+        "paping https://example.com/",
+        "Ping the port of the URL scheme (443).",
+    ),
+    (
+        "paping example.com 443 --proxy socks5://127.0.0.1:1080 --compare-direct",
+        "Measure through a SOCKS5 proxy and directly, and show the overhead.",
+    ),
+    (
+        "paping mail.example.com 25 --send 'EHLO paping\\r\\n' --recv",
+        "Check that the service answers, not only that it accepts connections.",
+    ),
+    (
+        "paping example.com 443 --max-failures 3",
+        "Exit with status 1 after 3 failed pings, e.g. in scripts.",
+    ),
+];
+
+/// Writes the full man page for `cmd`.
+pub fn render(cmd: clap::Command, w: &mut dyn Write) -> io::Result<()> {
+    let targets = cmd.get_after_long_help().map(|help| help.to_string());
+    // The target forms get their own section instead of clap_mangen's "EXTRA"
+    let man = Man::new(cmd.after_long_help(None::<&str>));
+    man.render_title(w)?;
+    man.render_name_section(w)?;
+    man.render_synopsis_section(w)?;
+    man.render_description_section(w)?;
+    man.render_options_section(w)?;
+    man.render_subcommands_section(w)?;
+
+    let mut roff = Roff::new();
+    if let Some(targets) = targets {
+        roff.control("SH", ["TARGETS"]);
+        roff.control("nf", []);
+        for line in targets.lines() {
+            roff.text([roman(line)]);
+        }
+        roff.control("fi", []);
+    }
+    definitions(&mut roff, "EXIT STATUS", EXIT_STATUS);
+    definitions(&mut roff, "ENVIRONMENT", ENVIRONMENT);
+    roff.control("SH", ["FILES"]);
+    roff.control("TP", []);
+    roff.text([italic("$XDG_CONFIG_HOME/paping/config.toml")]);
+    roff.text([roman(
+        "Settings and named profiles; see paping config path for the exact location, \
+         which is %APPDATA%\\paping\\config.toml on Windows.",
+    )]);
+    roff.control("SH", ["EXAMPLES"]);
+    for (command, what) in EXAMPLES {
+        roff.control("TP", []);
+        roff.text([bold(*command)]);
+        roff.text([roman(*what)]);
+    }
+    roff.to_writer(w)?;
+
+    man.render_version_section(w)
+}
+
+fn definitions(roff: &mut Roff, heading: &str, items: &[(&str, &str)]) {
+    roff.control("SH", [heading]);
+    for (term, meaning) in items {
+        roff.control("TP", []);
+        roff.text([bold(*term)]);
+        roff.text([roman(*meaning)]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn page() -> String {
+        let mut out = Vec::new();
+        render(crate::Cli::command(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn page_has_the_authored_sections() {
+        let page = page();
+        for section in ["EXIT STATUS", "ENVIRONMENT", "FILES", "EXAMPLES", "TARGETS"] {
+            assert!(
+                page.contains(&format!(".SH \"{}\"", section))
+                    || page.contains(&format!(".SH {}", section)),
+                "{section}"
+            );
+        }
+        assert!(!page.contains("EXTRA"));
+    }
+
+    #[test]
+    fn page_lists_every_long_option() {
+        let page = page();
+        for arg in crate::Cli::command().get_arguments() {
+            if let Some(long) = arg.get_long() {
+                // roff escapes dashes in option names
+                let escaped = format!("\\-\\-{}", long.replace('-', "\\-"));
+                assert!(page.contains(&escaped), "--{long} missing");
+            }
+        }
+    }
+}
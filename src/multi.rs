@@ -0,0 +1,420 @@
+//! Pinging several targets in turn (--target-list).
+//!
+//! A target list has one target per line, optionally followed by
+//! `key=value` options:
+//!
+//! ```text
+//! # primary gets three probes for each one of the fallback
+//! api.example.com:443 weight=3
+//! fallback.example.com:443
+//! # 100 probes, then out of the rotation
+//! db.example.com:5432 count=100
+//! ```
+//!
+//! Each round probes one target, picked by smooth weighted round-robin so
+//! that heavier targets get their share spread out rather than in bursts.
+//! A target with a count of its own leaves the rotation once it has been
+//! probed that many times. Every target keeps its own statistics.
+//!
+//! ```no_run
+//! use paping::multi::MultiPinger;
+//! use paping::pinger::Pinger;
+//! use paping::stop::StopToken;
+//!
+//! let primary = Pinger::builder("api.example.com", 443).build().unwrap();
+//! let fallback = Pinger::builder("fallback.example.com", 443).build().unwrap();
+//! let mut multi = MultiPinger::new(vec![(primary, 3), (fallback, 1)]);
+//! multi.print_header();
+//! multi.run(8, &StopToken::never());
+//! multi.print_stats();
+//! ```
+
+use std::sync::mpsc::Sender;
+
+use colored::Colorize;
+
+use crate::pinger::{PingResult, Pinger};
+use crate::stop::StopToken;
+use crate::target::{join_host_port, parse_target};
+
+/// Weight of a target list line without `weight=`.
+pub const DEFAULT_WEIGHT: u32 = 1;
+
+/// One line of a target list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TargetEntry {
+    pub host: String,
+    /// None when the line has no port (the CLI falls back to -p)
+    pub port: Option<u16>,
+    pub weight: u32,
+    /// Probes of this target, None for the run's count
+    pub count: Option<u32>,
+}
+
+/// Parses a target list: one target per line (anything `parse_target`
+/// accepts) with optional `weight=N` and `count=N` (N ≥ 1). Blank lines
+/// and `#` comments are skipped. Errors name the line.
+pub fn parse_target_list(text: &str) -> Result<Vec<TargetEntry>, String> {
+    let mut entries = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let mut words = line.split_whitespace();
+        let Some(first) = words.next() else {
+            continue;
+        };
+        let at = |e: String| format!("line {}: {}", n + 1, e);
+        let target = parse_target(first).map_err(at)?;
+        let mut entry = TargetEntry {
+            host: target.host,
+            port: target.port,
+            weight: DEFAULT_WEIGHT,
+            count: None,
+        };
+        for option in words {
+            match option.split_once('=') {
+                Some(("weight", value)) => {
+                    entry.weight = match value.parse() {
+                        Ok(w) if w >= 1 => w,
+                        _ => {
+                            return Err(at(format!(
+                                "invalid weight '{}' (a whole number, 1 or more)",
+                                value
+                            )))
+                        }
+                    }
+                }
+                Some(("count", value)) => {
+                    entry.count = match value.parse() {
+                        Ok(c) if c >= 1 => Some(c),
+                        _ => {
+                            return Err(at(format!(
+                                "invalid count '{}' (a whole number, 1 or more)",
+                                value
+                            )))
+                        }
+                    }
+                }
+                _ => {
+                    return Err(at(format!(
+                        "unknown option '{}' (valid: weight, count)",
+                        option
+                    )))
+                }
+            }
+        }
+        entries.push(entry);
+    }
+    if entries.is_empty() {
+        return Err("no targets in the list".to_string());
+    }
+    Ok(entries)
+}
+
+/// Smooth weighted round-robin (as in nginx): every pick, each item gains
+/// its weight, the one with the most is picked and loses the total. Over
+/// any `sum of weights` picks, each item is picked `weight` times, spread
+/// as evenly as possible: weights 3 and 1 give 0, 0, 1, 0. Items of
+/// weight 0 are never picked.
+#[derive(Clone, Debug)]
+pub struct WeightedRoundRobin {
+    weights: Vec<u32>,
+    current: Vec<i64>,
+}
+
+impl WeightedRoundRobin {
+    pub fn new(weights: Vec<u32>) -> Self {
+        let current = vec![0; weights.len()];
+        WeightedRoundRobin { weights, current }
+    }
+
+    /// Index of the next item, None without items (of weight 1 or more).
+    pub fn pick(&mut self) -> Option<usize> {
+        let total: i64 = self.weights.iter().map(|&w| w as i64).sum();
+        if total == 0 {
+            return None;
+        }
+        for (current, &weight) in self.current.iter_mut().zip(&self.weights) {
+            *current += weight as i64;
+        }
+        // First of the largest, so ties go to the earlier line
+        let (picked, _) = self
+            .current
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| self.weights[i] > 0)
+            .rev()
+            .max_by_key(|&(_, &c)| c)?;
+        self.current[picked] -= total;
+        Some(picked)
+    }
+
+    /// Whether no item is left to pick.
+    pub fn is_empty(&self) -> bool {
+        self.weights.iter().all(|&w| w == 0)
+    }
+
+    /// Takes item `index` out of the picks for good.
+    pub fn remove(&mut self, index: usize) {
+        if let Some(weight) = self.weights.get_mut(index) {
+            *weight = 0;
+            self.current[index] = 0;
+        }
+    }
+}
+
+/// Several pingers probed in turn by weighted round-robin, one per round.
+pub struct MultiPinger {
+    targets: Vec<(Pinger, u32)>,
+    /// Probes of each target, 0 for no count of its own
+    counts: Vec<u32>,
+    picker: WeightedRoundRobin,
+}
+
+impl MultiPinger {
+    /// Takes each pinger with its weight (0 counts as 1).
+    pub fn new(targets: Vec<(Pinger, u32)>) -> Self {
+        let targets: Vec<(Pinger, u32)> = targets.into_iter().map(|(p, w)| (p, w.max(1))).collect();
+        let picker = WeightedRoundRobin::new(targets.iter().map(|&(_, w)| w).collect());
+        let counts = vec![0; targets.len()];
+        MultiPinger {
+            targets,
+            counts,
+            picker,
+        }
+    }
+
+    /// Gives each target, in list order, a count of its own (0 = none): it
+    /// leaves the rotation once probed that many times, and the run ends
+    /// when every target has (or at the count given to `run`).
+    pub fn with_counts(mut self, counts: Vec<u32>) -> Self {
+        self.counts = counts;
+        self.counts.resize(self.targets.len(), 0);
+        self
+    }
+
+    /// The pingers with their weights, in list order.
+    pub fn targets(&self) -> &[(Pinger, u32)] {
+        &self.targets
+    }
+
+    /// Sends every probe result to `tx`, with the index of its target in
+    /// list order (see [`Pinger::on_result_channel`]).
+    pub fn on_result_channel(&mut self, tx: Sender<(usize, PingResult)>) {
+        for (index, (pinger, _)) in self.targets.iter_mut().enumerate() {
+            let tx = tx.clone();
+            pinger.on_result(move |result| {
+                let _ = tx.send((index, result.clone()));
+            });
+        }
+    }
+
+    /// Prints the targets with their weight and share of the probes.
+    pub fn print_header(&self) {
+        let total: u32 = self.targets.iter().map(|&(_, w)| w).sum();
+        println!();
+        println!(
+            "Connecting to  {}  targets by weighted round-robin:",
+            self.targets.len().to_string().green()
+        );
+        for ((pinger, weight), &count) in self.targets.iter().zip(&self.counts) {
+            let count = match count {
+                0 => String::new(),
+                1 => ", 1 probe".to_string(),
+                n => format!(", {} probes", n),
+            };
+            println!(
+                "  {}  weight {} ({:.0}%){}",
+                join_host_port(pinger.address(), pinger.port()).green(),
+                weight,
+                *weight as f64 * 100.0 / total as f64,
+                count
+            );
+        }
+        println!();
+    }
+
+    /// Probes `count` times in all (0 = until stopped), one target per
+    /// round, waiting the picked target's interval after each probe, until
+    /// the targets with a count of their own (see `with_counts`) are all
+    /// done too. The longest `with_startup_delay` of the targets is waited
+    /// first.
+    pub fn run(&mut self, count: u32, stop: &StopToken) {
+        let delay = self.targets.iter().filter_map(|(p, _)| p.startup_delay()).max();
+        if let Some(delay) = delay {
+            stop.wait_timeout(delay);
+        }
+        let mut rounds = 0;
+        while !stop.is_stopped() {
+            let Some(picked) = self.picker.pick() else {
+                break;
+            };
+            let pinger = &mut self.targets[picked].0;
+            pinger.ping_once();
+            pinger.beat();
+            rounds += 1;
+            if count > 0 && rounds >= count {
+                break;
+            }
+            let own = self.counts[picked];
+            if own > 0 && pinger.stats().attempted >= own {
+                self.picker.remove(picked);
+                if self.picker.is_empty() {
+                    break;
+                }
+            }
+            let pause = pinger.interval_after(pinger.stats().attempted);
+            pinger.pause(pause, stop);
+        }
+        println!();
+    }
+
+    /// Prints one statistics row per target.
+    pub fn print_stats(&self) {
+        let Some((first, _)) = self.targets.first() else {
+            return;
+        };
+        println!("Connection statistics:");
+        let series: Vec<(Option<String>, _)> = self
+            .targets
+            .iter()
+            .zip(&self.counts)
+            .map(|((p, w), &count)| {
+                let mut label = format!("{}  w={}", join_host_port(p.address(), p.port()), w);
+                if count > 0 {
+                    label.push_str(&format!(" c={}", count));
+                }
+                (Some(label), p.stats())
+            })
+            .collect();
+        first.print_series_table(&series);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_lists() {
+        let list = "# comment\n\nexample.com:443 weight=3 count=100\nhttps://api.example.com/v1  # trailing\n[::1]:22\n";
+        let entries = parse_target_list(list).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                TargetEntry {
+                    host: "example.com".into(),
+                    port: Some(443),
+                    weight: 3,
+                    count: Some(100),
+                },
+                TargetEntry {
+                    host: "api.example.com".into(),
+                    port: Some(443),
+                    weight: 1,
+                    count: None,
+                },
+                TargetEntry {
+                    host: "::1".into(),
+                    port: Some(22),
+                    weight: 1,
+                    count: None,
+                },
+            ]
+        );
+        assert_eq!(parse_target_list("example.com").unwrap()[0].port, None);
+
+        let err = |text| parse_target_list(text).unwrap_err();
+        assert_eq!(
+            err("a:1\nb:2 weight=0"),
+            "line 2: invalid weight '0' (a whole number, 1 or more)"
+        );
+        assert_eq!(
+            err("a:1 prio=2"),
+            "line 1: unknown option 'prio=2' (valid: weight, count)"
+        );
+        assert_eq!(
+            err("a:1 count=none"),
+            "line 1: invalid count 'none' (a whole number, 1 or more)"
+        );
+        assert!(err("a:99999").starts_with("line 1: "));
+        assert_eq!(err("# nothing\n"), "no targets in the list");
+    }
+
+    #[test]
+    fn weighted_round_robin_spreads_picks() {
+        let mut wrr = WeightedRoundRobin::new(vec![3, 1]);
+        let picks: Vec<_> = (0..8).map(|_| wrr.pick().unwrap()).collect();
+        assert_eq!(picks, [0, 0, 1, 0, 0, 0, 1, 0]);
+
+        let mut wrr = WeightedRoundRobin::new(vec![5, 1, 1]);
+        let picks: Vec<_> = (0..7).map(|_| wrr.pick().unwrap()).collect();
+        assert_eq!(picks, [0, 0, 1, 0, 2, 0, 0]);
+
+        assert_eq!(WeightedRoundRobin::new(vec![]).pick(), None);
+
+        let mut wrr = WeightedRoundRobin::new(vec![2, 1]);
+        wrr.remove(0);
+        assert_eq!(wrr.pick(), Some(1));
+        assert!(!wrr.is_empty());
+        wrr.remove(1);
+        assert!(wrr.is_empty());
+        assert_eq!(wrr.pick(), None);
+    }
+
+    /// Pingers of the given weights, to listeners that never accept: the
+    /// connects complete in the backlog.
+    fn local_targets(weights: &[u32]) -> (Vec<TcpListener>, Vec<(Pinger, u32)>) {
+        let listeners: Vec<_> = weights
+            .iter()
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let mut targets = Vec::new();
+        for (listener, &weight) in listeners.iter().zip(weights) {
+            let port = listener.local_addr().unwrap().port();
+            let pinger = Pinger::builder("127.0.0.1", port)
+                .interval(Duration::from_millis(1))
+                .silent(true)
+                .build()
+                .unwrap();
+            targets.push((pinger, weight));
+        }
+        (listeners, targets)
+    }
+
+    fn attempted(multi: &MultiPinger) -> Vec<u32> {
+        multi
+            .targets()
+            .iter()
+            .map(|(p, _)| p.stats().attempted)
+            .collect()
+    }
+
+    #[test]
+    fn probes_each_target_by_weight() {
+        let (_listeners, targets) = local_targets(&[2, 1]);
+        let mut multi = MultiPinger::new(targets);
+        multi.run(6, &StopToken::never());
+
+        assert_eq!(attempted(&multi), [4, 2]);
+        assert!(multi
+            .targets()
+            .iter()
+            .all(|(p, _)| p.stats().connected == p.stats().attempted));
+    }
+
+    #[test]
+    fn targets_with_a_count_leave_the_rotation() {
+        let (_listeners, targets) = local_targets(&[1, 1, 1]);
+        let mut multi = MultiPinger::new(targets).with_counts(vec![10, 2, 5]);
+        multi.run(0, &StopToken::never());
+        assert_eq!(attempted(&multi), [10, 2, 5]);
+
+        // The run's count still ends it first
+        let (_listeners, targets) = local_targets(&[1, 1]);
+        let mut multi = MultiPinger::new(targets).with_counts(vec![10, 0]);
+        multi.run(6, &StopToken::never());
+        assert_eq!(attempted(&multi), [3, 3]);
+    }
+}
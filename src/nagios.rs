@@ -0,0 +1,337 @@
+//! --nagios: a check for Nagios, Icinga and the like. One bounded run, then
+//! the status line with its performance data, and the exit status of the
+//! plugin API:
+//!
+//! ```text
+//! PAPING OK - 10/10 connects, rtt avg 12.3ms|rtt=12.3ms;100;250 loss=0%;10;20
+//! ```
+//!
+//! --warn and --crit take `RTT,LOSS%` (either may be left out: `100`,
+//! `10%`), each a Nagios range: `10` alerts above 10, `10:` below 10,
+//! `~:10` above 10 too, `10:20` outside 10 to 20, and `@10:20` inside.
+
+use std::fmt;
+
+use paping::pinger::PingStats;
+
+/// Probes of a check run with no -c.
+pub const DEFAULT_COUNT: u32 = 5;
+
+/// Outcome of a check, by exit status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// Ends a check that couldn't be made (bad arguments) the way the plugin
+/// API wants: UNKNOWN, exit status 3.
+pub fn unknown(message: &str) -> ! {
+    println!("PAPING {} - {}", Status::Unknown.as_str(), message);
+    std::process::exit(Status::Unknown as i32);
+}
+
+/// A threshold range, in the syntax of the Nagios plugin guidelines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Range {
+    start: f64,
+    end: f64,
+    /// `@`: alert inside the range rather than outside
+    inside: bool,
+    /// As given, for the performance data
+    text: String,
+}
+
+impl Range {
+    /// `[@]START:END`, `START` being 0 when left out (or no colon), `~`
+    /// for no lower bound, and `END` none when left out after the colon.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "'{}' isn't a range (e.g. 10, 10:, ~:10, 10:20, @10:20)",
+                spec
+            )
+        };
+        let (inside, range) = match spec.strip_prefix('@') {
+            Some(range) => (true, range),
+            None => (false, spec),
+        };
+        let (start, end) = match range.split_once(':') {
+            Some((start, end)) => (start, end),
+            None => ("", range),
+        };
+        let start = match start {
+            "~" => f64::NEG_INFINITY,
+            "" => 0.0,
+            start => start.parse().map_err(|_| invalid())?,
+        };
+        let end = match end {
+            "" if range.contains(':') => f64::INFINITY,
+            end => end.parse().map_err(|_| invalid())?,
+        };
+        if start.is_nan() || end.is_nan() || start > end {
+            return Err(invalid());
+        }
+        Ok(Range {
+            start,
+            end,
+            inside,
+            text: spec.to_string(),
+        })
+    }
+
+    /// Whether `value` is one to alert on.
+    pub fn alerts(&self, value: f64) -> bool {
+        let within = self.start <= value && value <= self.end;
+        within == self.inside
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// The ranges of --warn or --crit.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Thresholds {
+    /// On the average connection time, in milliseconds
+    pub rtt: Option<Range>,
+    /// On the loss, in percent
+    pub loss: Option<Range>,
+}
+
+impl Thresholds {
+    /// `RTT,LOSS%`, or just one of them: the loss is the one with a `%`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let range = |part: &str| match part {
+            "" => Ok(None),
+            part => Range::parse(part).map(Some),
+        };
+        let (rtt, loss) = match spec.split_once(',') {
+            Some((rtt, loss)) => (rtt, loss.strip_suffix('%').unwrap_or(loss)),
+            None => match spec.strip_suffix('%') {
+                Some(loss) => ("", loss),
+                None => (spec, ""),
+            },
+        };
+        if rtt.ends_with('%') {
+            return Err(format!(
+                "'{}': the latency comes first, then the loss (RTT,LOSS%)",
+                spec
+            ));
+        }
+        Ok(Thresholds {
+            rtt: range(rtt)?,
+            loss: range(loss)?,
+        })
+    }
+
+    /// Loss thresholds only, `percent` being the highest loss without an
+    /// alert (--warn and --crit from the configuration file).
+    pub fn loss_above(percent: f64) -> Self {
+        Thresholds {
+            rtt: None,
+            loss: Some(Range {
+                start: 0.0,
+                end: percent,
+                inside: false,
+                text: number(percent),
+            }),
+        }
+    }
+
+    fn alerts(&self, rtt: Option<f64>, loss: f64) -> bool {
+        let rtt_alerts = match (&self.rtt, rtt) {
+            (Some(range), Some(rtt)) => range.alerts(rtt),
+            _ => false,
+        };
+        rtt_alerts || self.loss.as_ref().is_some_and(|range| range.alerts(loss))
+    }
+}
+
+/// The status of a run, and its line: CRITICAL whenever nothing connected,
+/// `last_failure` then telling why.
+pub fn check(
+    stats: &PingStats,
+    warn: &Thresholds,
+    crit: &Thresholds,
+    last_failure: Option<&str>,
+) -> (Status, String) {
+    let rtt = stats.average();
+    let loss = stats.loss_percent();
+    let status = if stats.connected == 0 || crit.alerts(rtt, loss) {
+        Status::Critical
+    } else if warn.alerts(rtt, loss) {
+        Status::Warning
+    } else {
+        Status::Ok
+    };
+    let summary = match (rtt, last_failure) {
+        (Some(rtt), _) => format!("rtt avg {}ms", number(rtt)),
+        (None, Some(failure)) => format!("last: {}", failure),
+        (None, None) => "no connection".to_string(),
+    };
+    let range = |range: &Option<Range>| range.as_ref().map(Range::to_string).unwrap_or_default();
+    let line = format!(
+        "PAPING {} - {}/{} connects, {}|rtt={};{};{} loss={}%;{};{}",
+        status.as_str(),
+        stats.connected,
+        stats.attempted,
+        summary,
+        rtt.map(|rtt| format!("{}ms", number(rtt)))
+            .unwrap_or_else(|| "U".to_string()),
+        range(&warn.rtt),
+        range(&crit.rtt),
+        number(loss),
+        range(&warn.loss),
+        range(&crit.loss),
+    );
+    (status, line)
+}
+
+/// One decimal at most: `12.3`, `0`.
+fn number(value: f64) -> String {
+    let text = format!("{:.1}", value);
+    match text.strip_suffix(".0") {
+        Some(whole) => whole.to_string(),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(times: &[f64], failed: u32) -> PingStats {
+        PingStats {
+            attempted: times.len() as u32 + failed,
+            connected: times.len() as u32,
+            failed,
+            times: times.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_ranges() {
+        let alerts = |spec: &str, value| Range::parse(spec).unwrap().alerts(value);
+        // 10: outside 0..10
+        assert!(!alerts("10", 0.0));
+        assert!(!alerts("10", 10.0));
+        assert!(alerts("10", 10.1));
+        assert!(alerts("10", -1.0));
+        // 10: below 10
+        assert!(alerts("10:", 9.9));
+        assert!(!alerts("10:", 10.0));
+        assert!(!alerts("10:", 1e9));
+        // ~:10: above 10, however low
+        assert!(!alerts("~:10", -1e9));
+        assert!(alerts("~:10", 10.5));
+        // 10:20: outside, bounds included in the range
+        assert!(alerts("10:20", 9.0));
+        assert!(!alerts("10:20", 10.0));
+        assert!(!alerts("10:20", 20.0));
+        assert!(alerts("10:20", 21.0));
+        // @10:20: inside, bounds included
+        assert!(alerts("@10:20", 10.0));
+        assert!(alerts("@10:20", 20.0));
+        assert!(!alerts("@10:20", 20.5));
+        assert!(!alerts("@10:20", 9.5));
+        // Decimals and negatives, and @ with a single number
+        assert!(alerts("-5.5:-1", 0.0));
+        assert!(!alerts("-5.5:-1", -3.0));
+        assert!(alerts("@2.5", 1.0));
+        assert!(!alerts("@2.5", 3.0));
+        // :10 starts at 0, as if left out
+        assert!(alerts(":10", -0.5));
+
+        for bad in ["", "@", "abc", "20:10", "1:2:3", "~", "@~", "10::", "nan"] {
+            assert!(Range::parse(bad).is_err(), "{:?}", bad);
+        }
+        assert_eq!(Range::parse("@~:10").unwrap().to_string(), "@~:10");
+    }
+
+    #[test]
+    fn parses_thresholds() {
+        let parse = |spec| Thresholds::parse(spec).unwrap();
+        let both = parse("100,10%");
+        assert_eq!(both.rtt.unwrap().to_string(), "100");
+        assert_eq!(both.loss.unwrap().to_string(), "10");
+        assert!(parse("100,10").loss.is_some());
+        let rtt = parse("~:250");
+        assert!(rtt.rtt.is_some() && rtt.loss.is_none());
+        let loss = parse("20%");
+        assert!(loss.rtt.is_none() && loss.loss.is_some());
+        let loss = parse(",@0:50%");
+        assert!(loss.rtt.is_none() && loss.loss.unwrap().inside);
+        assert_eq!(parse(","), Thresholds::default());
+
+        assert!(Thresholds::parse("10%,100").is_err());
+        assert!(Thresholds::parse("fast").is_err());
+        assert!(Thresholds::parse("100,lots%").is_err());
+    }
+
+    #[test]
+    fn checks_the_run() {
+        let warn = Thresholds::parse("100,10%").unwrap();
+        let crit = Thresholds::parse("250,20%").unwrap();
+        let times = [12.0, 12.5, 12.4, 12.3, 12.3, 12.2, 12.3, 12.3, 12.2, 12.5];
+        assert_eq!(
+            check(&stats(&times, 0), &warn, &crit, None),
+            (
+                Status::Ok,
+                "PAPING OK - 10/10 connects, rtt avg 12.3ms|rtt=12.3ms;100;250 loss=0%;10;20"
+                    .to_string()
+            )
+        );
+        // Slow, or lossy
+        assert_eq!(
+            check(&stats(&[150.0], 0), &warn, &crit, None).0,
+            Status::Warning
+        );
+        assert_eq!(
+            check(&stats(&[300.0], 0), &warn, &crit, None).0,
+            Status::Critical
+        );
+        assert_eq!(
+            check(&stats(&[10.0; 17], 3), &warn, &crit, None).0,
+            Status::Warning
+        );
+        assert_eq!(
+            check(&stats(&[10.0; 3], 2), &warn, &crit, None).0,
+            Status::Critical
+        );
+
+        // Nothing connected: critical, whatever the thresholds
+        let none = Thresholds::default();
+        let (status, line) = check(&stats(&[], 5), &none, &none, Some("connection refused"));
+        assert_eq!(status, Status::Critical);
+        assert_eq!(
+            line,
+            "PAPING CRITICAL - 0/5 connects, last: connection refused|rtt=U;; loss=100%;;"
+        );
+    }
+
+    #[test]
+    fn loss_thresholds_from_percentages() {
+        let warn = Thresholds::loss_above(5.0);
+        assert!(!warn.alerts(None, 5.0));
+        assert!(warn.alerts(None, 5.5));
+        assert_eq!(warn.loss.unwrap().to_string(), "5");
+    }
+}
@@ -0,0 +1,277 @@
+//! Bytes sent once connected (--send), to check that the service behind
+//! the port answers rather than just accepting connections; or nothing sent,
+//! just the banner read that services like SSH or SMTP start with (--recv).
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use crate::result::{Exchange, Failure, FailureKind};
+use crate::socks5::ProxyStream;
+
+/// Largest reply kept from a single read.
+const REPLY_BUFFER: usize = 4096;
+
+/// What to send after connecting, and whether to wait for a reply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Payload {
+    pub bytes: Vec<u8>,
+    /// Wait for the first bytes of a reply before calling it a success
+    pub read_reply: bool,
+}
+
+impl Payload {
+    /// Parses a payload given on the command line:
+    ///   0x48454c4f          hex bytes
+    ///   HELO example\r\n    text, with \r \n \t \0 \\ and \xHH escapes
+    pub fn parse(spec: &str, read_reply: bool) -> Result<Self, String> {
+        let bytes = match spec.strip_prefix("0x") {
+            Some(hex) => parse_hex(hex)?,
+            None => unescape(spec)?,
+        };
+        if bytes.is_empty() {
+            return Err("empty payload".to_string());
+        }
+        Ok(Payload { bytes, read_reply })
+    }
+
+    /// Nothing to send: only read the first bytes the service sends.
+    pub fn banner() -> Self {
+        Payload {
+            bytes: Vec::new(),
+            read_reply: true,
+        }
+    }
+
+    /// Writes the payload and, if asked, reads the start of the reply.
+    /// `timeout` bounds the write and `read_timeout` the read.
+    pub(crate) fn exchange<S: Read + Write + Timeouts>(
+        &self,
+        stream: &mut S,
+        timeout: Duration,
+        read_timeout: Duration,
+    ) -> Result<Exchange, Failure> {
+        let failure = |e: io::Error, what: &str| Failure {
+            kind: FailureKind::classify(&e, false),
+            message: format!("connected, but {}: {}", what, e),
+        };
+        stream
+            .set_timeouts(read_timeout, timeout)
+            .map_err(|e| failure(e, "could not set timeouts"))?;
+
+        let start = Instant::now();
+        if !self.bytes.is_empty() {
+            stream
+                .write_all(&self.bytes)
+                .and_then(|_| stream.flush())
+                .map_err(|e| failure(e, "sending failed"))?;
+        }
+        if !self.read_reply {
+            return Ok(Exchange {
+                time: start.elapsed(),
+                sent: self.bytes.len(),
+                reply: None,
+            });
+        }
+
+        let what = if self.bytes.is_empty() { "banner" } else { "reply" };
+        let mut buf = vec![0u8; REPLY_BUFFER];
+        match stream.read(&mut buf) {
+            Ok(0) => Err(Failure {
+                kind: FailureKind::Other,
+                message: format!("connected, but the connection was closed without a {}", what),
+            }),
+            Ok(n) => {
+                buf.truncate(n);
+                Ok(Exchange {
+                    time: start.elapsed(),
+                    sent: self.bytes.len(),
+                    reply: Some(buf),
+                })
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                Err(Failure {
+                    kind: FailureKind::NoReply,
+                    message: format!(
+                        "connected, but no {} (read timeout, {}ms)",
+                        what,
+                        read_timeout.as_millis()
+                    ),
+                })
+            }
+            Err(e) => Err(failure(e, "reading the reply failed")),
+        }
+    }
+}
+
+/// Streams whose read and write timeouts can be set.
+pub(crate) trait Timeouts {
+    fn set_timeouts(&self, read: Duration, write: Duration) -> io::Result<()>;
+}
+
+impl Timeouts for TcpStream {
+    fn set_timeouts(&self, read: Duration, write: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(read))?;
+        self.set_write_timeout(Some(write))
+    }
+}
+
+impl Timeouts for ProxyStream {
+    fn set_timeouts(&self, read: Duration, write: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(read))?;
+        self.set_write_timeout(Some(write))
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in '0x{}'", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("invalid hex in '0x{}'", hex))
+        })
+        .collect()
+}
+
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut utf8 = [0u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(b) if hex.len() == 2 => bytes.push(b),
+                    _ => return Err(format!("invalid escape '\\x{}'", hex)),
+                }
+            }
+            Some(other) => return Err(format!("unknown escape '\\{}'", other)),
+            None => return Err("trailing '\\' in payload".to_string()),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Short printable form of a reply for the per-probe line.
+pub(crate) fn preview(reply: &[u8]) -> String {
+    const MAX: usize = 32;
+    let shown: String = reply
+        .iter()
+        .take(MAX)
+        .flat_map(|b| b.escape_ascii())
+        .map(char::from)
+        .collect();
+    if reply.len() > MAX {
+        format!("\"{}\"...", shown)
+    } else {
+        format!("\"{}\"", shown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn parse_hex_and_text() {
+        assert_eq!(Payload::parse("0x48454c4f", false).unwrap().bytes, b"HELO");
+        assert_eq!(
+            Payload::parse("GET / HTTP/1.0\\r\\n\\r\\n", true)
+                .unwrap()
+                .bytes,
+            b"GET / HTTP/1.0\r\n\r\n"
+        );
+        assert_eq!(
+            Payload::parse("a\\x00\\\\b", false).unwrap().bytes,
+            b"a\0\\b"
+        );
+        assert!(Payload::parse("0x123", false).is_err());
+        assert!(Payload::parse("0xzz", false).is_err());
+        assert!(Payload::parse("\\q", false).is_err());
+        assert!(Payload::parse("\\x4", false).is_err());
+        assert!(Payload::parse("", false).is_err());
+    }
+
+    #[test]
+    fn exchange_reads_the_reply() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut c, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4];
+            c.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"ping");
+            c.write_all(b"pong").unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let payload = Payload::parse("ping", true).unwrap();
+        let exchange = payload
+            .exchange(&mut stream, Duration::from_secs(2), Duration::from_secs(2))
+            .unwrap();
+        server.join().unwrap();
+        assert_eq!(exchange.sent, 4);
+        assert_eq!(exchange.reply.as_deref(), Some(&b"pong"[..]));
+    }
+
+    #[test]
+    fn exchange_times_out_on_a_silent_service() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let _accepted = listener.accept().unwrap();
+
+        let payload = Payload::parse("ping", true).unwrap();
+        let failure = payload
+            .exchange(&mut stream, Duration::from_secs(2), Duration::from_millis(50))
+            .unwrap_err();
+        assert_eq!(failure.kind, FailureKind::NoReply);
+        assert_eq!(
+            failure.message,
+            "connected, but no reply (read timeout, 50ms)"
+        );
+    }
+
+    #[test]
+    fn banner_is_read_without_sending() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut c, _) = listener.accept().unwrap();
+            c.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let exchange = Payload::banner()
+            .exchange(&mut stream, Duration::from_secs(2), Duration::from_secs(2))
+            .unwrap();
+        server.join().unwrap();
+        assert_eq!(exchange.sent, 0);
+        assert_eq!(exchange.reply.as_deref(), Some(&b"SSH-2.0-OpenSSH_9.6\r\n"[..]));
+    }
+
+    #[test]
+    fn reply_preview_is_escaped_and_short() {
+        assert_eq!(preview(b"OK\r\n"), "\"OK\\r\\n\"");
+        assert_eq!(preview(&[b'a'; 40]), format!("\"{}\"...", "a".repeat(32)));
+    }
+}
@@ -1,234 +1,3605 @@
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
 use socket2::{Domain, Protocol, Socket, Type};
+use std::fmt;
+use std::io::{Read, Write};
 use std::net::{IpAddr, SocketAddr, ToSocketAddrs, TcpStream};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::socks5::Socks5Proxy;
+pub use crate::builder::{BuildError, PingerBuilder};
+pub use crate::result::{
+    Change, Connection, Exchange, Failure, FailureKind, Outage, Outages, PingResult, PingStats,
+    Streak, TlsSession, DEFAULT_MAX_OUTAGES,
+};
+pub use crate::tls::TlsProbe;
+use crate::payload::{self, Payload, Timeouts};
+use crate::proxy_protocol::ProxyHeader;
+use crate::throughput::Throughput;
+use crate::socks5::{Socks5Proxy, Socks5Timings};
+use crate::stop::StopToken;
+use crate::style;
+use crate::template::{self, Record, Template};
+use crate::target::join_host_port;
 
+/// How often the pause between two probes checks for a stats request.
+const STATS_REQUEST_POLL: Duration = Duration::from_millis(100);
+
+/// Pings a TCP port: repeatedly connects to `address:port` (directly, from a
+/// given local IP, or through a SOCKS5 proxy) and records how long each
+/// connection takes to establish.
+///
+/// Every probe result goes through a list of observers (see
+/// [`Pinger::on_result`]). By default the first one prints the result to
+/// stdout as the CLI does; [`Pinger::with_silent`] removes it, so results
+/// are only recorded and can be read back with [`Pinger::stats`].
 pub struct Pinger {
     address: String,
     port: u16,
     timeout: Duration,
     proxy: Option<Socks5Proxy>,
     bind_addr: Option<IpAddr>,
-    attempted: u32,
-    connected: u32,
-    failed: u32,
-    times: Vec<f64>,
+    stats: PingStats,
+    compare: Option<Comparison>,
+    dual_stack: Option<DualStack>,
+    happy_eyeballs: Option<HappyEyeballs>,
+    rotation: Option<Rotation>,
+    /// Associate over SCTP rather than connect over TCP
+    sctp: bool,
+    /// Handshake once connected (--tls)
+    tls: Option<TlsProbe>,
+    silent: bool,
+    /// Printed as `[label]` in front of every line of output
+    label: Option<String>,
+    /// `seq=n/total` on result lines and a bar below them, in finite runs
+    progress: Option<Arc<Progress>>,
+    /// Layout of the per-probe lines (--format), instead of the usual one
+    format: Option<Template>,
+    /// A running count instead of the per-probe lines (--flood)
+    flood: Option<Arc<Flood>>,
+    /// Lines on changes of state only, instead of the per-probe ones
+    /// (--monitor)
+    monitor: Option<Arc<Monitor>>,
+    /// Probes in a row making a change of state, and outages kept
+    confirm: u32,
+    max_outages: usize,
+    interval: Duration,
+    ramp: Option<Ramp>,
+    /// The interval and ramp are never let below this (but --flood is)
+    min_interval: Duration,
+    /// Count the interval from the start of each round, not its end
+    strict_interval: bool,
+    /// End `run` once this many probes connected / failed
+    stop_after_success: Option<u32>,
+    stop_after_failure: Option<u32>,
+    /// Give up (and report it) once this many probes failed in total
+    max_failures: Option<u32>,
+    /// Loss percentages shown in yellow / red in the statistics
+    loss_warn: f64,
+    loss_crit: f64,
+    /// Print how long each step of a proxied connection took
+    timing_detail: bool,
+    /// Shown next to the port in the header (where it came from)
+    port_label: Option<String>,
+    /// Sent once connected (--send)
+    payload: Option<Payload>,
+    /// PROXY protocol header sent before anything else (--proxy-protocol)
+    proxy_header: Option<ProxyHeader>,
+    /// Data moved once connected (--throughput)
+    throughput: Option<Throughput>,
+    /// Extra lookups after a failed one, before a probe counts as failed
+    dns_retries: u32,
+    /// Lookups reused for a while rather than made for every probe
+    dns_cache: Option<DnsCache>,
+    /// Tell on stderr whether each lookup came from the cache
+    verbose: bool,
+    /// Longest a direct probe may take, lookups (and their retries)
+    /// and connect together
+    deadline: Option<Duration>,
+    /// Connections taking longer count as failures
+    max_latency: Option<Duration>,
+    /// Pause before the first probe of `run` (--startup-delay)
+    startup_delay: Option<Duration>,
+    /// Set from elsewhere (a SIGUSR1 handler) for `run` to print the
+    /// statistics so far
+    stats_request: Option<Arc<AtomicBool>>,
+    /// Called from the `run` loop, to tell it isn't stuck (a watchdog)
+    heartbeat: Option<Heartbeat>,
+    /// How long to wait for the reply or banner, when not the timeout
+    read_timeout: Option<Duration>,
+    /// Within a `run` without a count: a network outage is reported once
+    /// and probed less and less often until it ends
+    continuous: bool,
+    outage: Option<NetworkOutage>,
+    /// Called with every probe result. When not silent, the console
+    /// printer sits at index 0. Only ever accessed through `&mut self`
+    /// (`get_mut`); the Mutex just keeps `Pinger` Sync so probes can run
+    /// from scoped threads.
+    observers: Mutex<Vec<Observer>>,
 }
 
-impl Pinger {
-    pub fn new(
-        address: String,
-        port: u16,
-        timeout: Duration,
-        proxy: Option<Socks5Proxy>,
-        bind_addr: Option<IpAddr>,
-    ) -> Self {
-        Self {
-            address,
-            port,
-            timeout,
-            proxy,
-            bind_addr,
-            attempted: 0,
-            connected: 0,
-            failed: 0,
-            times: Vec::new(),
+/// Callback receiving each probe result as it happens.
+pub type Observer = Box<dyn FnMut(&PingResult) + Send>;
+
+/// The `run_with` callback, passed down to wherever results are produced.
+type Sink<'a> = &'a mut dyn FnMut(&PingResult);
+
+/// What [`Pinger::wait_for`] waits for, `confirm` probes in a row (at
+/// least 1), to ride out flapping.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Awaited {
+    /// Connections succeeding (--wait)
+    Open { confirm: u32 },
+    /// Connections refused or timing out (--wait-closed); other failures,
+    /// such as DNS errors, say nothing of the port and break the run
+    Closed { confirm: u32 },
+}
+
+impl Awaited {
+    /// Whether `result` is a probe in the awaited state.
+    fn matches(self, result: &PingResult) -> bool {
+        match (self, result) {
+            (Awaited::Open { .. }, result) => result.is_success(),
+            (Awaited::Closed { .. }, PingResult::Failed(f)) => {
+                matches!(f.kind, FailureKind::Refused | FailureKind::Timeout)
+            }
+            (Awaited::Closed { .. }, PingResult::Connected(_)) => false,
         }
     }
 
-    pub fn print_header(&self) {
-        println!();
-        let bind_info = match self.bind_addr {
-            Some(ip) => format!(" from  {}", ip.to_string().yellow()),
-            None => String::new(),
-        };
-        if let Some(ref proxy) = self.proxy {
-            println!(
-                "Connecting to  {}  on TCP  {}{}  via proxy  {}:{}:",
-                self.address.green(),
-                self.port.to_string().green(),
-                bind_info,
-                proxy.host.cyan(),
-                proxy.port.to_string().cyan()
-            );
-        } else {
-            println!(
-                "Connecting to  {}  on TCP  {}{}:",
-                self.address.green(),
-                self.port.to_string().green(),
-                bind_info
-            );
+    fn confirm(self) -> u32 {
+        match self {
+            Awaited::Open { confirm } | Awaited::Closed { confirm } => confirm.max(1),
         }
-        println!();
     }
+}
+
+/// How [`Pinger::wait_for`] ended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wait {
+    pub awaited: Awaited,
+    /// Whether the port got to the awaited state
+    pub reached: bool,
+    /// When it did: the start of the first probe of the confirming run
+    pub since: Option<SystemTime>,
+    /// Probes made
+    pub attempts: u32,
+    /// From the first probe to the end of the last
+    pub elapsed: Duration,
+    /// The last probe's result (`None` if stopped before any)
+    pub last: Option<PingResult>,
+}
+
+/// Iterator over probe results, from [`Pinger::iter`] or [`Pinger::raw_iter`].
+pub struct Iter<'a> {
+    pinger: &'a mut Pinger,
+    stop: StopToken,
+    done: u32,
+    paced: bool,
+}
+
+impl Iter<'_> {
+    /// Statistics gathered so far (the pinger's, so earlier runs count too).
+    pub fn stats(&self) -> &PingStats {
+        self.pinger.stats()
+    }
+}
 
-    fn resolve(&self) -> Option<SocketAddr> {
-        let target = format!("{}:{}", self.address, self.port);
-        match target.to_socket_addrs() {
-            Ok(mut addrs) => addrs.next(),
-            Err(_) => None,
+impl Iterator for Iter<'_> {
+    type Item = PingResult;
+
+    fn next(&mut self) -> Option<PingResult> {
+        if self.stop.is_stopped() {
+            return None;
+        }
+        if self.paced
+            && self.done > 0
+            && self.stop.wait_timeout(self.pinger.interval_after(self.done))
+        {
+            return None;
         }
+        let result = self.pinger.probe();
+        self.pinger.record(&result);
+        self.done += 1;
+        Some(result)
     }
+}
 
-    /// Opens a TCP connection to the target address.
-    /// If a local interface is specified (via the -i flag), the socket is bound
-    /// to that IP before connecting, which forces traffic through the desired
-    /// network interface (e.g. VPN, Ethernet, WiFi...).
-    fn connect_with_bind(&self, addr: &SocketAddr) -> std::io::Result<TcpStream> {
-        match self.bind_addr {
-            Some(local_ip) => {
-                let domain = if addr.is_ipv4() {
-                    Domain::IPV4
-                } else {
-                    Domain::IPV6
+/// Formats probe results as the lines printed by the CLI.
+#[derive(Clone)]
+pub struct ConsoleRenderer {
+    address: String,
+    port: u16,
+    protocol: &'static str,
+    /// Shows the TLS version and cipher too
+    verbose: bool,
+}
+
+impl ConsoleRenderer {
+    /// Formats a probe result as a single line, colored like the CLI output.
+    pub fn render(&self, result: &PingResult) -> String {
+        match result {
+            PingResult::Connected(c) => {
+                let via = match c.proxy {
+                    Some(transport) => format!("  proxy={}", transport.cyan()),
+                    None => String::new(),
+                };
+                let exchange = match c.exchange {
+                    Some(ref x) => {
+                        let time = format!("{:.2}ms", x.time.as_secs_f64() * 1000.0).green();
+                        match x.reply {
+                            Some(ref reply) => {
+                                format!("  reply={} {}", time, payload::preview(reply))
+                            }
+                            None => format!("  sent={}B in {}", x.sent, time),
+                        }
+                    }
+                    None => String::new(),
+                };
+                let transfer = match c.transfer {
+                    Some(ref t) => format!(
+                        "  {}={} ({:.2}MB in {:.2}s)",
+                        t.direction.as_str(),
+                        format!("{:.2}MB/s", t.megabytes_per_sec()).green(),
+                        t.bytes as f64 / 1e6,
+                        t.time.as_secs_f64()
+                    ),
+                    None => String::new(),
                 };
-                let socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
-                let local_addr: SocketAddr = SocketAddr::new(local_ip, 0);
-                socket.bind(&local_addr.into())?;
-                socket.connect_timeout(&(*addr).into(), self.timeout)?;
-                Ok(TcpStream::from(socket))
+                let tls = match c.tls {
+                    Some(ref s) => {
+                        let mut tls = format!(
+                            "  tls={}",
+                            format!("{:.2}ms", s.handshake.as_secs_f64() * 1000.0).green()
+                        );
+                        if self.verbose {
+                            tls += &format!(" ({}, {})", s.version, s.cipher);
+                        }
+                        if let Some(days) = s.cert_expires_in_days {
+                            let text = format!("{}d", days);
+                            let text = match days {
+                                ..=13 => text.red(),
+                                14..=29 => text.yellow(),
+                                _ => text.green(),
+                            };
+                            tls += &format!("  cert_expires_in={}", text);
+                        }
+                        tls
+                    }
+                    None => String::new(),
+                };
+                format!(
+                    "Connected to {}: time={}  protocol={}  port={}{}{}{}{}",
+                    self.address.green(),
+                    format!("{:.2}ms", c.time.as_secs_f64() * 1000.0).green(),
+                    self.protocol.green(),
+                    self.port.to_string().green(),
+                    via,
+                    tls,
+                    exchange,
+                    transfer
+                )
             }
-            None => TcpStream::connect_timeout(addr, self.timeout),
+            PingResult::Failed(f) => format!(
+                "Connection to {} {}: {}",
+                self.address.green(),
+                "failed".red(),
+                f.message
+            ),
         }
     }
+}
+
+/// Where a finite run is at (`Pinger::with_progress`): tags result lines
+/// with `seq=n/total` and keeps a bar on the last line of the terminal,
+/// which other lines are printed above.
+#[derive(Default)]
+struct Progress {
+    /// Round underway, counted from 1
+    seq: AtomicU32,
+    /// Rounds finished
+    done: AtomicU32,
+    /// 0 outside of a run with a count: no tags, no bar
+    total: AtomicU32,
+}
 
-    fn ping(&mut self) {
-        self.attempted += 1;
+/// Width of the bar itself, between the brackets.
+const PROGRESS_BAR_WIDTH: u32 = 30;
 
-        let start = Instant::now();
-        let result = if let Some(ref proxy) = self.proxy {
-            // Route through the SOCKS5 proxy to reach the target
-            proxy.connect(&self.address, self.port, self.timeout)
+impl Progress {
+    fn start(&self, total: u32) {
+        self.seq.store(0, Ordering::Relaxed);
+        self.done.store(0, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    /// "  seq=12/500" during a run with a count, empty otherwise.
+    fn tag(&self) -> String {
+        match self.total.load(Ordering::Relaxed) {
+            0 => String::new(),
+            total => format!("  seq={}/{}", self.seq.load(Ordering::Relaxed), total),
+        }
+    }
+
+    /// "[#########.....]  12/500 (2%)", without the colors.
+    fn bar(&self) -> String {
+        let total = self.total.load(Ordering::Relaxed).max(1);
+        let done = self.done.load(Ordering::Relaxed).min(total);
+        let filled = (done as u64 * PROGRESS_BAR_WIDTH as u64 / total as u64) as usize;
+        let glyphs = style::glyphs();
+        format!(
+            "[{}{}]  {}/{} ({}%)",
+            glyphs.bar_full.repeat(filled),
+            glyphs.bar_empty.repeat(PROGRESS_BAR_WIDTH as usize - filled),
+            done,
+            total,
+            done as u64 * 100 / total as u64
+        )
+    }
+
+    /// Prints `line`, redrawing the bar below it while a run is underway.
+    fn println(&self, line: impl fmt::Display) {
+        if self.total.load(Ordering::Relaxed) == 0 {
+            return println!("{}", line);
+        }
+        let mut out = std::io::stdout().lock();
+        let _ = write!(out, "{}{}\n{}", self.erase(), line, self.bar().cyan());
+        let _ = out.flush();
+    }
+
+    /// Draws the bar again, after `done` changed.
+    fn redraw(&self) {
+        let mut out = std::io::stdout().lock();
+        let _ = write!(out, "{}{}", self.erase(), self.bar().cyan());
+        let _ = out.flush();
+    }
+
+    /// Ends the run: the bar goes away.
+    fn finish(&self) {
+        let erase = self.erase();
+        if self.total.swap(0, Ordering::Relaxed) > 0 {
+            let mut out = std::io::stdout().lock();
+            let _ = write!(out, "{}", erase);
+            let _ = out.flush();
+        }
+    }
+
+    /// Goes back to the start of the bar's line and erases it: with an ANSI
+    /// escape, or by writing spaces over it in ASCII mode. The bar only
+    /// grows, so the current one is at least as long as the one drawn.
+    fn erase(&self) -> String {
+        if style::is_ascii() {
+            format!("\r{}\r", " ".repeat(self.bar().len()))
         } else {
-            // Direct connection, no proxy
-            let addr = match self.resolve() {
-                Some(a) => a,
-                None => {
-                    self.failed += 1;
-                    println!(
-                        "Connection to {} {}: {}",
-                        self.address.green(),
-                        "failed".red(),
-                        "could not resolve address"
-                    );
-                    return;
-                }
-            };
-            self.connect_with_bind(&addr)
+            "\r\x1b[2K".to_string()
+        }
+    }
+}
+
+/// The line --flood keeps redrawing in place of a line per probe: connects
+/// made, how many failed, and the rate.
+#[derive(Default)]
+struct Flood {
+    sent: AtomicU32,
+    failed: AtomicU32,
+    /// Start of the run, last redraw and length of the line drawn
+    drawn: Mutex<Option<(Instant, Instant, usize)>>,
+}
+
+/// Redrawing the counter after every connect would keep the terminal busier
+/// than the target.
+const FLOOD_REDRAW: Duration = Duration::from_millis(100);
+
+impl Flood {
+    fn start(&self) {
+        self.sent.store(0, Ordering::Relaxed);
+        self.failed.store(0, Ordering::Relaxed);
+        let now = Instant::now();
+        *self.drawn.lock().unwrap() = Some((now, now, 0));
+    }
+
+    fn record(&self, result: &PingResult, label: Option<&str>) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        if !result.is_success() {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.draw(label, false);
+    }
+
+    /// "Flooding: 12345 connects, 3 failed (4115/s)"; redrawn over the
+    /// previous one (padded with spaces if shorter), at most every
+    /// `FLOOD_REDRAW` unless `force`d.
+    fn draw(&self, label: Option<&str>, force: bool) {
+        let mut drawn = self.drawn.lock().unwrap();
+        let Some((start, ref mut last, ref mut len)) = *drawn else {
+            return;
         };
+        if !force && last.elapsed() < FLOOD_REDRAW {
+            return;
+        }
+        let sent = self.sent.load(Ordering::Relaxed);
+        let failed = self.failed.load(Ordering::Relaxed);
+        let rate = sent as f64 / start.elapsed().as_secs_f64().max(0.001);
+        let line = labelled(
+            label,
+            format!("Flooding: {} connects, {} failed ({:.0}/s)", sent, failed, rate),
+        );
+        let line = style::text(&line);
+        let mut out = std::io::stdout().lock();
+        let _ = write!(out, "\r{}{}", line, " ".repeat(len.saturating_sub(line.len())));
+        let _ = out.flush();
+        *last = Instant::now();
+        *len = line.len();
+    }
 
-        match result {
-            Ok(conn) => {
-                let elapsed = start.elapsed();
-                drop(conn);
-                self.connected += 1;
+    /// Draws the final count and ends its line.
+    fn finish(&self, label: Option<&str>) {
+        self.draw(label, true);
+        if self.drawn.lock().unwrap().take().is_some() {
+            println!();
+        }
+    }
+}
 
-                let ms = elapsed.as_secs_f64() * 1000.0;
-                self.times.push(ms);
+/// Prints a line of output, above the progress bar while one is drawn.
+fn print_line(progress: Option<&Progress>, line: impl fmt::Display) {
+    let line = line.to_string();
+    let line = style::text(&line);
+    match progress {
+        Some(p) => p.println(line),
+        None => println!("{}", line),
+    }
+}
 
-                let via = if self.proxy.is_some() {
-                    format!("  proxy={}", "SOCKS5".cyan())
-                } else {
-                    String::new()
-                };
+/// The lines of --monitor, in place of a line per probe: one whenever the
+/// target goes up or down (once `confirm` probes in a row agree), and one
+/// every `heartbeat` in between. The changes are listed again with the
+/// statistics.
+struct Monitor {
+    heartbeat: Option<Duration>,
+    state: Mutex<MonitorState>,
+}
 
-                println!(
-                    "Connected to {}: time={}  protocol={}  port={}{}",
-                    self.address.green(),
-                    format!("{:.2}ms", ms).green(),
-                    "TCP".green(),
-                    self.port.to_string().green(),
-                    via
-                );
-            }
-            Err(e) => {
-                self.failed += 1;
-                println!(
-                    "Connection to {} {}: {}",
-                    self.address.green(),
-                    "failed".red(),
-                    e
-                );
-            }
+#[derive(Default)]
+struct MonitorState {
+    /// Up or down, by the rules of the outages in the statistics
+    tracker: Outages,
+    /// Every state taken, the first one included
+    transitions: Vec<Transition>,
+    /// When the last line was printed, and the probes and failures since
+    last_line: Option<Instant>,
+    probes: u32,
+    failed: u32,
+}
+
+/// A state taken by the target, as --monitor saw it.
+struct Transition {
+    change: Change,
+    /// The failure that confirmed it, going down
+    reason: Option<String>,
+}
+
+impl Transition {
+    /// "DOWN after 41m 7s up: connection timed out", "UP".
+    fn describe(&self) -> String {
+        let (state, previous) = match self.change.up {
+            true => ("UP".green(), "down"),
+            false => ("DOWN".red(), "up"),
+        };
+        let mut text = state.to_string();
+        if let Some(after) = self.change.after {
+            let lasted = template::duration_text(after.as_secs_f64());
+            text += &format!(" after {} {}", lasted, previous);
+        }
+        if let Some(ref reason) = self.reason {
+            text += &format!(": {}", reason);
         }
+        text
     }
+}
 
-    /// Pause between each ping. Split into small 100ms chunks so we can
-    /// react quickly when the user presses Ctrl+C.
-    fn sleep_interruptible(duration: Duration, stop: &Arc<AtomicBool>) {
-        let start = Instant::now();
-        while start.elapsed() < duration {
-            if stop.load(Ordering::SeqCst) {
-                return;
-            }
-            std::thread::sleep(Duration::from_millis(100));
+impl Monitor {
+    fn new(heartbeat: Option<Duration>) -> Self {
+        Monitor {
+            heartbeat: heartbeat.filter(|h| !h.is_zero()),
+            state: Mutex::default(),
         }
     }
 
-    pub fn run(&mut self, count: u32, stop: &Arc<AtomicBool>) {
-        if count > 0 {
-            for i in 0..count {
-                if stop.load(Ordering::SeqCst) {
-                    break;
-                }
-                self.ping();
-                if i < count - 1 && !stop.load(Ordering::SeqCst) {
-                    Self::sleep_interruptible(Duration::from_secs(1), stop);
-                }
+    /// Forgets a previous run. Changes count once `confirm` probes agree.
+    fn start(&self, confirm: u32) {
+        *self.state.lock().unwrap() = MonitorState {
+            tracker: Outages::new(confirm, 1),
+            last_line: Some(Instant::now()),
+            ..Default::default()
+        };
+    }
+
+    /// Takes a probe result, printing a line for `target` when it changes
+    /// the state or the heartbeat is due.
+    fn record(
+        &self,
+        result: &PingResult,
+        target: &str,
+        label: Option<&str>,
+        progress: Option<&Progress>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let now = SystemTime::now();
+        state.probes += 1;
+        if !result.is_success() {
+            state.failed += 1;
+        }
+        if let Some(change) = state.tracker.record(result.is_success(), now) {
+            let reason = match result {
+                PingResult::Failed(f) => Some(f.message.clone()),
+                PingResult::Connected(_) => None,
+            };
+            let transition = Transition { change, reason };
+            let line = format!(
+                "{}  {} is {}",
+                template::timestamp(change.at),
+                target.green(),
+                transition.describe()
+            );
+            print_line(progress, labelled(label, line));
+            state.transitions.push(transition);
+            state.last_line = Some(Instant::now());
+            state.probes = 0;
+            state.failed = 0;
+            return;
+        }
+        let due = match (self.heartbeat, state.last_line) {
+            (Some(every), Some(last)) => last.elapsed() >= every,
+            _ => false,
+        };
+        if due {
+            let since = match state.tracker.state() {
+                Some((true, since)) => format!("{} for {}", "UP".green(), lasted(since, now)),
+                Some((false, since)) => format!("{} for {}", "DOWN".red(), lasted(since, now)),
+                None => "not settled yet".yellow().to_string(),
+            };
+            let line = format!(
+                "{}  {} still {}, {} probes since the last line, {} failed",
+                template::timestamp(now),
+                target.green(),
+                since,
+                state.probes,
+                state.failed
+            );
+            print_line(progress, labelled(label, line));
+            state.last_line = Some(Instant::now());
+            state.probes = 0;
+            state.failed = 0;
+        }
+    }
+}
+
+/// How long from `since` to `now`, as `duration_text`.
+fn lasted(since: SystemTime, now: SystemTime) -> String {
+    template::duration_text(now.duration_since(since).unwrap_or_default().as_secs_f64())
+}
+
+/// `line` behind `[label]`, or as is without a label.
+fn labelled(label: Option<&str>, line: impl fmt::Display) -> String {
+    match label {
+        Some(label) => format!("{} {}", format!("[{}]", label).bold(), line),
+        None => line.to_string(),
+    }
+}
+
+/// Default loss percentage above which the statistics show it in yellow.
+pub const LOSS_WARN_PERCENT: f64 = 5.0;
+/// Default loss percentage above which the statistics show it in red.
+pub const LOSS_CRIT_PERCENT: f64 = 20.0;
+
+/// Colors `text` by how bad `loss` (a percentage) is.
+fn loss_colored(text: String, loss: f64, warn: f64, crit: f64) -> colored::ColoredString {
+    if loss > crit {
+        text.red()
+    } else if loss > warn {
+        text.yellow()
+    } else {
+        text.green()
+    }
+}
+
+/// Lays out rows of cells in columns as wide as their widest cell,
+/// indented by two spaces. Widths are measured on the text without color
+/// codes, so colored and plain output line up the same way.
+fn render_table(rows: &[Vec<ColoredString>]) -> Vec<String> {
+    let width = |cell: &ColoredString| cell.chars().count();
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            match widths.get_mut(i) {
+                Some(w) => *w = (*w).max(width(cell)),
+                None => widths.push(width(cell)),
             }
-        } else {
-            loop {
-                if stop.load(Ordering::SeqCst) {
-                    break;
-                }
-                self.ping();
-                if !stop.load(Ordering::SeqCst) {
-                    Self::sleep_interruptible(Duration::from_secs(1), stop);
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let mut line = String::from(" ");
+            for (i, cell) in row.iter().enumerate() {
+                line.push(' ');
+                line.push_str(&cell.to_string());
+                if i + 1 < row.len() {
+                    line.push_str(&" ".repeat(widths[i] - width(cell) + 1));
                 }
             }
+            line
+        })
+        .collect()
+}
+
+/// The --timing-detail line: every step of a proxied connection.
+fn render_timings(t: &Socks5Timings) -> String {
+    let ms = |d: Duration| format!("{:.2}ms", d.as_secs_f64() * 1000.0);
+    let mut steps = vec![format!("proxy connect={}", ms(t.connect))];
+    if let Some(tls) = t.tls {
+        steps.push(format!("tls={}", ms(tls)));
+    }
+    steps.push(format!("method={}", ms(t.method)));
+    if let Some(auth) = t.auth {
+        steps.push(format!("auth={}", ms(auth)));
+    }
+    steps.push(format!("connect reply={}", ms(t.request)));
+    steps.join("  ").dimmed().to_string()
+}
+
+/// Interval schedule for --ramp: start at `start`, halve the interval
+/// every `every` pings, never going below `floor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ramp {
+    pub start: Duration,
+    pub floor: Duration,
+    pub every: u32,
+}
+
+impl Ramp {
+    /// Parses `<start_ms>:<floor_ms>:<every>`, e.g. `1000:50:10`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        let [start, floor, every] = parts[..] else {
+            return Err(format!(
+                "invalid ramp '{}'. Expected format: <start_ms>:<floor_ms>:<every>",
+                spec
+            ));
+        };
+        let number = |what: &str, value: &str| {
+            value
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("invalid {} '{}' in ramp '{}'", what, value, spec))
+        };
+        let start = number("start", start)?;
+        let floor = number("floor", floor)?;
+        let every = number("step", every)?;
+        if start == 0 {
+            return Err(format!("ramp start must be greater than 0 in '{}'", spec));
         }
-        println!();
+        if floor > start {
+            return Err(format!(
+                "ramp floor ({}ms) must not exceed the start ({}ms)",
+                floor, start
+            ));
+        }
+        let every = u32::try_from(every)
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| format!("ramp step must be between 1 and {} in '{}'", u32::MAX, spec))?;
+        Ok(Ramp {
+            start: Duration::from_millis(start),
+            floor: Duration::from_millis(floor),
+            every,
+        })
     }
 
-    pub fn print_stats(&self) {
-        let fail_pct = if self.attempted > 0 {
-            (self.failed as f64 / self.attempted as f64) * 100.0
+    /// Pause to make after `done` pings.
+    pub fn interval_after(&self, done: u32) -> Duration {
+        match 1u32.checked_shl(done / self.every) {
+            Some(divisor) => (self.start / divisor).max(self.floor),
+            // Halved more than 31 times: long past the floor
+            None => self.floor,
+        }
+    }
+}
+
+/// State for --dual-stack: each round connects once over IPv4 and once over
+/// IPv6. `Pinger::stats` gets both, these get one family each.
+#[derive(Default)]
+struct DualStack {
+    v4: PingStats,
+    v6: PingStats,
+}
+
+/// State for source rotation (-i with several IPs): each round connects
+/// from the next source in turn. `Pinger::stats` gets every round, these
+/// one source each.
+struct Rotation {
+    sources: Vec<(IpAddr, PingStats)>,
+    next: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    fn of(addr: &SocketAddr) -> Self {
+        if addr.is_ipv4() {
+            Family::V4
         } else {
-            0.0
-        };
+            Family::V6
+        }
+    }
 
-        println!("Connection statistics:");
-        println!(
-            "\tAttempted = {}, Connected = {}, Failed = {}",
-            self.attempted.to_string().green(),
-            self.connected.to_string().green(),
-            format!("{} ({:.1}%)", self.failed, fail_pct).green()
-        );
+    fn matches(self, addr: &SocketAddr) -> bool {
+        match self {
+            Family::V4 => addr.is_ipv4(),
+            Family::V6 => addr.is_ipv6(),
+        }
+    }
 
-        if !self.times.is_empty() {
-            let min = self
-                .times
-                .iter()
-                .cloned()
-                .fold(f64::INFINITY, f64::min);
-            let max = self
-                .times
-                .iter()
-                .cloned()
-                .fold(f64::NEG_INFINITY, f64::max);
-            let avg: f64 = self.times.iter().sum::<f64>() / self.times.len() as f64;
-
-            println!("Approximate connection times:");
-            println!(
-                "\tMinimum = {}, Maximum = {}, Average = {}",
-                format!("{:.2}ms", min).green(),
-                format!("{:.2}ms", max).green(),
-                format!("{:.2}ms", avg).green()
-            );
+    /// Prefix of the result lines
+    fn tag(self) -> &'static str {
+        match self {
+            Family::V4 => "[v4]",
+            Family::V6 => "[v6]",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Family::V4 => "IPv4",
+            Family::V6 => "IPv6",
+        }
+    }
+}
+
+/// How long the IPv6 attempt gets a head start in --happy-eyeballs mode
+/// (the "Connection Attempt Delay" of RFC 8305).
+pub const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+/// Pause before looking the target up again after a failed lookup
+/// (--dns-retries).
+pub const DNS_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// --dns-cache: the addresses of the last successful lookup, reused until
+/// they are `ttl` old.
+struct DnsCache {
+    ttl: Duration,
+    entry: Mutex<Option<(Vec<SocketAddr>, Instant)>>,
+}
+
+/// Shortest pause between two probes of `run` unless `with_min_interval`
+/// says otherwise, whatever the interval or ramp ask for: a guard against
+/// flooding a host by mistake.
+pub const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Longest pause between probes while the local network is down, unless
+/// the interval is longer.
+pub const NETWORK_DOWN_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// The local network being down, in a continuous run: probes go on,
+/// doubling the pause each time, but their failures aren't printed.
+struct NetworkOutage {
+    since: Instant,
+    backoff: Duration,
+}
+
+/// `with_heartbeat`: the callback, and when it last ran.
+struct Heartbeat {
+    every: Duration,
+    beat: Arc<dyn Fn() + Send + Sync>,
+    last: Instant,
+}
+
+/// --happy-eyeballs tally: which family won each successful round.
+#[derive(Default)]
+struct HappyEyeballs {
+    v4_wins: u32,
+    v6_wins: u32,
+}
+
+/// Connects to `addr`, from `bind_addr` when set. A free function so
+/// connects can run on their own threads.
+pub(crate) fn connect_from(
+    bind_addr: Option<IpAddr>,
+    addr: &SocketAddr,
+    timeout: Duration,
+) -> std::io::Result<TcpStream> {
+    match bind_addr {
+        Some(local_ip) => {
+            let domain = if addr.is_ipv4() {
+                Domain::IPV4
+            } else {
+                Domain::IPV6
+            };
+            let socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
+            let local_addr: SocketAddr = SocketAddr::new(local_ip, 0);
+            socket.bind(&local_addr.into())?;
+            socket.connect_timeout(&(*addr).into(), timeout)?;
+            Ok(TcpStream::from(socket))
+        }
+        None => TcpStream::connect_timeout(addr, timeout),
+    }
+}
+
+/// A direct connection: TCP, or an SCTP association (`with_sctp`).
+enum Stream {
+    Tcp(TcpStream),
+    Sctp(Socket),
+}
+
+impl Stream {
+    fn open(
+        sctp: bool,
+        bind_addr: Option<IpAddr>,
+        addr: &SocketAddr,
+        timeout: Duration,
+    ) -> std::io::Result<Self> {
+        match sctp {
+            true => associate_from(bind_addr, addr, timeout).map(Stream::Sctp),
+            false => connect_from(bind_addr, addr, timeout).map(Stream::Tcp),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            Stream::Sctp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            Stream::Sctp(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            Stream::Sctp(s) => s.flush(),
+        }
+    }
+}
+
+impl Timeouts for Stream {
+    fn set_timeouts(&self, read: Duration, write: Duration) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.set_timeouts(read, write),
+            Stream::Sctp(s) => {
+                s.set_read_timeout(Some(read))?;
+                s.set_write_timeout(Some(write))
+            }
         }
     }
 }
+
+/// Sets up an SCTP association with `addr` (one-to-one style, as a stream
+/// socket), from `bind_addr` when set.
+#[cfg(target_os = "linux")]
+fn associate_from(
+    bind_addr: Option<IpAddr>,
+    addr: &SocketAddr,
+    timeout: Duration,
+) -> std::io::Result<Socket> {
+    let socket = Socket::new(
+        Domain::for_address(*addr),
+        Type::STREAM,
+        Some(Protocol::SCTP),
+    )?;
+    if let Some(local_ip) = bind_addr {
+        socket.bind(&SocketAddr::new(local_ip, 0).into())?;
+    }
+    socket.connect_timeout(&(*addr).into(), timeout)?;
+    Ok(socket)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn associate_from(
+    _bind_addr: Option<IpAddr>,
+    _addr: &SocketAddr,
+    _timeout: Duration,
+) -> std::io::Result<Socket> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "SCTP is only supported on Linux",
+    ))
+}
+
+/// Whether SCTP sockets can be opened here: on Linux, with SCTP in the
+/// kernel (the `sctp` module loaded, or loadable).
+pub fn sctp_supported() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::SCTP)).is_ok()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        false
+    }
+}
+
+/// A failed TLS handshake: timed out, or `FailureKind::Tls`.
+fn tls_failure(e: &std::io::Error) -> Failure {
+    match FailureKind::classify(e, false) {
+        FailureKind::Timeout => Failure {
+            kind: FailureKind::Timeout,
+            message: format!("TLS handshake timed out ({})", e),
+        },
+        _ => Failure {
+            kind: FailureKind::Tls,
+            message: match crate::tls::is_certificate_error(e) {
+                true => format!(
+                    "TLS: {} (--ca-cert for a private CA, --insecure to skip the check)",
+                    e
+                ),
+                false => format!("TLS handshake failed: {}", e),
+            },
+        },
+    }
+}
+
+/// "once", "twice"... for messages.
+fn times(n: u32) -> String {
+    match n {
+        1 => "once".to_string(),
+        2 => "twice".to_string(),
+        n => format!("{} times", n),
+    }
+}
+
+/// State for --compare-direct: each round measures the target both through
+/// the proxy (kept in `Pinger::stats`) and directly (kept here).
+struct Comparison {
+    concurrent: bool,
+    direct: PingStats,
+    /// proxy - direct, for rounds where both connects succeeded
+    overheads: Vec<f64>,
+}
+
+impl Pinger {
+    /// Starts configuring a pinger for `address:port`; see
+    /// [`PingerBuilder`]. Unlike `new` and the `with_*` methods, the builder
+    /// rejects options that don't make sense together.
+    pub fn builder(address: impl Into<String>, port: u16) -> PingerBuilder {
+        PingerBuilder::new(address.into(), port)
+    }
+
+    /// Creates a pinger for `address:port`. `address` may be an IP or a
+    /// domain name; with a proxy it's passed to the proxy unresolved.
+    /// `bind_addr` forces the local source IP (and thus the interface).
+    pub fn new(
+        address: String,
+        port: u16,
+        timeout: Duration,
+        proxy: Option<Socks5Proxy>,
+        bind_addr: Option<IpAddr>,
+    ) -> Self {
+        Self {
+            address,
+            port,
+            timeout,
+            proxy,
+            bind_addr,
+            stats: PingStats::default(),
+            compare: None,
+            dual_stack: None,
+            sctp: false,
+            tls: None,
+            happy_eyeballs: None,
+            rotation: None,
+            // No console printer yet: with_silent(false) below adds it
+            silent: true,
+            label: None,
+            progress: None,
+            format: None,
+            flood: None,
+            monitor: None,
+            confirm: 1,
+            max_outages: DEFAULT_MAX_OUTAGES,
+            interval: Duration::from_secs(1),
+            ramp: None,
+            min_interval: MIN_INTERVAL,
+            strict_interval: false,
+            stop_after_success: None,
+            stop_after_failure: None,
+            max_failures: None,
+            loss_warn: LOSS_WARN_PERCENT,
+            loss_crit: LOSS_CRIT_PERCENT,
+            timing_detail: false,
+            port_label: None,
+            payload: None,
+            proxy_header: None,
+            throughput: None,
+            dns_retries: 0,
+            dns_cache: None,
+            verbose: false,
+            deadline: None,
+            startup_delay: None,
+            stats_request: None,
+            heartbeat: None,
+            max_latency: None,
+            read_timeout: None,
+            continuous: false,
+            outage: None,
+            observers: Mutex::new(Vec::new()),
+        }
+        .with_silent(false)
+    }
+
+    /// Don't print anything while pinging; results are only recorded
+    /// (and passed to the observers registered with `on_result`).
+    pub fn with_silent(mut self, silent: bool) -> Self {
+        self.set_silent(silent);
+        self
+    }
+
+    fn set_silent(&mut self, silent: bool) {
+        match (self.silent, silent) {
+            (false, true) => drop(self.observers_mut().remove(0)),
+            (true, false) => {
+                let renderer = self.renderer();
+                let label = self.label.clone();
+                let progress = self.progress.clone();
+                let format = self.format.clone();
+                let flood = self.flood.clone();
+                let monitor = self.monitor.clone();
+                let mut seq = 0;
+                self.observers_mut().insert(
+                    0,
+                    Box::new(move |result: &PingResult| {
+                        seq += 1;
+                        if let Some(ref flood) = flood {
+                            return flood.record(result, label.as_deref());
+                        }
+                        if let Some(ref monitor) = monitor {
+                            let target = join_host_port(&renderer.address, renderer.port);
+                            return monitor.record(
+                                result,
+                                &target,
+                                label.as_deref(),
+                                progress.as_deref(),
+                            );
+                        }
+                        if let Some(ref template) = format {
+                            let line = template.render(&Record {
+                                at: SystemTime::now(),
+                                target: &renderer.address,
+                                port: renderer.port,
+                                seq,
+                                label: label.as_deref(),
+                                result,
+                            });
+                            return print_line(progress.as_deref(), line);
+                        }
+                        let line = match progress {
+                            Some(ref p) => format!("{}{}", renderer.render(result), p.tag()),
+                            None => renderer.render(result),
+                        };
+                        print_line(progress.as_deref(), labelled(label.as_deref(), line));
+                    }),
+                );
+            }
+            _ => {}
+        }
+        self.silent = silent;
+    }
+
+    /// Prefixes every line printed (results, header, statistics...) with
+    /// `[label]`, to tell apart runs sharing a terminal or log (--label).
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self.refresh_printer();
+        self
+    }
+
+    /// In runs with a count, tags each result line with `seq=n/total` and
+    /// keeps a progress bar below the output, erased once the run ends or
+    /// is stopped. Meant for terminals: the bar is redrawn with ANSI
+    /// escapes. Nothing changes when silent.
+    pub fn with_progress(mut self) -> Self {
+        self.progress = Some(Arc::default());
+        self.refresh_printer();
+        self
+    }
+
+    /// Prints each probe's line from `template` instead of the usual layout
+    /// (--format). Those lines are never colored, nor prefixed with the
+    /// label or followed by `seq=n/total`: `{label}` and `{seq}` are there
+    /// for that. Only the plain probe lines change: --compare-direct,
+    /// --dual-stack and --happy-eyeballs keep their own.
+    pub fn with_format(mut self, template: Template) -> Self {
+        self.format = Some(template);
+        self.refresh_printer();
+        self
+    }
+
+    /// Probes back to back, with no pause, showing a running count of
+    /// connects and failures instead of a line per probe (--flood, like
+    /// `ping -f`), for stress tests. Replaces any interval or ramp; meant
+    /// for hosts of one's own.
+    pub fn with_flood(mut self) -> Self {
+        self.flood = Some(Arc::default());
+        self.interval = Duration::ZERO;
+        self.ramp = None;
+        self.refresh_printer();
+        self
+    }
+
+    /// Prints a line only when the target goes up or down, with the time
+    /// and how long the previous state lasted, instead of a line per probe
+    /// (--monitor), for watches over days. Changes follow the rules of the
+    /// outages (`with_confirm`). With a `heartbeat`, a line tells every so
+    /// often that the state holds, and how many probes were made meanwhile.
+    /// `print_stats` then lists every change. Only the plain probe lines
+    /// change, as with `with_format`.
+    pub fn with_monitor(mut self, heartbeat: Option<Duration>) -> Self {
+        self.monitor = Some(Arc::new(Monitor::new(heartbeat)));
+        self.refresh_printer();
+        self
+    }
+
+    /// Only counts the target as gone up or down once `confirm` probes in
+    /// a row agree (at least 1; 1 by default), so a lost SYN here and there
+    /// doesn't make an outage, nor a --monitor line (--confirm). The change
+    /// dates from the first of them.
+    pub fn with_confirm(mut self, confirm: u32) -> Self {
+        self.confirm = confirm.max(1);
+        self.stats.outages = Outages::new(self.confirm, self.max_outages);
+        self
+    }
+
+    /// Keeps the last `limit` outages only (`DEFAULT_MAX_OUTAGES` by
+    /// default), counting the earlier ones, so a link going up and down all
+    /// day doesn't fill the memory (--max-outages).
+    pub fn with_max_outages(mut self, limit: usize) -> Self {
+        self.max_outages = limit.max(1);
+        self.stats.outages = Outages::new(self.confirm, self.max_outages);
+        self
+    }
+
+    /// The console printer has its own copy of the label, progress, format
+    /// and renderer: replaces it after they changed.
+    fn refresh_printer(&mut self) {
+        if !self.silent {
+            self.set_silent(true);
+            self.set_silent(false);
+        }
+    }
+
+    /// Prints a line of output, behind the label if any.
+    fn say(&self, line: impl fmt::Display) {
+        print_line(self.progress.as_deref(), labelled(self.label.as_deref(), line));
+    }
+
+    /// `seq=n/total` for the lines of the round underway (`with_progress`).
+    fn seq_tag(&self) -> String {
+        self.progress.as_ref().map(|p| p.tag()).unwrap_or_default()
+    }
+
+    /// Registers a callback invoked with every probe result, in
+    /// registration order, after the statistics have been updated.
+    ///
+    /// Observers run synchronously on the probing thread: a slow observer
+    /// delays the next probe by as long as it takes. Anything that may block
+    /// (network, disk, locks) should hand the result off instead, e.g. with
+    /// [`Pinger::on_result_channel`].
+    ///
+    /// With --compare-direct, observers see the proxied result, then the
+    /// direct one, for every round.
+    pub fn on_result(&mut self, observer: impl FnMut(&PingResult) + Send + 'static) {
+        self.observers_mut().push(Box::new(observer));
+    }
+
+    fn observers_mut(&mut self) -> &mut Vec<Observer> {
+        self.observers
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Sends a copy of every probe result to `tx`. Sending never blocks,
+    /// so a slow consumer can't stall probing; results are dropped once the
+    /// receiver is gone.
+    pub fn on_result_channel(&mut self, tx: std::sync::mpsc::Sender<PingResult>) {
+        self.on_result(move |result| {
+            let _ = tx.send(result.clone());
+        });
+    }
+
+    /// Renderer producing the CLI's per-probe lines for this target.
+    pub fn renderer(&self) -> ConsoleRenderer {
+        ConsoleRenderer {
+            address: self.address.clone(),
+            port: self.port,
+            protocol: self.protocol(),
+            verbose: self.verbose,
+        }
+    }
+
+    /// Passes a result to the observers. `console` is false when the
+    /// caller prints the result in its own format (compare mode).
+    fn notify(&mut self, result: &PingResult, console: bool, sink: Sink<'_>) {
+        let skip = if !console && !self.silent { 1 } else { 0 };
+        for observer in self.observers_mut().iter_mut().skip(skip) {
+            observer(result);
+        }
+        sink(result);
+    }
+
+    /// Statistics gathered so far. With --compare-direct, these are the
+    /// proxied measurements; with --dual-stack, both families together.
+    pub fn stats(&self) -> &PingStats {
+        &self.stats
+    }
+
+    /// The host (name or IP) being pinged.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Measures every round both through the proxy and directly, to show how
+    /// much latency the proxy adds. With `concurrent`, both connects are
+    /// started at the same time instead of one after the other.
+    pub fn with_compare_direct(mut self, concurrent: bool) -> Self {
+        self.compare = Some(Comparison {
+            concurrent,
+            direct: PingStats::default(),
+            overheads: Vec::new(),
+        });
+        self
+    }
+
+    /// Ends `run` after `n` successful connections, even if the count
+    /// isn't reached (--stop-after-success).
+    pub fn with_stop_after_success(mut self, n: u32) -> Self {
+        self.stop_after_success = Some(n);
+        self
+    }
+
+    /// Ends `run` after `n` failed connections, even if the count isn't
+    /// reached (--stop-after-failure).
+    pub fn with_stop_after_failure(mut self, n: u32) -> Self {
+        self.stop_after_failure = Some(n);
+        self
+    }
+
+    /// After each proxied result, prints how long connecting to the proxy,
+    /// negotiating, authenticating and waiting for the CONNECT reply took
+    /// (--timing-detail). Nothing changes when silent.
+    pub fn with_timing_detail(mut self) -> Self {
+        self.timing_detail = true;
+        self
+    }
+
+    /// Text shown in parentheses after the port in the header, e.g. the
+    /// URL scheme it was inferred from.
+    pub fn with_port_label(mut self, label: impl Into<String>) -> Self {
+        self.port_label = Some(label.into());
+        self
+    }
+
+    /// Circuit breaker for endless runs: `run` gives up once `n` probes
+    /// have failed in total, consecutive or not (--max-failures). Unlike
+    /// `with_stop_after_failure`, this counts as the run failing: see
+    /// `max_failures_reached`.
+    pub fn with_max_failures(mut self, n: u32) -> Self {
+        self.max_failures = Some(n);
+        self
+    }
+
+    /// Whether the `with_max_failures` limit was hit.
+    pub fn max_failures_reached(&self) -> bool {
+        self.max_failures.is_some_and(|n| self.stats.failed >= n)
+    }
+
+    /// Sends `payload` on every connection once established, and counts
+    /// the probe as failed if that (or waiting for the reply, with
+    /// `read_reply`) fails within the timeout (--send, --recv). The
+    /// exchange is timed separately from the connection.
+    pub fn with_payload(mut self, payload: Payload) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    /// Sends a PROXY protocol header first thing on every connection, as a
+    /// load balancer would, for backends expecting one (--proxy-protocol).
+    /// The header names the address connected to as the destination; a
+    /// failure to send it fails the probe.
+    pub fn with_proxy_header(mut self, header: ProxyHeader) -> Self {
+        self.proxy_header = Some(header);
+        self
+    }
+
+    /// Sends or receives data on every connection once established, to or
+    /// from a cooperative listener (`paping listen --discard`, `--source`),
+    /// and shows the rate on the result line (--throughput). A transfer
+    /// that fails or stalls for the timeout fails the probe. See
+    /// [`crate::throughput`].
+    pub fn with_throughput(mut self, throughput: Throughput) -> Self {
+        self.throughput = Some(throughput);
+        self
+    }
+
+    /// Waits this long for the reply or banner of a payload with
+    /// `read_reply`, rather than the connect timeout (--read-timeout), so
+    /// that a slow or silent service doesn't hold the loop up for longer
+    /// than a banner is worth. Running out of it fails the probe with
+    /// `FailureKind::NoReply`.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Looks the target up again, `n` times at most and
+    /// `DNS_RETRY_DELAY` apart, when a lookup fails, so that a resolver
+    /// hiccup doesn't count as the target being down (--dns-retries). Only
+    /// direct connections resolve the name: a proxy resolves it itself.
+    pub fn with_dns_retries(mut self, n: u32) -> Self {
+        self.dns_retries = n;
+        self
+    }
+
+    /// Reuses the addresses the target resolved to for `ttl`, rather than
+    /// looking it up for every probe (--dns-cache). The connection time
+    /// then leaves the lookup out. A target moving to other addresses is
+    /// only followed once the cached ones expire; failed lookups aren't
+    /// cached.
+    pub fn with_dns_cache(mut self, ttl: Duration) -> Self {
+        self.dns_cache = Some(DnsCache {
+            ttl,
+            entry: Mutex::new(None),
+        });
+        self
+    }
+
+    /// Tells on stderr whether each lookup was answered from the
+    /// `with_dns_cache` cache or made afresh (--verbose).
+    pub fn with_verbose(mut self) -> Self {
+        self.verbose = true;
+        self.refresh_printer();
+        self
+    }
+
+    /// Bounds the time one direct probe may take in all: DNS retries stop
+    /// once the next one couldn't start in time, and the connect timeout is
+    /// cut down to what's left (--deadline). A failure caused by the
+    /// deadline rather than by the timeout says so. A single lookup can't
+    /// be interrupted, so a slow resolver may still overrun it.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Waits `delay` once before the first probe of `run` (and `run_with`),
+    /// for a network that may not be up yet when paping starts
+    /// (--startup-delay). The header, printed before `run`, comes first;
+    /// stopping cuts the wait short, with nothing probed.
+    pub fn with_startup_delay(mut self, delay: Duration) -> Self {
+        self.startup_delay = Some(delay);
+        self
+    }
+
+    pub(crate) fn startup_delay(&self) -> Option<Duration> {
+        self.startup_delay
+    }
+
+    /// Has `run` (and `run_with`) print the statistics so far, and carry
+    /// on, whenever `request` is set: from a signal handler, say (SIGUSR1).
+    /// It's checked after every probe and during the pauses between them,
+    /// and cleared once seen. Nothing is printed when silent.
+    pub fn with_stats_request(mut self, request: Arc<AtomicBool>) -> Self {
+        self.stats_request = Some(request);
+        self
+    }
+
+    /// Has `run` (`run_with`, `wait_for`) call `beat` after every probe, and at
+    /// least every `every` while waiting between probes (or before the
+    /// first): a watchdog fed this way only goes hungry when a probe hangs,
+    /// however long the interval or the backoff of an outage.
+    pub fn with_heartbeat(mut self, every: Duration, beat: Arc<dyn Fn() + Send + Sync>) -> Self {
+        self.heartbeat = Some(Heartbeat {
+            every: every.max(Duration::from_millis(1)),
+            beat,
+            last: Instant::now(),
+        });
+        self
+    }
+
+    /// Counts connections that took longer than `max` as failures, of kind
+    /// `Slow` (--max-latency): they weigh on the loss like any other, with
+    /// the time they took in the message.
+    pub fn with_max_latency(mut self, max: Duration) -> Self {
+        self.max_latency = Some(max);
+        self
+    }
+
+    /// `result`, failed when it connected above the latency limit.
+    fn check_latency(&self, result: PingResult) -> PingResult {
+        match (result, self.max_latency) {
+            (PingResult::Connected(c), Some(max)) if c.time > max => {
+                PingResult::Failed(Failure {
+                    kind: FailureKind::Slow,
+                    message: format!(
+                        "too slow (time={:.2}ms, max {}ms)",
+                        c.time.as_secs_f64() * 1000.0,
+                        max.as_millis()
+                    ),
+                })
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Whether a --stop-after-success/--stop-after-failure/--max-failures
+    /// limit is reached.
+    fn stop_condition_met(&self) -> bool {
+        let reached = |limit: Option<u32>, value: u32| limit.is_some_and(|n| value >= n);
+        reached(self.stop_after_success, self.stats.connected)
+            || reached(self.stop_after_failure, self.stats.failed)
+            || self.max_failures_reached()
+    }
+
+    /// Loss percentages above which the statistics show the loss in yellow
+    /// (`warn`) and red (`crit`), instead of `LOSS_WARN_PERCENT` and
+    /// `LOSS_CRIT_PERCENT` (--warn, --crit).
+    pub fn with_loss_thresholds(mut self, warn: f64, crit: f64) -> Self {
+        self.loss_warn = warn;
+        self.loss_crit = crit;
+        self
+    }
+
+    /// Pause between two pings (one second by default).
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Keeps `run`'s rounds one interval apart from start to start: the
+    /// pause after a round is the interval minus the time the round took
+    /// (none if it took longer), so slow connects don't stretch the period
+    /// (--strict-interval). By default the whole interval is waited after
+    /// each round.
+    pub fn with_strict_interval(mut self) -> Self {
+        self.strict_interval = true;
+        self
+    }
+
+    /// Shortens the pause between pings following `ramp` instead of
+    /// waiting the same interval every time (--ramp).
+    pub fn with_ramp(mut self, ramp: Ramp) -> Self {
+        self.ramp = Some(ramp);
+        self
+    }
+
+    /// Never pauses less than `floor` between two probes of `run`, however
+    /// short the interval or the ramp's floor (`MIN_INTERVAL` by default);
+    /// `Duration::ZERO` lets them go as low as they like (--min-interval,
+    /// --allow-flood). `with_flood` ignores it: that's flooding on purpose.
+    pub fn with_min_interval(mut self, floor: Duration) -> Self {
+        self.min_interval = floor;
+        self
+    }
+
+    /// The shortest pause asked for by the interval or ramp and the
+    /// `with_min_interval` floor, when the former is below the latter:
+    /// `run` then waits the floor instead.
+    pub fn clamped_interval(&self) -> Option<(Duration, Duration)> {
+        let shortest = match self.ramp {
+            Some(ref ramp) => ramp.floor,
+            None => self.interval,
+        };
+        (self.flood.is_none() && shortest < self.min_interval)
+            .then_some((shortest, self.min_interval))
+    }
+
+    pub(crate) fn interval_after(&self, done: u32) -> Duration {
+        let interval = match self.ramp {
+            Some(ref ramp) => ramp.interval_after(done),
+            None => self.interval,
+        };
+        match self.flood {
+            Some(_) => interval,
+            None => interval.max(self.min_interval),
+        }
+    }
+
+    /// Connects over both IPv4 and IPv6 every round and keeps separate
+    /// statistics for each family, to spot a broken stack that resolving a
+    /// single address would hide (--dual-stack). Direct connections only.
+    pub fn with_dual_stack(mut self) -> Self {
+        self.dual_stack = Some(DualStack::default());
+        self
+    }
+
+    /// Races IPv6 and IPv4 like browsers do: the IPv6 connect starts first,
+    /// the IPv4 one `HAPPY_EYEBALLS_DELAY` later (or as soon as IPv6 fails),
+    /// and the first to connect wins (--happy-eyeballs). Direct connections
+    /// only.
+    pub fn with_happy_eyeballs(mut self) -> Self {
+        self.happy_eyeballs = Some(HappyEyeballs::default());
+        self
+    }
+
+    /// Probes by setting up SCTP associations rather than TCP connections
+    /// (--sctp), timing the four-way handshake. Direct connections only,
+    /// on Linux with SCTP in the kernel: see `sctp_supported`.
+    pub fn with_sctp(mut self) -> Self {
+        self.sctp = true;
+        self.refresh_printer();
+        self
+    }
+
+    /// Makes a TLS handshake once connected, timed apart from the connect,
+    /// checking the target's certificate as `probe` says (--tls). The
+    /// payload and throughput, if any, then go over the TLS session; a
+    /// PROXY protocol header comes before it. A failed handshake or a
+    /// refused certificate fails the probe, as `FailureKind::Tls`.
+    pub fn with_tls(mut self, probe: TlsProbe) -> Self {
+        self.tls = Some(probe);
+        self
+    }
+
+    /// "TCP", or "SCTP" with `with_sctp`.
+    pub fn protocol(&self) -> &'static str {
+        match self.sctp {
+            true => "SCTP",
+            false => "TCP",
+        }
+    }
+
+    /// Connects from each of `sources` in turn, one per round, to spread the
+    /// load over several local IPs or test the path behind each. Lines are
+    /// tagged with the source and the statistics kept per source too. A
+    /// single source is the same as `bind_addr`. Direct connections only.
+    pub fn with_source_rotation(mut self, sources: Vec<IpAddr>) -> Self {
+        self.bind_addr = sources.first().copied();
+        self.rotation = (sources.len() > 1).then(|| Rotation {
+            sources: sources.into_iter().map(|ip| (ip, PingStats::default())).collect(),
+            next: 0,
+        });
+        self
+    }
+
+    /// Prints the "Connecting to ..." banner.
+    pub fn print_header(&self) {
+        println!();
+        let port = match self.port_label {
+            Some(ref label) => format!("{} ({})", self.port.to_string().green(), label),
+            None => self.port.to_string().green().to_string(),
+        };
+        let bind_info = match (&self.rotation, self.bind_addr) {
+            (Some(rotation), _) => {
+                let ips: Vec<String> = rotation
+                    .sources
+                    .iter()
+                    .map(|(ip, _)| ip.to_string().yellow().to_string())
+                    .collect();
+                format!(" from  {}  in turn", ips.join(", "))
+            }
+            (None, Some(ip)) => format!(" from  {}", ip.to_string().yellow()),
+            (None, None) => String::new(),
+        };
+        if let Some(ref proxy) = self.proxy {
+            self.say(format_args!(
+                "Connecting to  {}  on {}  {}{}  via proxy  {}{}:",
+                self.address.green(),
+                self.protocol(),
+                port,
+                bind_info,
+                join_host_port(&proxy.host, proxy.port).cyan(),
+                if proxy.tls {
+                    format!("  ({})", proxy.transport().cyan())
+                } else {
+                    String::new()
+                }
+            ));
+        } else {
+            self.say(format_args!(
+                "Connecting to  {}  on {}  {}{}:",
+                self.address.green(),
+                self.protocol(),
+                port,
+                bind_info
+            ));
+        }
+        if let Some(ref throughput) = self.throughput {
+            self.say(format_args!(
+                "{}",
+                format!(
+                    "Measuring {} throughput to whatever accepts the connection: a proxy or \
+                     load balancer in between is where the measured path ends",
+                    throughput.direction.as_str()
+                )
+                .yellow()
+            ));
+        }
+        println!();
+    }
+
+    /// Resolves the target, retrying failed lookups (see
+    /// `with_dns_retries`). Returns the addresses, or the failure once
+    /// every lookup failed, and when the last lookup started.
+    fn lookup(&self) -> (Result<Vec<SocketAddr>, Failure>, Instant) {
+        if let Some(ref cache) = self.dns_cache {
+            let entry = cache.entry.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some((ref addrs, at)) = *entry {
+                if let Some(left) = cache.ttl.checked_sub(at.elapsed()) {
+                    self.trace_lookup(addrs, format_args!("cached, {}s left", left.as_secs()));
+                    return (Ok(addrs.clone()), Instant::now());
+                }
+            }
+        }
+        let (result, start) = self.resolve();
+        if let (Some(cache), Ok(addrs)) = (&self.dns_cache, &result) {
+            self.trace_lookup(
+                addrs,
+                format_args!("fresh, cached for {}s", cache.ttl.as_secs()),
+            );
+            *cache.entry.lock().unwrap_or_else(|e| e.into_inner()) =
+                Some((addrs.clone(), Instant::now()));
+        }
+        (result, start)
+    }
+
+    /// "dns: example.com: 93.184.216.34:443 (cached, 25s left)" on stderr,
+    /// with --verbose.
+    fn trace_lookup(&self, addrs: &[SocketAddr], how: fmt::Arguments) {
+        if self.verbose {
+            eprintln!("dns: {}: {} ({})", self.address, addrs[0], how);
+        }
+    }
+
+    /// `lookup` without the cache.
+    fn resolve(&self) -> (Result<Vec<SocketAddr>, Failure>, Instant) {
+        let began = Instant::now();
+        let mut attempt = 0;
+        loop {
+            let start = Instant::now();
+            let addrs: Vec<SocketAddr> =
+                match (self.address.as_str(), self.port).to_socket_addrs() {
+                    Ok(addrs) => addrs.collect(),
+                    Err(_) => Vec::new(),
+                };
+            if !addrs.is_empty() {
+                return (Ok(addrs), start);
+            }
+            if attempt == self.dns_retries {
+                return (Err(self.resolve_failure()), start);
+            }
+            if let Some(deadline) = self.deadline {
+                if began.elapsed() + DNS_RETRY_DELAY >= deadline {
+                    let failure = Failure {
+                        kind: FailureKind::Resolve,
+                        message: format!(
+                            "could not resolve address before the {}ms deadline (tried {})",
+                            deadline.as_millis(),
+                            times(attempt + 1)
+                        ),
+                    };
+                    return (Err(failure), start);
+                }
+            }
+            attempt += 1;
+            std::thread::sleep(DNS_RETRY_DELAY);
+        }
+    }
+
+    fn resolve_failure(&self) -> Failure {
+        let message = match self.dns_retries {
+            0 => "could not resolve address".to_string(),
+            1 => "could not resolve address (DNS failed, retried once)".to_string(),
+            n => format!("could not resolve address (DNS failed, retried {} times)", n),
+        };
+        Failure {
+            kind: FailureKind::Resolve,
+            message,
+        }
+    }
+
+    /// Opens a TCP connection (or SCTP association) to the target address.
+    /// If a local interface is specified (via the -i flag), the socket is bound
+    /// to that IP before connecting, which forces traffic through the desired
+    /// network interface (e.g. VPN, Ethernet, WiFi...).
+    fn connect_with_bind(&self, addr: &SocketAddr) -> std::io::Result<Stream> {
+        Stream::open(self.sctp, self.bind_addr, addr, self.timeout)
+    }
+
+    /// Makes one connection attempt, through the proxy if one is set.
+    /// Nothing is printed or recorded: see `record` and `render`.
+    pub fn probe(&self) -> PingResult {
+        let result = match self.proxy {
+            Some(ref proxy) => self.probe_proxied(proxy),
+            None => self.probe_direct(),
+        };
+        self.check_latency(result)
+    }
+
+    /// Direct connection, no proxy. The time includes the (successful)
+    /// DNS lookup.
+    fn probe_direct(&self) -> PingResult {
+        let began = Instant::now();
+        let (addr, start) = match self.lookup() {
+            (Ok(addrs), start) => (addrs[0], start),
+            (Err(failure), _) => return PingResult::Failed(failure),
+        };
+        let left = self.deadline.map(|d| d.saturating_sub(began.elapsed()));
+        let timeout = left.map_or(self.timeout, |left| left.min(self.timeout));
+        if timeout.is_zero() {
+            return self.deadline_exceeded();
+        }
+        match Stream::open(self.sctp, self.bind_addr, &addr, timeout) {
+            Ok(conn) => self.connected(conn, start.elapsed(), Some(addr), None),
+            Err(e) => match Failure::from_io(&e, false) {
+                f if f.kind == FailureKind::Timeout && timeout < self.timeout => {
+                    self.deadline_exceeded()
+                }
+                f => PingResult::Failed(f),
+            },
+        }
+    }
+
+    fn deadline_exceeded(&self) -> PingResult {
+        let deadline = self.deadline.unwrap_or_default();
+        PingResult::Failed(Failure {
+            kind: FailureKind::Timeout,
+            message: format!("{}ms deadline exceeded", deadline.as_millis()),
+        })
+    }
+
+    /// Route through the SOCKS5 proxy to reach the target.
+    /// The time covers the whole tunnel setup.
+    fn probe_proxied(&self, proxy: &Socks5Proxy) -> PingResult {
+        self.probe_proxied_timed(proxy).0
+    }
+
+    /// `probe_proxied`, with the time of each step when it connected.
+    fn probe_proxied_timed(&self, proxy: &Socks5Proxy) -> (PingResult, Option<Socks5Timings>) {
+        let start = Instant::now();
+        match proxy.connect_timed(&self.address, self.port, self.timeout) {
+            Ok((conn, timings)) => {
+                let time = start.elapsed();
+                (
+                    self.connected(conn, time, None, Some(proxy.transport())),
+                    Some(timings),
+                )
+            }
+            Err(e) => (PingResult::Failed(Failure::from_io(&e, true)), None),
+        }
+    }
+
+    /// Prints the --timing-detail line for a proxied connection.
+    fn print_timings(&self, timings: Option<Socks5Timings>) {
+        if let Some(t) = timings.filter(|_| self.timing_detail && !self.silent) {
+            self.say(format_args!("    {}", render_timings(&t)));
+        }
+    }
+
+    /// Result of a probe that connected after `time`, once the PROXY
+    /// protocol header, the TLS handshake, the --send exchange and the
+    /// --throughput transfer (if any) are done on `stream`.
+    fn connected<S: Read + Write + Timeouts>(
+        &self,
+        mut stream: S,
+        time: Duration,
+        addr: Option<SocketAddr>,
+        proxy: Option<&'static str>,
+    ) -> PingResult {
+        if let (Some(header), Some(addr)) = (self.proxy_header, addr) {
+            if let Err(failure) = header.send(&mut stream, addr, self.timeout) {
+                return PingResult::Failed(failure);
+            }
+        }
+        match self.tls {
+            Some(ref tls) => match tls.handshake(stream, &self.address, self.timeout) {
+                Ok((stream, session)) => self.exchanged(stream, time, addr, proxy, Some(session)),
+                Err(e) => PingResult::Failed(tls_failure(&e)),
+            },
+            None => self.exchanged(stream, time, addr, proxy, None),
+        }
+    }
+
+    /// The end of `connected`, past the TLS handshake if any.
+    fn exchanged<S: Read + Write + Timeouts>(
+        &self,
+        mut stream: S,
+        time: Duration,
+        addr: Option<SocketAddr>,
+        proxy: Option<&'static str>,
+        tls: Option<TlsSession>,
+    ) -> PingResult {
+        let exchange = match self.payload {
+            Some(ref payload) => match payload.exchange(
+                &mut stream,
+                self.timeout,
+                self.read_timeout.unwrap_or(self.timeout),
+            ) {
+                Ok(exchange) => Some(exchange),
+                Err(failure) => return PingResult::Failed(failure),
+            },
+            None => None,
+        };
+        let transfer = match self.throughput {
+            Some(ref throughput) => match throughput.measure(&mut stream, self.timeout) {
+                Ok(transfer) => Some(transfer),
+                Err(failure) => return PingResult::Failed(failure),
+            },
+            None => None,
+        };
+        PingResult::Connected(Connection {
+            time,
+            addr,
+            proxy,
+            exchange,
+            transfer,
+            tls,
+        })
+    }
+
+    /// Applies a probe result to the statistics.
+    pub fn record(&mut self, result: &PingResult) {
+        self.stats.record(result);
+    }
+
+    /// Formats a probe result as the line printed by the CLI.
+    pub fn render(&self, result: &PingResult) -> String {
+        self.renderer().render(result)
+    }
+
+    /// One probe (or round, with --compare-direct, --dual-stack...),
+    /// recorded and printed as by `run`, without pausing.
+    pub fn ping_once(&mut self) {
+        self.ping(&mut |_| {});
+    }
+
+    fn ping(&mut self, sink: Sink<'_>) {
+        if self.compare.is_some() {
+            return self.ping_compare(sink);
+        }
+        if self.dual_stack.is_some() {
+            return self.ping_dual_stack(sink);
+        }
+        if self.happy_eyeballs.is_some() {
+            return self.ping_happy_eyeballs(sink);
+        }
+        if self.rotation.is_some() {
+            return self.ping_rotating(sink);
+        }
+
+        let (result, timings) = match self.proxy {
+            Some(ref proxy) if self.timing_detail => {
+                let (result, timings) = self.probe_proxied_timed(proxy);
+                (self.check_latency(result), timings)
+            }
+            _ => (self.probe(), None),
+        };
+        self.record(&result);
+        let hushed = self.track_outage(&result);
+        self.notify(&result, !hushed, sink);
+        self.print_timings(timings);
+    }
+
+    /// Follows network outages in continuous runs: the first failure
+    /// because of one prints a single line instead of its own, the next
+    /// ones are hushed (true), and the first result after it tells how long
+    /// it lasted.
+    fn track_outage(&mut self, result: &PingResult) -> bool {
+        if !self.continuous {
+            return false;
+        }
+        let lines = !self.silent
+            && self.format.is_none()
+            && self.flood.is_none()
+            && self.monitor.is_none();
+        let interval = self.interval_after(self.stats.attempted);
+        let PingResult::Failed(Failure {
+            kind: FailureKind::NetworkDown,
+            ref message,
+        }) = *result
+        else {
+            if let Some(outage) = self.outage.take() {
+                if lines {
+                    let down = outage.since.elapsed().as_secs_f64();
+                    self.say(format!("Network back after {:.0}s", down).green());
+                }
+            }
+            return false;
+        };
+        match self.outage {
+            Some(ref mut outage) => {
+                outage.backoff = (outage.backoff * 2).min(NETWORK_DOWN_MAX_BACKOFF.max(interval))
+            }
+            None => {
+                self.outage = Some(NetworkOutage {
+                    since: Instant::now(),
+                    backoff: interval,
+                });
+                if lines {
+                    let line = format!("Network interface unavailable, waiting... ({})", message);
+                    self.say(line.yellow());
+                }
+            }
+        }
+        lines
+    }
+
+    /// One --dual-stack round: resolves the target, then connects to its
+    /// first IPv4 and first IPv6 address in turn. Times cover the connect
+    /// only, as the lookup is shared.
+    fn ping_dual_stack(&mut self, sink: Sink<'_>) {
+        let addrs = self.lookup().0;
+
+        for family in [Family::V4, Family::V6] {
+            let found = match addrs {
+                Ok(ref addrs) => Ok(addrs.iter().find(|a| family.matches(a))),
+                Err(ref failure) => Err(failure.clone()),
+            };
+            let result = self.check_latency(match found {
+                Err(failure) => PingResult::Failed(failure),
+                Ok(Some(addr)) => {
+                    let start = Instant::now();
+                    match self.connect_with_bind(addr) {
+                        Ok(conn) => self.connected(conn, start.elapsed(), Some(*addr), None),
+                        Err(e) => PingResult::Failed(Failure::from_io(&e, false)),
+                    }
+                }
+                Ok(None) => PingResult::Failed(Failure {
+                    kind: FailureKind::Resolve,
+                    message: format!("no {} address", family.name()),
+                }),
+            });
+
+            self.stats.record(&result);
+            let dual = self.dual_stack.as_mut().unwrap();
+            match family {
+                Family::V4 => dual.v4.record(&result),
+                Family::V6 => dual.v6.record(&result),
+            }
+            self.notify(&result, false, sink);
+            if !self.silent {
+                let line = self.render(&result);
+                self.say(format_args!("{} {}{}", family.tag().cyan(), line, self.seq_tag()));
+            }
+        }
+    }
+
+    /// One round from the next source of the rotation, tagged with it.
+    fn ping_rotating(&mut self, sink: Sink<'_>) {
+        let rotation = self.rotation.as_mut().unwrap();
+        let index = rotation.next;
+        rotation.next = (index + 1) % rotation.sources.len();
+        let source = rotation.sources[index].0;
+        self.bind_addr = Some(source);
+
+        let result = self.probe();
+        self.record(&result);
+        self.rotation.as_mut().unwrap().sources[index].1.record(&result);
+        // --format, --flood and --monitor lines have no room for the tag
+        let console = self.format.is_some() || self.flood.is_some() || self.monitor.is_some();
+        self.notify(&result, console, sink);
+        if !console && !self.silent {
+            let line = self.render(&result);
+            let tag = format!("[{}]", source);
+            self.say(format_args!("{} {}{}", tag.cyan(), line, self.seq_tag()));
+        }
+    }
+
+    /// One --happy-eyeballs round, with the winning family on the line.
+    fn ping_happy_eyeballs(&mut self, sink: Sink<'_>) {
+        let result = self.check_latency(self.probe_happy_eyeballs());
+        self.record(&result);
+
+        let winner = match result {
+            PingResult::Connected(Connection {
+                addr: Some(ref addr),
+                ..
+            }) => Some(Family::of(addr)),
+            _ => None,
+        };
+        let tally = self.happy_eyeballs.as_mut().unwrap();
+        match winner {
+            Some(Family::V4) => tally.v4_wins += 1,
+            Some(Family::V6) => tally.v6_wins += 1,
+            None => {}
+        }
+
+        self.notify(&result, false, sink);
+        if !self.silent {
+            match winner {
+                Some(family) => self.say(format_args!(
+                    "{} {}{}",
+                    family.tag().cyan(),
+                    self.render(&result),
+                    self.seq_tag()
+                )),
+                None => self.say(format_args!("{}{}", self.render(&result), self.seq_tag())),
+            }
+        }
+    }
+
+    /// Races the first IPv6 and first IPv4 address of the target. Each
+    /// connect runs on its own thread; once one wins, the other's socket is
+    /// closed as soon as its connect returns. The time includes the DNS
+    /// lookup, as in `probe_direct`.
+    fn probe_happy_eyeballs(&self) -> PingResult {
+        let (addrs, start) = match self.lookup() {
+            (Ok(addrs), start) => (addrs, start),
+            (Err(failure), _) => return PingResult::Failed(failure),
+        };
+        let mut pending = [Family::V6, Family::V4]
+            .into_iter()
+            .filter_map(|family| addrs.iter().copied().find(|a| family.matches(a)))
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let spawn = |addr: SocketAddr| {
+            let tx = tx.clone();
+            let (sctp, bind_addr, timeout) = (self.sctp, self.bind_addr, self.timeout);
+            std::thread::spawn(move || {
+                // Fails once a winner was picked: the loser is dropped here
+                let _ = tx.send((addr, Stream::open(sctp, bind_addr, &addr, timeout)));
+            });
+        };
+
+        let mut running = 0;
+        match pending.next() {
+            Some(addr) => {
+                spawn(addr);
+                running += 1;
+            }
+            None => return PingResult::Failed(self.resolve_failure()),
+        }
+
+        let mut last_error = None;
+        loop {
+            let attempt = match pending.len() {
+                0 => rx.recv().ok(),
+                _ => rx.recv_timeout(HAPPY_EYEBALLS_DELAY).ok(),
+            };
+            match attempt {
+                Some((addr, Ok(conn))) => {
+                    return self.connected(conn, start.elapsed(), Some(addr), None)
+                }
+                Some((_, Err(e))) => {
+                    running -= 1;
+                    last_error = Some(e);
+                }
+                // Head start over: launch the other family alongside
+                None => {}
+            }
+            match pending.next() {
+                Some(addr) => {
+                    spawn(addr);
+                    running += 1;
+                }
+                None if running == 0 => break,
+                None => {}
+            }
+        }
+
+        let e = last_error.expect("every attempt failed");
+        PingResult::Failed(Failure::from_io(&e, false))
+    }
+
+    /// One --compare-direct round: a proxied and a direct connect, printed
+    /// on a single line with the difference between the two.
+    fn ping_compare(&mut self, sink: Sink<'_>) {
+        let proxy = self
+            .proxy
+            .as_ref()
+            .expect("--compare-direct requires a proxy");
+        let concurrent = self.compare.as_ref().is_some_and(|c| c.concurrent);
+
+        let ((proxied, timings), direct) = if concurrent {
+            std::thread::scope(|scope| {
+                let direct = scope.spawn(|| self.probe_direct());
+                let proxied = self.probe_proxied_timed(proxy);
+                (
+                    proxied,
+                    direct.join().expect("direct connect thread panicked"),
+                )
+            })
+        } else {
+            (self.probe_proxied_timed(proxy), self.probe_direct())
+        };
+        let (proxied, direct) = (self.check_latency(proxied), self.check_latency(direct));
+
+        self.stats.record(&proxied);
+        let compare = self.compare.as_mut().unwrap();
+        compare.direct.record(&direct);
+
+        let overhead = proxied.time_ms().zip(direct.time_ms()).map(|(p, d)| p - d);
+        if let Some(o) = overhead {
+            compare.overheads.push(o);
+        }
+        self.notify(&proxied, false, sink);
+        self.notify(&direct, false, sink);
+        if self.silent {
+            return;
+        }
+
+        let fmt = |result: &PingResult| match result {
+            PingResult::Connected(c) => format!("{:.2}ms", c.time.as_secs_f64() * 1000.0)
+                .green()
+                .to_string(),
+            PingResult::Failed(f) => format!("{} ({})", "failed".red(), f.message),
+        };
+        let delta = match overhead {
+            Some(o) => format!("  delta={}", format!("{:+.2}ms", o).yellow()),
+            None => String::new(),
+        };
+
+        self.say(format_args!(
+            "{} {}: proxy={}  direct={}{}  port={}{}",
+            if proxied.is_success() || direct.is_success() {
+                "Connected to"
+            } else {
+                "Connection to"
+            },
+            self.address.green(),
+            fmt(&proxied),
+            fmt(&direct),
+            delta,
+            self.port.to_string().green(),
+            self.seq_tag()
+        ));
+        self.print_timings(timings);
+    }
+
+    /// Pings `count` times (0 = until stopped), one interval apart (or
+    /// following the `with_ramp` schedule). Stops early once a
+    /// `with_stop_after_*` limit is reached.
+    /// Calling `stop()` on the token's handle (e.g. from a Ctrl+C handler)
+    /// ends the run after the current attempt, cutting the pause short.
+    pub fn run(&mut self, count: u32, stop: &StopToken) {
+        self.run_loop(count, stop, &mut |_| {});
+    }
+
+    /// Like `run`, but hands every result to `callback` instead of printing
+    /// anything (observers registered with `on_result` still see them):
+    ///
+    /// ```no_run
+    /// # use paping::pinger::Pinger;
+    /// # use paping::stop::StopHandle;
+    /// let mut pinger = Pinger::builder("example.com", 443).build().unwrap();
+    /// let stop = StopHandle::new();
+    /// let mut failures = 0;
+    /// pinger.run_with(10, &stop.token(), |result| {
+    ///     if !result.is_success() {
+    ///         failures += 1;
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// `run` is this with the console printer as the callback. The callback
+    /// runs on the calling thread between two probes, with no lock held, so
+    /// it may itself call `stop()`; a Ctrl+C handler calling `stop()` never
+    /// waits for it either. A slow callback delays the next probe.
+    pub fn run_with<F: FnMut(&PingResult)>(
+        &mut self,
+        count: u32,
+        stop: &StopToken,
+        mut callback: F,
+    ) {
+        let silent = self.silent;
+        self.set_silent(true);
+        self.run_loop(count, stop, &mut callback);
+        self.set_silent(silent);
+    }
+
+    /// Probes one interval apart until the port is in the `awaited` state
+    /// (--wait, --wait-closed), for scripts waiting on a service to come up
+    /// or to be drained. Gives up after `count` probes (0 = no limit), once
+    /// `limit` has passed, or, waiting for it to open, when a
+    /// `with_stop_after_failure`/`with_max_failures` limit is reached. A
+    /// probe started before `limit` runs to its end (at most the timeout).
+    /// Results go to the observers as with `run`.
+    pub fn wait_for(
+        &mut self,
+        awaited: Awaited,
+        count: u32,
+        limit: Option<Duration>,
+        stop: &StopToken,
+    ) -> Wait {
+        let start = Instant::now();
+        let mut wait = Wait {
+            awaited,
+            reached: false,
+            since: None,
+            attempts: 0,
+            elapsed: Duration::ZERO,
+            last: None,
+        };
+        let mut streak = Streak::default();
+        let mut run_start = None;
+        while !stop.is_stopped() {
+            let probed_at = SystemTime::now();
+            let result = self.probe();
+            self.record(&result);
+            self.notify(&result, true, &mut |_| {});
+            self.beat();
+            wait.attempts += 1;
+            if streak.push(awaited.matches(&result)) {
+                run_start = Some(probed_at);
+            }
+            wait.last = Some(result);
+            wait.reached = streak.holds(true, awaited.confirm());
+            if wait.reached {
+                wait.since = run_start;
+                break;
+            }
+            let limits_apply = matches!(awaited, Awaited::Open { .. });
+            if (count > 0 && wait.attempts >= count) || (limits_apply && self.stop_condition_met())
+            {
+                break;
+            }
+            let mut pause = self.interval_after(wait.attempts);
+            if let Some(limit) = limit {
+                match limit.checked_sub(start.elapsed()) {
+                    Some(left) if !left.is_zero() => pause = pause.min(left),
+                    _ => break,
+                }
+            }
+            if self.pause(pause, stop) {
+                break;
+            }
+            if limit.is_some_and(|limit| start.elapsed() >= limit) {
+                break;
+            }
+        }
+        wait.elapsed = start.elapsed();
+        wait
+    }
+
+    /// The --wait/--wait-closed outcome on one line: how long it took and,
+    /// for a port that closed, since when; or why it gave up.
+    pub fn wait_line(&self, wait: &Wait) -> String {
+        let target = join_host_port(&self.address, self.port);
+        let attempts = match wait.attempts {
+            1 => "1 attempt".to_string(),
+            n => format!("{} attempts", n),
+        };
+        let after = format!("{:.2}s", wait.elapsed.as_secs_f64());
+        let last = match wait.last {
+            Some(PingResult::Failed(ref f)) => format!(": {}", f.message),
+            _ => String::new(),
+        };
+        let line = match (wait.awaited, wait.reached) {
+            (Awaited::Open { .. }, true) => {
+                format!("{} is open after {} ({})", target.green(), after, attempts)
+            }
+            (Awaited::Closed { .. }, true) => format!(
+                "{} is closed since {}, after {} ({}){}",
+                target.green(),
+                template::timestamp(wait.since.unwrap_or_else(SystemTime::now)),
+                after,
+                attempts,
+                last
+            ),
+            (Awaited::Open { .. }, false) => format!(
+                "{} still {} after {} ({}){}",
+                target.green(),
+                "not open".red(),
+                after,
+                attempts,
+                last
+            ),
+            (Awaited::Closed { .. }, false) => format!(
+                "{} still {} after {} ({}){}",
+                target.green(),
+                "not closed".red(),
+                after,
+                attempts,
+                last
+            ),
+        };
+        labelled(self.label.as_deref(), line)
+    }
+
+    fn run_loop(&mut self, count: u32, stop: &StopToken, sink: Sink<'_>) {
+        if let Some(delay) = self.startup_delay.filter(|d| !d.is_zero()) {
+            if !self.silent {
+                self.say(format!("Waiting {}ms before the first ping", delay.as_millis()).yellow());
+            }
+            self.pause(delay, stop);
+        }
+        let flood = self.flood.clone().filter(|_| !self.silent);
+        let progress = self
+            .progress
+            .clone()
+            .filter(|_| count > 0 && !self.silent && flood.is_none());
+        if let Some(ref p) = progress {
+            p.start(count);
+        }
+        if let Some(ref f) = flood {
+            f.start();
+        }
+        if let Some(ref m) = self.monitor {
+            m.start(self.confirm);
+        }
+        let mut done = 0;
+        let mut interval = self.interval_after(0);
+        self.continuous = count == 0;
+        while !stop.is_stopped() {
+            let round = Instant::now();
+            if let Some(ref p) = progress {
+                p.seq.store(done + 1, Ordering::Relaxed);
+            }
+            self.ping(sink);
+            done += 1;
+            if let Some(ref p) = progress {
+                p.done.store(done, Ordering::Relaxed);
+                p.redraw();
+            }
+            self.print_requested_stats();
+            self.beat();
+            if (count > 0 && done >= count) || self.stop_condition_met() {
+                break;
+            }
+            let next = self.interval_after(done);
+            if next != interval && !self.silent {
+                self.say(format!("Interval now {}ms", next.as_millis()).yellow());
+            }
+            interval = next;
+            let pause = match self.outage {
+                Some(ref outage) => outage.backoff,
+                None if self.strict_interval => interval.saturating_sub(round.elapsed()),
+                None => interval,
+            };
+            if self.pause(pause, stop) {
+                break;
+            }
+        }
+        self.continuous = false;
+        self.outage = None;
+        if let Some(ref p) = progress {
+            p.finish();
+        }
+        if let Some(ref f) = flood {
+            f.finish(self.label.as_deref());
+        }
+        if !self.silent {
+            if self.max_failures_reached() {
+                self.say(format!("Giving up after {} failures", self.stats.failed).red());
+            }
+            println!();
+        }
+    }
+
+    /// Iterates over probe results, one per `next()`, for as long as `stop`
+    /// isn't stopped (use `take(n)` for a count):
+    ///
+    /// ```no_run
+    /// # use paping::pinger::Pinger;
+    /// # use paping::stop::StopToken;
+    /// let mut pinger = Pinger::builder("example.com", 443).build().unwrap();
+    /// for result in pinger.iter(&StopToken::never()).take(5) {
+    ///     println!("{:?}", result.time_ms());
+    /// }
+    /// println!("{:.1}% loss", pinger.stats().loss_percent());
+    /// ```
+    ///
+    /// `next()` itself waits the interval (or ramp step) before every probe
+    /// but the first, and returns `None` without probing if `stop` fires
+    /// during that wait; a probe already started always yields its result.
+    /// Results are recorded in the statistics but not passed to the
+    /// observers or printed, and the --compare-direct, --dual-stack and
+    /// --happy-eyeballs modes don't apply: each item is a single `probe()`.
+    pub fn iter(&mut self, stop: &StopToken) -> Iter<'_> {
+        Iter {
+            pinger: self,
+            stop: stop.clone(),
+            done: 0,
+            paced: true,
+        }
+    }
+
+    /// Like `iter`, but `next()` probes right away: pacing is left to the
+    /// caller.
+    pub fn raw_iter(&mut self, stop: &StopToken) -> Iter<'_> {
+        Iter {
+            paced: false,
+            ..self.iter(stop)
+        }
+    }
+
+    /// Old form of `run`, stopped by setting a shared flag.
+    #[deprecated(note = "use `run` with a `paping::stop::StopToken`")]
+    pub fn run_with_flag(&mut self, count: u32, stop: &Arc<AtomicBool>) {
+        self.run(count, &StopToken::from_flag(stop.clone()));
+    }
+
+    /// Waits `pause` between two probes of `run`, printing the statistics
+    /// whenever requested meanwhile, and keeping the heartbeat going.
+    /// Returns whether `stop` fired.
+    pub(crate) fn pause(&mut self, pause: Duration, stop: &StopToken) -> bool {
+        let slice = match (&self.stats_request, &self.heartbeat) {
+            (None, None) => return stop.wait_timeout(pause),
+            (Some(_), Some(h)) => STATS_REQUEST_POLL.min(h.every),
+            (Some(_), None) => STATS_REQUEST_POLL,
+            (None, Some(h)) => h.every,
+        };
+        let end = Instant::now() + pause;
+        loop {
+            let left = end.saturating_duration_since(Instant::now());
+            if stop.wait_timeout(left.min(slice)) {
+                return true;
+            }
+            self.print_requested_stats();
+            if self
+                .heartbeat
+                .as_ref()
+                .is_some_and(|h| h.last.elapsed() >= h.every)
+            {
+                self.beat();
+            }
+            if left <= slice {
+                return false;
+            }
+        }
+    }
+
+    /// Calls the `with_heartbeat` callback, if any.
+    pub(crate) fn beat(&mut self) {
+        if let Some(ref mut heartbeat) = self.heartbeat {
+            (heartbeat.beat)();
+            heartbeat.last = Instant::now();
+        }
+    }
+
+    /// The statistics so far, if `with_stats_request` asked for them.
+    fn print_requested_stats(&self) {
+        let requested = self.stats_request.as_ref();
+        if requested.is_some_and(|r| r.swap(false, Ordering::SeqCst)) && !self.silent {
+            self.print_interim_stats();
+        }
+    }
+
+    /// Prints the statistics so far, as `print_stats` does once the run
+    /// ends, for a run still going on.
+    pub fn print_interim_stats(&self) {
+        self.say(format_args!(
+            "Statistics so far ({} probes):",
+            self.stats.attempted
+        ));
+        self.print_stats_tables();
+    }
+
+    /// Prints the final statistics block: one table row per series
+    /// (proxied and direct with --compare-direct, each family with
+    /// --dual-stack).
+    pub fn print_stats(&self) {
+        self.say("Connection statistics:");
+        self.print_stats_tables();
+    }
+
+    fn print_stats_tables(&self) {
+        match self.compare {
+            Some(ref compare) => {
+                let proxy = self
+                    .proxy
+                    .as_ref()
+                    .map(|p| p.transport())
+                    .unwrap_or("proxy");
+                self.print_series_table(&[
+                    (Some(format!("via {}", proxy)), &self.stats),
+                    (Some("direct".to_string()), &compare.direct),
+                ]);
+                if !compare.overheads.is_empty() {
+                    let avg =
+                        compare.overheads.iter().sum::<f64>() / compare.overheads.len() as f64;
+                    self.say("Proxy overhead:");
+                    self.say(format_args!(
+                        "  Average = {} over {} rounds",
+                        format!("{:+.2}ms", avg).yellow(),
+                        compare.overheads.len()
+                    ));
+                }
+            }
+            None => match self.dual_stack {
+                Some(ref dual) => self.print_series_table(&[
+                    (Some(Family::V4.name().to_string()), &dual.v4),
+                    (Some(Family::V6.name().to_string()), &dual.v6),
+                ]),
+                None if self.rotation.is_some() => {
+                    let rotation = self.rotation.as_ref().unwrap();
+                    let series: Vec<(Option<String>, &PingStats)> = rotation
+                        .sources
+                        .iter()
+                        .map(|(ip, stats)| (Some(format!("from {}", ip)), stats))
+                        .collect();
+                    self.print_series_table(&series);
+                }
+                None => {
+                    self.print_series_table(&[(None, &self.stats)]);
+                    if let Some(ref tally) = self.happy_eyeballs {
+                        self.say("Happy Eyeballs winners:");
+                        self.say(format_args!(
+                            "  IPv6 = {}, IPv4 = {}",
+                            tally.v6_wins.to_string().green(),
+                            tally.v4_wins.to_string().green()
+                        ));
+                    }
+                }
+            },
+        }
+        self.print_failure_breakdown();
+        self.print_outages();
+        self.print_transitions();
+    }
+
+    /// Lists the windows the target was down in, if any.
+    fn print_outages(&self) {
+        let outages = &self.stats.outages;
+        if outages.is_empty() {
+            return;
+        }
+        let now = SystemTime::now();
+        self.say("Outages:");
+        for outage in outages.windows() {
+            let end = match outage.end {
+                Some(end) => template::timestamp(end),
+                None => "ongoing".yellow().to_string(),
+            };
+            self.say(format_args!(
+                "  {} to {}  {}  {} failed",
+                template::timestamp(outage.start),
+                end,
+                template::duration_text(outage.duration(now).as_secs_f64()).red(),
+                outage.failed
+            ));
+        }
+        match outages.earlier() {
+            0 => {}
+            1 => self.say("  and 1 earlier outage"),
+            n => self.say(format_args!("  and {} earlier outages", n)),
+        }
+    }
+
+    /// Lists the states --monitor saw the target take, from the first.
+    fn print_transitions(&self) {
+        let Some(ref monitor) = self.monitor else {
+            return;
+        };
+        let state = monitor.state.lock().unwrap();
+        if state.transitions.is_empty() {
+            return;
+        }
+        self.say("State changes:");
+        for transition in &state.transitions {
+            self.say(format_args!(
+                "  {}  {}",
+                template::timestamp(transition.change.at),
+                transition.describe()
+            ));
+        }
+    }
+
+    /// Splits the failures into lookups that failed (retries included) and
+    /// everything after them, when the former happened at all.
+    fn print_failure_breakdown(&self) {
+        let direct = self.compare.as_ref().map(|c| &c.direct);
+        let failed = self.stats.failed + direct.map_or(0, |d| d.failed);
+        let dns = self.stats.resolve_failed + direct.map_or(0, |d| d.resolve_failed);
+        if dns == 0 {
+            return;
+        }
+        // The deadline may have stopped the retries early
+        let up_to = if self.deadline.is_some() { "up to " } else { "" };
+        let retries = match self.dns_retries {
+            0 => String::new(),
+            1 => format!(" (retried {}once each)", up_to),
+            n => format!(" (retried {}{} times each)", up_to, n),
+        };
+        self.say("Failure breakdown:");
+        self.say(format_args!(
+            "  DNS = {}{}, connect = {}",
+            dns.to_string().red(),
+            retries,
+            (failed - dns).to_string().red()
+        ));
+    }
+
+    /// The --compact output: the `last` result and a summary of the run on
+    /// one line, for watch(1) and status bars.
+    pub fn compact_line(&self, last: Option<&PingResult>) -> String {
+        let result = match last {
+            Some(PingResult::Connected(c)) => {
+                format!("time={:.2}ms", c.time.as_secs_f64() * 1000.0).green()
+            }
+            Some(PingResult::Failed(f)) => format!("failed: {}", f.message).red(),
+            None => "not probed".yellow(),
+        };
+        let stats = &self.stats;
+        let times = match (stats.min(), stats.average(), stats.max()) {
+            (Some(min), Some(avg), Some(max)) => {
+                format!("  min/avg/max={:.2}/{:.2}/{:.2}ms", min, avg, max)
+            }
+            _ => String::new(),
+        };
+        let line = format!(
+            "{}  {}  {}/{} connected  {}{}",
+            join_host_port(&self.address, self.port).green(),
+            result,
+            stats.connected,
+            stats.attempted,
+            loss_colored(
+                format!("{:.1}% loss", stats.loss_percent()),
+                stats.loss_percent(),
+                self.loss_warn,
+                self.loss_crit,
+            ),
+            times
+        );
+        labelled(self.label.as_deref(), line)
+    }
+
+    /// Counters and times of each series, with its label in the first
+    /// column when there are several.
+    pub(crate) fn print_series_table(&self, series: &[(Option<String>, &PingStats)]) {
+        let labelled = series.iter().any(|(label, _)| label.is_some());
+        let mut header: Vec<ColoredString> = Vec::new();
+        if labelled {
+            header.push("".normal());
+        }
+        for title in ["Attempted", "Connected", "Failed", "Minimum", "Maximum", "Average"] {
+            header.push(title.normal());
+        }
+
+        let mut rows = vec![header];
+        for (label, stats) in series {
+            let ms = |v: Option<f64>| match v {
+                Some(ms) => format!("{:.2}ms", ms).green(),
+                None => "-".normal(),
+            };
+            let mut row = Vec::new();
+            if labelled {
+                row.push(label.clone().unwrap_or_default().normal());
+            }
+            row.extend([
+                stats.attempted.to_string().green(),
+                stats.connected.to_string().green(),
+                loss_colored(
+                    format!("{} ({:.1}%)", stats.failed, stats.loss_percent()),
+                    stats.loss_percent(),
+                    self.loss_warn,
+                    self.loss_crit,
+                ),
+                ms(stats.min()),
+                ms(stats.max()),
+                ms(stats.average()),
+            ]);
+            rows.push(row);
+        }
+        for line in render_table(&rows) {
+            self.say(line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loss_color_thresholds() {
+        use colored::Color;
+        let color = |loss| {
+            loss_colored(String::new(), loss, LOSS_WARN_PERCENT, LOSS_CRIT_PERCENT).fgcolor
+        };
+        assert_eq!(color(0.0), Some(Color::Green));
+        assert_eq!(color(LOSS_WARN_PERCENT), Some(Color::Green));
+        assert_eq!(color(10.0), Some(Color::Yellow));
+        assert_eq!(color(LOSS_CRIT_PERCENT), Some(Color::Yellow));
+        assert_eq!(color(100.0), Some(Color::Red));
+    }
+
+    #[test]
+    fn table_columns_line_up() {
+        colored::control::set_override(false);
+        let rows = vec![
+            vec!["".normal(), "Attempted".normal(), "Failed".normal()],
+            vec!["IPv4".normal(), "1200".green(), "0 (0.0%)".green()],
+            vec!["IPv6".normal(), "3".green(), "3 (100.0%)".red()],
+        ];
+        assert_eq!(
+            render_table(&rows),
+            vec![
+                "        Attempted  Failed",
+                "  IPv4  1200       0 (0.0%)",
+                "  IPv6  3          3 (100.0%)",
+            ]
+        );
+    }
+
+    #[test]
+    fn timings_line_skips_unused_steps() {
+        colored::control::set_override(false);
+        let mut t = Socks5Timings {
+            connect: Duration::from_micros(1500),
+            tls: None,
+            method: Duration::from_micros(250),
+            auth: None,
+            request: Duration::from_millis(40),
+        };
+        assert_eq!(
+            render_timings(&t),
+            "proxy connect=1.50ms  method=0.25ms  connect reply=40.00ms"
+        );
+        t.tls = Some(Duration::from_millis(3));
+        t.auth = Some(Duration::from_millis(1));
+        assert_eq!(
+            render_timings(&t),
+            "proxy connect=1.50ms  tls=3.00ms  method=0.25ms  auth=1.00ms  connect reply=40.00ms"
+        );
+    }
+
+    #[test]
+    fn ramp_parse() {
+        let ramp = Ramp::parse("1000:50:10").unwrap();
+        assert_eq!(ramp.start, Duration::from_millis(1000));
+        assert_eq!(ramp.floor, Duration::from_millis(50));
+        assert_eq!(ramp.every, 10);
+
+        assert!(Ramp::parse("1000:50").is_err());
+        assert!(Ramp::parse("1000:50:10:1").is_err());
+        assert!(Ramp::parse("fast:50:10").is_err());
+        assert!(Ramp::parse("0:0:10").is_err());
+        assert!(Ramp::parse("100:500:10").is_err());
+        assert!(Ramp::parse("1000:50:0").is_err());
+    }
+
+    #[test]
+    fn ramp_halves_down_to_floor() {
+        let ramp = Ramp::parse("1000:100:3").unwrap();
+        let ms = |done| ramp.interval_after(done).as_millis();
+        assert_eq!(ms(0), 1000);
+        assert_eq!(ms(2), 1000);
+        assert_eq!(ms(3), 500);
+        assert_eq!(ms(6), 250);
+        assert_eq!(ms(9), 125);
+        assert_eq!(ms(12), 100);
+        assert_eq!(ms(u32::MAX), 100);
+    }
+
+    #[test]
+    fn progress_tags_and_bar_follow_the_run() {
+        let progress = Progress::default();
+        assert_eq!(progress.tag(), "");
+
+        progress.start(200);
+        progress.seq.store(51, Ordering::Relaxed);
+        progress.done.store(50, Ordering::Relaxed);
+        assert_eq!(progress.tag(), "  seq=51/200");
+        assert_eq!(
+            progress.bar(),
+            format!("[{}{}]  50/200 (25%)", "█".repeat(7), "░".repeat(23))
+        );
+
+        progress.finish();
+        assert_eq!(progress.tag(), "");
+    }
+
+    fn pinger() -> Pinger {
+        Pinger::new(
+            "example.com".to_string(),
+            443,
+            Duration::from_millis(100),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn render_matches_cli_lines() {
+        colored::control::set_override(false);
+        let p = pinger();
+
+        let ok = PingResult::Connected(Connection {
+            time: Duration::from_micros(12_345),
+            addr: None,
+            proxy: Some("SOCKS5"),
+            exchange: None,
+            transfer: None,
+            tls: None,
+        });
+        assert_eq!(
+            p.render(&ok),
+            "Connected to example.com: time=12.35ms  protocol=TCP  port=443  proxy=SOCKS5"
+        );
+
+        let err = PingResult::Failed(Failure {
+            kind: FailureKind::Timeout,
+            message: "connection timed out".to_string(),
+        });
+        assert_eq!(
+            p.render(&err),
+            "Connection to example.com failed: connection timed out"
+        );
+    }
+
+    #[test]
+    fn intervals_stay_above_the_floor() {
+        let ms = Duration::from_millis;
+        let p = pinger().with_interval(ms(5)).with_min_interval(ms(20));
+        assert_eq!(p.interval_after(0), ms(20));
+        assert_eq!(p.clamped_interval(), Some((ms(5), ms(20))));
+
+        // A ramp slows down only once it reaches the floor
+        let ramp = Ramp {
+            start: ms(100),
+            floor: ms(0),
+            every: 1,
+        };
+        let p = pinger().with_ramp(ramp);
+        assert_eq!(p.interval_after(2), ms(25));
+        assert_eq!(p.interval_after(40), MIN_INTERVAL);
+        assert_eq!(p.clamped_interval(), Some((Duration::ZERO, MIN_INTERVAL)));
+        let p = p.with_min_interval(Duration::ZERO);
+        assert_eq!(p.interval_after(40), Duration::ZERO);
+        assert_eq!(p.clamped_interval(), None);
+
+        // Flooding on purpose, and intervals above the floor
+        assert_eq!(pinger().with_flood().interval_after(0), Duration::ZERO);
+        assert_eq!(pinger().with_flood().clamped_interval(), None);
+        assert_eq!(pinger().clamped_interval(), None);
+    }
+
+    #[test]
+    fn monitor_reports_confirmed_changes_only() {
+        let monitor = Monitor::new(None);
+        monitor.start(2);
+        let up = PingResult::Connected(Connection {
+            time: Duration::from_millis(1),
+            addr: None,
+            proxy: None,
+            exchange: None,
+            transfer: None,
+            tls: None,
+        });
+        let down = PingResult::Failed(Failure {
+            kind: FailureKind::Refused,
+            message: "connection refused".to_string(),
+        });
+        // A lone failure or success in a run of the other doesn't count
+        for result in [&up, &down, &up, &up, &down, &up, &down, &down, &down, &up, &up] {
+            monitor.record(result, "h:1", None, None);
+        }
+        let state = monitor.state.lock().unwrap();
+        let changes: Vec<(bool, bool, Option<&str>)> = state
+            .transitions
+            .iter()
+            .map(|t| (t.change.up, t.change.after.is_some(), t.reason.as_deref()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (true, false, None),
+                (false, true, Some("connection refused")),
+                (true, true, None),
+            ]
+        );
+        // Dated from the first probe of the confirming run
+        assert!(state.transitions[1].change.at < state.transitions[2].change.at);
+        assert_eq!(state.tracker.state().map(|(up, _)| up), Some(true));
+
+        colored::control::set_override(false);
+        let change = Transition {
+            change: Change {
+                up: false,
+                at: SystemTime::now(),
+                after: Some(Duration::from_secs(2467)),
+            },
+            reason: Some("connection timed out".to_string()),
+        };
+        assert_eq!(change.describe(), "DOWN after 41m 7s up: connection timed out");
+    }
+
+    #[test]
+    fn network_outages_are_reported_once_with_backoff() {
+        let mut p = pinger().with_interval(Duration::from_secs(3));
+        p.continuous = true;
+        let down = PingResult::Failed(Failure {
+            kind: FailureKind::NetworkDown,
+            message: "network is unreachable".to_string(),
+        });
+        let backoffs: Vec<u64> = (0..5)
+            .map(|_| {
+                assert!(p.track_outage(&down), "outage failures are hushed");
+                p.outage.as_ref().unwrap().backoff.as_secs()
+            })
+            .collect();
+        assert_eq!(backoffs, [3, 6, 10, 10, 10]);
+
+        // Other failures end the outage and print as usual
+        let refused = PingResult::Failed(Failure {
+            kind: FailureKind::Refused,
+            message: "connection refused".to_string(),
+        });
+        assert!(!p.track_outage(&refused));
+        assert!(p.outage.is_none());
+
+        // Only continuous runs back off
+        p.continuous = false;
+        assert!(!p.track_outage(&down));
+        assert!(p.outage.is_none());
+    }
+
+    #[test]
+    fn observers_receive_results_in_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut p = pinger().with_silent(true);
+        let sink = seen.clone();
+        p.on_result(move |r| sink.lock().unwrap().push(r.is_success()));
+        let (tx, rx) = std::sync::mpsc::channel();
+        p.on_result_channel(tx);
+
+        let ok = PingResult::Connected(Connection {
+            time: Duration::from_millis(1),
+            addr: None,
+            proxy: None,
+            exchange: None,
+            transfer: None,
+            tls: None,
+        });
+        p.notify(&ok, true, &mut |_| {});
+        p.notify(
+            &PingResult::Failed(Failure {
+                kind: FailureKind::Timeout,
+                message: "timed out".to_string(),
+            }),
+            true,
+            &mut |_| {},
+        );
+
+        assert_eq!(*seen.lock().unwrap(), vec![true, false]);
+        assert_eq!(rx.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn silent_removes_only_the_console_observer() {
+        let mut p = pinger();
+        assert_eq!(p.observers_mut().len(), 1);
+        p.on_result(|_| {});
+        let mut p = p.with_silent(true);
+        assert_eq!(p.observers_mut().len(), 1);
+        let mut p = p.with_silent(false);
+        assert_eq!(p.observers_mut().len(), 2);
+    }
+
+    #[test]
+    fn compact_line_sums_up_the_run() {
+        colored::control::set_override(false);
+        let mut p = pinger();
+        assert_eq!(
+            p.compact_line(None),
+            "example.com:443  not probed  0/0 connected  0.0% loss"
+        );
+        assert_eq!(
+            pinger().with_label("direct").compact_line(None),
+            "[direct] example.com:443  not probed  0/0 connected  0.0% loss"
+        );
+
+        let ok = PingResult::Connected(Connection {
+            time: Duration::from_millis(10),
+            addr: None,
+            proxy: None,
+            exchange: None,
+            transfer: None,
+            tls: None,
+        });
+        let err = PingResult::Failed(Failure {
+            kind: FailureKind::Refused,
+            message: "connection refused".to_string(),
+        });
+        p.record(&ok);
+        assert_eq!(
+            p.compact_line(Some(&ok)),
+            "example.com:443  time=10.00ms  1/1 connected  0.0% loss  \
+             min/avg/max=10.00/10.00/10.00ms"
+        );
+        p.record(&err);
+        assert_eq!(
+            p.compact_line(Some(&err)),
+            "example.com:443  failed: connection refused  1/2 connected  50.0% loss  \
+             min/avg/max=10.00/10.00/10.00ms"
+        );
+    }
+
+    #[test]
+    fn record_feeds_stats() {
+        let mut p = pinger();
+        p.record(&PingResult::Failed(Failure {
+            kind: FailureKind::Resolve,
+            message: "could not resolve address".to_string(),
+        }));
+        assert_eq!(p.stats().attempted, 1);
+        assert_eq!(p.stats().failed, 1);
+    }
+
+    #[test]
+    fn dual_stack_keeps_families_apart() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut p = Pinger::new(
+            "127.0.0.1".to_string(),
+            port,
+            Duration::from_millis(500),
+            None,
+            None,
+        )
+        .with_silent(true)
+        .with_dual_stack();
+
+        p.run(1, &StopToken::never());
+
+        let dual = p.dual_stack.as_ref().unwrap();
+        assert_eq!((dual.v4.attempted, dual.v4.connected), (1, 1));
+        // An IPv4 literal has no IPv6 address
+        assert_eq!((dual.v6.attempted, dual.v6.failed), (1, 1));
+        assert_eq!(p.stats().attempted, 2);
+    }
+
+    #[test]
+    fn rotates_through_the_sources() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // An IPv6 source can't reach an IPv4 target: every other ping fails
+        let sources = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+        let mut p = Pinger::builder("127.0.0.1", port)
+            .source_rotation(sources)
+            .interval(Duration::from_millis(1))
+            .silent(true)
+            .build()
+            .unwrap();
+
+        p.run(5, &StopToken::never());
+
+        let rotation = p.rotation.as_ref().unwrap();
+        let counts: Vec<(u32, u32)> = rotation
+            .sources
+            .iter()
+            .map(|(_, s)| (s.attempted, s.connected))
+            .collect();
+        assert_eq!(counts, [(3, 3), (2, 0)]);
+        assert_eq!((p.stats().attempted, p.stats().connected), (5, 3));
+    }
+
+    #[test]
+    fn happy_eyeballs_reports_the_winning_family() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut p = Pinger::builder("127.0.0.1", port)
+            .happy_eyeballs(true)
+            .silent(true)
+            .build()
+            .unwrap();
+
+        p.run(1, &StopToken::never());
+
+        let tally = p.happy_eyeballs.as_ref().unwrap();
+        assert_eq!((tally.v4_wins, tally.v6_wins), (1, 0));
+        assert_eq!(p.stats().connected, 1);
+    }
+
+    #[test]
+    fn happy_eyeballs_fails_with_the_last_error() {
+        // Bind then drop to get a port nobody listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let p = Pinger::builder("127.0.0.1", port)
+            .happy_eyeballs(true)
+            .silent(true)
+            .build()
+            .unwrap();
+
+        match p.probe_happy_eyeballs() {
+            PingResult::Failed(f) => assert_eq!(f.kind, FailureKind::Refused),
+            PingResult::Connected(_) => panic!("nothing listens on port {}", port),
+        }
+    }
+
+    #[test]
+    fn dns_retries_are_spent_before_failing() {
+        // An empty host never resolves, and without asking any server
+        let mut p = Pinger::builder("", 443)
+            .dns_retries(2)
+            .silent(true)
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        let result = p.probe();
+        assert!(start.elapsed() >= DNS_RETRY_DELAY * 2);
+        match result {
+            PingResult::Failed(ref f) => {
+                assert_eq!(f.kind, FailureKind::Resolve);
+                assert_eq!(
+                    f.message,
+                    "could not resolve address (DNS failed, retried 2 times)"
+                );
+            }
+            PingResult::Connected(_) => panic!("an empty host resolved"),
+        }
+        p.record(&result);
+        assert_eq!((p.stats().failed, p.stats().resolve_failed), (1, 1));
+    }
+
+    #[test]
+    fn slow_connections_count_as_failed() {
+        let mut p = pinger()
+            .with_silent(true)
+            .with_max_latency(Duration::from_millis(10));
+        let connected = |ms| {
+            PingResult::Connected(Connection {
+                time: Duration::from_millis(ms),
+                addr: None,
+                proxy: None,
+                exchange: None,
+                transfer: None,
+                tls: None,
+            })
+        };
+        assert!(p.check_latency(connected(10)).is_success());
+        match p.check_latency(connected(25)) {
+            PingResult::Failed(f) => {
+                assert_eq!(f.kind, FailureKind::Slow);
+                assert_eq!(f.message, "too slow (time=25.00ms, max 10ms)");
+                p.record(&PingResult::Failed(f));
+            }
+            PingResult::Connected(_) => panic!("a slow connection passed"),
+        }
+        assert_eq!((p.stats().failed, p.stats().loss_percent()), (1, 100.0));
+    }
+
+    #[test]
+    fn dns_cache_reuses_lookups_until_they_expire() {
+        let p = Pinger::builder("localhost", 443)
+            .dns_cache(Duration::from_millis(200))
+            .silent(true)
+            .build()
+            .unwrap();
+        let cached_at = || {
+            let cache = p.dns_cache.as_ref().unwrap();
+            cache.entry.lock().unwrap().as_ref().map(|&(_, at)| at)
+        };
+        assert_eq!(cached_at(), None);
+        let first = p.lookup().0.unwrap();
+        let at = cached_at().unwrap();
+        assert_eq!(p.lookup().0.unwrap(), first);
+        assert_eq!(cached_at(), Some(at));
+
+        std::thread::sleep(Duration::from_millis(250));
+        p.lookup().0.unwrap();
+        assert!(cached_at().unwrap() > at);
+    }
+
+    #[test]
+    fn deadline_cuts_dns_retries_short() {
+        let p = Pinger::builder("", 443)
+            .dns_retries(20)
+            .deadline(Duration::from_millis(500))
+            .silent(true)
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        match p.probe() {
+            PingResult::Failed(f) => {
+                assert_eq!(f.kind, FailureKind::Resolve);
+                assert_eq!(
+                    f.message,
+                    "could not resolve address before the 500ms deadline (tried 3 times)"
+                );
+            }
+            PingResult::Connected(_) => panic!("an empty host resolved"),
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn iter_yields_until_taken_or_stopped() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut p = Pinger::builder("127.0.0.1", port)
+            .interval(Duration::from_millis(10))
+            .silent(true)
+            .build()
+            .unwrap();
+
+        let results: Vec<PingResult> = p.iter(&StopToken::never()).take(3).collect();
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(PingResult::is_success));
+        assert_eq!(p.stats().attempted, 3);
+
+        // Stopping between two items ends the iteration without probing
+        let handle = crate::stop::StopHandle::new();
+        let mut iter = p.raw_iter(&handle.token());
+        assert!(iter.next().is_some());
+        handle.stop();
+        assert!(iter.next().is_none());
+        assert_eq!(iter.stats().attempted, 4);
+    }
+
+    #[test]
+    fn stop_after_failure_ends_the_run() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut p = Pinger::builder("127.0.0.1", port)
+            .interval(Duration::from_millis(10))
+            .stop_after_success(1)
+            .stop_after_failure(2)
+            .silent(true)
+            .build()
+            .unwrap();
+
+        p.run(10, &StopToken::never());
+        assert_eq!(p.stats().attempted, 2);
+        assert_eq!(p.stats().failed, 2);
+        assert!(!p.max_failures_reached());
+    }
+
+    #[test]
+    fn max_failures_trips_an_endless_run() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut p = Pinger::builder("127.0.0.1", port)
+            .interval(Duration::from_millis(10))
+            .max_failures(3)
+            .silent(true)
+            .build()
+            .unwrap();
+
+        p.run(0, &StopToken::never());
+        assert_eq!(p.stats().failed, 3);
+        assert!(p.max_failures_reached());
+    }
+
+    #[test]
+    fn wait_returns_once_a_late_listener_accepts() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            let listener = std::net::TcpListener::bind(addr).unwrap();
+            let _ = listener.accept();
+        });
+        let mut p = Pinger::builder("127.0.0.1", addr.port())
+            .interval(Duration::from_millis(50))
+            .silent(true)
+            .build()
+            .unwrap();
+
+        let open = Awaited::Open { confirm: 1 };
+        let wait = p.wait_for(open, 0, Some(Duration::from_secs(10)), &StopToken::never());
+        server.join().unwrap();
+        assert!(wait.reached, "{:?}", wait);
+        assert!(wait.attempts > 1);
+        assert!(wait.elapsed >= Duration::from_millis(300));
+        assert_eq!(p.stats().connected, 1);
+    }
+
+    #[test]
+    fn wait_gives_up_at_the_limit() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut p = Pinger::builder("127.0.0.1", port)
+            .interval(Duration::from_millis(50))
+            .silent(true)
+            .build()
+            .unwrap();
+
+        let open = Awaited::Open { confirm: 1 };
+        let wait = p.wait_for(open, 0, Some(Duration::from_millis(200)), &StopToken::never());
+        assert!(!wait.reached);
+        assert!(wait.elapsed < Duration::from_secs(2));
+        assert!(matches!(wait.last, Some(PingResult::Failed(_))));
+        let wait = p.wait_for(open, 2, None, &StopToken::never());
+        assert_eq!((wait.reached, wait.attempts), (false, 2));
+    }
+
+    #[test]
+    fn wait_closed_needs_the_confirmations_in_a_row() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut p = Pinger::builder("127.0.0.1", addr.port())
+            .interval(Duration::from_millis(20))
+            .silent(true)
+            .build()
+            .unwrap();
+
+        let closed = Awaited::Closed { confirm: 3 };
+        let wait = p.wait_for(closed, 2, None, &StopToken::never());
+        assert_eq!((wait.reached, wait.attempts), (false, 2));
+
+        drop(listener);
+        let before = SystemTime::now();
+        let wait = p.wait_for(closed, 10, None, &StopToken::never());
+        assert!(wait.reached, "{:?}", wait);
+        assert_eq!(wait.attempts, 3);
+        assert!(wait.since.unwrap() >= before);
+    }
+
+    #[test]
+    fn strict_interval_counts_from_the_start_of_each_round() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let run = |strict: bool| {
+            let mut p = Pinger::builder("127.0.0.1", port)
+                .interval(Duration::from_millis(150))
+                .strict_interval(strict)
+                .silent(true)
+                .build()
+                .unwrap();
+            let start = Instant::now();
+            // A slow callback stands in for slow connects
+            p.run_with(3, &StopToken::never(), |_| {
+                std::thread::sleep(Duration::from_millis(100))
+            });
+            start.elapsed()
+        };
+        // Rounds start at 0, 150 and 300ms, the last one ends at 400ms,
+        // against 3 * 100 + 2 * 150 = 600ms otherwise
+        assert!(run(true) < Duration::from_millis(550));
+        assert!(run(false) >= Duration::from_millis(600));
+    }
+
+    #[test]
+    fn endless_run_stops_when_asked_from_another_thread() {
+        // A port nothing listens on: refused at once, no network involved
+        let port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let pinger = move || {
+            Pinger::builder("127.0.0.1", port)
+                .timeout(Duration::from_millis(50))
+                .interval(Duration::from_secs(30))
+                .silent(true)
+                .build()
+                .unwrap()
+        };
+        // Stops within the 30s pause, with what was probed until then
+        let check = |run: std::thread::JoinHandle<Pinger>, stop: &dyn Fn()| {
+            std::thread::sleep(Duration::from_millis(100));
+            let start = Instant::now();
+            stop();
+            let p = run.join().unwrap();
+            assert!(start.elapsed() < Duration::from_secs(2), "{:?}", start.elapsed());
+            assert_eq!((p.stats().attempted, p.stats().failed), (1, 1));
+        };
+
+        let handle = crate::stop::StopHandle::new();
+        let token = handle.token();
+        let run = std::thread::spawn(move || {
+            let mut p = pinger();
+            p.run(0, &token);
+            p
+        });
+        check(run, &|| handle.stop());
+
+        // The old shared-flag form, which polls rather than being woken
+        let flag = Arc::new(AtomicBool::new(false));
+        let shared = flag.clone();
+        let run = std::thread::spawn(move || {
+            let mut p = pinger();
+            #[allow(deprecated)]
+            p.run_with_flag(0, &shared);
+            p
+        });
+        check(run, &|| flag.store(true, Ordering::SeqCst));
+    }
+
+    #[test]
+    fn stats_requests_are_seen_during_the_pause() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let request = Arc::new(AtomicBool::new(false));
+        let mut p = Pinger::builder("127.0.0.1", port)
+            .interval(Duration::from_secs(30))
+            .stats_request(Arc::clone(&request))
+            .build()
+            .unwrap();
+        let handle = crate::stop::StopHandle::new();
+        let token = handle.token();
+        let run = std::thread::spawn(move || {
+            p.run(0, &token);
+            p
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        request.store(true, Ordering::SeqCst);
+        // Seen, and cleared, long before the next probe
+        let start = Instant::now();
+        while request.load(Ordering::SeqCst) {
+            assert!(start.elapsed() < Duration::from_secs(2));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        handle.stop();
+        assert_eq!(run.join().unwrap().stats().attempted, 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sctp_associates_where_supported() {
+        use crate::builder::BuildError;
+
+        if !sctp_supported() {
+            let built = Pinger::builder("127.0.0.1", 9).sctp(true).build();
+            assert_eq!(built.err(), Some(BuildError::SctpUnsupported));
+            return;
+        }
+        let listener = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::SCTP)).unwrap();
+        listener
+            .bind(&"127.0.0.1:0".parse::<SocketAddr>().unwrap().into())
+            .unwrap();
+        listener.listen(8).unwrap();
+        let port = listener.local_addr().unwrap().as_socket().unwrap().port();
+        let p = Pinger::builder("127.0.0.1", port).sctp(true).build().unwrap();
+        let result = p.probe();
+        assert!(result.is_success(), "{:?}", result);
+        assert!(p.render(&result).contains("SCTP"));
+        drop(listener);
+        assert!(!p.probe().is_success());
+    }
+
+    #[test]
+    fn heartbeats_go_on_during_long_pauses() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let beats = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let counted = Arc::clone(&beats);
+        let mut p = Pinger::builder("127.0.0.1", port)
+            .interval(Duration::from_secs(30))
+            .silent(true)
+            .heartbeat(
+                Duration::from_millis(50),
+                Arc::new(move || {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                }),
+            )
+            .build()
+            .unwrap();
+        let handle = crate::stop::StopHandle::new();
+        let token = handle.token();
+        let run = std::thread::spawn(move || {
+            p.run(0, &token);
+            p
+        });
+        std::thread::sleep(Duration::from_millis(500));
+        handle.stop();
+        assert_eq!(run.join().unwrap().stats().attempted, 1);
+        // One for the probe, then about one every 50ms of the pause
+        let beats = beats.load(Ordering::SeqCst);
+        assert!((4..=11).contains(&beats), "{}", beats);
+    }
+
+    #[test]
+    fn startup_delay_comes_before_the_first_probe() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let pinger = move |delay| {
+            Pinger::builder("127.0.0.1", port)
+                .startup_delay(delay)
+                .silent(true)
+                .build()
+                .unwrap()
+        };
+        let start = Instant::now();
+        let mut p = pinger(Duration::from_millis(300));
+        p.run(1, &StopToken::never());
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(p.stats().connected, 1);
+
+        // Stopping during the delay ends the run without probing
+        let handle = crate::stop::StopHandle::new();
+        let token = handle.token();
+        let run = std::thread::spawn(move || {
+            let mut p = pinger(Duration::from_secs(30));
+            p.run(0, &token);
+            p
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        handle.stop();
+        assert_eq!(run.join().unwrap().stats().attempted, 0);
+    }
+
+    #[test]
+    fn run_with_callback_can_stop_its_own_run() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut p = Pinger::builder("127.0.0.1", port)
+            .interval(Duration::from_secs(30))
+            .build()
+            .unwrap();
+        let observed = Arc::new(Mutex::new(0));
+        let counter = observed.clone();
+        p.on_result(move |_| *counter.lock().unwrap() += 1);
+
+        let handle = crate::stop::StopHandle::new();
+        let mut seen = Vec::new();
+        p.run_with(0, &handle.token(), |result| {
+            seen.push(result.is_success());
+            // Would wait 30s for the next probe if stop() didn't wake the run
+            handle.stop();
+        });
+        assert_eq!(seen, vec![true]);
+        assert_eq!(*observed.lock().unwrap(), 1);
+        // The console printer is back afterwards
+        assert_eq!(p.observers_mut().len(), 2);
+    }
+
+    #[test]
+    fn reads_the_banner_of_a_listener_then_sees_it_reject() {
+        let listener = crate::listener::Listener::bind("127.0.0.1:0".parse().unwrap())
+            .unwrap()
+            .with_banner("220 ready\r\n")
+            .with_reject(true);
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            listener.serve(1, &StopToken::never(), |_| {});
+        });
+        let p = Pinger::builder("127.0.0.1", port)
+            .payload(Payload::banner())
+            .silent(true)
+            .build()
+            .unwrap();
+        match p.probe() {
+            PingResult::Connected(c) => {
+                assert_eq!(c.exchange.unwrap().reply.unwrap(), b"220 ready\r\n")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn sends_the_proxy_header_first() {
+        use crate::proxy_protocol::{ProxyHeader, Version};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            conn.read_to_end(&mut received).unwrap();
+            received
+        });
+        let p = Pinger::builder("127.0.0.1", port)
+            .proxy_header(ProxyHeader::new(Version::V1, "1.2.3.4:5678".parse().unwrap()))
+            .payload(Payload::parse("hello", false).unwrap())
+            .silent(true)
+            .build()
+            .unwrap();
+        assert!(p.probe().is_success());
+        let expected = format!("PROXY TCP4 1.2.3.4 127.0.0.1 5678 {}\r\nhello", port);
+        assert_eq!(String::from_utf8(server.join().unwrap()).unwrap(), expected);
+    }
+}
@@ -0,0 +1,199 @@
+//! --progress-fd: a line of JSON per ping on a file descriptor the caller
+//! opened (or a file), for a frontend to follow the run by, while the
+//! usual output goes to stdout:
+//!
+//! ```text
+//! {"ts":"2026-10-15T09:30:00.123Z","seq":3,"total":10,"percent":30.0,"status":"connected","time_ms":12.5,"connected":3,"failed":0,"loss_percent":0.0}
+//! ```
+//!
+//! `total` and `percent` are left out of endless runs (-c 0), `time_ms` of
+//! failed pings. Windows has no descriptors to inherit this way: a path
+//! stands in for one there (a named pipe, say).
+
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::time::SystemTime;
+
+use colored::Colorize;
+use paping::pinger::PingResult;
+use paping::template::rfc3339;
+use serde::Serialize;
+
+/// One line of progress.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Progress {
+    pub ts: String,
+    /// 1 for the first ping of the run
+    pub seq: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+    /// "connected" or "failed"
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_ms: Option<f64>,
+    pub connected: u32,
+    pub failed: u32,
+    pub loss_percent: f64,
+}
+
+impl Progress {
+    /// The progress after `result`, the `seq`th ping of `total` (0 for an
+    /// endless run), `connected` of them so far (this one included).
+    fn new(result: &PingResult, seq: u32, total: u32, connected: u32) -> Self {
+        let failed = seq - connected;
+        Progress {
+            ts: rfc3339(SystemTime::now()),
+            seq,
+            total: (total > 0).then_some(total),
+            percent: (total > 0).then(|| seq as f64 * 100.0 / total as f64),
+            status: if result.is_success() {
+                "connected"
+            } else {
+                "failed"
+            },
+            time_ms: result.time_ms(),
+            connected,
+            failed,
+            loss_percent: failed as f64 * 100.0 / seq as f64,
+        }
+    }
+}
+
+/// Where the progress goes.
+pub struct ProgressOut {
+    out: LineWriter<File>,
+    what: String,
+}
+
+impl ProgressOut {
+    /// Opens `spec`: a descriptor number on Unix (one the caller left
+    /// open, other than stdin), or else the path of a file to create.
+    pub fn open(spec: &str) -> Result<Self, String> {
+        let file = match spec.parse::<i32>() {
+            Ok(fd) => descriptor(fd)?,
+            Err(_) => File::create(spec).map_err(|e| format!("cannot open {}: {}", spec, e))?,
+        };
+        let what = match spec.parse::<i32>() {
+            Ok(fd) => format!("fd {}", fd),
+            Err(_) => spec.to_string(),
+        };
+        Ok(ProgressOut {
+            out: LineWriter::new(file),
+            what,
+        })
+    }
+
+    /// Observer to register with `Pinger::on_result`, for a run of `total`
+    /// pings (0 = endless). Stops writing at the first error (the reader
+    /// gone, most likely), and warns of it once.
+    pub fn observer(mut self, total: u32) -> impl FnMut(&PingResult) + Send + 'static {
+        let (mut seq, mut connected) = (0, 0);
+        let mut failed = false;
+        move |result| {
+            seq += 1;
+            connected += result.is_success() as u32;
+            if failed {
+                return;
+            }
+            if let Err(e) = self.write(&Progress::new(result, seq, total, connected)) {
+                failed = true;
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Warning: --progress-fd: cannot write to {}: {} (no more progress)",
+                        self.what, e
+                    )
+                    .yellow()
+                );
+            }
+        }
+    }
+
+    fn write(&mut self, progress: &Progress) -> io::Result<()> {
+        let line = serde_json::to_string(progress)?;
+        writeln!(self.out, "{}", line)
+    }
+}
+
+#[cfg(unix)]
+fn descriptor(fd: i32) -> Result<File, String> {
+    use std::os::fd::FromRawFd;
+
+    if fd == libc::STDIN_FILENO || fd < 0 {
+        return Err(format!("{} isn't a descriptor to write to", fd));
+    }
+    // SAFETY: only asks whether the descriptor is open
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(format!(
+            "descriptor {} isn't open (e.g. run paping with {}>progress.ndjson)",
+            fd, fd
+        ));
+    }
+    // SAFETY: open, and nothing else in paping uses it. The File owns it
+    // from now on, and closes it once the run ends.
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn descriptor(fd: i32) -> Result<File, String> {
+    Err(format!(
+        "descriptor numbers ({}) are only supported on Unix: give the path of a file \
+         (or a named pipe) instead",
+        fd
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paping::pinger::FailureKind;
+    use std::time::Duration;
+
+    #[test]
+    fn writes_a_line_per_ping() {
+        let path = std::env::temp_dir().join(format!("paping-progress-{}", std::process::id()));
+        let out = ProgressOut::open(path.to_str().unwrap()).unwrap();
+        let up = PingResult::connected(Duration::from_micros(12_500));
+        let down = PingResult::failed(FailureKind::Timeout, "timed out");
+        let mut observer = out.observer(4);
+        for result in [&up, &down, &up] {
+            observer(result);
+        }
+        drop(observer);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["status"], "connected");
+        assert_eq!(lines[0]["time_ms"], 12.5);
+        assert_eq!(lines[1]["status"], "failed");
+        assert!(lines[1].get("time_ms").is_none());
+        assert_eq!(lines[2]["seq"], 3);
+        assert_eq!(lines[2]["total"], 4);
+        assert_eq!(lines[2]["percent"], 75.0);
+        assert_eq!(lines[2]["connected"], 2);
+        assert_eq!(lines[2]["failed"], 1);
+    }
+
+    #[test]
+    fn endless_runs_have_no_total() {
+        let down = PingResult::failed(FailureKind::Timeout, "timed out");
+        let progress = Progress::new(&down, 2, 0, 1);
+        assert_eq!((progress.total, progress.percent), (None, None));
+        assert_eq!(progress.loss_percent, 50.0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_descriptors_not_open() {
+        assert!(ProgressOut::open("0").is_err());
+        let err = ProgressOut::open("987").err().unwrap();
+        assert!(err.contains("isn't open"), "{}", err);
+    }
+}
@@ -0,0 +1,318 @@
+//! --prometheus: the results as Prometheus metrics, served over HTTP at
+//! /metrics for scraping.
+//!
+//! ```text
+//! paping_attempts_total{target="example.com",port="443"} 120
+//! paping_failures_total{target="example.com",port="443",kind="timeout"} 2
+//! paping_last_rtt_seconds{target="example.com",port="443"} 0.0125
+//! paping_rtt_seconds_bucket{target="example.com",port="443",le="0.025"} 117
+//! ```
+//!
+//! All targets share one registry behind a lock, taken once per result and
+//! once per scrape, so a scrape never sees a result half counted. The
+//! responder is a plain loop over a `TcpListener`, a request at a time.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use paping::pinger::PingResult;
+
+/// Upper bounds of the RTT histogram buckets, in seconds.
+const BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+/// How long a scraper may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// Requests are read up to this size; the rest is ignored.
+const MAX_REQUEST: usize = 8192;
+
+/// What is known of one target.
+#[derive(Default)]
+struct Series {
+    target: String,
+    port: u16,
+    attempts: u64,
+    /// By failure kind, in the order they first happened
+    failures: Vec<(&'static str, u64)>,
+    last_rtt: Option<f64>,
+    /// Non-cumulative: what fell in each bucket, the last one being +Inf
+    buckets: [u64; BUCKETS.len() + 1],
+    rtt_sum: f64,
+    rtt_count: u64,
+}
+
+impl Series {
+    fn record(&mut self, result: &PingResult) {
+        self.attempts += 1;
+        match result {
+            PingResult::Connected(c) => {
+                let rtt = c.time.as_secs_f64();
+                self.last_rtt = Some(rtt);
+                let bucket = BUCKETS
+                    .iter()
+                    .position(|&le| rtt <= le)
+                    .unwrap_or(BUCKETS.len());
+                self.buckets[bucket] += 1;
+                self.rtt_sum += rtt;
+                self.rtt_count += 1;
+            }
+            PingResult::Failed(f) => {
+                let kind = f.kind.as_str();
+                match self.failures.iter_mut().find(|(k, _)| *k == kind) {
+                    Some((_, n)) => *n += 1,
+                    None => self.failures.push((kind, 1)),
+                }
+            }
+        }
+    }
+
+    fn labels(&self) -> String {
+        format!("target=\"{}\",port=\"{}\"", escape(&self.target), self.port)
+    }
+}
+
+/// The metrics of every target, in the text exposition format.
+fn render(series: &[Series]) -> String {
+    let mut out = String::new();
+    out.push_str("# HELP paping_attempts_total Connection attempts.\n");
+    out.push_str("# TYPE paping_attempts_total counter\n");
+    for s in series {
+        let _ = writeln!(
+            out,
+            "paping_attempts_total{{{}}} {}",
+            s.labels(),
+            s.attempts
+        );
+    }
+    out.push_str("# HELP paping_failures_total Failed connection attempts, by kind of error.\n");
+    out.push_str("# TYPE paping_failures_total counter\n");
+    for s in series {
+        for (kind, n) in &s.failures {
+            let _ = writeln!(
+                out,
+                "paping_failures_total{{{},kind=\"{}\"}} {}",
+                s.labels(),
+                kind,
+                n
+            );
+        }
+    }
+    out.push_str(
+        "# HELP paping_last_rtt_seconds Connection time of the last successful attempt.\n",
+    );
+    out.push_str("# TYPE paping_last_rtt_seconds gauge\n");
+    for s in series {
+        if let Some(rtt) = s.last_rtt {
+            let _ = writeln!(out, "paping_last_rtt_seconds{{{}}} {}", s.labels(), rtt);
+        }
+    }
+    out.push_str("# HELP paping_rtt_seconds Connection time of successful attempts.\n");
+    out.push_str("# TYPE paping_rtt_seconds histogram\n");
+    for s in series {
+        let labels = s.labels();
+        let mut cumulative = 0;
+        for (i, n) in s.buckets.iter().enumerate() {
+            cumulative += n;
+            let le = match BUCKETS.get(i) {
+                Some(le) => le.to_string(),
+                None => "+Inf".to_string(),
+            };
+            let _ = writeln!(
+                out,
+                "paping_rtt_seconds_bucket{{{},le=\"{}\"}} {}",
+                labels, le, cumulative
+            );
+        }
+        let _ = writeln!(out, "paping_rtt_seconds_sum{{{}}} {}", labels, s.rtt_sum);
+        let _ = writeln!(
+            out,
+            "paping_rtt_seconds_count{{{}}} {}",
+            labels, s.rtt_count
+        );
+    }
+    out
+}
+
+/// Label values escape backslashes, double quotes and newlines.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The /metrics endpoint: bound by `bind`, so that an address in use fails
+/// at startup, then served from a thread by `start`.
+pub struct Exporter {
+    listener: TcpListener,
+    series: Arc<Mutex<Vec<Series>>>,
+}
+
+impl Exporter {
+    /// Binds to `addr`: IP:PORT, [IPv6]:PORT, or PORT on every interface.
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = match addr.parse::<u16>() {
+            Ok(port) => TcpListener::bind(("0.0.0.0", port))?,
+            Err(_) => TcpListener::bind(addr)?,
+        };
+        Ok(Exporter {
+            listener,
+            series: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answers scrapes from a thread, for as long as the process runs.
+    pub fn start(&self) -> io::Result<()> {
+        let listener = self.listener.try_clone()?;
+        let series = Arc::clone(&self.series);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A scraper gone wrong only affects its own request
+                let _ = respond(stream, &series);
+            }
+        });
+        Ok(())
+    }
+
+    /// Updates the `/metrics` series of `target`:`port`, which are served
+    /// from now on, even before a result.
+    pub fn observer(&self, target: &str, port: u16) -> impl FnMut(&PingResult) + Send + 'static {
+        let series = Arc::clone(&self.series);
+        let index = {
+            let mut all = lock(&series);
+            match all
+                .iter()
+                .position(|s| s.target == target && s.port == port)
+            {
+                Some(index) => index,
+                None => {
+                    all.push(Series {
+                        target: target.to_string(),
+                        port,
+                        ..Series::default()
+                    });
+                    all.len() - 1
+                }
+            }
+        };
+        move |result| lock(&series)[index].record(result)
+    }
+}
+
+fn lock(series: &Mutex<Vec<Series>>) -> std::sync::MutexGuard<'_, Vec<Series>> {
+    series.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Reads one request, answers it and closes the connection.
+fn respond(mut stream: TcpStream, series: &Mutex<Vec<Series>>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        match stream.read(&mut buf)? {
+            0 => break,
+            n => request.extend_from_slice(&buf[..n]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut words = request.lines().next().unwrap_or("").split_whitespace();
+    let (method, path) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+    let path = path.split('?').next().unwrap_or("");
+    let (status, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", render(&lock(series))),
+        ("GET", _) => (
+            "404 Not Found",
+            "Not found: the metrics are at /metrics\n".to_string(),
+        ),
+        _ => (
+            "405 Method Not Allowed",
+            "Only GET is supported\n".to_string(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paping::pinger::FailureKind;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: paping\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn counts_results_per_target() {
+        let mut series = Series {
+            target: "a\"b".to_string(),
+            port: 443,
+            ..Series::default()
+        };
+        for result in [
+            PingResult::connected(Duration::from_millis(3)),
+            PingResult::connected(Duration::from_millis(40)),
+            PingResult::failed(FailureKind::Timeout, ""),
+            PingResult::failed(FailureKind::Timeout, ""),
+        ] {
+            series.record(&result);
+        }
+        series.record(&PingResult::failed(FailureKind::Refused, ""));
+        let text = render(&[series]);
+        let labels = "target=\"a\\\"b\",port=\"443\"";
+        for line in [
+            format!("paping_attempts_total{{{}}} 5", labels),
+            format!("paping_failures_total{{{},kind=\"timeout\"}} 2", labels),
+            format!("paping_failures_total{{{},kind=\"refused\"}} 1", labels),
+            format!("paping_last_rtt_seconds{{{}}} 0.04", labels),
+            format!("paping_rtt_seconds_bucket{{{},le=\"0.0025\"}} 0", labels),
+            format!("paping_rtt_seconds_bucket{{{},le=\"0.005\"}} 1", labels),
+            format!("paping_rtt_seconds_bucket{{{},le=\"0.05\"}} 2", labels),
+            format!("paping_rtt_seconds_bucket{{{},le=\"+Inf\"}} 2", labels),
+            format!("paping_rtt_seconds_count{{{}}} 2", labels),
+        ] {
+            assert!(text.lines().any(|l| l == line), "no {} in\n{}", line, text);
+        }
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let exporter = Exporter::bind("127.0.0.1:0").unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let mut a = exporter.observer("a.example", 443);
+        let mut b = exporter.observer("b.example", 80);
+        exporter.start().unwrap();
+        a(&PingResult::connected(Duration::from_millis(12)));
+        b(&PingResult::failed(FailureKind::Timeout, ""));
+
+        let response = get(addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("paping_attempts_total{target=\"a.example\",port=\"443\"} 1"));
+        assert!(response.contains(
+            "paping_failures_total{target=\"b.example\",port=\"80\",kind=\"timeout\"} 1"
+        ));
+        assert!(get(addr, "/").starts_with("HTTP/1.1 404"));
+
+        // Taken at startup: a second exporter can't have the port
+        assert!(Exporter::bind(&addr.to_string()).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! PROXY protocol header (--proxy-protocol), sent first thing on every
+//! connection so that a backend behind a load balancer speaking the
+//! protocol sees a made-up client address, and accepts the connection
+//! rather than waiting for a header or dropping it.
+//!
+//! Version 1 is a line of text, version 2 a binary header; see
+//! <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>.
+
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use crate::payload::Timeouts;
+use crate::result::{Failure, FailureKind};
+
+/// Start of every version 2 header.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Version 2, PROXY command (as opposed to LOCAL).
+const V2_PROXY: u8 = 0x21;
+
+/// Version 2 address family and transport: TCP over IPv4, or over IPv6.
+const V2_TCP4: u8 = 0x11;
+const V2_TCP6: u8 = 0x21;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    /// Text: `PROXY TCP4 1.2.3.4 10.0.0.1 5678 443\r\n`
+    V1,
+    /// Binary, with a fixed signature
+    V2,
+}
+
+impl Version {
+    /// Parses `v1`/`1` or `v2`/`2`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.to_ascii_lowercase().as_str() {
+            "v1" | "1" => Ok(Version::V1),
+            "v2" | "2" => Ok(Version::V2),
+            _ => Err(format!(
+                "unknown PROXY protocol version '{}' (v1 or v2)",
+                spec
+            )),
+        }
+    }
+}
+
+/// The header to send, claiming connections come from `source`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProxyHeader {
+    pub version: Version,
+    pub source: SocketAddr,
+}
+
+impl ProxyHeader {
+    pub fn new(version: Version, source: SocketAddr) -> Self {
+        ProxyHeader { version, source }
+    }
+
+    /// The header of a connection to `destination`. Both addresses must be
+    /// of the same family: when they aren't, the IPv4 one is given as an
+    /// IPv4-mapped IPv6 address.
+    pub fn encode(&self, destination: SocketAddr) -> Vec<u8> {
+        let (source, destination) = match (self.source, destination) {
+            (s @ SocketAddr::V4(_), d @ SocketAddr::V6(_))
+            | (s @ SocketAddr::V6(_), d @ SocketAddr::V4(_)) => (to_v6(s), to_v6(d)),
+            pair => pair,
+        };
+        match self.version {
+            Version::V1 => {
+                let family = if source.is_ipv4() { "TCP4" } else { "TCP6" };
+                format!(
+                    "PROXY {} {} {} {} {}\r\n",
+                    family,
+                    source.ip(),
+                    destination.ip(),
+                    source.port(),
+                    destination.port()
+                )
+                .into_bytes()
+            }
+            Version::V2 => {
+                let mut header = V2_SIGNATURE.to_vec();
+                header.push(V2_PROXY);
+                let (family, addresses) = match (source.ip(), destination.ip()) {
+                    (IpAddr::V4(s), IpAddr::V4(d)) => {
+                        (V2_TCP4, [s.octets().to_vec(), d.octets().to_vec()].concat())
+                    }
+                    (s, d) => (V2_TCP6, [v6_octets(s), v6_octets(d)].concat()),
+                };
+                header.push(family);
+                header.extend_from_slice(&(addresses.len() as u16 + 4).to_be_bytes());
+                header.extend_from_slice(&addresses);
+                header.extend_from_slice(&source.port().to_be_bytes());
+                header.extend_from_slice(&destination.port().to_be_bytes());
+                header
+            }
+        }
+    }
+
+    /// Writes the header of a connection to `destination` on `stream`,
+    /// within `timeout`.
+    pub(crate) fn send<S: Write + Timeouts>(
+        &self,
+        stream: &mut S,
+        destination: SocketAddr,
+        timeout: Duration,
+    ) -> Result<(), Failure> {
+        stream
+            .set_timeouts(timeout, timeout)
+            .and_then(|_| stream.write_all(&self.encode(destination)))
+            .and_then(|_| stream.flush())
+            .map_err(|e| Failure {
+                kind: FailureKind::classify(&e, false),
+                message: format!("connected, but sending the PROXY header failed: {}", e),
+            })
+    }
+}
+
+fn to_v6(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(v4) => SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port()),
+        v6 => v6,
+    }
+}
+
+fn v6_octets(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped().octets().to_vec(),
+        IpAddr::V6(v6) => v6.octets().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(Version::parse("v1"), Ok(Version::V1));
+        assert_eq!(Version::parse("2"), Ok(Version::V2));
+        assert_eq!(Version::parse("V2"), Ok(Version::V2));
+        assert!(Version::parse("v3").is_err());
+    }
+
+    #[test]
+    fn encodes_v1_lines() {
+        let header = ProxyHeader::new(Version::V1, addr("1.2.3.4:5678"));
+        assert_eq!(
+            header.encode(addr("10.0.0.1:443")),
+            b"PROXY TCP4 1.2.3.4 10.0.0.1 5678 443\r\n"
+        );
+        let header = ProxyHeader::new(Version::V1, addr("[2001:db8::1]:5678"));
+        assert_eq!(
+            header.encode(addr("[2001:db8::2]:443")),
+            b"PROXY TCP6 2001:db8::1 2001:db8::2 5678 443\r\n"
+        );
+        // Mixed families: the IPv4 destination is mapped
+        assert_eq!(
+            header.encode(addr("10.0.0.1:443")),
+            b"PROXY TCP6 2001:db8::1 ::ffff:10.0.0.1 5678 443\r\n"
+        );
+    }
+
+    #[test]
+    fn encodes_v2_headers() {
+        let header = ProxyHeader::new(Version::V2, addr("1.2.3.4:5678"));
+        let bytes = header.encode(addr("10.0.0.1:443"));
+        assert_eq!(&bytes[..12], &V2_SIGNATURE);
+        assert_eq!(
+            &bytes[12..],
+            [
+                0x21, 0x11, 0, 12, // PROXY, TCP over IPv4, 12 bytes of addresses and ports
+                1, 2, 3, 4, 10, 0, 0, 1, // source and destination
+                0x16, 0x2e, 0x01, 0xbb, // ports 5678 and 443
+            ]
+        );
+
+        let bytes = header.encode(addr("[2001:db8::2]:443"));
+        assert_eq!(&bytes[12..16], [0x21, 0x21, 0, 36]);
+        assert_eq!(bytes.len(), 16 + 36);
+        assert_eq!(
+            &bytes[16..32],
+            &"::ffff:1.2.3.4"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets()
+        );
+    }
+}
@@ -0,0 +1,873 @@
+//! Probe results and statistics, shared by every way of running probes.
+//!
+//! With the `serde` feature these types implement `Serialize` and
+//! `Deserialize` with a stable schema (shown here as JSON):
+//!
+//! ```text
+//! {"status": "connected", "time_ms": 12.5, "addr": "1.1.1.1:443", "proxy": null}
+//! {"status": "failed", "kind": "timeout", "message": "connection timed out"}
+//! {"attempted": 3, "connected": 2, "failed": 1, "resolve_failed": 0, "times_ms": [12.5, 13.1],
+//!  "loss_percent": 33.3, "min_ms": 12.5, "avg_ms": 12.8, "max_ms": 13.1}
+//! ```
+//!
+//! `kind` is one of `resolve`, `timeout`, `refused`, `unreachable`,
+//! `network_down`, `proxy`, `tls`, `slow`, `no_reply` and `other`; `proxy` is
+//! `null`, `"SOCKS5"` or `"SOCKS5+TLS"`. Probes that sent a payload
+//! (`--send`) also carry
+//! `"exchange": {"time_ms": 0.8, "sent": 5, "reply": [79, 75]}`, where
+//! `reply` is `null` when no reply was waited for. Probes measuring
+//! throughput (`--throughput`) carry
+//! `"transfer": {"direction": "upload", "bytes": 10000000, "time_ms": 105.2}`,
+//! and TLS probes (`--tls`) `"tls": {"time_ms": 24.1, "version": "TLSv1.3",
+//! "cipher": "TLS13_AES_256_GCM_SHA384", "cert_expires_in_days": 61}`.
+//!
+//! The statistics end with figures worked out from the counters (`null`
+//! without connections), and once the target has been down, with
+//! `"outages": [{"start": "2026-10-15T09:41:07.456Z", "end": null,
+//! "duration_ms": 12000.0, "failed": 4}]` (the last kept, `end` being
+//! `null` while ongoing) and `"earlier_outages": 3` (those dropped). These
+//! are only written: reading statistics back ignores them.
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Outcome of a single probe (one connection attempt).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "status", rename_all = "lowercase"))]
+pub enum PingResult {
+    Connected(Connection),
+    Failed(Failure),
+}
+
+/// A successful connection.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Connection {
+    /// Time taken to establish the connection (DNS lookup included for
+    /// direct connections, whole tunnel setup through a proxy)
+    #[cfg_attr(feature = "serde", serde(rename = "time_ms", with = "serde_impl::millis"))]
+    pub time: Duration,
+    /// Address we connected to. `None` through a proxy, which resolves
+    /// the target itself.
+    pub addr: Option<SocketAddr>,
+    /// Proxy transport used ("SOCKS5", "SOCKS5+TLS"), if any
+    pub proxy: Option<&'static str>,
+    /// What happened after connecting, when a payload was sent
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub exchange: Option<Exchange>,
+    /// Data moved after connecting, when measuring throughput
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub transfer: Option<Transfer>,
+    /// The TLS session set up once connected, with --tls
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub tls: Option<TlsSession>,
+}
+
+/// A TLS handshake made once connected (see `Pinger::with_tls`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TlsSession {
+    /// From the ClientHello to the end of the handshake. Not included in
+    /// the connection time.
+    #[cfg_attr(feature = "serde", serde(rename = "time_ms", with = "serde_impl::millis"))]
+    pub handshake: Duration,
+    /// Protocol version negotiated: "TLSv1.3", "TLSv1.2"
+    pub version: String,
+    /// Cipher suite negotiated, e.g. "TLS13_AES_256_GCM_SHA384"
+    pub cipher: String,
+    /// Whole days until the server certificate expires, negative once it
+    /// has; `None` when its validity couldn't be read
+    pub cert_expires_in_days: Option<i64>,
+}
+
+/// A payload sent once connected, and the reply to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Exchange {
+    /// From writing the payload to the first bytes of the reply, or until
+    /// the payload was written when no reply was waited for. Not included
+    /// in the connection time.
+    #[cfg_attr(feature = "serde", serde(rename = "time_ms", with = "serde_impl::millis"))]
+    pub time: Duration,
+    /// Payload size in bytes
+    pub sent: usize,
+    /// First bytes of the reply (what a single read returned), if waited for
+    pub reply: Option<Vec<u8>>,
+}
+
+/// Bulk data sent to or received from a cooperative listener once
+/// connected (see [`crate::throughput`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Transfer {
+    pub direction: Direction,
+    pub bytes: u64,
+    /// From the first byte of data to the last one being accounted for
+    /// by the receiving end. Not included in the connection time.
+    #[cfg_attr(feature = "serde", serde(rename = "time_ms", with = "serde_impl::millis"))]
+    pub time: Duration,
+}
+
+/// Which way data goes, seen from the pinging end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Direction {
+    /// To the listener
+    Upload,
+    /// From the listener
+    Download,
+}
+
+impl Transfer {
+    /// Megabytes (10^6 bytes) per second.
+    pub fn megabytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / 1e6 / self.time.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+impl Direction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::Upload => "upload",
+            Direction::Download => "download",
+        }
+    }
+}
+
+/// A failed connection attempt.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Failure {
+    pub kind: FailureKind,
+    /// Human-readable reason, as printed on the failure line
+    pub message: String,
+}
+
+/// Broad category of a failure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum FailureKind {
+    /// The target name could not be resolved
+    Resolve,
+    /// No answer within the timeout
+    Timeout,
+    /// The target (or the proxy, on its behalf) refused the connection
+    Refused,
+    /// No route to the host
+    Unreachable,
+    /// Our own side of the network is down: interface gone or without a
+    /// route, source address no longer ours
+    #[cfg_attr(feature = "serde", serde(rename = "network_down"))]
+    NetworkDown,
+    /// The proxy misbehaved or rejected us (handshake, auth, protocol)
+    Proxy,
+    /// Connected, but the TLS handshake failed or the certificate was
+    /// refused (--tls)
+    Tls,
+    /// Connected, but above the latency limit (--max-latency)
+    Slow,
+    /// Connected, but the reply or banner didn't come in time (--recv)
+    #[cfg_attr(feature = "serde", serde(rename = "no_reply"))]
+    NoReply,
+    /// Anything else
+    Other,
+}
+
+impl PingResult {
+    /// A connection made in `time`, with nothing else to tell (no address,
+    /// proxy, payload or TLS): what tests of result observers feed them.
+    pub fn connected(time: Duration) -> Self {
+        PingResult::Connected(Connection {
+            time,
+            addr: None,
+            proxy: None,
+            exchange: None,
+            transfer: None,
+            tls: None,
+        })
+    }
+
+    /// A failure of `kind`, reading `message`.
+    pub fn failed(kind: FailureKind, message: &str) -> Self {
+        PingResult::Failed(Failure {
+            kind,
+            message: message.to_string(),
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        matches!(self, PingResult::Connected(_))
+    }
+
+    /// Connection time in milliseconds, for successful probes.
+    pub fn time_ms(&self) -> Option<f64> {
+        match self {
+            PingResult::Connected(c) => Some(c.time.as_secs_f64() * 1000.0),
+            PingResult::Failed(_) => None,
+        }
+    }
+}
+
+impl Failure {
+    /// Builds a failure from the I/O error of a connection attempt.
+    /// `via_proxy` tells whether the error came out of the SOCKS5 handshake.
+    pub fn from_io(e: &io::Error, via_proxy: bool) -> Self {
+        Failure {
+            kind: FailureKind::classify(e, via_proxy),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl FailureKind {
+    /// Maps an I/O error to a failure category.
+    pub fn classify(e: &io::Error, via_proxy: bool) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => FailureKind::Resolve,
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => FailureKind::Timeout,
+            io::ErrorKind::ConnectionRefused => FailureKind::Refused,
+            io::ErrorKind::HostUnreachable => FailureKind::Unreachable,
+            io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::NetworkDown
+            | io::ErrorKind::AddrNotAvailable => FailureKind::NetworkDown,
+            io::ErrorKind::PermissionDenied
+            | io::ErrorKind::InvalidData
+            | io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+                if via_proxy =>
+            {
+                FailureKind::Proxy
+            }
+            _ => FailureKind::Other,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureKind::Resolve => "resolve",
+            FailureKind::Timeout => "timeout",
+            FailureKind::Refused => "refused",
+            FailureKind::Unreachable => "unreachable",
+            FailureKind::NetworkDown => "network_down",
+            FailureKind::Proxy => "proxy",
+            FailureKind::Tls => "tls",
+            FailureKind::Slow => "slow",
+            FailureKind::NoReply => "no_reply",
+            FailureKind::Other => "other",
+        }
+    }
+}
+
+/// Counters and connection times for one series of pings.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct PingStats {
+    /// Number of connection attempts made
+    pub attempted: u32,
+    /// Attempts that connected
+    pub connected: u32,
+    /// Attempts that failed (timeout, refused, DNS error, proxy error...)
+    pub failed: u32,
+    /// Failed attempts that didn't get past resolving the target name
+    /// (included in `failed`)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub resolve_failed: u32,
+    /// Connection time of each successful attempt, in milliseconds
+    #[cfg_attr(feature = "serde", serde(rename = "times_ms"))]
+    pub times: Vec<f64>,
+    /// When the target was down
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub outages: Outages,
+}
+
+impl PingStats {
+    /// Applies the outcome of one probe to the counters.
+    pub fn record(&mut self, result: &PingResult) {
+        self.attempted += 1;
+        self.outages.record(result.is_success(), SystemTime::now());
+        match result.time_ms() {
+            Some(ms) => {
+                self.connected += 1;
+                self.times.push(ms);
+            }
+            None => self.failed += 1,
+        }
+        if let PingResult::Failed(Failure {
+            kind: FailureKind::Resolve,
+            ..
+        }) = result
+        {
+            self.resolve_failed += 1;
+        }
+    }
+
+    /// Percentage of attempts that failed (0 when nothing was attempted).
+    pub fn loss_percent(&self) -> f64 {
+        if self.attempted > 0 {
+            (self.failed as f64 / self.attempted as f64) * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Average connection time in milliseconds, if anything connected.
+    pub fn average(&self) -> Option<f64> {
+        if self.times.is_empty() {
+            None
+        } else {
+            Some(self.times.iter().sum::<f64>() / self.times.len() as f64)
+        }
+    }
+
+    /// Fastest connection time in milliseconds, if anything connected.
+    pub fn min(&self) -> Option<f64> {
+        self.times.iter().cloned().reduce(f64::min)
+    }
+
+    /// Slowest connection time in milliseconds, if anything connected.
+    pub fn max(&self) -> Option<f64> {
+        self.times.iter().cloned().reduce(f64::max)
+    }
+}
+
+/// The run of consecutive probes in the same state (connected or not,
+/// closed or not...), for decisions that shouldn't flip on one probe.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Streak {
+    /// State of the probes in the run
+    pub state: bool,
+    /// Probes in the run (0 before the first)
+    pub len: u32,
+}
+
+impl Streak {
+    /// Adds a probe in `state`. Returns whether it started a new run.
+    pub fn push(&mut self, state: bool) -> bool {
+        if self.len > 0 && self.state == state {
+            self.len += 1;
+            false
+        } else {
+            *self = Streak { state, len: 1 };
+            true
+        }
+    }
+
+    /// Whether the last `n` probes (at least) were all in `state`.
+    pub fn holds(&self, state: bool, n: u32) -> bool {
+        self.len > 0 && self.state == state && self.len >= n
+    }
+}
+
+/// Windows kept by default by [`Outages`], the latest ones.
+pub const DEFAULT_MAX_OUTAGES: usize = 100;
+
+/// The up and down states of a target over a series of probes, and the
+/// windows it was down in. A change only counts once `confirm` probes in
+/// a row agree (`Streak`), and is dated from the first of them; only the
+/// last `limit` windows are kept, the others just counted.
+#[derive(Clone, Debug)]
+pub struct Outages {
+    confirm: u32,
+    limit: usize,
+    streak: Streak,
+    /// When the current run of probes started, and its failures
+    run_since: Option<SystemTime>,
+    run_failed: u32,
+    /// Up or down, and since when; unknown until `confirm` probes agree
+    state: Option<(bool, SystemTime)>,
+    windows: VecDeque<Outage>,
+    earlier: u32,
+}
+
+/// A window the target was down in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outage {
+    pub start: SystemTime,
+    /// `None` while it's still down
+    pub end: Option<SystemTime>,
+    /// Probes that failed in it
+    pub failed: u32,
+}
+
+impl Outage {
+    /// How long it lasted, or has so far (until `now`).
+    pub fn duration(&self, now: SystemTime) -> Duration {
+        let end = self.end.unwrap_or(now);
+        end.duration_since(self.start).unwrap_or_default()
+    }
+}
+
+/// A change of state, as returned by [`Outages::record`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub up: bool,
+    /// The first probe of the run confirming it
+    pub at: SystemTime,
+    /// How long the previous state lasted (none for the first state)
+    pub after: Option<Duration>,
+}
+
+impl Default for Outages {
+    fn default() -> Self {
+        Outages::new(1, DEFAULT_MAX_OUTAGES)
+    }
+}
+
+impl Outages {
+    /// Changes count once `confirm` probes (at least 1) agree; `limit`
+    /// windows are kept at most (at least 1).
+    pub fn new(confirm: u32, limit: usize) -> Self {
+        Outages {
+            confirm: confirm.max(1),
+            limit: limit.max(1),
+            streak: Streak::default(),
+            run_since: None,
+            run_failed: 0,
+            state: None,
+            windows: VecDeque::new(),
+            earlier: 0,
+        }
+    }
+
+    /// Takes a probe made `at`, returning the change of state it confirmed.
+    pub fn record(&mut self, up: bool, at: SystemTime) -> Option<Change> {
+        if self.streak.push(up) {
+            self.run_since = Some(at);
+            self.run_failed = 0;
+        }
+        if !up {
+            self.run_failed += 1;
+            if self.state.is_some_and(|(state, _)| !state) {
+                if let Some(window) = self.windows.back_mut() {
+                    window.failed += 1;
+                }
+            }
+        }
+        let settled = self.state.map(|(state, _)| state);
+        if !self.streak.holds(up, self.confirm) || settled == Some(up) {
+            return None;
+        }
+        let since = self.run_since.unwrap_or(at);
+        let after = self
+            .state
+            .map(|(_, before)| since.duration_since(before).unwrap_or_default());
+        self.state = Some((up, since));
+        match up {
+            true => {
+                if let Some(window) = self.windows.back_mut().filter(|w| w.end.is_none()) {
+                    window.end = Some(since);
+                }
+            }
+            false => {
+                if self.windows.len() == self.limit {
+                    self.windows.pop_front();
+                    self.earlier += 1;
+                }
+                self.windows.push_back(Outage {
+                    start: since,
+                    end: None,
+                    failed: self.run_failed,
+                });
+            }
+        }
+        Some(Change {
+            up,
+            at: since,
+            after,
+        })
+    }
+
+    /// Up or down, and since when, once settled.
+    pub fn state(&self) -> Option<(bool, SystemTime)> {
+        self.state
+    }
+
+    /// The windows kept, oldest first; the last one may be ongoing.
+    pub fn windows(&self) -> &VecDeque<Outage> {
+        &self.windows
+    }
+
+    /// Windows no longer kept, before the first of `windows`.
+    pub fn earlier(&self) -> u32 {
+        self.earlier
+    }
+
+    /// Whether the target was never seen down.
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty() && self.earlier == 0
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::{Connection, Exchange, PingStats, TlsSession, Transfer};
+    use crate::template::rfc3339;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::net::SocketAddr;
+    use std::time::{Duration, SystemTime};
+
+    /// `Duration` as fractional milliseconds.
+    pub mod millis {
+        use serde::{Deserialize, Deserializer, Serializer};
+        use std::time::Duration;
+
+        pub fn serialize<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_f64(d.as_secs_f64() * 1000.0)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+            let ms = f64::deserialize(d)?;
+            Duration::try_from_secs_f64(ms / 1000.0).map_err(serde::de::Error::custom)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct ConnectionRepr {
+        #[serde(with = "millis")]
+        time_ms: Duration,
+        addr: Option<SocketAddr>,
+        proxy: Option<String>,
+        #[serde(default)]
+        exchange: Option<Exchange>,
+        #[serde(default)]
+        transfer: Option<Transfer>,
+        #[serde(default)]
+        tls: Option<TlsSession>,
+    }
+
+    // Written by hand: a derived impl would only accept 'static input
+    // because of the `&'static str` field.
+    impl<'de> Deserialize<'de> for Connection {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let repr = ConnectionRepr::deserialize(d)?;
+            // Only ever one of the known transport names
+            let proxy = match repr.proxy.as_deref() {
+                None => None,
+                Some("SOCKS5") => Some("SOCKS5"),
+                Some("SOCKS5+TLS") => Some("SOCKS5+TLS"),
+                Some(other) => {
+                    return Err(serde::de::Error::custom(format!(
+                        "unknown proxy transport '{}'",
+                        other
+                    )))
+                }
+            };
+            Ok(Connection {
+                time: repr.time_ms,
+                addr: repr.addr,
+                proxy,
+                exchange: repr.exchange,
+                transfer: repr.transfer,
+                tls: repr.tls,
+            })
+        }
+    }
+
+    #[derive(Serialize)]
+    struct StatsRepr<'a> {
+        attempted: u32,
+        connected: u32,
+        failed: u32,
+        resolve_failed: u32,
+        times_ms: &'a [f64],
+        loss_percent: f64,
+        min_ms: Option<f64>,
+        avg_ms: Option<f64>,
+        max_ms: Option<f64>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        outages: Vec<OutageRepr>,
+        #[serde(skip_serializing_if = "is_zero")]
+        earlier_outages: u32,
+    }
+
+    #[derive(Serialize)]
+    struct OutageRepr {
+        start: String,
+        end: Option<String>,
+        duration_ms: f64,
+        failed: u32,
+    }
+
+    fn is_zero(n: &u32) -> bool {
+        *n == 0
+    }
+
+    // Written by hand for the figures and outages, which aren't fields
+    impl Serialize for PingStats {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let now = SystemTime::now();
+            StatsRepr {
+                attempted: self.attempted,
+                connected: self.connected,
+                failed: self.failed,
+                resolve_failed: self.resolve_failed,
+                times_ms: &self.times,
+                loss_percent: self.loss_percent(),
+                min_ms: self.min(),
+                avg_ms: self.average(),
+                max_ms: self.max(),
+                outages: self
+                    .outages
+                    .windows()
+                    .iter()
+                    .map(|outage| OutageRepr {
+                        start: rfc3339(outage.start),
+                        end: outage.end.map(rfc3339),
+                        duration_ms: outage.duration(now).as_secs_f64() * 1000.0,
+                        failed: outage.failed,
+                    })
+                    .collect(),
+                earlier_outages: self.outages.earlier(),
+            }
+            .serialize(s)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connected(ms: u64) -> PingResult {
+        PingResult::connected(Duration::from_millis(ms))
+    }
+
+    fn failed(kind: FailureKind) -> PingResult {
+        PingResult::failed(kind, "boom")
+    }
+
+    #[test]
+    fn classify_direct_errors() {
+        let err = |kind| io::Error::new(kind, "x");
+        assert_eq!(
+            FailureKind::classify(&err(io::ErrorKind::TimedOut), false),
+            FailureKind::Timeout
+        );
+        assert_eq!(
+            FailureKind::classify(&err(io::ErrorKind::ConnectionRefused), false),
+            FailureKind::Refused
+        );
+        assert_eq!(
+            FailureKind::classify(&err(io::ErrorKind::NotFound), false),
+            FailureKind::Resolve
+        );
+        assert_eq!(
+            FailureKind::classify(&err(io::ErrorKind::HostUnreachable), false),
+            FailureKind::Unreachable
+        );
+        for kind in [
+            io::ErrorKind::NetworkUnreachable,
+            io::ErrorKind::NetworkDown,
+            io::ErrorKind::AddrNotAvailable,
+        ] {
+            assert_eq!(FailureKind::classify(&err(kind), false), FailureKind::NetworkDown);
+        }
+        assert_eq!(
+            FailureKind::classify(&err(io::ErrorKind::InvalidData), false),
+            FailureKind::Other
+        );
+    }
+
+    #[test]
+    fn classify_proxy_errors() {
+        let err = |kind| io::Error::new(kind, "x");
+        assert_eq!(
+            FailureKind::classify(&err(io::ErrorKind::PermissionDenied), true),
+            FailureKind::Proxy
+        );
+        assert_eq!(
+            FailureKind::classify(&err(io::ErrorKind::InvalidData), true),
+            FailureKind::Proxy
+        );
+        // A refusal relayed by the proxy is still a refusal
+        assert_eq!(
+            FailureKind::classify(&err(io::ErrorKind::ConnectionRefused), true),
+            FailureKind::Refused
+        );
+    }
+
+    #[test]
+    fn failure_keeps_error_message() {
+        let e = io::Error::new(io::ErrorKind::TimedOut, "connection timed out");
+        let f = Failure::from_io(&e, false);
+        assert_eq!(f.kind, FailureKind::Timeout);
+        assert_eq!(f.message, "connection timed out");
+    }
+
+    #[test]
+    fn record_updates_counters() {
+        let mut stats = PingStats::default();
+        stats.record(&connected(10));
+        stats.record(&failed(FailureKind::Timeout));
+        stats.record(&connected(30));
+        stats.record(&failed(FailureKind::Refused));
+        stats.record(&failed(FailureKind::Resolve));
+
+        assert_eq!(stats.attempted, 5);
+        assert_eq!(stats.connected, 2);
+        assert_eq!(stats.failed, 3);
+        assert_eq!(stats.resolve_failed, 1);
+        assert_eq!(stats.loss_percent(), 60.0);
+        assert_eq!(stats.min(), Some(10.0));
+        assert_eq!(stats.max(), Some(30.0));
+        assert_eq!(stats.average(), Some(20.0));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_schema() {
+        let ok = PingResult::Connected(Connection {
+            time: Duration::from_micros(12_500),
+            addr: Some("1.1.1.1:443".parse().unwrap()),
+            proxy: None,
+            exchange: None,
+            transfer: None,
+            tls: None,
+        });
+        assert_eq!(
+            serde_json::to_string(&ok).unwrap(),
+            r#"{"status":"connected","time_ms":12.5,"addr":"1.1.1.1:443","proxy":null}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&failed(FailureKind::Timeout)).unwrap(),
+            r#"{"status":"failed","kind":"timeout","message":"boom"}"#
+        );
+
+        let mut stats = PingStats::default();
+        stats.record(&connected(10));
+        stats.record(&failed(FailureKind::Refused));
+        let mut json: serde_json::Value = serde_json::to_value(&stats).unwrap();
+        // Down since the refusal, timed as it is written
+        let outages = json.as_object_mut().unwrap().remove("outages").unwrap();
+        assert_eq!(
+            json.to_string(),
+            r#"{"attempted":2,"avg_ms":10.0,"connected":1,"failed":1,"loss_percent":50.0,"max_ms":10.0,"min_ms":10.0,"resolve_failed":0,"times_ms":[10.0]}"#
+        );
+        assert_eq!((outages[0]["end"].is_null(), &outages[0]["failed"]), (true, &1.into()));
+        assert!(outages[0]["start"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let results = [
+            connected(42),
+            PingResult::Connected(Connection {
+                time: Duration::from_micros(1_250),
+                addr: None,
+                proxy: Some("SOCKS5+TLS"),
+                exchange: None,
+                transfer: None,
+                tls: None,
+            }),
+            PingResult::Connected(Connection {
+                time: Duration::from_millis(3),
+                addr: Some("[::1]:7".parse().unwrap()),
+                proxy: None,
+                exchange: Some(Exchange {
+                    time: Duration::from_micros(500),
+                    sent: 4,
+                    reply: Some(b"pong".to_vec()),
+                }),
+                transfer: None,
+                tls: None,
+            }),
+            PingResult::Connected(Connection {
+                time: Duration::from_millis(2),
+                addr: Some("10.0.0.1:9000".parse().unwrap()),
+                proxy: None,
+                exchange: None,
+                transfer: Some(Transfer {
+                    direction: Direction::Download,
+                    bytes: 10_000_000,
+                    time: Duration::from_millis(100),
+                }),
+                tls: Some(TlsSession {
+                    handshake: Duration::from_micros(2500),
+                    version: "TLSv1.3".to_string(),
+                    cipher: "TLS13_AES_128_GCM_SHA256".to_string(),
+                    cert_expires_in_days: Some(-2),
+                }),
+            }),
+            failed(FailureKind::Resolve),
+            failed(FailureKind::Tls),
+            failed(FailureKind::Unreachable),
+            failed(FailureKind::Proxy),
+            failed(FailureKind::Other),
+        ];
+        for result in results {
+            let json = serde_json::to_string(&result).unwrap();
+            assert_eq!(serde_json::from_str::<PingResult>(&json).unwrap(), result);
+        }
+
+        let mut stats = PingStats::default();
+        stats.record(&connected(10));
+        stats.record(&failed(FailureKind::Timeout));
+        let json = serde_json::to_string(&stats).unwrap();
+        let back: PingStats = serde_json::from_str(&json).unwrap();
+        assert_eq!((back.attempted, back.failed), (2, 1));
+        assert_eq!(back.times, stats.times);
+
+        assert!(serde_json::from_str::<PingResult>(
+            r#"{"status":"connected","time_ms":1.0,"addr":null,"proxy":"HTTP"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn empty_stats() {
+        let stats = PingStats::default();
+        assert_eq!(stats.loss_percent(), 0.0);
+        assert_eq!(stats.average(), None);
+        assert_eq!(stats.min(), None);
+    }
+
+    #[test]
+    fn outages_are_confirmed_windows() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut outages = Outages::new(2, 2);
+        // up, a lone failure, down for good from 30s, back up at 60s
+        let probes = [true, true, false, true, false, false, false, true, true];
+        let changes: Vec<Change> = probes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &up)| outages.record(up, at(i as u64 * 10 + 10)))
+            .collect();
+        assert_eq!(
+            changes.iter().map(|c| c.up).collect::<Vec<_>>(),
+            [true, false, true]
+        );
+        assert_eq!(changes[1].at, at(50));
+        assert_eq!(changes[2].after, Some(Duration::from_secs(30)));
+        assert_eq!(
+            outages.windows().iter().collect::<Vec<_>>(),
+            [&Outage {
+                start: at(50),
+                end: Some(at(80)),
+                failed: 3,
+            }]
+        );
+
+        // Two more, the oldest dropped, the last still ongoing
+        let probes = [false, false, true, true, false, false];
+        for (i, &up) in probes.iter().enumerate() {
+            outages.record(up, at(100 + i as u64 * 10));
+        }
+        assert_eq!(outages.earlier(), 1);
+        let windows = outages.windows();
+        assert_eq!(windows.len(), 2);
+        assert_eq!((windows[0].start, windows[0].end), (at(100), Some(at(120))));
+        assert_eq!((windows[1].end, windows[1].failed), (None, 2));
+        assert_eq!(windows[1].duration(at(160)), Duration::from_secs(20));
+        assert_eq!(outages.state(), Some((false, at(140))));
+    }
+}
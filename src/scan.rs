@@ -0,0 +1,459 @@
+//! `paping scan`: which ports of a host accept connections, many at a time.
+//!
+//! Each port is connected to once and classified as open (connected),
+//! closed (refused) or filtered (no answer, or an ICMP error). Connects
+//! run on a pool of worker threads, optionally capped to a global rate.
+//!
+//! ```no_run
+//! use paping::scan::{PortState, Scanner};
+//! use paping::stop::StopToken;
+//!
+//! let scanner = Scanner::new("192.0.2.1".parse().unwrap(), (1..=1024).collect())
+//!     .with_workers(200)
+//!     .with_rate(500);
+//! let summary = scanner.run(&StopToken::never(), |report| {
+//!     if report.state == PortState::Open {
+//!         println!("{} open", report.port);
+//!     }
+//! });
+//! println!("{:.0} ports/s", summary.rate());
+//! ```
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::pinger::connect_from;
+use crate::stop::StopToken;
+pub use crate::top_ports::TOP_PORTS;
+
+/// Worker threads, unless `with_workers` says otherwise.
+pub const DEFAULT_WORKERS: usize = 100;
+/// First pause when the system runs out of sockets, doubled on each retry.
+const BACKOFF_START: Duration = Duration::from_millis(50);
+const BACKOFF_MAX: Duration = Duration::from_secs(2);
+
+/// What a connect to a port told about it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PortState {
+    /// The connection was accepted
+    Open,
+    /// The connection was refused: the host answered, nothing listens
+    Closed,
+    /// No answer before the timeout, or an unreachable error: a firewall
+    /// is likely dropping or rejecting the connection
+    Filtered,
+}
+
+impl PortState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+        }
+    }
+}
+
+/// The outcome for one port.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PortReport {
+    pub port: u16,
+    pub state: PortState,
+    /// How long the connect took to succeed or fail
+    pub time: Duration,
+}
+
+/// How a scan went.
+#[derive(Clone, Debug)]
+pub struct ScanSummary {
+    /// One per port scanned, by port number; fewer than `total` if stopped
+    pub reports: Vec<PortReport>,
+    /// Ports there were to scan
+    pub total: usize,
+    pub elapsed: Duration,
+    /// Connects retried after the system ran out of sockets
+    pub backoffs: u32,
+}
+
+impl ScanSummary {
+    /// Ports found in `state`.
+    pub fn count(&self, state: PortState) -> usize {
+        self.reports.iter().filter(|r| r.state == state).count()
+    }
+
+    /// Whether the scan was stopped before every port was scanned.
+    pub fn interrupted(&self) -> bool {
+        self.reports.len() < self.total
+    }
+
+    /// Ports scanned per second.
+    pub fn rate(&self) -> f64 {
+        self.reports.len() as f64 / self.elapsed.as_secs_f64().max(0.001)
+    }
+}
+
+/// Scans a list of ports of one host.
+pub struct Scanner {
+    addr: IpAddr,
+    ports: Vec<u16>,
+    timeout: Duration,
+    workers: usize,
+    rate: Option<u32>,
+    bind_addr: Option<IpAddr>,
+}
+
+impl Scanner {
+    /// Scans `ports` of `addr`, in that order, with `DEFAULT_WORKERS`
+    /// workers, a 1s timeout and no rate limit.
+    pub fn new(addr: IpAddr, ports: Vec<u16>) -> Self {
+        Scanner {
+            addr,
+            ports,
+            timeout: Duration::from_secs(1),
+            workers: DEFAULT_WORKERS,
+            rate: None,
+            bind_addr: None,
+        }
+    }
+
+    /// Longest wait for each connect, after which the port is filtered.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Connects in flight at once (at least 1).
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// At most `rate` connects per second, all workers together (0 = no
+    /// limit).
+    pub fn with_rate(mut self, rate: u32) -> Self {
+        self.rate = (rate > 0).then_some(rate);
+        self
+    }
+
+    /// Connects from this local IP.
+    pub fn with_bind_addr(mut self, bind_addr: IpAddr) -> Self {
+        self.bind_addr = Some(bind_addr);
+        self
+    }
+
+    /// Scans every port, or until `stop` is stopped: workers finish the
+    /// connect underway (at most the timeout) and take no more ports.
+    /// `on_port` is called on the calling thread as each port is done, in
+    /// no particular order.
+    pub fn run(&self, stop: &StopToken, mut on_port: impl FnMut(&PortReport)) -> ScanSummary {
+        let start = Instant::now();
+        let next = AtomicUsize::new(0);
+        let backoffs = AtomicU32::new(0);
+        let pacer = self.rate.map(Pacer::new);
+        let mut reports = Vec::with_capacity(self.ports.len());
+        let (tx, rx) = mpsc::channel();
+        std::thread::scope(|scope| {
+            for _ in 0..self.workers.min(self.ports.len()) {
+                let tx = tx.clone();
+                let (next, backoffs, pacer) = (&next, &backoffs, pacer.as_ref());
+                scope.spawn(move || self.work(next, pacer, backoffs, stop, tx));
+            }
+            drop(tx);
+            for report in rx {
+                on_port(&report);
+                reports.push(report);
+            }
+        });
+        reports.sort_by_key(|r| r.port);
+        ScanSummary {
+            reports,
+            total: self.ports.len(),
+            elapsed: start.elapsed(),
+            backoffs: backoffs.into_inner(),
+        }
+    }
+
+    /// A worker: takes the next port until there are none left.
+    fn work(
+        &self,
+        next: &AtomicUsize,
+        pacer: Option<&Pacer>,
+        backoffs: &AtomicU32,
+        stop: &StopToken,
+        tx: Sender<PortReport>,
+    ) {
+        while !stop.is_stopped() {
+            let Some(&port) = self.ports.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                return;
+            };
+            match self.scan_port(port, pacer, backoffs, stop) {
+                Some(report) => {
+                    if tx.send(report).is_err() {
+                        return;
+                    }
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// Connects to `port`, retrying for as long as the system is out of
+    /// sockets. `None` when stopped first.
+    fn scan_port(
+        &self,
+        port: u16,
+        pacer: Option<&Pacer>,
+        backoffs: &AtomicU32,
+        stop: &StopToken,
+    ) -> Option<PortReport> {
+        let addr = SocketAddr::new(self.addr, port);
+        let mut backoff = BACKOFF_START;
+        loop {
+            if let Some(pacer) = pacer {
+                if !pacer.wait(stop) {
+                    return None;
+                }
+            }
+            let start = Instant::now();
+            let state = match connect_from(self.bind_addr, &addr, self.timeout) {
+                Ok(_) => PortState::Open,
+                // Says nothing about the port: wait for sockets to free up
+                Err(ref e) if out_of_sockets(e) => {
+                    backoffs.fetch_add(1, Ordering::Relaxed);
+                    if stop.wait_timeout(backoff) {
+                        return None;
+                    }
+                    backoff = (backoff * 2).min(BACKOFF_MAX);
+                    continue;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => PortState::Closed,
+                Err(_) => PortState::Filtered,
+            };
+            return Some(PortReport {
+                port,
+                state,
+                time: start.elapsed(),
+            });
+        }
+    }
+}
+
+/// Hands out connect slots `1/rate` seconds apart, to all workers.
+struct Pacer {
+    gap: Duration,
+    next: Mutex<Instant>,
+}
+
+impl Pacer {
+    fn new(rate: u32) -> Self {
+        Pacer {
+            gap: Duration::from_secs(1) / rate,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits for the next free slot. False when stopped meanwhile.
+    fn wait(&self, stop: &StopToken) -> bool {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.gap;
+            slot
+        };
+        match slot.checked_duration_since(Instant::now()) {
+            Some(wait) if !wait.is_zero() => !stop.wait_timeout(wait),
+            _ => !stop.is_stopped(),
+        }
+    }
+}
+
+/// Whether connecting failed for want of file descriptors, buffers or
+/// local ports, rather than because of the target.
+fn out_of_sockets(e: &io::Error) -> bool {
+    // EMFILE, ENFILE, ENOBUFS
+    #[cfg(target_os = "linux")]
+    const CODES: &[i32] = &[24, 23, 105];
+    #[cfg(all(unix, not(target_os = "linux")))]
+    const CODES: &[i32] = &[24, 23, 55];
+    // WSAEMFILE, WSAENOBUFS
+    #[cfg(windows)]
+    const CODES: &[i32] = &[10024, 10055];
+    #[cfg(not(any(unix, windows)))]
+    const CODES: &[i32] = &[];
+
+    e.kind() == io::ErrorKind::AddrNotAvailable
+        || e.raw_os_error().is_some_and(|code| CODES.contains(&code))
+}
+
+/// The `n` most common open TCP ports (--top-ports), most frequent first;
+/// all of `TOP_PORTS` if `n` is larger.
+pub fn top_ports(n: usize) -> &'static [u16] {
+    &TOP_PORTS[..n.min(TOP_PORTS.len())]
+}
+
+/// `first`, then the ports of `second` not in it.
+pub fn union(first: &[u16], second: &[u16]) -> Vec<u16> {
+    let mut ports = first.to_vec();
+    ports.extend(second.iter().filter(|p| !first.contains(p)));
+    ports
+}
+
+/// Drops the `excluded` ports from `ports` (--exclude-ports), keeping the
+/// order of the rest. Returns how many were dropped.
+pub fn exclude(ports: &mut Vec<u16>, excluded: &[u16]) -> usize {
+    let before = ports.len();
+    ports.retain(|p| !excluded.contains(p));
+    before - ports.len()
+}
+
+/// Shuffles `ports` (--randomize), the same way for the same `seed`.
+pub fn shuffle(ports: &mut [u16], seed: u64) {
+    // SplitMix64: plenty to defeat sequential-scan detection, no dependency
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    // Fisher-Yates
+    for i in (1..ports.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        ports.swap(i, j);
+    }
+}
+
+/// Parses a port list such as `22,80,8000-8100`. Ports keep the order
+/// given; repeats are dropped.
+pub fn parse_ports(spec: &str) -> Result<Vec<u16>, String> {
+    let mut seen = vec![false; 65_536];
+    let mut ports = Vec::new();
+    for item in spec.split(',').map(str::trim) {
+        let (first, last) = match item.split_once('-') {
+            Some((first, last)) => (parse_port(first)?, parse_port(last)?),
+            None => {
+                let port = parse_port(item)?;
+                (port, port)
+            }
+        };
+        if first > last {
+            return Err(format!("invalid port range '{}'", item));
+        }
+        for port in first..=last {
+            if !std::mem::replace(&mut seen[port as usize], true) {
+                ports.push(port);
+            }
+        }
+    }
+    Ok(ports)
+}
+
+fn parse_port(s: &str) -> Result<u16, String> {
+    match s.trim().parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => Err(format!("invalid port '{}'", s.trim())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn port_lists() {
+        assert_eq!(
+            parse_ports("22,80,8000-8002").unwrap(),
+            [22, 80, 8000, 8001, 8002]
+        );
+        assert_eq!(parse_ports("443, 80-81,443,80").unwrap(), [443, 80, 81]);
+        assert!(parse_ports("0").is_err());
+        assert!(parse_ports("10-1").is_err());
+        assert!(parse_ports("22,").is_err());
+        assert!(parse_ports("65536").is_err());
+
+        assert_eq!(top_ports(3), [80, 23, 443]);
+        assert_eq!(top_ports(1000).len(), TOP_PORTS.len());
+        assert_eq!(union(top_ports(3), &[443, 8443, 80]), [80, 23, 443, 8443]);
+    }
+
+    #[test]
+    fn exclusions_and_shuffling() {
+        let mut ports = parse_ports("20-30").unwrap();
+        assert_eq!(exclude(&mut ports, &parse_ports("25,137-139,22").unwrap()), 2);
+        assert_eq!(ports, [20, 21, 23, 24, 26, 27, 28, 29, 30]);
+
+        let sorted: Vec<u16> = (1..=1000).collect();
+        let (mut a, mut b, mut c) = (sorted.clone(), sorted.clone(), sorted.clone());
+        shuffle(&mut a, 42);
+        shuffle(&mut b, 42);
+        shuffle(&mut c, 43);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, sorted);
+        a.sort_unstable();
+        assert_eq!(a, sorted);
+    }
+
+    #[test]
+    fn tells_open_from_closed_ports() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let scanner =
+            Scanner::new("127.0.0.1".parse().unwrap(), vec![closed, open]).with_workers(4);
+        let mut seen = 0;
+        let summary = scanner.run(&StopToken::never(), |_| seen += 1);
+        assert_eq!(seen, 2);
+        assert!(!summary.interrupted());
+        let states: Vec<_> = summary.reports.iter().map(|r| (r.port, r.state)).collect();
+        let mut expected = vec![(open, PortState::Open), (closed, PortState::Closed)];
+        expected.sort_by_key(|&(port, _)| port);
+        assert_eq!(states, expected);
+    }
+
+    #[test]
+    fn rate_caps_all_workers_together() {
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let scanner = Scanner::new("127.0.0.1".parse().unwrap(), vec![closed; 11])
+            .with_workers(8)
+            .with_rate(100);
+        let summary = scanner.run(&StopToken::never(), |_| {});
+        assert_eq!(summary.reports.len(), 11);
+        // 11 slots 10ms apart
+        assert!(
+            summary.elapsed >= Duration::from_millis(95),
+            "{:?}",
+            summary.elapsed
+        );
+    }
+
+    #[test]
+    fn stopping_keeps_the_partial_results() {
+        let handle = crate::stop::StopHandle::new();
+        let token = handle.token();
+        let scanner = Scanner::new("127.0.0.1".parse().unwrap(), (1..=1000).collect())
+            .with_workers(2)
+            .with_rate(50);
+        let summary = scanner.run(&token, |report| {
+            if report.port >= 3 {
+                handle.stop();
+            }
+        });
+        assert!(summary.interrupted());
+        assert!(summary.reports.len() >= 3 && summary.reports.len() < 10);
+    }
+}
@@ -0,0 +1,61 @@
+//! SIGUSR1 (and SIGINFO, Ctrl+T, where there is one: macOS and the BSDs)
+//! for the statistics so far, without stopping the run, as `dd` does.
+//!
+//! The handler only sets a flag, which the pinger checks (see
+//! `Pinger::with_stats_request`). Windows has neither signal: there,
+//! nothing is installed.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Installs the handler, returning the flag it sets; `None` where the
+/// signals don't exist. Meant to be called once.
+#[cfg(unix)]
+pub fn stats_request() -> Option<Arc<AtomicBool>> {
+    use std::sync::atomic::Ordering;
+    use std::sync::OnceLock;
+
+    static REQUEST: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+    extern "C" fn handler(_: libc::c_int) {
+        // Only atomics here: no allocation, no locks
+        if let Some(request) = REQUEST.get() {
+            request.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let request = Arc::clone(REQUEST.get_or_init(|| Arc::new(AtomicBool::new(false))));
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))]
+    let signals = [libc::SIGUSR1, libc::SIGINFO];
+    #[cfg(not(any(
+        target_os = "macos",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    )))]
+    let signals = [libc::SIGUSR1];
+    for signal in signals {
+        // SAFETY: the handler is async-signal-safe. SA_RESTART resumes the
+        // system calls it interrupts rather than failing them.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+    Some(request)
+}
+
+#[cfg(not(unix))]
+pub fn stats_request() -> Option<Arc<AtomicBool>> {
+    None
+}
@@ -0,0 +1,629 @@
+//! --sqlite: the history of the probes in a SQLite database, and
+//! `paping report` to sum it up.
+//!
+//! ```sql
+//! CREATE TABLE runs (
+//!     id             INTEGER PRIMARY KEY,
+//!     started        REAL NOT NULL,   -- Unix time in seconds
+//!     ended          REAL,            -- NULL until the run ends (or if killed)
+//!     target         TEXT NOT NULL,
+//!     port           INTEGER NOT NULL,
+//!     label          TEXT,            -- --label, NULL without one
+//!     paping_version TEXT,
+//!     attempted      INTEGER,         -- the statistics, once the run ends
+//!     connected      INTEGER,
+//!     failed         INTEGER,
+//!     min_ms         REAL,
+//!     avg_ms         REAL,
+//!     max_ms         REAL
+//! );
+//! CREATE TABLE probes (
+//!     run_id     INTEGER NOT NULL REFERENCES runs (id),
+//!     seq        INTEGER NOT NULL,    -- 1 for the first probe of the run
+//!     ts         REAL NOT NULL,       -- Unix time in seconds
+//!     target     TEXT NOT NULL,
+//!     port       INTEGER NOT NULL,
+//!     ok         INTEGER NOT NULL,    -- 0 or 1
+//!     rtt_ms     REAL,                -- NULL when failed
+//!     error_kind TEXT,                -- NULL when connected
+//!     error_msg  TEXT,
+//!     PRIMARY KEY (run_id, seq)
+//! );
+//! ```
+//!
+//! Rows are written by a thread of their own, in one transaction per batch,
+//! so neither disk latency nor high-rate probing slows the pinger down.
+//!
+//! `schema_version` holds the version of the tables, which `MIGRATIONS`
+//! bring up to date on opening. The `pings` table of earlier versions of
+//! paping is moved into them, a run per target, port and label.
+
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use colored::Colorize;
+use paping::pinger::{PingResult, PingStats};
+use paping::target::join_host_port;
+use paping::template::rfc3339;
+use rusqlite::{params, Connection, OpenFlags};
+
+/// Rows written at once at most.
+const BATCH_SIZE: usize = 256;
+/// Longest a row waits before being written.
+const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+/// What takes the tables from a version to the next: the schema is at
+/// version N once the first N have run.
+const MIGRATIONS: &[fn(&Connection) -> rusqlite::Result<()>] = &[runs_and_probes];
+
+type Row = (SystemTime, PingResult);
+
+/// Handle on the writer thread. Results go in through `observer`; `finish`
+/// writes what's left once every observer is gone, and the statistics.
+pub struct Recorder {
+    tx: Sender<Row>,
+    writer: JoinHandle<Result<Connection, String>>,
+    run_id: i64,
+}
+
+impl Recorder {
+    /// Opens (or creates) the database, brings its tables up to date and
+    /// adds the run, so that a bad path or a database of a newer paping
+    /// is reported before pinging starts.
+    pub fn open(path: &Path, target: &str, port: u16, label: Option<&str>) -> Result<Self, String> {
+        let mut db = Connection::open(path).map_err(|e| e.to_string())?;
+        migrate(&mut db)?;
+        db.execute(
+            "INSERT INTO runs (started, target, port, label, paping_version)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                unix(SystemTime::now()),
+                target,
+                port,
+                label,
+                env!("CARGO_PKG_VERSION")
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        let run_id = db.last_insert_rowid();
+
+        let (tx, rx) = mpsc::channel();
+        let run = Run {
+            id: run_id,
+            target: target.to_string(),
+            port,
+        };
+        let writer = std::thread::spawn(move || write_rows(db, rx, &run));
+        Ok(Recorder { tx, writer, run_id })
+    }
+
+    /// Observer to register with `Pinger::on_result`. Never blocks.
+    pub fn observer(&self) -> impl FnMut(&PingResult) + Send + 'static {
+        let tx = self.tx.clone();
+        move |result| {
+            let _ = tx.send((SystemTime::now(), result.clone()));
+        }
+    }
+
+    /// Waits for the last rows to be written, then ends the run with its
+    /// statistics. The pinger (holding the observers) must be dropped
+    /// first.
+    pub fn finish(self, stats: &PingStats) -> Result<(), String> {
+        drop(self.tx);
+        let db = self
+            .writer
+            .join()
+            .unwrap_or_else(|_| Err("writer thread panicked".to_string()))?;
+        db.execute(
+            "UPDATE runs SET ended = ?1, attempted = ?2, connected = ?3, failed = ?4,
+             min_ms = ?5, avg_ms = ?6, max_ms = ?7 WHERE id = ?8",
+            params![
+                unix(SystemTime::now()),
+                stats.attempted,
+                stats.connected,
+                stats.failed,
+                stats.min(),
+                stats.average(),
+                stats.max(),
+                self.run_id
+            ],
+        )
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+}
+
+/// What every probe of a run has in common.
+struct Run {
+    id: i64,
+    target: String,
+    port: u16,
+}
+
+fn unix(at: SystemTime) -> f64 {
+    at.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// The version of the tables, 0 for none.
+fn version(db: &Connection) -> rusqlite::Result<usize> {
+    if !has_table(db, "schema_version")? {
+        return Ok(0);
+    }
+    let version: Option<i64> =
+        db.query_row("SELECT MAX(version) FROM schema_version", [], |r| r.get(0))?;
+    Ok(version.unwrap_or(0) as usize)
+}
+
+fn has_table(db: &Connection, name: &str) -> rusqlite::Result<bool> {
+    db.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")?
+        .exists([name])
+}
+
+/// Runs the migrations the tables miss, each in a transaction of its own.
+fn migrate(db: &mut Connection) -> Result<(), String> {
+    let current = version(db).map_err(|e| e.to_string())?;
+    if current > MIGRATIONS.len() {
+        return Err(format!(
+            "written by a newer paping (schema {}, this one knows up to {})",
+            current,
+            MIGRATIONS.len()
+        ));
+    }
+    for (done, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let version = done + 1;
+        apply(db, *migration, version)
+            .map_err(|e| format!("updating the tables to schema {}: {}", version, e))?;
+    }
+    Ok(())
+}
+
+fn apply(
+    db: &mut Connection,
+    migration: fn(&Connection) -> rusqlite::Result<()>,
+    version: usize,
+) -> rusqlite::Result<()> {
+    let tx = db.transaction()?;
+    tx.execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")?;
+    migration(&tx)?;
+    tx.execute("DELETE FROM schema_version", [])?;
+    tx.execute(
+        "INSERT INTO schema_version (version) VALUES (?1)",
+        [version as i64],
+    )?;
+    tx.commit()
+}
+
+/// Schema 1: `runs` and `probes`, taking over from `pings`.
+fn runs_and_probes(db: &Connection) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "CREATE TABLE runs (
+            id INTEGER PRIMARY KEY,
+            started REAL NOT NULL,
+            ended REAL,
+            target TEXT NOT NULL,
+            port INTEGER NOT NULL,
+            label TEXT,
+            paping_version TEXT,
+            attempted INTEGER,
+            connected INTEGER,
+            failed INTEGER,
+            min_ms REAL,
+            avg_ms REAL,
+            max_ms REAL
+        );
+        CREATE TABLE probes (
+            run_id INTEGER NOT NULL REFERENCES runs (id),
+            seq INTEGER NOT NULL,
+            ts REAL NOT NULL,
+            target TEXT NOT NULL,
+            port INTEGER NOT NULL,
+            ok INTEGER NOT NULL,
+            rtt_ms REAL,
+            error_kind TEXT,
+            error_msg TEXT,
+            PRIMARY KEY (run_id, seq)
+        );
+        CREATE INDEX probes_ts ON probes (ts);",
+    )?;
+    if !has_table(db, "pings")? {
+        return Ok(());
+    }
+    // The first versions of the table had no label
+    let has_label = db
+        .prepare("SELECT 1 FROM pragma_table_info('pings') WHERE name = 'label'")?
+        .exists([])?;
+    if !has_label {
+        db.execute_batch("ALTER TABLE pings ADD COLUMN label TEXT")?;
+    }
+    db.execute_batch(
+        "INSERT INTO runs (started, ended, target, port, label,
+                           attempted, connected, failed, min_ms, avg_ms, max_ms)
+         SELECT MIN(timestamp), MAX(timestamp), target, port, label,
+                COUNT(*), SUM(success), COUNT(*) - SUM(success),
+                MIN(time_ms), AVG(time_ms), MAX(time_ms)
+         FROM pings GROUP BY target, port, label ORDER BY MIN(timestamp);
+         INSERT INTO probes (run_id, seq, ts, target, port, ok, rtt_ms, error_msg)
+         SELECT runs.id, ROW_NUMBER() OVER (PARTITION BY runs.id ORDER BY pings.rowid),
+                pings.timestamp, pings.target, pings.port, pings.success, pings.time_ms,
+                pings.error
+         FROM pings JOIN runs ON runs.target = pings.target AND runs.port = pings.port
+                             AND runs.label IS pings.label;
+         DROP TABLE pings;",
+    )
+}
+
+/// The thread's side: numbers the probes and writes them in batches.
+/// Hands the database back for `finish`.
+fn write_rows(mut db: Connection, rx: Receiver<Row>, run: &Run) -> Result<Connection, String> {
+    let mut batch: Vec<Row> = Vec::new();
+    let mut seq = 0;
+    let mut since = Instant::now();
+    loop {
+        let wait = FLUSH_EVERY.saturating_sub(since.elapsed());
+        let open = match rx.recv_timeout(wait) {
+            Ok(row) => {
+                batch.push(row);
+                true
+            }
+            Err(RecvTimeoutError::Timeout) => true,
+            Err(RecvTimeoutError::Disconnected) => false,
+        };
+        if !open || batch.len() >= BATCH_SIZE || since.elapsed() >= FLUSH_EVERY {
+            insert(&mut db, &batch, run, seq).map_err(|e| e.to_string())?;
+            seq += batch.len() as u32;
+            batch.clear();
+            since = Instant::now();
+        }
+        if !open {
+            return Ok(db);
+        }
+    }
+}
+
+/// Writes `rows`, the probes after the `seq`th.
+fn insert(db: &mut Connection, rows: &[Row], run: &Run, seq: u32) -> rusqlite::Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let tx = db.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO probes (run_id, seq, ts, target, port, ok, rtt_ms, error_kind, error_msg)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?;
+        for (n, (at, result)) in rows.iter().enumerate() {
+            let (kind, error) = match result {
+                PingResult::Connected(_) => (None, None),
+                PingResult::Failed(f) => (Some(f.kind.as_str()), Some(f.message.as_str())),
+            };
+            stmt.execute(params![
+                run.id,
+                seq + n as u32 + 1,
+                unix(*at),
+                run.target,
+                run.port,
+                result.is_success(),
+                result.time_ms(),
+                kind,
+                error
+            ])?;
+        }
+    }
+    tx.commit()
+}
+
+/// One run, as `paping report` shows it: figured out from its probes, so
+/// that a run killed before its end counts too.
+#[derive(Debug, PartialEq)]
+pub struct RunSummary {
+    pub id: i64,
+    pub started: f64,
+    /// None until the run ended
+    pub ended: Option<f64>,
+    pub target: String,
+    pub port: u16,
+    pub label: Option<String>,
+    pub attempted: u32,
+    pub connected: u32,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+/// The runs of the database at `path`, the `last` ones only if given,
+/// oldest first. The database must exist; one of an earlier paping is
+/// brought up to date.
+pub fn runs(path: &Path, last: Option<u32>) -> Result<Vec<RunSummary>, String> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let mut db = Connection::open_with_flags(path, flags).map_err(|e| e.to_string())?;
+    let known = version(&db).map_err(|e| e.to_string())? > 0
+        || has_table(&db, "pings").map_err(|e| e.to_string())?;
+    if !known {
+        return Err("no paping history in it (record some with --sqlite)".to_string());
+    }
+    migrate(&mut db)?;
+    let mut stmt = db
+        .prepare(
+            "SELECT * FROM (
+                 SELECT r.id, r.started, r.ended, r.target, r.port, r.label,
+                        COUNT(p.seq), COALESCE(SUM(p.ok), 0),
+                        MIN(p.rtt_ms), AVG(p.rtt_ms), MAX(p.rtt_ms)
+                 FROM runs r LEFT JOIN probes p ON p.run_id = r.id
+                 GROUP BY r.id ORDER BY r.id DESC LIMIT ?1
+             ) ORDER BY 1",
+        )
+        .map_err(|e| e.to_string())?;
+    let limit = last.map_or(-1, i64::from);
+    let summaries = stmt
+        .query_map([limit], |r| {
+            Ok(RunSummary {
+                id: r.get(0)?,
+                started: r.get(1)?,
+                ended: r.get(2)?,
+                target: r.get(3)?,
+                port: r.get(4)?,
+                label: r.get(5)?,
+                attempted: r.get(6)?,
+                connected: r.get(7)?,
+                min_ms: r.get(8)?,
+                avg_ms: r.get(9)?,
+                max_ms: r.get(10)?,
+            })
+        })
+        .and_then(|rows| rows.collect())
+        .map_err(|e| e.to_string());
+    summaries
+}
+
+/// paping report: a line per run of the database.
+pub fn report(path: &Path, last: Option<u32>) -> Result<(), String> {
+    let runs = runs(path, last).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if runs.is_empty() {
+        println!("No runs in {}", path.display());
+        return Ok(());
+    }
+    let ms = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.2}ms", v));
+    let mut rows = vec![[
+        "Run",
+        "Started",
+        "Took",
+        "Target",
+        "Attempted",
+        "Connected",
+        "Failed",
+        "Min",
+        "Avg",
+        "Max",
+    ]
+    .map(str::to_string)];
+    let mut losses = vec![None];
+    for run in &runs {
+        let failed = run.attempted - run.connected;
+        let loss = match run.attempted {
+            0 => 0.0,
+            n => failed as f64 * 100.0 / n as f64,
+        };
+        let started = UNIX_EPOCH + Duration::from_secs_f64(run.started.max(0.0));
+        let took = match run.ended {
+            Some(ended) => format!("{:.0}s", (ended - run.started).max(0.0)),
+            None => "unfinished".to_string(),
+        };
+        let mut target = join_host_port(&run.target, run.port);
+        if let Some(ref label) = run.label {
+            target.push_str(&format!(" [{}]", label));
+        }
+        rows.push([
+            run.id.to_string(),
+            // To the second
+            rfc3339(started)
+                .chars()
+                .take(19)
+                .collect::<String>()
+                .replace('T', " "),
+            took,
+            target,
+            run.attempted.to_string(),
+            run.connected.to_string(),
+            format!("{} ({:.1}%)", failed, loss),
+            ms(run.min_ms),
+            ms(run.avg_ms),
+            ms(run.max_ms),
+        ]);
+        losses.push(Some(loss));
+    }
+    let mut widths = [0; 10];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    println!();
+    println!(
+        "{} run{} in  {}:",
+        runs.len(),
+        if runs.len() == 1 { "" } else { "s" },
+        path.display().to_string().green()
+    );
+    println!();
+    for (row, loss) in rows.iter().zip(losses) {
+        let mut line = String::new();
+        for (i, (cell, width)) in row.iter().zip(widths).enumerate() {
+            let padded = format!("  {:<width$}", cell, width = width);
+            let colored = match (i, loss) {
+                (6, Some(loss)) if loss > 0.0 => padded.yellow().to_string(),
+                (6, Some(_)) => padded.green().to_string(),
+                _ => padded,
+            };
+            line.push_str(&colored);
+        }
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paping::pinger::FailureKind;
+
+    /// seq, target, port, ok, rtt_ms, error_kind, error_msg
+    type Stored = (
+        u32,
+        String,
+        u16,
+        bool,
+        Option<f64>,
+        Option<String>,
+        Option<String>,
+    );
+
+    fn temp_db(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("paping-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Records `results` as a run, returning its statistics.
+    fn record(path: &Path, label: Option<&str>, results: &[PingResult]) -> PingStats {
+        let recorder = Recorder::open(path, "example.com", 443, label).unwrap();
+        let mut observer = recorder.observer();
+        let mut stats = PingStats::default();
+        for result in results {
+            observer(result);
+            stats.record(result);
+        }
+        drop(observer);
+        recorder.finish(&stats).unwrap();
+        stats
+    }
+
+    #[test]
+    fn probes_and_runs_are_written_on_finish() {
+        let path = temp_db("history");
+        let refused = PingResult::failed(FailureKind::Refused, "connection refused");
+        let fast = PingResult::connected(Duration::from_millis(12));
+        let slow = PingResult::connected(Duration::from_millis(20));
+        record(&path, None, &[fast, refused]);
+        record(&path, Some("via vpn"), &[slow]);
+
+        let db = Connection::open(&path).unwrap();
+        let probes: Vec<Stored> = db
+            .prepare(
+                "SELECT seq, target, port, ok, rtt_ms, error_kind, error_msg FROM probes
+                 WHERE run_id = 1 ORDER BY seq",
+            )
+            .unwrap()
+            .query_map([], |r| {
+                Ok((
+                    r.get(0)?,
+                    r.get(1)?,
+                    r.get(2)?,
+                    r.get(3)?,
+                    r.get(4)?,
+                    r.get(5)?,
+                    r.get(6)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            probes,
+            vec![
+                (1, "example.com".into(), 443, true, Some(12.0), None, None),
+                (
+                    2,
+                    "example.com".into(),
+                    443,
+                    false,
+                    None,
+                    Some("refused".into()),
+                    Some("connection refused".into())
+                ),
+            ]
+        );
+        let (attempted, failed, ended): (u32, u32, Option<f64>) = db
+            .query_row(
+                "SELECT attempted, failed, ended FROM runs WHERE id = 1",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((attempted, failed), (2, 1));
+        assert!(ended.is_some());
+
+        let runs = runs(&path, Some(1)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].id, 2);
+        assert_eq!(runs[0].label.as_deref(), Some("via vpn"));
+        assert_eq!((runs[0].attempted, runs[0].connected), (1, 1));
+    }
+
+    #[test]
+    fn pings_of_earlier_versions_become_runs() {
+        let path = temp_db("old");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE pings (timestamp REAL NOT NULL, target TEXT NOT NULL,
+                 port INTEGER NOT NULL, success INTEGER NOT NULL, time_ms REAL, error TEXT);
+                 INSERT INTO pings VALUES (100.0, 'a.example', 80, 1, 5.0, NULL);
+                 INSERT INTO pings VALUES (101.0, 'a.example', 80, 0, NULL, 'timed out');
+                 INSERT INTO pings VALUES (102.0, 'b.example', 22, 1, 7.0, NULL);",
+            )
+            .unwrap();
+
+        let old = runs(&path, None).unwrap();
+        assert_eq!(old.len(), 2);
+        assert_eq!(
+            (old[0].target.as_str(), old[0].attempted, old[0].connected),
+            ("a.example", 2, 1)
+        );
+        assert_eq!((old[0].started, old[0].ended), (100.0, Some(101.0)));
+        assert_eq!(
+            (old[1].target.as_str(), old[1].avg_ms),
+            ("b.example", Some(7.0))
+        );
+
+        // New runs go alongside
+        let up = PingResult::connected(Duration::from_millis(1));
+        record(&path, None, &[up]);
+        let db = Connection::open(&path).unwrap();
+        assert!(!has_table(&db, "pings").unwrap());
+        assert_eq!(version(&db).unwrap(), MIGRATIONS.len());
+        drop(db);
+        assert_eq!(runs(&path, None).unwrap().len(), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn newer_and_foreign_databases_are_refused() {
+        let path = temp_db("newer");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE schema_version (version INTEGER NOT NULL);
+                 INSERT INTO schema_version VALUES (99);",
+            )
+            .unwrap();
+        let err = Recorder::open(&path, "example.com", 443, None)
+            .err()
+            .unwrap();
+        assert!(err.contains("newer paping (schema 99"), "{}", err);
+        std::fs::remove_file(&path).unwrap();
+
+        let path = temp_db("foreign");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE other (x INTEGER)")
+            .unwrap();
+        assert!(runs(&path, None).unwrap_err().contains("no paping history"));
+        std::fs::remove_file(&path).unwrap();
+        assert!(runs(&path, None).is_err(), "a missing file isn't created");
+    }
+}
@@ -0,0 +1,257 @@
+//! --statsd: a UDP packet of StatsD metrics per probe, for a statsd or
+//! Datadog agent to aggregate.
+//!
+//! ```text
+//! paping.example_com_443.attempts:1|c
+//! paping.example_com_443.failures.timeout:1|c
+//! paping.example_com_443.consecutive_failures:2|g
+//! ```
+//!
+//! With --statsd-tags, the target and the kind of failure are Datadog tags
+//! instead of parts of the names:
+//!
+//! ```text
+//! paping.attempts:1|c|#target:example.com,port:443
+//! paping.rtt:12.50|ms|#target:example.com,port:443
+//! ```
+//!
+//! Packets are sent from the probing thread on a non-blocking socket: a
+//! packet that can't go out is dropped, and counted for `finish`.
+
+use std::fmt::Write as _;
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use colored::Colorize;
+use paping::pinger::PingResult;
+
+/// How the metrics are named.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Naming {
+    /// Put before every name, with a dot (none when empty)
+    pub prefix: String,
+    /// Datadog tags rather than names per target and kind of failure
+    pub tags: bool,
+}
+
+/// The metrics of one probe of `target:port`, a line each: attempts,
+/// failures (by kind) or the connection time, then the failures in a row
+/// so far.
+pub fn lines(
+    naming: &Naming,
+    target: &str,
+    port: u16,
+    result: &PingResult,
+    consecutive_failures: u32,
+) -> String {
+    let base = match naming.tags {
+        true => String::new(),
+        false => format!("{}_{}", name_part(target), port),
+    };
+    let name = |metric: &str| {
+        [naming.prefix.as_str(), base.as_str(), metric]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(".")
+    };
+    let tags = |kind: Option<&str>| {
+        if !naming.tags {
+            return String::new();
+        }
+        let mut tags = format!("|#target:{},port:{}", tag_value(target), port);
+        if let Some(kind) = kind {
+            let _ = write!(tags, ",kind:{}", kind);
+        }
+        tags
+    };
+    let mut out = String::new();
+    let _ = writeln!(out, "{}:1|c{}", name("attempts"), tags(None));
+    match result {
+        PingResult::Connected(c) => {
+            let ms = c.time.as_secs_f64() * 1000.0;
+            let _ = writeln!(out, "{}:{:.2}|ms{}", name("rtt"), ms, tags(None));
+        }
+        PingResult::Failed(f) if naming.tags => {
+            let _ = writeln!(
+                out,
+                "{}:1|c{}",
+                name("failures"),
+                tags(Some(f.kind.as_str()))
+            );
+        }
+        PingResult::Failed(f) => {
+            let metric = format!("failures.{}", f.kind.as_str());
+            let _ = writeln!(out, "{}:1|c", name(&metric));
+        }
+    }
+    let _ = write!(
+        out,
+        "{}:{}|g{}",
+        name("consecutive_failures"),
+        consecutive_failures,
+        tags(None)
+    );
+    out
+}
+
+/// Anything but letters, digits, `_` and `-` would split or break a name.
+fn name_part(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Tag values can't hold the separators of the tag list.
+fn tag_value(value: &str) -> String {
+    value.replace([',', '|', '#', ' '], "_")
+}
+
+/// The socket the packets go out of, shared by the observers.
+pub struct Statsd {
+    socket: Arc<UdpSocket>,
+    naming: Naming,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Statsd {
+    /// Resolves HOST:PORT and connects a non-blocking socket to it.
+    pub fn connect(addr: &str, naming: Naming) -> io::Result<Self> {
+        let target = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::other("no address"))?;
+        let bind = if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(target)?;
+        socket.set_nonblocking(true)?;
+        Ok(Statsd {
+            socket: Arc::new(socket),
+            naming,
+            dropped: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Sends the counters and timing of each probe of `target`:`port` to
+    /// the StatsD server, one UDP packet per result.
+    pub fn observer(&self, target: &str, port: u16) -> impl FnMut(&PingResult) + Send + 'static {
+        let socket = Arc::clone(&self.socket);
+        let naming = self.naming.clone();
+        let dropped = Arc::clone(&self.dropped);
+        let target = target.to_string();
+        let mut consecutive_failures = 0;
+        move |result| {
+            consecutive_failures = match result.is_success() {
+                true => 0,
+                false => consecutive_failures + 1,
+            };
+            let packet = lines(&naming, &target, port, result, consecutive_failures);
+            // Including refusals reported for an earlier packet
+            if socket.send(packet.as_bytes()).is_err() {
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Tells how many packets were dropped, if any.
+    pub fn finish(self) {
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            eprintln!(
+                "{}",
+                format!("Warning: --statsd: {} packet(s) could not be sent", dropped).yellow()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paping::pinger::FailureKind;
+    use std::time::Duration;
+
+    #[test]
+    fn serializes_metrics_with_and_without_tags() {
+        let up = PingResult::connected(Duration::from_micros(12_500));
+        let down = PingResult::failed(FailureKind::Timeout, "timed out");
+        let plain = Naming {
+            prefix: "paping".into(),
+            tags: false,
+        };
+        assert_eq!(
+            lines(&plain, "db.example.com", 5432, &up, 0),
+            "paping.db_example_com_5432.attempts:1|c\n\
+             paping.db_example_com_5432.rtt:12.50|ms\n\
+             paping.db_example_com_5432.consecutive_failures:0|g"
+        );
+        assert_eq!(
+            lines(&plain, "::1", 22, &down, 3),
+            "paping.__1_22.attempts:1|c\n\
+             paping.__1_22.failures.timeout:1|c\n\
+             paping.__1_22.consecutive_failures:3|g"
+        );
+
+        let tagged = Naming {
+            prefix: "net.probe".into(),
+            tags: true,
+        };
+        assert_eq!(
+            lines(&tagged, "a,b", 443, &down, 1),
+            "net.probe.attempts:1|c|#target:a_b,port:443\n\
+             net.probe.failures:1|c|#target:a_b,port:443,kind:timeout\n\
+             net.probe.consecutive_failures:1|g|#target:a_b,port:443"
+        );
+
+        let bare = Naming {
+            prefix: String::new(),
+            tags: true,
+        };
+        assert!(lines(&bare, "x", 1, &up, 0).starts_with("attempts:1|c|#"));
+    }
+
+    #[test]
+    fn sends_a_packet_per_probe() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let naming = Naming {
+            prefix: "paping".into(),
+            tags: true,
+        };
+        let statsd = Statsd::connect(&server.local_addr().unwrap().to_string(), naming).unwrap();
+        let up = PingResult::connected(Duration::from_micros(12_500));
+        let down = PingResult::failed(FailureKind::Timeout, "timed out");
+        let mut observer = statsd.observer("example.com", 443);
+        for result in [&down, &down, &up] {
+            observer(result);
+        }
+        let mut buf = [0u8; 1024];
+        let mut gauges = Vec::new();
+        for _ in 0..3 {
+            let n = server.recv(&mut buf).unwrap();
+            let packet = String::from_utf8_lossy(&buf[..n]).to_string();
+            gauges.push(packet.lines().last().unwrap().to_string());
+        }
+        let gauge = |n| {
+            format!(
+                "paping.consecutive_failures:{}|g|#target:example.com,port:443",
+                n
+            )
+        };
+        assert_eq!(gauges, [gauge(1), gauge(2), gauge(0)]);
+        assert_eq!(statsd.dropped.load(Ordering::Relaxed), 0);
+    }
+}
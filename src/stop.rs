@@ -0,0 +1,171 @@
+//! Cancellation for [`Pinger::run`](crate::pinger::Pinger::run).
+//!
+//! A [`StopHandle`] stops a run from anywhere (another thread, a Ctrl+C
+//! handler...); the run itself only sees the matching [`StopToken`]. Stopping
+//! wakes the pause between two probes right away.
+//!
+//! ```
+//! use paping::stop::StopHandle;
+//! use std::time::Duration;
+//!
+//! let handle = StopHandle::new();
+//! let token = handle.token();
+//! std::thread::spawn(move || handle.stop());
+//! // Returns as soon as stop() is called, not after a minute
+//! token.wait_timeout(Duration::from_secs(60));
+//! assert!(token.is_stopped());
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Stops the runs holding one of its tokens. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct StopHandle {
+    inner: Arc<Inner>,
+}
+
+/// Lets a run check for, and wait on, a stop request. Cheap to clone.
+#[derive(Clone)]
+pub struct StopToken {
+    source: Source,
+}
+
+#[derive(Default)]
+struct Inner {
+    stopped: Mutex<bool>,
+    wakeup: Condvar,
+}
+
+#[derive(Clone)]
+enum Source {
+    Handle(Arc<Inner>),
+    /// Old `Arc<AtomicBool>` API: can only be polled
+    Flag(Arc<AtomicBool>),
+}
+
+impl StopHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token observing this handle.
+    pub fn token(&self) -> StopToken {
+        StopToken {
+            source: Source::Handle(self.inner.clone()),
+        }
+    }
+
+    /// Requests a stop and wakes every token waiting in `wait_timeout`.
+    /// Probes already in flight still finish (or time out).
+    pub fn stop(&self) {
+        *lock(&self.inner.stopped) = true;
+        self.inner.wakeup.notify_all();
+    }
+}
+
+impl StopToken {
+    /// A token that is never stopped.
+    pub fn never() -> Self {
+        StopHandle::new().token()
+    }
+
+    /// Wraps the flag of the old `run(count, &Arc<AtomicBool>)` API.
+    pub(crate) fn from_flag(flag: Arc<AtomicBool>) -> Self {
+        StopToken {
+            source: Source::Flag(flag),
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        match self.source {
+            Source::Handle(ref inner) => *lock(&inner.stopped),
+            Source::Flag(ref flag) => flag.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Sleeps for `duration`, or less if a stop is requested meanwhile.
+    /// Returns whether the token is stopped.
+    pub fn wait_timeout(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        match self.source {
+            Source::Handle(ref inner) => {
+                let mut stopped = lock(&inner.stopped);
+                while !*stopped {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        break;
+                    }
+                    stopped = inner
+                        .wakeup
+                        .wait_timeout(stopped, left)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0;
+                }
+                *stopped
+            }
+            Source::Flag(ref flag) => {
+                // Nothing to wake us up: poll in small chunks
+                while !flag.load(Ordering::SeqCst) {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return false;
+                    }
+                    std::thread::sleep(left.min(Duration::from_millis(100)));
+                }
+                true
+            }
+        }
+    }
+}
+
+fn lock(stopped: &Mutex<bool>) -> std::sync::MutexGuard<'_, bool> {
+    stopped.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_wakes_waiter_early() {
+        let handle = StopHandle::new();
+        let token = handle.token();
+        let waiter = std::thread::spawn(move || {
+            let start = Instant::now();
+            let stopped = token.wait_timeout(Duration::from_secs(30));
+            (stopped, start.elapsed())
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        handle.stop();
+        let (stopped, waited) = waiter.join().unwrap();
+        assert!(stopped);
+        assert!(waited < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn wait_times_out_when_not_stopped() {
+        let token = StopToken::never();
+        assert!(!token.wait_timeout(Duration::from_millis(20)));
+        assert!(!token.is_stopped());
+    }
+
+    #[test]
+    fn stop_is_seen_by_every_token() {
+        let handle = StopHandle::new();
+        let (a, b) = (handle.token(), handle.clone().token());
+        handle.stop();
+        assert!(a.is_stopped() && b.is_stopped());
+        assert!(a.wait_timeout(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn flag_token_follows_the_flag() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let token = StopToken::from_flag(flag.clone());
+        assert!(!token.is_stopped());
+        flag.store(true, Ordering::SeqCst);
+        assert!(token.wait_timeout(Duration::from_secs(30)));
+    }
+}
@@ -0,0 +1,92 @@
+//! How output is drawn: with Unicode glyphs by default, or in plain 7-bit
+//! ASCII after [`set_ascii`] (--ascii, for serial consoles and terminals
+//! that garble anything else). Output helpers drawing more than text take
+//! their characters from [`glyphs`] instead of hardcoding them.
+
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ASCII: AtomicBool = AtomicBool::new(false);
+
+/// Switches every output helper of the process to ASCII (or back).
+/// Colors are left alone: turn them off with `colored::control`.
+pub fn set_ascii(ascii: bool) {
+    ASCII.store(ascii, Ordering::Relaxed);
+}
+
+/// Whether output is restricted to 7-bit ASCII.
+pub fn is_ascii() -> bool {
+    ASCII.load(Ordering::Relaxed)
+}
+
+/// Characters used to draw, in the current style.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Glyphs {
+    /// Filled part of a bar
+    pub bar_full: &'static str,
+    /// Rest of a bar
+    pub bar_empty: &'static str,
+}
+
+const UNICODE: Glyphs = Glyphs {
+    bar_full: "█",
+    bar_empty: "░",
+};
+
+const ASCII_GLYPHS: Glyphs = Glyphs {
+    bar_full: "#",
+    bar_empty: ".",
+};
+
+/// The glyphs of the current style.
+pub fn glyphs() -> &'static Glyphs {
+    Glyphs::of(is_ascii())
+}
+
+impl Glyphs {
+    fn of(ascii: bool) -> &'static Glyphs {
+        if ascii {
+            &ASCII_GLYPHS
+        } else {
+            &UNICODE
+        }
+    }
+}
+
+/// `text` ready to print: as is, or in ASCII mode with every other
+/// character (from host names, OS error messages, replies...) replaced
+/// by `?`.
+pub fn text(text: &str) -> Cow<'_, str> {
+    if is_ascii() {
+        to_ascii(text)
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn to_ascii(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(
+        text.chars()
+            .map(|c| if c.is_ascii() { c } else { '?' })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_style_is_7_bit() {
+        let glyphs = Glyphs::of(true);
+        assert!(glyphs.bar_full.is_ascii() && glyphs.bar_empty.is_ascii());
+        assert_eq!(
+            to_ascii("Connection to bücher.example failed: délai"),
+            "Connection to b?cher.example failed: d?lai"
+        );
+        assert!(matches!(to_ascii("example.com"), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,298 @@
+//! Sweeping a range: `paping 192.168.1.0/24 -p 22` probes every host of
+//! it in turn, as --target-list does a list, and prints a line per host
+//! once its probes (one, unless -c) are done:
+//!
+//! ```text
+//! 192.168.1.1:22    open      0.52ms
+//! 192.168.1.2:22    closed    Connection refused (os error 111)
+//! 192.168.1.3:22    filtered  Connection timed out
+//! ```
+//!
+//! A host is open when any of its probes connected; otherwise its last
+//! failure tells: refused is closed, no answer or unreachable is filtered.
+//! Ranges of more than `CONFIRM_ABOVE` hosts are only swept once confirmed
+//! (or with --yes), of more than `MAX_HOSTS` not at all.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use colored::Colorize;
+use ipnet::IpNet;
+use paping::multi::{TargetEntry, DEFAULT_WEIGHT};
+use paping::pinger::{FailureKind, PingResult};
+use paping::target::join_host_port;
+
+/// Hosts swept without asking first.
+pub const CONFIRM_ABOVE: usize = 256;
+/// Hosts of the largest range swept (a /16 of IPv4).
+pub const MAX_HOSTS: usize = 65_536;
+/// Pause between two probes, unless --interval.
+pub const INTERVAL: Duration = Duration::from_millis(10);
+
+/// The range `address` is written as, if it is one: "10.0.0.0/24",
+/// "2001:db8::/120".
+pub fn parse(address: &str) -> Option<IpNet> {
+    address.parse().ok()
+}
+
+/// The hosts of `net` (for IPv4, without the network and broadcast
+/// addresses of ranges larger than a /31).
+pub fn hosts(net: &IpNet) -> Result<Vec<IpAddr>, String> {
+    let bits = u32::from(net.max_prefix_len() - net.prefix_len());
+    if bits > MAX_HOSTS.ilog2() {
+        return Err(format!(
+            "{} has more than {} hosts: sweep it a part at a time",
+            net, MAX_HOSTS
+        ));
+    }
+    Ok(net.hosts().collect())
+}
+
+/// The hosts as target list lines, each to be probed `count` times.
+pub fn entries(hosts: &[IpAddr], count: u32) -> Vec<TargetEntry> {
+    hosts
+        .iter()
+        .map(|ip| TargetEntry {
+            host: ip.to_string(),
+            port: None,
+            weight: DEFAULT_WEIGHT,
+            count: Some(count),
+        })
+        .collect()
+}
+
+/// Asks on the terminal before sweeping more than `CONFIRM_ABOVE` hosts,
+/// unless `yes`. Without a terminal to ask on, --yes is needed.
+pub fn confirm(net: &IpNet, hosts: usize, yes: bool) -> Result<(), String> {
+    if yes || hosts <= CONFIRM_ABOVE {
+        return Ok(());
+    }
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        return Err(format!(
+            "{} has {} hosts: --yes to sweep them all",
+            net, hosts
+        ));
+    }
+    eprint!("Sweep all {} hosts of {}? [y/N] ", hosts, net);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    stdin
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|e| e.to_string())?;
+    match answer.trim() {
+        "y" | "Y" | "yes" => Ok(()),
+        _ => Err("sweep cancelled".to_string()),
+    }
+}
+
+/// What the probes of one host found so far.
+#[derive(Clone, Debug, Default)]
+struct Host {
+    probes: u32,
+    times: Vec<f64>,
+    last_failure: Option<(FailureKind, String)>,
+}
+
+impl Host {
+    fn record(&mut self, result: &PingResult) {
+        self.probes += 1;
+        match result {
+            PingResult::Connected(c) => self.times.push(c.time.as_secs_f64() * 1000.0),
+            PingResult::Failed(f) => self.last_failure = Some((f.kind, f.message.clone())),
+        }
+    }
+
+    /// "open", "closed", "filtered" or "failed", with the average time
+    /// or the last failure.
+    fn verdict(&self) -> (&'static str, String) {
+        if !self.times.is_empty() {
+            let average = self.times.iter().sum::<f64>() / self.times.len() as f64;
+            return ("open", format!("{:.2}ms", average));
+        }
+        match self.last_failure {
+            Some((FailureKind::Refused, ref message)) => ("closed", message.clone()),
+            Some((FailureKind::Timeout | FailureKind::Unreachable, ref message)) => {
+                ("filtered", message.clone())
+            }
+            Some((_, ref message)) => ("failed", message.clone()),
+            None => ("failed", String::new()),
+        }
+    }
+}
+
+/// Hosts done, by verdict.
+#[derive(Debug, Default)]
+struct Tally {
+    open: Vec<IpAddr>,
+    closed: u32,
+    filtered: u32,
+    failed: u32,
+}
+
+/// Prints the line of each host as it is done, and the counts at the end.
+pub struct Sweep {
+    net: IpNet,
+    port: u16,
+    hosts: usize,
+    /// Of the longest host:port, for the verdicts to line up
+    width: usize,
+    tally: Arc<Mutex<Tally>>,
+}
+
+impl Sweep {
+    pub fn new(net: IpNet, port: u16, hosts: &[IpAddr]) -> Self {
+        let width = hosts
+            .iter()
+            .map(|ip| join_host_port(&ip.to_string(), port).len())
+            .max()
+            .unwrap_or_default();
+        Sweep {
+            net,
+            port,
+            hosts: hosts.len(),
+            width,
+            tally: Arc::new(Mutex::new(Tally::default())),
+        }
+    }
+
+    /// Observer to register with the `Pinger::on_result` of `host`, probed
+    /// `probes` times.
+    pub fn observer(&self, host: IpAddr, probes: u32) -> impl FnMut(&PingResult) + Send + 'static {
+        let (port, width) = (self.port, self.width);
+        let tally = Arc::clone(&self.tally);
+        let mut seen = Host::default();
+        move |result| {
+            seen.record(result);
+            if seen.probes != probes {
+                return;
+            }
+            let (verdict, detail) = seen.verdict();
+            // Padded before coloring, which the width would count
+            let padded = format!("{:<8}", verdict);
+            let mut tally = tally.lock().unwrap_or_else(|e| e.into_inner());
+            let state = match verdict {
+                "open" => {
+                    tally.open.push(host);
+                    padded.green()
+                }
+                "closed" => {
+                    tally.closed += 1;
+                    padded.red()
+                }
+                "filtered" => {
+                    tally.filtered += 1;
+                    padded.yellow()
+                }
+                _ => {
+                    tally.failed += 1;
+                    padded.red()
+                }
+            };
+            let target = join_host_port(&host.to_string(), port);
+            println!("{:<width$}  {}  {}", target, state, detail, width = width);
+        }
+    }
+
+    pub fn print_header(&self) {
+        println!();
+        println!(
+            "Sweeping  {}  hosts of {} on port {}:",
+            self.hosts.to_string().green(),
+            self.net.trunc().to_string().green(),
+            self.port.to_string().green()
+        );
+        println!();
+    }
+
+    /// The counts of each verdict, and the open hosts.
+    pub fn print_summary(&self) {
+        let tally = self.tally.lock().unwrap_or_else(|e| e.into_inner());
+        let mut counts = vec![
+            format!("{} open", tally.open.len()).green().to_string(),
+            format!("{} closed", tally.closed),
+            format!("{} filtered", tally.filtered),
+        ];
+        if tally.failed > 0 {
+            counts.push(format!("{} failed", tally.failed));
+        }
+        let done = tally.open.len() + (tally.closed + tally.filtered + tally.failed) as usize;
+        if done < self.hosts {
+            counts.push(format!("{} not swept", self.hosts - done));
+        }
+        println!("Sweep statistics:");
+        println!("  {} hosts: {}", self.hosts, counts.join(", "));
+        if !tally.open.is_empty() {
+            let open: Vec<String> = tally.open.iter().map(IpAddr::to_string).collect();
+            println!("  Open: {}", open.join(", ").green());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_ranges() {
+        let hosts = |spec: &str| hosts(&parse(spec).unwrap()).unwrap();
+        assert_eq!(
+            hosts("192.168.1.0/30"),
+            [
+                "192.168.1.1".parse::<IpAddr>().unwrap(),
+                "192.168.1.2".parse().unwrap()
+            ]
+        );
+        assert_eq!(hosts("10.0.0.7/32").len(), 1);
+        assert_eq!(hosts("10.0.0.6/31").len(), 2);
+        assert_eq!(hosts("10.1.2.3/24").len(), 254);
+        assert_eq!(hosts("2001:db8::/126").len(), 4);
+        assert_eq!(hosts("10.0.0.0/16").len(), 65_534);
+        assert!(super::hosts(&parse("10.0.0.0/15").unwrap()).is_err());
+        assert!(super::hosts(&parse("2001:db8::/64").unwrap()).is_err());
+        for single in [
+            "10.0.0.1",
+            "example.com",
+            "https://example.com/a/b",
+            "[::1]:22",
+        ] {
+            assert_eq!(parse(single), None, "{}", single);
+        }
+        assert!(confirm(&parse("10.0.0.0/16").unwrap(), 65_534, true).is_ok());
+    }
+
+    #[test]
+    fn tells_open_closed_and_filtered_hosts() {
+        let up = PingResult::connected(Duration::from_millis(2));
+        let verdict = |results: &[PingResult]| {
+            let mut host = Host::default();
+            results.iter().for_each(|r| host.record(r));
+            host.verdict()
+        };
+        let refused = PingResult::failed(FailureKind::Refused, "connection refused");
+        let timeout = PingResult::failed(FailureKind::Timeout, "connection timed out");
+        assert_eq!(
+            verdict(&[refused.clone(), up]),
+            ("open", "2.00ms".to_string())
+        );
+        assert_eq!(verdict(&[timeout.clone(), refused]).0, "closed");
+        assert_eq!(verdict(&[timeout]).0, "filtered");
+        assert_eq!(
+            verdict(&[PingResult::failed(FailureKind::Tls, "bad certificate")]).0,
+            "failed"
+        );
+
+        let net = parse("127.0.0.0/30").unwrap();
+        let hosts = hosts(&net).unwrap();
+        let sweep = Sweep::new(net, 22, &hosts);
+        let mut observer = sweep.observer(hosts[0], 2);
+        let refused = PingResult::failed(FailureKind::Refused, "connection refused");
+        observer(&refused);
+        assert_eq!(sweep.tally.lock().unwrap().closed, 0, "counted once done");
+        observer(&refused);
+        assert_eq!(sweep.tally.lock().unwrap().closed, 1);
+    }
+}
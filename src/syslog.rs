@@ -0,0 +1,535 @@
+//! --syslog: every result, and every change of state, as an RFC 5424
+//! message to the local syslog daemon (/dev/log) or a remote one:
+//!
+//! ```text
+//! <11>1 2026-10-15T09:30:00.123Z web1 paping 4242 probe [paping@32473 target="example.com" port="443" seq="12" kind="timeout"] example.com:443: connection timed out
+//! <13>1 2026-10-15T09:30:01.124Z web1 paping 4242 state [paping@32473 target="example.com" port="443" seq="13" rtt_ms="12.50"] example.com:443 is up again
+//! ```
+//!
+//! The facility is `user`; the severity is info for a connection, warning
+//! for one slower than --max-latency and err for a failure, a change of
+//! state being notice (up) or err (down). 32473 is the enterprise number
+//! set aside for examples (RFC 5612): the structured data is ours only.
+//!
+//! Messages go out from a thread, so that a syslog daemon that's slow or
+//! gone never holds up probing; those it can't take are dropped, and
+//! counted. A failed send drops the connection (or socket), made again
+//! for the next message, after a pause once it failed.
+
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use colored::Colorize;
+use paping::pinger::{FailureKind, PingResult};
+use paping::target::{join_host_port, split_host_port};
+use paping::template::rfc3339;
+
+/// The standard port of syslog over UDP, and over TCP (RFC 6587).
+const DEFAULT_PORT: u16 = 514;
+/// Messages waiting to be sent at most.
+const QUEUE: usize = 256;
+/// Bounds connecting, and each send.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// After a failure, no new attempt before this long.
+const RETRY_AFTER: Duration = Duration::from_secs(5);
+/// `user`
+const FACILITY: u8 = 1;
+/// Structured data element of the messages.
+const SD_ID: &str = "paping@32473";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Err = 3,
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+}
+
+/// Where messages go.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// A local datagram socket
+    Unix(std::path::PathBuf),
+    Udp(String),
+    /// Framed by octet counting (RFC 6587)
+    Tcp(String),
+}
+
+impl Transport {
+    /// The local daemon without `spec`; otherwise `udp://HOST[:PORT]`,
+    /// `tcp://HOST[:PORT]`, `unix:PATH`, or `HOST[:PORT]` for UDP.
+    pub fn parse(spec: Option<&str>) -> Result<Self, String> {
+        let Some(spec) = spec.filter(|s| !s.is_empty()) else {
+            return local();
+        };
+        if let Some(path) = spec.strip_prefix("unix:") {
+            return Ok(Transport::Unix(path.into()));
+        }
+        let (remote, tcp) = match spec.split_once("://") {
+            Some(("udp", rest)) => (rest, false),
+            Some(("tcp", rest)) => (rest, true),
+            Some((scheme, _)) => {
+                return Err(format!(
+                    "unknown scheme '{}' in '{}' (udp://, tcp:// or unix:)",
+                    scheme, spec
+                ))
+            }
+            None => (spec, false),
+        };
+        let (host, port) = split_host_port(remote.trim_end_matches('/'))?;
+        if host.is_empty() {
+            return Err(format!("no host in '{}'", spec));
+        }
+        let address = join_host_port(&host, port.unwrap_or(DEFAULT_PORT));
+        Ok(if tcp {
+            Transport::Tcp(address)
+        } else {
+            Transport::Udp(address)
+        })
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        match self {
+            #[cfg(unix)]
+            Transport::Unix(path) => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                socket.set_write_timeout(Some(TIMEOUT))?;
+                Ok(Connection::Unix(socket))
+            }
+            #[cfg(not(unix))]
+            Transport::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unix sockets are not supported on this system",
+            )),
+            Transport::Udp(address) => {
+                let target = address
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| io::Error::other("no address"))?;
+                let bind = if target.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(bind)?;
+                socket.connect(target)?;
+                Ok(Connection::Udp(socket))
+            }
+            Transport::Tcp(address) => {
+                let mut last = None;
+                for addr in address.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&addr, TIMEOUT) {
+                        Ok(stream) => {
+                            stream.set_write_timeout(Some(TIMEOUT))?;
+                            return Ok(Connection::Tcp(stream));
+                        }
+                        Err(e) => last = Some(e),
+                    }
+                }
+                Err(last.unwrap_or_else(|| io::Error::other("no address")))
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Transport::Unix(path) => path.display().to_string(),
+            Transport::Udp(address) => format!("udp://{}", address),
+            Transport::Tcp(address) => format!("tcp://{}", address),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn local() -> Result<Transport, String> {
+    // macOS has no /dev/log
+    ["/dev/log", "/var/run/syslog"]
+        .iter()
+        .map(std::path::Path::new)
+        .find(|path| path.exists())
+        .map(|path| Transport::Unix(path.into()))
+        .ok_or_else(|| "no local syslog socket (/dev/log): give an address".to_string())
+}
+
+#[cfg(not(unix))]
+fn local() -> Result<Transport, String> {
+    Err(
+        "there is no local syslog on this system (the Event Log isn't supported): \
+         give the address of a syslog server, e.g. udp://192.0.2.10"
+            .to_string(),
+    )
+}
+
+enum Connection {
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl Connection {
+    fn send(&mut self, message: &str) -> io::Result<()> {
+        match self {
+            #[cfg(unix)]
+            Connection::Unix(socket) => socket.send(message.as_bytes()).map(|_| ()),
+            Connection::Udp(socket) => socket.send(message.as_bytes()).map(|_| ()),
+            Connection::Tcp(stream) => {
+                write!(stream, "{} {}", message.len(), message)?;
+                stream.flush()
+            }
+        }
+    }
+}
+
+/// What a message is about.
+struct Event<'a> {
+    msgid: &'static str,
+    severity: Severity,
+    target: &'a str,
+    port: u16,
+    seq: u32,
+    result: &'a PingResult,
+    text: String,
+}
+
+/// Severity of a probe's result.
+pub fn severity(result: &PingResult) -> Severity {
+    match result {
+        PingResult::Connected(_) => Severity::Info,
+        PingResult::Failed(f) if f.kind == FailureKind::Slow => Severity::Warning,
+        PingResult::Failed(_) => Severity::Err,
+    }
+}
+
+/// The message, RFC 5424 formatted.
+fn render(event: &Event, at: SystemTime, hostname: &str, pid: u32) -> String {
+    let mut data = format!(
+        "[{} target=\"{}\" port=\"{}\" seq=\"{}\"",
+        SD_ID,
+        sd_escape(event.target),
+        event.port,
+        event.seq
+    );
+    match event.result {
+        PingResult::Connected(c) => {
+            data.push_str(&format!(" rtt_ms=\"{:.2}\"", c.time.as_secs_f64() * 1000.0))
+        }
+        PingResult::Failed(f) => data.push_str(&format!(" kind=\"{}\"", f.kind.as_str())),
+    }
+    data.push(']');
+    format!(
+        "<{}>1 {} {} paping {} {} {} {}",
+        FACILITY * 8 + event.severity as u8,
+        rfc3339(at),
+        hostname,
+        pid,
+        event.msgid,
+        data,
+        event.text
+    )
+}
+
+/// Parameter values escape `"`, `\` and `]`.
+fn sd_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// This machine's name, or the nil value.
+fn hostname() -> String {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok());
+    // PRINTUSASCII without spaces, at most 255 characters
+    match name.map(|n| n.trim().to_string()) {
+        Some(n)
+            if !n.is_empty() && n.len() <= 255 && n.bytes().all(|b| (33..=126).contains(&b)) =>
+        {
+            n
+        }
+        _ => "-".to_string(),
+    }
+}
+
+/// Sends the messages of the observers it hands out.
+pub struct Syslog {
+    queue: SyncSender<String>,
+    dropped: Arc<AtomicU32>,
+    thread: JoinHandle<()>,
+}
+
+impl Syslog {
+    /// Starts the thread sending to `transport`.
+    pub fn start(transport: Transport) -> Self {
+        let (queue, messages) = mpsc::sync_channel(QUEUE);
+        let dropped = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&dropped);
+        let thread = std::thread::spawn(move || send_all(&transport, messages, &counter));
+        Syslog {
+            queue,
+            dropped,
+            thread,
+        }
+    }
+
+    /// Queues a message per probe of `target`:`port` for the syslog
+    /// server, and one more when it goes down or up. Dropped (and counted)
+    /// when the queue is full.
+    pub fn observer(&self, target: &str, port: u16) -> impl FnMut(&PingResult) + Send + 'static {
+        let queue = self.queue.clone();
+        let dropped = Arc::clone(&self.dropped);
+        let (target, port) = (target.to_string(), port);
+        let (hostname, pid) = (hostname(), std::process::id());
+        let name = join_host_port(&target, port);
+        let mut seq = 0;
+        let mut last_up = None;
+        let mut failures = 0;
+        move |result| {
+            seq += 1;
+            let up = result.is_success();
+            let mut events = vec![Event {
+                msgid: "probe",
+                severity: severity(result),
+                target: &target,
+                port,
+                seq,
+                result,
+                text: match result {
+                    PingResult::Connected(c) => format!(
+                        "{}: connected in {:.2}ms",
+                        name,
+                        c.time.as_secs_f64() * 1000.0
+                    ),
+                    PingResult::Failed(f) => format!("{}: {}", name, f.message),
+                },
+            }];
+            // The first result of the run only sets the state
+            if last_up.is_some_and(|was| was != up) {
+                events.push(Event {
+                    msgid: "state",
+                    severity: if up { Severity::Notice } else { Severity::Err },
+                    target: &target,
+                    port,
+                    seq,
+                    result,
+                    text: match result {
+                        PingResult::Connected(_) => format!(
+                            "{} is up again, after {} failed probe{}",
+                            name,
+                            failures,
+                            if failures == 1 { "" } else { "s" }
+                        ),
+                        PingResult::Failed(f) => format!("{} is down: {}", name, f.message),
+                    },
+                });
+            }
+            last_up = Some(up);
+            failures = if up { 0 } else { failures + 1 };
+            let now = SystemTime::now();
+            for event in events {
+                let message = render(&event, now, &hostname, pid);
+                if queue.try_send(message).is_err() {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Sends what is still queued. The pingers (holding the observers) must
+    /// be dropped first.
+    pub fn finish(self) {
+        drop(self.queue);
+        let _ = self.thread.join();
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            eprintln!(
+                "{}",
+                format!(
+                    "Warning: --syslog: {} message{} dropped",
+                    dropped,
+                    if dropped == 1 { "" } else { "s" }
+                )
+                .yellow()
+            );
+        }
+    }
+}
+
+/// The thread's side: sends each message, connecting as needed.
+fn send_all(transport: &Transport, messages: Receiver<String>, dropped: &AtomicU32) {
+    let mut connection = None;
+    let mut failed_at: Option<Instant> = None;
+    for message in messages {
+        if connection.is_none() {
+            if failed_at.is_some_and(|at| at.elapsed() < RETRY_AFTER) {
+                dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            match transport.connect() {
+                Ok(c) => connection = Some(c),
+                Err(e) => {
+                    fail(transport, &mut failed_at, e);
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            }
+        }
+        let sent = connection.as_mut().map(|c| c.send(&message));
+        match sent {
+            Some(Ok(())) if failed_at.take().is_some() => {
+                eprintln!("--syslog: sending to {} again", transport.describe());
+            }
+            Some(Err(e)) => {
+                connection = None;
+                fail(transport, &mut failed_at, e);
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Warns of the first failure of an outage.
+fn fail(transport: &Transport, failed_at: &mut Option<Instant>, e: io::Error) {
+    if failed_at.is_none() {
+        eprintln!(
+            "{}",
+            format!(
+                "Warning: --syslog: cannot send to {}: {} (retrying)",
+                transport.describe(),
+                e
+            )
+            .yellow()
+        );
+    }
+    *failed_at = Some(Instant::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_results_to_severities() {
+        assert_eq!(
+            severity(&PingResult::connected(Duration::from_millis(12))),
+            Severity::Info
+        );
+        assert_eq!(
+            severity(&PingResult::failed(FailureKind::Slow, "too slow")),
+            Severity::Warning
+        );
+        assert_eq!(
+            severity(&PingResult::failed(FailureKind::Refused, "refused")),
+            Severity::Err
+        );
+    }
+
+    #[test]
+    fn formats_rfc5424_messages() {
+        let at = SystemTime::UNIX_EPOCH + Duration::from_millis(1_760_520_600_123);
+        let result = PingResult::failed(FailureKind::Timeout, "connection timed out");
+        let event = Event {
+            msgid: "probe",
+            severity: Severity::Err,
+            target: "we\"ird]",
+            port: 443,
+            seq: 12,
+            result: &result,
+            text: "example.com:443: connection timed out".to_string(),
+        };
+        assert_eq!(
+            render(&event, at, "web1", 4242),
+            "<11>1 2025-10-15T09:30:00.123Z web1 paping 4242 probe \
+             [paping@32473 target=\"we\\\"ird\\]\" port=\"443\" seq=\"12\" kind=\"timeout\"] \
+             example.com:443: connection timed out"
+        );
+        let result = PingResult::connected(Duration::from_millis(12));
+        let event = Event {
+            msgid: "state",
+            severity: Severity::Notice,
+            target: "example.com",
+            port: 443,
+            seq: 13,
+            result: &result,
+            text: "example.com:443 is up again".to_string(),
+        };
+        assert!(render(&event, at, "-", 1)
+            .starts_with("<13>1 2025-10-15T09:30:00.123Z - paping 1 state [paping@32473 target=\"example.com\" port=\"443\" seq=\"13\" rtt_ms=\"12.00\"] "));
+    }
+
+    #[test]
+    fn parses_transports() {
+        assert_eq!(
+            Transport::parse(Some("192.0.2.10")),
+            Ok(Transport::Udp("192.0.2.10:514".into()))
+        );
+        assert_eq!(
+            Transport::parse(Some("tcp://logs.example.com:6514")),
+            Ok(Transport::Tcp("logs.example.com:6514".into()))
+        );
+        assert_eq!(
+            Transport::parse(Some("udp://[2001:db8::1]")),
+            Ok(Transport::Udp("[2001:db8::1]:514".into()))
+        );
+        assert_eq!(
+            Transport::parse(Some("unix:/run/syslog.sock")),
+            Ok(Transport::Unix("/run/syslog.sock".into()))
+        );
+        assert!(Transport::parse(Some("http://logs.example.com")).is_err());
+    }
+
+    #[test]
+    fn sends_state_changes_and_reconnects_over_tcp() {
+        use std::io::Read;
+        use std::net::TcpListener;
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let transport = Transport::Tcp(server.local_addr().unwrap().to_string());
+        let syslog = Syslog::start(transport);
+        let mut observer = syslog.observer("example.com", 443);
+
+        observer(&PingResult::connected(Duration::from_millis(12)));
+        let (first, _) = server.accept().unwrap();
+        // Gone: the next sends fail, and the one after the pause reconnects
+        drop(first);
+        let refused = PingResult::failed(FailureKind::Refused, "connection refused");
+        for _ in 0..3 {
+            observer(&refused);
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        std::thread::sleep(RETRY_AFTER);
+        observer(&PingResult::connected(Duration::from_millis(12)));
+        let (mut second, _) = server.accept().unwrap();
+        drop(observer);
+        syslog.finish();
+
+        let mut text = String::new();
+        second.read_to_string(&mut text).unwrap();
+        // Octet-counted: "LEN <PRI>1 ..."
+        let (len, rest) = text.split_once(' ').unwrap();
+        let first = &rest[..len.parse::<usize>().unwrap()];
+        assert!(first.starts_with("<14>1 "), "{}", text);
+        assert!(
+            first.ends_with("example.com:443: connected in 12.00ms"),
+            "{}",
+            text
+        );
+        assert!(text.contains("state [paping@32473"), "{}", text);
+        assert!(text.contains("example.com:443 is up again, after 3 failed probes"));
+    }
+}
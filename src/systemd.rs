@@ -0,0 +1,413 @@
+//! Running as a systemd service, on Linux.
+//!
+//! With `Type=notify`, paping tells systemd it's ready once the pinger is
+//! set up (the target resolved, the proxy checked), keeps the unit's status
+//! line up to date, and with `WatchdogSec=` feeds the watchdog from the
+//! probe loop: during the pauses too, so only a hung probe has the service
+//! restarted, however long the interval. With --journal, and stdout going
+//! to the journal, each result is a structured entry (`TARGET=`, `PORT=`,
+//! `RTT_MS=`, ...) sent to the journal's own socket rather than a line of
+//! text:
+//!
+//! ```text
+//! journalctl -u paping-db -o verbose TARGET=db.example.com
+//! ```
+//!
+//! Without NOTIFY_SOCKET (or JOURNAL_STREAM) in the environment, none of
+//! this does anything; elsewhere than on Linux, it isn't even built.
+
+#[cfg(target_os = "linux")]
+pub use linux::*;
+#[cfg(not(target_os = "linux"))]
+pub use other::*;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::io;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use colored::Colorize;
+    use paping::pinger::PingResult;
+    use paping::target::join_host_port;
+
+    use crate::syslog;
+
+    /// Where journald takes entries in its native protocol.
+    const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+    /// The socket to $NOTIFY_SOCKET.
+    pub struct Notifier {
+        socket: UnixDatagram,
+        addr: SocketAddr,
+    }
+
+    impl Notifier {
+        /// `None` unless started by systemd with NOTIFY_SOCKET set (a path,
+        /// or an abstract name after `@`).
+        pub fn from_env() -> Option<Self> {
+            let path = std::env::var_os("NOTIFY_SOCKET")?;
+            match Notifier::connect(path.to_str()?) {
+                Ok(notifier) => Some(notifier),
+                Err(e) => {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "Warning: cannot notify systemd ({}): {}",
+                            path.to_string_lossy(),
+                            e
+                        )
+                        .yellow()
+                    );
+                    None
+                }
+            }
+        }
+
+        fn connect(path: &str) -> io::Result<Self> {
+            let addr = match path.strip_prefix('@') {
+                Some(name) => {
+                    use std::os::linux::net::SocketAddrExt;
+                    SocketAddr::from_abstract_name(name)?
+                }
+                None => SocketAddr::from_pathname(path)?,
+            };
+            Ok(Notifier {
+                socket: UnixDatagram::unbound()?,
+                addr,
+            })
+        }
+
+        /// Sends `state` (`READY=1`, `STATUS=...`), as `sd_notify` does:
+        /// nothing is done about failures.
+        pub fn notify(&self, state: &str) {
+            let _ = self.socket.send_to_addr(state.as_bytes(), &self.addr);
+        }
+
+        /// How often to feed the watchdog: half of `WatchdogSec=`, as
+        /// systemd recommends. `None` without one, or when it's meant for
+        /// another process (WATCHDOG_PID).
+        pub fn watchdog(&self) -> Option<Duration> {
+            watchdog_period(
+                std::env::var("WATCHDOG_USEC").ok().as_deref(),
+                std::env::var("WATCHDOG_PID").ok().as_deref(),
+                std::process::id(),
+            )
+        }
+
+        /// Observer keeping the status line of `systemctl status` up to
+        /// date with the last result of `target:port` and the loss so far.
+        pub fn status_observer(
+            self: &Arc<Self>,
+            target: &str,
+            port: u16,
+        ) -> impl FnMut(&PingResult) + Send + 'static {
+            let notifier = Arc::clone(self);
+            let name = join_host_port(target, port);
+            let (mut seq, mut failed) = (0u32, 0u32);
+            move |result| {
+                seq += 1;
+                failed += !result.is_success() as u32;
+                notifier.notify(&status(&name, result, seq, failed));
+            }
+        }
+    }
+
+    /// `STATUS=` after the `seq`th result, `failed` of them so far.
+    fn status(name: &str, result: &PingResult, seq: u32, failed: u32) -> String {
+        let last = match result {
+            PingResult::Connected(c) => {
+                format!("connected in {:.2}ms", c.time.as_secs_f64() * 1000.0)
+            }
+            PingResult::Failed(f) => f.message.clone(),
+        };
+        format!(
+            "STATUS={}: {} ({:.1}% loss over {} probes)",
+            name,
+            last,
+            failed as f64 * 100.0 / seq as f64,
+            seq
+        )
+    }
+
+    /// WATCHDOG_USEC halved, unless WATCHDOG_PID is another process's.
+    fn watchdog_period(usec: Option<&str>, pid: Option<&str>, own: u32) -> Option<Duration> {
+        if pid.is_some_and(|pid| pid.parse() != Ok(own)) {
+            return None;
+        }
+        let usec: u64 = usec?.parse().ok().filter(|&usec| usec > 0)?;
+        Some(Duration::from_micros(usec / 2))
+    }
+
+    /// Entries for the journal's native socket.
+    pub struct Journal {
+        socket: Arc<UnixDatagram>,
+        path: &'static Path,
+        dropped: Arc<AtomicU64>,
+    }
+
+    impl Journal {
+        /// `None` unless stdout goes to the journal (JOURNAL_STREAM names
+        /// it) and journald's socket is there.
+        pub fn from_env() -> Option<Self> {
+            let stream = std::env::var("JOURNAL_STREAM").ok()?;
+            if !stdout_is(&stream) || !Path::new(JOURNAL_SOCKET).exists() {
+                return None;
+            }
+            Journal::open(Path::new(JOURNAL_SOCKET)).ok()
+        }
+
+        fn open(path: &'static Path) -> io::Result<Self> {
+            Ok(Journal {
+                socket: Arc::new(UnixDatagram::unbound()?),
+                path,
+                dropped: Arc::new(AtomicU64::new(0)),
+            })
+        }
+
+        /// Writes an entry per probe of `target`:`port` to the journal, with
+        /// the outcome as structured fields.
+        pub fn observer(
+            &self,
+            target: &str,
+            port: u16,
+            label: Option<&str>,
+        ) -> impl FnMut(&PingResult) + Send + 'static {
+            let socket = Arc::clone(&self.socket);
+            let path = self.path;
+            let dropped = Arc::clone(&self.dropped);
+            let (target, label) = (target.to_string(), label.map(str::to_string));
+            let mut seq = 0;
+            move |result| {
+                seq += 1;
+                let fields = fields(&target, port, label.as_deref(), seq, result);
+                if socket.send_to(&entry(&fields), path).is_err() {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        /// Tells how many entries couldn't be sent, if any.
+        pub fn finish(self) {
+            let dropped = self.dropped.load(Ordering::Relaxed);
+            if dropped > 0 {
+                eprintln!(
+                    "{}",
+                    format!("Warning: --journal: {} entries could not be sent", dropped).yellow()
+                );
+            }
+        }
+    }
+
+    /// Whether JOURNAL_STREAM (`DEVICE:INODE`) is stdout, rather than
+    /// something stdout was redirected from.
+    fn stdout_is(stream: &str) -> bool {
+        let Some((dev, ino)) = stream.split_once(':') else {
+            return false;
+        };
+        // SAFETY: fstat only fills in the struct
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(libc::STDOUT_FILENO, &mut stat) } != 0 {
+            return false;
+        }
+        dev.parse() == Ok(stat.st_dev as u64) && ino.parse() == Ok(stat.st_ino as u64)
+    }
+
+    /// The fields of the `seq`th result of `target:port`.
+    fn fields(
+        target: &str,
+        port: u16,
+        label: Option<&str>,
+        seq: u32,
+        result: &PingResult,
+    ) -> Vec<(&'static str, String)> {
+        let name = join_host_port(target, port);
+        let mut fields = vec![
+            (
+                "MESSAGE",
+                match result {
+                    PingResult::Connected(c) => format!(
+                        "{}: connected in {:.2}ms",
+                        name,
+                        c.time.as_secs_f64() * 1000.0
+                    ),
+                    PingResult::Failed(f) => format!("{}: {}", name, f.message),
+                },
+            ),
+            ("PRIORITY", (syslog::severity(result) as u8).to_string()),
+            ("SYSLOG_IDENTIFIER", "paping".to_string()),
+            ("TARGET", target.to_string()),
+            ("PORT", port.to_string()),
+            ("SEQ", seq.to_string()),
+        ];
+        match result {
+            PingResult::Connected(c) => {
+                fields.push(("RTT_MS", format!("{:.2}", c.time.as_secs_f64() * 1000.0)))
+            }
+            PingResult::Failed(f) => {
+                fields.push(("ERROR_KIND", f.kind.as_str().to_string()));
+                fields.push(("ERROR", f.message.clone()));
+            }
+        }
+        if let Some(label) = label {
+            fields.push(("LABEL", label.to_string()));
+        }
+        fields
+    }
+
+    /// An entry in the native protocol: `KEY=value` lines, but values
+    /// holding a newline go as `KEY`, a newline, their length (64-bit
+    /// little-endian), then the value itself.
+    fn entry(fields: &[(&str, String)]) -> Vec<u8> {
+        let mut entry = Vec::new();
+        for (key, value) in fields {
+            entry.extend_from_slice(key.as_bytes());
+            if value.contains('\n') {
+                entry.push(b'\n');
+                entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            } else {
+                entry.push(b'=');
+            }
+            entry.extend_from_slice(value.as_bytes());
+            entry.push(b'\n');
+        }
+        entry
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use paping::pinger::FailureKind;
+
+        #[test]
+        fn serializes_journal_entries() {
+            let connected = PingResult::connected(Duration::from_micros(12_500));
+            let up = entry(&fields("db.example.com", 5432, Some("db"), 3, &connected));
+            assert_eq!(
+                String::from_utf8(up).unwrap(),
+                "MESSAGE=db.example.com:5432: connected in 12.50ms\n\
+                 PRIORITY=6\n\
+                 SYSLOG_IDENTIFIER=paping\n\
+                 TARGET=db.example.com\n\
+                 PORT=5432\n\
+                 SEQ=3\n\
+                 RTT_MS=12.50\n\
+                 LABEL=db\n"
+            );
+
+            let refused = PingResult::failed(FailureKind::Refused, "connection refused\nby peer");
+            let down = entry(&fields("::1", 22, None, 1, &refused));
+            let mut multiline = b"ERROR\n".to_vec();
+            multiline.extend_from_slice(&26u64.to_le_bytes());
+            multiline.extend_from_slice(b"connection refused\nby peer\n");
+            assert!(down.ends_with(&multiline));
+            assert!(down.starts_with(b"MESSAGE\n"));
+            let text = String::from_utf8_lossy(&down);
+            assert!(text.contains("\nPRIORITY=3\n"), "{}", text);
+            assert!(text.contains("\nERROR_KIND=refused\n"), "{}", text);
+        }
+
+        #[test]
+        fn watchdog_period_is_half_of_watchdog_sec() {
+            let period = |usec, pid| watchdog_period(usec, pid, 42);
+            assert_eq!(
+                period(Some("30000000"), None),
+                Some(Duration::from_secs(15))
+            );
+            assert_eq!(
+                period(Some("30000000"), Some("42")),
+                Some(Duration::from_secs(15))
+            );
+            assert_eq!(period(Some("30000000"), Some("7")), None);
+            assert_eq!(period(Some("0"), None), None);
+            assert_eq!(period(Some("soon"), None), None);
+            assert_eq!(period(None, None), None);
+        }
+
+        #[test]
+        fn notifies_the_socket() {
+            let path = std::env::temp_dir().join(format!("paping-notify-{}", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let server = UnixDatagram::bind(&path).unwrap();
+            server
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let notifier = Arc::new(Notifier::connect(path.to_str().unwrap()).unwrap());
+            notifier.notify("READY=1");
+            let mut observer = notifier.status_observer("example.com", 443);
+            observer(&PingResult::connected(Duration::from_micros(12_500)));
+            observer(&PingResult::failed(FailureKind::Refused, "refused"));
+
+            let mut buf = [0u8; 256];
+            let mut received = Vec::new();
+            for _ in 0..3 {
+                let n = server.recv(&mut buf).unwrap();
+                received.push(String::from_utf8_lossy(&buf[..n]).to_string());
+            }
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(received[0], "READY=1");
+            assert_eq!(
+                received[1],
+                "STATUS=example.com:443: connected in 12.50ms (0.0% loss over 1 probes)"
+            );
+            assert!(
+                received[2].ends_with("(50.0% loss over 2 probes)"),
+                "{}",
+                received[2]
+            );
+        }
+    }
+}
+
+/// Nothing to talk to: the same calls, doing nothing.
+#[cfg(not(target_os = "linux"))]
+mod other {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use paping::pinger::PingResult;
+
+    pub struct Notifier;
+
+    impl Notifier {
+        pub fn from_env() -> Option<Self> {
+            None
+        }
+
+        pub fn notify(&self, _state: &str) {}
+
+        pub fn watchdog(&self) -> Option<Duration> {
+            None
+        }
+
+        pub fn status_observer(
+            self: &Arc<Self>,
+            _target: &str,
+            _port: u16,
+        ) -> impl FnMut(&PingResult) + Send + 'static {
+            |_: &PingResult| {}
+        }
+    }
+
+    pub struct Journal;
+
+    impl Journal {
+        pub fn from_env() -> Option<Self> {
+            None
+        }
+
+        pub fn observer(
+            &self,
+            _target: &str,
+            _port: u16,
+            _label: Option<&str>,
+        ) -> impl FnMut(&PingResult) + Send + 'static {
+            |_: &PingResult| {}
+        }
+
+        pub fn finish(self) {}
+    }
+}
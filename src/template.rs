@@ -0,0 +1,276 @@
+//! --format: per-probe lines laid out by a template such as
+//! `"{ts} {target}:{port} {status} {time_ms}ms seq={seq}"`.
+//!
+//! `{name}` is replaced by the matching field of the probe (see [`FIELDS`]),
+//! `{{` and `}}` stand for literal braces. Templated lines are never colored.
+//!
+//! ```
+//! use paping::template::Template;
+//!
+//! assert!(Template::parse("{target} {status}").is_ok());
+//! assert!(Template::parse("{host}").is_err());
+//! ```
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::result::PingResult;
+
+/// The placeholders, with what they stand for.
+pub const FIELDS: &[(&str, &str)] = &[
+    (
+        "ts",
+        "when the probe ended, UTC (2026-10-15T09:30:00.123Z), or in ms since the epoch",
+    ),
+    ("target", "the target address, as given"),
+    ("port", "the target port"),
+    (
+        "addr",
+        "the IP connected to (empty through a proxy or on failure)",
+    ),
+    ("seq", "the probe's number, from 1"),
+    ("status", "connected or failed"),
+    ("time_ms", "the connection time in ms (- on failure)"),
+    (
+        "kind",
+        "why it failed: resolve, timeout, refused, unreachable, network_down, proxy, tls, \
+         slow, no_reply or other",
+    ),
+    ("error", "the failure message (empty when connected)"),
+    ("label", "the --label text (empty without one)"),
+];
+
+/// Everything a template can show about one probe.
+pub struct Record<'a> {
+    pub at: SystemTime,
+    pub target: &'a str,
+    pub port: u16,
+    pub seq: u32,
+    pub label: Option<&'a str>,
+    pub result: &'a PingResult,
+}
+
+/// A parsed --format template.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    /// Index in `FIELDS`
+    Field(usize),
+}
+
+impl Template {
+    /// Parses `spec`, rejecting unknown placeholders (the error lists the
+    /// valid ones) and unbalanced braces.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = spec.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed '{{{}'", name)),
+                        }
+                    }
+                    let field = FIELDS
+                        .iter()
+                        .position(|&(known, _)| known == name)
+                        .ok_or_else(|| {
+                            let names: Vec<String> =
+                                FIELDS.iter().map(|(n, _)| format!("{{{}}}", n)).collect();
+                            format!(
+                                "unknown placeholder {{{}}} (valid: {})",
+                                name,
+                                names.join(", ")
+                            )
+                        })?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => return Err("unmatched '}' (write '}}' for a brace)".to_string()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template { parts })
+    }
+
+    /// The line for `record`.
+    pub fn render(&self, record: &Record<'_>) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            match *part {
+                Part::Text(ref text) => line.push_str(text),
+                Part::Field(i) => {
+                    let _ = match (FIELDS[i].0, record.result) {
+                        ("ts", _) => write!(line, "{}", timestamp(record.at)),
+                        ("target", _) => write!(line, "{}", record.target),
+                        ("port", _) => write!(line, "{}", record.port),
+                        ("addr", PingResult::Connected(c)) => match c.addr {
+                            Some(addr) => write!(line, "{}", addr.ip()),
+                            None => Ok(()),
+                        },
+                        ("seq", _) => write!(line, "{}", record.seq),
+                        ("status", PingResult::Connected(_)) => write!(line, "connected"),
+                        ("status", PingResult::Failed(_)) => write!(line, "failed"),
+                        ("time_ms", PingResult::Connected(c)) => {
+                            write!(line, "{:.2}", c.time.as_secs_f64() * 1000.0)
+                        }
+                        ("time_ms", PingResult::Failed(_)) => write!(line, "-"),
+                        ("kind", PingResult::Failed(f)) => write!(line, "{}", f.kind.as_str()),
+                        ("error", PingResult::Failed(f)) => write!(line, "{}", f.message),
+                        ("label", _) => write!(line, "{}", record.label.unwrap_or_default()),
+                        // Fields that only apply to the other outcome
+                        _ => Ok(()),
+                    };
+                }
+            }
+        }
+        line
+    }
+}
+
+static EPOCH: AtomicBool = AtomicBool::new(false);
+
+/// Switches every timestamp of the process (`{ts}`, listener logs...) to
+/// milliseconds since the Unix epoch (--epoch), or back to RFC 3339.
+pub fn set_epoch(epoch: bool) {
+    EPOCH.store(epoch, Ordering::Relaxed);
+}
+
+/// `at` as [`rfc3339`] or, after `set_epoch(true)`, [`epoch_millis`].
+pub fn timestamp(at: SystemTime) -> String {
+    if EPOCH.load(Ordering::Relaxed) {
+        epoch_millis(at).to_string()
+    } else {
+        rfc3339(at)
+    }
+}
+
+/// Milliseconds since 1970-01-01T00:00:00Z (0 for earlier times).
+pub fn epoch_millis(at: SystemTime) -> u128 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+/// `at` in UTC, RFC 3339 with milliseconds: "2026-10-15T09:30:00.123Z".
+pub fn rfc3339(at: SystemTime) -> String {
+    let since = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    let time = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        since.subsec_millis()
+    )
+}
+
+/// "45s", "12m 5s", "3h 20m": how long a state lasted, for people.
+pub fn duration_text(secs: f64) -> String {
+    let secs = secs as u64;
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::result::{Connection, FailureKind};
+    use std::time::Duration;
+
+    fn record<'a>(result: &'a PingResult) -> Record<'a> {
+        Record {
+            at: UNIX_EPOCH + Duration::from_millis(1_760_520_600_123),
+            target: "example.com",
+            port: 443,
+            seq: 7,
+            label: None,
+            result,
+        }
+    }
+
+    #[test]
+    fn fills_in_the_placeholders() {
+        let template =
+            Template::parse("{ts} {target}:{port} {status} {time_ms}ms seq={seq} {{{kind}}}")
+                .unwrap();
+        let connected = PingResult::Connected(Connection {
+            time: Duration::from_micros(12_340),
+            addr: Some("93.184.216.34:443".parse().unwrap()),
+            proxy: None,
+            exchange: None,
+            transfer: None,
+            tls: None,
+        });
+        assert_eq!(
+            template.render(&record(&connected)),
+            "2025-10-15T09:30:00.123Z example.com:443 connected 12.34ms seq=7 {}"
+        );
+
+        let failed = PingResult::failed(FailureKind::Refused, "connection refused");
+        let template = Template::parse("{status} ({kind}): {error} [{addr}]").unwrap();
+        assert_eq!(
+            template.render(&record(&failed)),
+            "failed (refused): connection refused []"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders_and_stray_braces() {
+        let err = Template::parse("{target} {rtt}").unwrap_err();
+        assert!(
+            err.starts_with("unknown placeholder {rtt} (valid: {ts}, {target},"),
+            "{err}"
+        );
+        assert!(Template::parse("{target").is_err());
+        assert!(Template::parse("target}").is_err());
+        assert!(Template::parse("{{target}}").is_ok());
+    }
+
+    #[test]
+    fn timestamps_in_both_forms() {
+        let at = UNIX_EPOCH + Duration::from_millis(1_760_520_600_123);
+        assert_eq!(rfc3339(at), "2025-10-15T09:30:00.123Z");
+        assert_eq!(epoch_millis(at), 1_760_520_600_123);
+        assert_eq!(epoch_millis(UNIX_EPOCH - Duration::from_secs(1)), 0);
+    }
+}
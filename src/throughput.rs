@@ -0,0 +1,526 @@
+//! Throughput measurement (--throughput): once connected, data is pushed to
+//! or pulled from a cooperative listener (`paping listen --discard` or
+//! `--source`) to see what the path carries, not just whether it connects.
+//!
+//! Both ends first agree on what to do. The pinging end sends a request,
+//! the listener answers it:
+//!
+//! ```text
+//! request  MAGIC '?' <u16 length> <version u8> <direction u8> <limit kind u8> <amount u64>
+//! answer   MAGIC '!' <status u8>
+//! ```
+//!
+//! The data then goes in chunks of `<u32 length>` followed by that many
+//! bytes, a chunk of length 0 ending it. The listener answers an upload
+//! with the number of bytes it received (u64), so that the time covers
+//! the data reaching it rather than the local send buffer. Integers are
+//! big-endian; the request body may grow within a version, which its
+//! length allows for.
+//!
+//! What is measured is the path to whatever accepted the connection: a
+//! proxy or load balancer in between ends it, if it speaks the protocol
+//! at all.
+
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::payload::Timeouts;
+use crate::result::{Direction, Failure, FailureKind, Transfer};
+
+/// Start of a request and of an answer.
+const MAGIC: &[u8] = b"PAPING-TP";
+/// After MAGIC, for a request and for an answer.
+const REQUEST: u8 = b'?';
+const ANSWER: u8 = b'!';
+/// Version of the protocol, in requests.
+const VERSION: u8 = 1;
+/// Request body of version 1: version, direction, limit kind, amount.
+const BODY_LEN: usize = 11;
+
+/// Answer statuses.
+const OK: u8 = 0;
+const NOT_OFFERED: u8 = 1;
+const UNSUPPORTED: u8 = 2;
+
+/// Data is written in chunks of this size, and longer chunks are refused.
+const CHUNK: usize = 64 * 1024;
+
+/// How much data to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Bytes(u64),
+    Duration(Duration),
+}
+
+impl Limit {
+    /// Parses an amount of data or a duration:
+    ///   10000000, 500K, 10M, 1G (or 10MB...)   bytes, in powers of 1000
+    ///   500ms, 5s                              time
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let lower = spec.trim().to_ascii_lowercase();
+        let invalid = || {
+            format!(
+                "invalid amount '{}' (e.g. 10M for 10 megabytes, or 5s for 5 seconds)",
+                spec
+            )
+        };
+        let split = lower
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(lower.len());
+        let (digits, unit) = lower.split_at(split);
+        let n: u64 = digits.parse().map_err(|_| invalid())?;
+        let limit = match unit {
+            "ms" => Limit::Duration(Duration::from_millis(n)),
+            "s" => Limit::Duration(Duration::from_secs(n)),
+            _ => {
+                let scale = match unit.strip_suffix('b').unwrap_or(unit) {
+                    "" => 1,
+                    "k" => 1_000,
+                    "m" => 1_000_000,
+                    "g" => 1_000_000_000,
+                    _ => return Err(invalid()),
+                };
+                Limit::Bytes(n.checked_mul(scale).ok_or_else(invalid)?)
+            }
+        };
+        match limit {
+            Limit::Bytes(0) => Err("the amount of data must be greater than 0".to_string()),
+            Limit::Duration(d) if d.is_zero() => {
+                Err("the duration must be greater than 0".to_string())
+            }
+            limit => Ok(limit),
+        }
+    }
+
+    fn encode(self) -> (u8, u64) {
+        match self {
+            Limit::Bytes(n) => (0, n),
+            Limit::Duration(d) => (1, d.as_millis().min(u64::MAX as u128) as u64),
+        }
+    }
+
+    fn decode(kind: u8, amount: u64) -> Option<Self> {
+        match kind {
+            0 => Some(Limit::Bytes(amount)),
+            1 => Some(Limit::Duration(Duration::from_millis(amount))),
+            _ => None,
+        }
+    }
+}
+
+/// Why `Throughput::try_measure` measured nothing.
+pub(crate) enum Refusal {
+    /// The other end sent the request back
+    Echo,
+    Failed(Failure),
+}
+
+impl From<Failure> for Refusal {
+    fn from(failure: Failure) -> Self {
+        Refusal::Failed(failure)
+    }
+}
+
+/// What to measure on every connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Throughput {
+    pub limit: Limit,
+    pub direction: Direction,
+}
+
+impl Throughput {
+    pub fn new(limit: Limit, direction: Direction) -> Self {
+        Throughput { limit, direction }
+    }
+
+    fn request(&self) -> Vec<u8> {
+        let (kind, amount) = self.limit.encode();
+        let mut request = MAGIC.to_vec();
+        request.push(REQUEST);
+        request.extend_from_slice(&(BODY_LEN as u16).to_be_bytes());
+        request.extend_from_slice(&[VERSION, direction_byte(self.direction), kind]);
+        request.extend_from_slice(&amount.to_be_bytes());
+        request
+    }
+
+    /// Asks the listener on the other end of `stream` for the transfer,
+    /// then does it. `timeout` bounds each read and write, so that a
+    /// stalled transfer fails rather than hangs.
+    pub(crate) fn measure<S: Read + Write + Timeouts>(
+        &self,
+        stream: &mut S,
+        timeout: Duration,
+    ) -> Result<Transfer, Failure> {
+        self.try_measure(stream, timeout).map_err(|refusal| match refusal {
+            Refusal::Echo => Failure {
+                kind: FailureKind::Other,
+                message: "connected, but the listener echoes (run paping listen with \
+                          --discard or --source)"
+                    .to_string(),
+            },
+            Refusal::Failed(failure) => failure,
+        })
+    }
+
+    /// Like `measure`, telling an echoing listener apart, which may be
+    /// measured otherwise.
+    pub(crate) fn try_measure<S: Read + Write + Timeouts>(
+        &self,
+        stream: &mut S,
+        timeout: Duration,
+    ) -> Result<Transfer, Refusal> {
+        let failure = |e: io::Error, what: &str| Failure {
+            kind: FailureKind::classify(&e, false),
+            message: format!("connected, but {}: {}", what, e),
+        };
+        let refused = |why: &str| {
+            Refusal::Failed(Failure {
+                kind: FailureKind::Other,
+                message: format!("connected, but {}", why),
+            })
+        };
+        stream
+            .set_timeouts(timeout, timeout)
+            .map_err(|e| failure(e, "could not set timeouts"))?;
+        stream
+            .write_all(&self.request())
+            .and_then(|_| stream.flush())
+            .map_err(|e| failure(e, "sending the throughput request failed"))?;
+
+        let mut answer = [0u8; MAGIC.len() + 2];
+        match stream.read_exact(&mut answer) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(refused(
+                    "the throughput request was left unanswered (is the other end \
+                     paping listen --discard?)",
+                ))
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                return Err(Refusal::Failed(Failure {
+                    kind: FailureKind::NoReply,
+                    message: format!(
+                        "connected, but no answer to the throughput request within {}ms \
+                         (is the other end paping listen --discard?)",
+                        timeout.as_millis()
+                    ),
+                }))
+            }
+            Err(e) => return Err(failure(e, "reading the throughput answer failed").into()),
+        }
+        let (magic, answer) = answer.split_at(MAGIC.len());
+        match (magic == MAGIC, answer[0], answer[1]) {
+            (true, ANSWER, OK) => {}
+            (true, ANSWER, NOT_OFFERED) => {
+                return Err(refused(match self.direction {
+                    Direction::Upload => "the listener takes no uploads (run it with --discard)",
+                    Direction::Download => {
+                        "the listener offers no downloads (run it with --source)"
+                    }
+                }))
+            }
+            (true, ANSWER, UNSUPPORTED) => {
+                return Err(refused(
+                    "the listener doesn't support this throughput request (update it)",
+                ))
+            }
+            (true, REQUEST, _) => return Err(Refusal::Echo),
+            _ => {
+                return Err(refused(
+                    "the other end doesn't speak the throughput protocol",
+                ))
+            }
+        }
+
+        let start = Instant::now();
+        let bytes = match self.direction {
+            Direction::Upload => {
+                let sent = send_chunks(stream, self.limit)
+                    .map_err(|e| failure(e, "sending data failed"))?;
+                let mut count = [0u8; 8];
+                stream
+                    .read_exact(&mut count)
+                    .map_err(|e| failure(e, "reading the count of bytes received failed"))?;
+                let received = u64::from_be_bytes(count);
+                if received != sent {
+                    return Err(refused(&format!(
+                        "the listener received {} of the {} bytes sent",
+                        received, sent
+                    )));
+                }
+                sent
+            }
+            Direction::Download => {
+                receive_chunks(stream).map_err(|e| failure(e, "receiving data failed"))?
+            }
+        };
+        Ok(Transfer {
+            direction: self.direction,
+            bytes,
+            time: start.elapsed(),
+        })
+    }
+}
+
+/// The listening end (`paping listen --discard`, `--source`): answers a
+/// throughput request, then receives or sends the data. `upload` and
+/// `download` tell which directions are offered. A connection not starting
+/// with a request is read to the end and thrown away when uploads are
+/// offered (the transfer returned then covers it), and refused otherwise;
+/// one closed without sending anything returns `None`.
+pub(crate) fn answer<S: Read + Write>(
+    stream: &mut S,
+    upload: bool,
+    download: bool,
+) -> io::Result<Option<Transfer>> {
+    let start = Instant::now();
+    let mut head = [0u8; MAGIC.len() + 1];
+    let n = read_up_to(stream, &mut head)?;
+    if n == 0 {
+        return Ok(None);
+    }
+    if n < head.len() || &head[..MAGIC.len()] != MAGIC || head[MAGIC.len()] != REQUEST {
+        if !upload {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a throughput request",
+            ));
+        }
+        let rest = io::copy(stream, &mut io::sink())?;
+        return Ok(Some(Transfer {
+            direction: Direction::Upload,
+            bytes: n as u64 + rest,
+            time: start.elapsed(),
+        }));
+    }
+
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut body = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut body)?;
+    let request = match body[..] {
+        [VERSION, direction, kind, ..] if body.len() >= BODY_LEN => {
+            let amount = u64::from_be_bytes(body[3..BODY_LEN].try_into().unwrap());
+            direction_of(direction).zip(Limit::decode(kind, amount))
+        }
+        _ => None,
+    };
+    let status = match request {
+        None => UNSUPPORTED,
+        Some((Direction::Upload, _)) if !upload => NOT_OFFERED,
+        Some((Direction::Download, _)) if !download => NOT_OFFERED,
+        Some(_) => OK,
+    };
+    stream.write_all(MAGIC)?;
+    stream.write_all(&[ANSWER, status])?;
+    stream.flush()?;
+    let (direction, limit) = match request {
+        Some(request) if status == OK => request,
+        Some((direction, _)) => {
+            return Err(io::Error::other(format!(
+                "refused a{} {} (not offered)",
+                if direction == Direction::Upload {
+                    "n"
+                } else {
+                    ""
+                },
+                direction.as_str()
+            )))
+        }
+        None => {
+            return Err(io::Error::other(
+                "refused an unsupported throughput request",
+            ))
+        }
+    };
+
+    let start = Instant::now();
+    let bytes = match direction {
+        Direction::Upload => {
+            let bytes = receive_chunks(stream)?;
+            stream.write_all(&bytes.to_be_bytes())?;
+            bytes
+        }
+        Direction::Download => send_chunks(stream, limit)?,
+    };
+    stream.flush()?;
+    Ok(Some(Transfer {
+        direction,
+        bytes,
+        time: start.elapsed(),
+    }))
+}
+
+/// Writes chunks until `limit` is reached, then the end chunk. Returns the
+/// number of bytes of data written.
+fn send_chunks<W: Write>(out: &mut W, limit: Limit) -> io::Result<u64> {
+    let data = vec![0u8; CHUNK];
+    let start = Instant::now();
+    let mut sent = 0u64;
+    loop {
+        let size = match limit {
+            Limit::Bytes(total) => (total - sent).min(CHUNK as u64) as usize,
+            Limit::Duration(d) if start.elapsed() < d => CHUNK,
+            Limit::Duration(_) => 0,
+        };
+        out.write_all(&(size as u32).to_be_bytes())?;
+        if size == 0 {
+            out.flush()?;
+            return Ok(sent);
+        }
+        out.write_all(&data[..size])?;
+        sent += size as u64;
+    }
+}
+
+/// Reads chunks up to the end chunk. Returns the number of bytes of data
+/// read.
+fn receive_chunks<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut buf = vec![0u8; CHUNK];
+    let mut received = 0u64;
+    loop {
+        let mut len = [0u8; 4];
+        input.read_exact(&mut len)?;
+        let size = u32::from_be_bytes(len) as usize;
+        if size == 0 {
+            return Ok(received);
+        }
+        if size > CHUNK {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("chunk of {} bytes, more than {}", size, CHUNK),
+            ));
+        }
+        input.read_exact(&mut buf[..size])?;
+        received += size as u64;
+    }
+}
+
+/// Fills `buf` unless the stream ends first. Returns how much was read.
+fn read_up_to<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match input.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+fn direction_byte(direction: Direction) -> u8 {
+    match direction {
+        Direction::Upload => 0,
+        Direction::Download => 1,
+    }
+}
+
+fn direction_of(byte: u8) -> Option<Direction> {
+    match byte {
+        0 => Some(Direction::Upload),
+        1 => Some(Direction::Download),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn parses_limits() {
+        assert_eq!(Limit::parse("1000"), Ok(Limit::Bytes(1000)));
+        assert_eq!(Limit::parse("10M"), Ok(Limit::Bytes(10_000_000)));
+        assert_eq!(Limit::parse("2kb"), Ok(Limit::Bytes(2000)));
+        assert_eq!(Limit::parse("1G"), Ok(Limit::Bytes(1_000_000_000)));
+        assert_eq!(
+            Limit::parse("500ms"),
+            Ok(Limit::Duration(Duration::from_millis(500)))
+        );
+        assert_eq!(
+            Limit::parse("5s"),
+            Ok(Limit::Duration(Duration::from_secs(5)))
+        );
+        for bad in ["", "10X", "ms", "-1", "0", "0s", "99999999999999G"] {
+            assert!(Limit::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    /// Runs `answer` on one accepted connection, and `client` against it.
+    fn exchange<T>(
+        upload: bool,
+        download: bool,
+        client: impl FnOnce(TcpStream) -> T,
+    ) -> (T, io::Result<Option<Transfer>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            answer(&mut stream, upload, download)
+        });
+        let got = client(TcpStream::connect(addr).unwrap());
+        (got, server.join().unwrap())
+    }
+
+    fn measure(throughput: Throughput, mut stream: TcpStream) -> Result<Transfer, Failure> {
+        throughput.measure(&mut stream, Duration::from_secs(5))
+    }
+
+    #[test]
+    fn uploads_and_downloads() {
+        let up = Throughput::new(Limit::Bytes(200_000), Direction::Upload);
+        let (client, server) = exchange(true, false, |s| measure(up, s));
+        let client = client.unwrap();
+        assert_eq!(
+            (client.direction, client.bytes),
+            (Direction::Upload, 200_000)
+        );
+        assert_eq!(server.unwrap().unwrap().bytes, 200_000);
+
+        let down = Throughput::new(
+            Limit::Duration(Duration::from_millis(50)),
+            Direction::Download,
+        );
+        let (client, server) = exchange(false, true, |s| measure(down, s));
+        let (client, server) = (client.unwrap(), server.unwrap().unwrap());
+        assert_eq!(client.direction, Direction::Download);
+        assert!(client.bytes > 0);
+        assert_eq!(client.bytes, server.bytes);
+    }
+
+    #[test]
+    fn refusals_are_explained() {
+        let down = Throughput::new(Limit::Bytes(1000), Direction::Download);
+        let (client, server) = exchange(true, false, |s| measure(down, s));
+        assert!(client.unwrap_err().message.contains("--source"));
+        assert!(server.is_err());
+
+        // Anything else is thrown away when taking uploads
+        let (_, server) = exchange(true, false, |mut s| s.write_all(b"hello").unwrap());
+        assert_eq!(server.unwrap().unwrap().bytes, 5);
+        let (_, server) = exchange(true, false, drop);
+        assert_eq!(server.unwrap(), None);
+
+        // An echo server sends the request back
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let echo = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 64];
+            let n = stream.read(&mut buf).unwrap();
+            stream.write_all(&buf[..n]).unwrap();
+        });
+        let up = Throughput::new(Limit::Bytes(1000), Direction::Upload);
+        let failure = measure(up, TcpStream::connect(addr).unwrap()).unwrap_err();
+        assert!(failure.message.contains("echoes"), "{}", failure.message);
+        echo.join().unwrap();
+    }
+}
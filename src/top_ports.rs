@@ -0,0 +1,41 @@
+//! The most common open TCP ports, most frequent first: the top 100 of the
+//! nmap-services frequency ranking (what `nmap --top-ports 100` scans).
+//!
+//! Data only, in the order of the ranking; keep it that way when adding
+//! entries (the tests check a few well-known positions).
+
+/// TCP ports by how often they are found open, most frequent first.
+pub const TOP_PORTS: [u16; 100] = [
+    80, 23, 443, 21, 22, 25, 3389, 110, 445, 139, //
+    143, 53, 135, 3306, 8080, 1723, 111, 995, 993, 5900, //
+    1025, 587, 8888, 199, 1720, 465, 548, 113, 81, 6001, //
+    10000, 514, 5060, 179, 1026, 2000, 8443, 8000, 32768, 554, //
+    26, 1433, 49152, 2001, 515, 8008, 49154, 1027, 5666, 646, //
+    5000, 5631, 631, 49153, 8081, 2049, 88, 79, 5800, 106, //
+    2121, 1110, 49155, 6000, 513, 990, 5357, 427, 49156, 543, //
+    544, 5101, 144, 7, 389, 8009, 3128, 444, 9999, 5009, //
+    7070, 5190, 3000, 5432, 1900, 3986, 13, 1029, 9, 5051, //
+    6646, 49157, 1028, 873, 1755, 2717, 4899, 9100, 119, 37, //
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank(port: u16) -> usize {
+        TOP_PORTS.iter().position(|&p| p == port).unwrap()
+    }
+
+    #[test]
+    fn ranked_by_frequency() {
+        let mut unique = TOP_PORTS.to_vec();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), TOP_PORTS.len(), "no port twice");
+        assert_eq!(&TOP_PORTS[..5], &[80, 23, 443, 21, 22]);
+        // Web and remote access well ahead of the rarer services
+        assert!(rank(443) < rank(3389) && rank(3389) < rank(3306));
+        assert!(rank(22) < rank(8080) && rank(8080) < rank(5432));
+        assert!(rank(3389) < 10);
+    }
+}
@@ -0,0 +1,556 @@
+//! Full-screen views of a run, with the `tui` feature: the --tui dashboard
+//! of a --target-list run, one row per target, and the --graph chart of a
+//! single target's connection times.
+//!
+//! The pinging happens on a thread of its own, exactly as without these
+//! views (the pingers are only silenced), and the results reach the screen
+//! through an observer. Other observers (--sqlite...) keep seeing every
+//! result. `q`, Esc or Ctrl+C stops the run.
+//!
+//! The terminal is restored when the run ends, on error and on panic
+//! (`ratatui::init` installs a panic hook doing so).
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use paping::multi::MultiPinger;
+use paping::pinger::{PingResult, PingStats, Pinger};
+use paping::stop::{StopHandle, StopToken};
+use paping::target::join_host_port;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols::Marker;
+use ratatui::text::Line;
+use ratatui::widgets::{Axis, Block, Cell, Chart, Dataset, GraphType, Paragraph, Row, Table};
+use ratatui::Frame;
+
+/// Recent connection times shown in the sparkline of each dashboard row.
+const RECENT: usize = 30;
+/// Probes the graph remembers, more than the widest terminal shows.
+const HISTORY: usize = 1000;
+/// Lowest top of the graph's y-axis, so that jitter of a few tenths of a
+/// millisecond doesn't fill the whole height.
+const GRAPH_FLOOR_MS: f64 = 5.0;
+/// Below this size, the graph shrinks to a sparkline.
+const GRAPH_MIN_SIZE: (u16, u16) = (60, 10);
+/// Longest wait for a key press between two redraws.
+const REDRAW_EVERY: Duration = Duration::from_millis(100);
+
+/// What a view shows, fed with the results of the run.
+trait Screen {
+    /// A result of the target at `index` (in list order).
+    fn record(&mut self, index: usize, result: PingResult);
+
+    /// Draws everything; `stopping` once quitting was asked for.
+    fn render(&self, frame: &mut Frame, stopping: bool);
+
+    /// A key press other than those quitting.
+    fn key(&mut self, _code: KeyCode) {}
+}
+
+/// Runs `work` on a thread of its own behind `screen`, redrawing as its
+/// results come in on `rx`, until it returns (or the terminal fails).
+fn watch<S, T>(
+    screen: &mut S,
+    rx: Receiver<(usize, PingResult)>,
+    stop: &StopHandle,
+    work: impl FnOnce(StopToken) -> T + Send + 'static,
+) -> io::Result<T>
+where
+    S: Screen,
+    T: Send + 'static,
+{
+    let mut terminal = ratatui::try_init()?;
+    let token = stop.token();
+    let worker = std::thread::spawn(move || work(token));
+
+    let mut stopping = false;
+    let mut shown = || -> io::Result<()> {
+        loop {
+            // Checked before draining, so the last results are shown too
+            let finished = worker.is_finished();
+            for (index, result) in rx.try_iter() {
+                screen.record(index, result);
+            }
+            if finished {
+                return Ok(());
+            }
+            terminal.draw(|frame| screen.render(frame, stopping))?;
+
+            // Resizes only need the redraw this loop does anyway
+            if !event::poll(REDRAW_EVERY)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                // Raw mode turns Ctrl+C into a key press rather than SIGINT
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    stop.stop();
+                    stopping = true;
+                }
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    stop.stop();
+                    stopping = true;
+                }
+                code => screen.key(code),
+            }
+        }
+    };
+    let shown = shown();
+    ratatui::restore();
+    if shown.is_err() {
+        // The run can't be watched any more: don't leave it going unseen
+        stop.stop();
+    }
+    let done = worker.join().expect("pinger thread panicked");
+    shown.map(|_| done)
+}
+
+/// The keys, or that the run is ending.
+fn help_line(stopping: bool, keys: &str) -> Paragraph<'_> {
+    let text = if stopping {
+        " stopping after the probe underway...".to_string()
+    } else {
+        format!(" q quit{}", keys)
+    };
+    Paragraph::new(Line::from(text))
+}
+
+fn ms(v: Option<f64>) -> String {
+    v.map(|ms| format!("{:.2}ms", ms))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Green, yellow above the warning threshold, red above the critical one.
+fn loss_color(loss: f64, (warn, crit): (f64, f64)) -> Color {
+    if loss > crit {
+        Color::Red
+    } else if loss > warn {
+        Color::Yellow
+    } else {
+        Color::Green
+    }
+}
+
+/// "up", or why the last probe failed.
+fn status_cell(last: Option<&PingResult>) -> Cell<'static> {
+    match last {
+        None => Cell::from("-"),
+        Some(PingResult::Connected(_)) => Cell::from("up").style(Style::new().fg(Color::Green)),
+        Some(PingResult::Failed(failure)) => {
+            Cell::from(failure.kind.as_str()).style(Style::new().fg(Color::Red))
+        }
+    }
+}
+
+/// Column the dashboard rows are sorted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortBy {
+    /// The order of the list file
+    List,
+    /// Slowest last connection first
+    Last,
+    /// Slowest average first
+    Average,
+    /// Highest loss first
+    Loss,
+}
+
+impl SortBy {
+    fn next(self) -> Self {
+        match self {
+            SortBy::List => SortBy::Last,
+            SortBy::Last => SortBy::Average,
+            SortBy::Average => SortBy::Loss,
+            SortBy::Loss => SortBy::List,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            SortBy::List => "list order",
+            SortBy::Last => "last",
+            SortBy::Average => "average",
+            SortBy::Loss => "loss",
+        }
+    }
+}
+
+/// What the dashboard knows of one target.
+struct Target {
+    name: String,
+    weight: u32,
+    /// Gathered from the same results as the pinger's own statistics
+    stats: PingStats,
+    last: Option<PingResult>,
+    /// Connection times of the last probes, None for failures
+    recent: Vec<Option<f64>>,
+}
+
+impl Target {
+    fn last_ms(&self) -> Option<f64> {
+        self.last.as_ref().and_then(|r| r.time_ms())
+    }
+}
+
+struct Dashboard {
+    targets: Vec<Target>,
+    sort: SortBy,
+    loss_thresholds: (f64, f64),
+}
+
+/// --tui: pings the targets of `multi`, `count` times in all (0 = until
+/// stopped), behind the dashboard, and hands `multi` back for the final
+/// statistics. `stop` ends the run early, as does quitting; `s` changes
+/// the column the rows are sorted by.
+pub fn dashboard(
+    mut multi: MultiPinger,
+    count: u32,
+    stop: &StopHandle,
+    loss_thresholds: (f64, f64),
+) -> io::Result<MultiPinger> {
+    let (tx, rx) = mpsc::channel();
+    multi.on_result_channel(tx);
+    let mut dashboard = Dashboard {
+        targets: multi
+            .targets()
+            .iter()
+            .map(|(pinger, weight)| Target {
+                name: join_host_port(pinger.address(), pinger.port()),
+                weight: *weight,
+                stats: PingStats::default(),
+                last: None,
+                recent: Vec::with_capacity(RECENT),
+            })
+            .collect(),
+        sort: SortBy::List,
+        loss_thresholds,
+    };
+    watch(&mut dashboard, rx, stop, move |token| {
+        multi.run(count, &token);
+        multi
+    })
+}
+
+impl Dashboard {
+    /// Indexes of the targets in display order.
+    fn order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.targets.len()).collect();
+        // Worst first; targets without a time yet go last
+        let key = |t: &Target| match self.sort {
+            SortBy::List => None,
+            SortBy::Last => t.last_ms(),
+            SortBy::Average => t.stats.average(),
+            SortBy::Loss => (t.stats.attempted > 0).then(|| t.stats.loss_percent()),
+        };
+        if self.sort != SortBy::List {
+            order.sort_by(|&a, &b| {
+                let (a, b) = (key(&self.targets[a]), key(&self.targets[b]));
+                b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+        order
+    }
+}
+
+impl Screen for Dashboard {
+    fn record(&mut self, index: usize, result: PingResult) {
+        let target = &mut self.targets[index];
+        target.stats.record(&result);
+        if target.recent.len() == RECENT {
+            target.recent.remove(0);
+        }
+        target.recent.push(result.time_ms());
+        target.last = Some(result);
+    }
+
+    fn render(&self, frame: &mut Frame, stopping: bool) {
+        let [table_area, help_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+        let header = Row::new(["Target", "Weight", "Status", "Last", "Average", "Loss", "Recent"])
+            .style(Style::new().add_modifier(Modifier::BOLD));
+        let rows = self.order().into_iter().map(|index| {
+            let t = &self.targets[index];
+            let loss = t.stats.loss_percent();
+            Row::new([
+                Cell::from(t.name.as_str()),
+                Cell::from(t.weight.to_string()),
+                status_cell(t.last.as_ref()),
+                Cell::from(ms(t.last_ms())),
+                Cell::from(ms(t.stats.average())),
+                Cell::from(format!("{:.1}%", loss))
+                    .style(Style::new().fg(loss_color(loss, self.loss_thresholds))),
+                Cell::from(sparkline(&t.recent)),
+            ])
+        });
+        let widths = [
+            Constraint::Min(20),
+            Constraint::Length(6),
+            Constraint::Length(11),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(7),
+            Constraint::Length(RECENT as u16),
+        ];
+        let probes: u32 = self.targets.iter().map(|t| t.stats.attempted).sum();
+        let title = format!(" paping: {} targets, {} probes ", self.targets.len(), probes);
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::bordered().title(title));
+        frame.render_widget(table, table_area);
+
+        let keys = format!("   s sort (by {})", self.sort.as_str());
+        frame.render_widget(help_line(stopping, &keys), help_area);
+    }
+
+    fn key(&mut self, code: KeyCode) {
+        if code == KeyCode::Char('s') {
+            self.sort = self.sort.next();
+        }
+    }
+}
+
+/// What the graph knows of its target.
+struct Graph {
+    name: String,
+    stats: PingStats,
+    last: Option<PingResult>,
+    /// Sequence number and connection time (None for failures) of the
+    /// last probes
+    history: VecDeque<(u32, Option<f64>)>,
+    loss_thresholds: (f64, f64),
+}
+
+/// --graph: pings `pinger` `count` times (0 = until stopped) behind a
+/// scrolling chart of its connection times, and hands it back for the
+/// final statistics. `stop` ends the run early, as does quitting.
+pub fn graph(
+    mut pinger: Pinger,
+    count: u32,
+    stop: &StopHandle,
+    loss_thresholds: (f64, f64),
+) -> io::Result<Pinger> {
+    let (tx, rx) = mpsc::channel();
+    pinger.on_result(move |result| {
+        let _ = tx.send((0, result.clone()));
+    });
+    let mut graph = Graph {
+        name: join_host_port(pinger.address(), pinger.port()),
+        stats: PingStats::default(),
+        last: None,
+        history: VecDeque::with_capacity(HISTORY),
+        loss_thresholds,
+    };
+    watch(&mut graph, rx, stop, move |token| {
+        pinger.run(count, &token);
+        pinger
+    })
+}
+
+impl Graph {
+    /// The connection times as a line over the last probes that fit, with
+    /// failures as red marks on the x-axis.
+    fn render_chart(&self, frame: &mut Frame, area: Rect) {
+        // Braille cells hold two points across; keep room for the y labels
+        let fit = (area.width.saturating_sub(12) as usize * 2).max(2);
+        let shown: Vec<(u32, Option<f64>)> =
+            self.history.iter().skip(self.history.len().saturating_sub(fit)).copied().collect();
+        let times: Vec<(f64, f64)> = shown
+            .iter()
+            .filter_map(|&(seq, time)| Some((seq as f64, time?)))
+            .collect();
+        let failures: Vec<(f64, f64)> = shown
+            .iter()
+            .filter(|(_, time)| time.is_none())
+            .map(|&(seq, _)| (seq as f64, 0.0))
+            .collect();
+
+        let first = shown.first().map(|&(seq, _)| seq).unwrap_or(1);
+        let last = first + fit as u32 - 1;
+        let slowest = times.iter().map(|&(_, ms)| ms).fold(0.0, f64::max);
+        let top = (slowest * 1.2).max(GRAPH_FLOOR_MS);
+
+        let datasets = vec![
+            Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::new().fg(Color::Cyan))
+                .data(&times),
+            Dataset::default()
+                .marker(Marker::Dot)
+                .graph_type(GraphType::Scatter)
+                .style(Style::new().fg(Color::Red))
+                .data(&failures),
+        ];
+        let chart = Chart::new(datasets)
+            .block(Block::bordered().title(format!(" {} ", self.name)))
+            .x_axis(
+                Axis::default()
+                    .bounds([first as f64, last as f64])
+                    .labels([format!("#{}", first), format!("#{}", last)]),
+            )
+            .y_axis(Axis::default().bounds([0.0, top]).labels([
+                "0".to_string(),
+                format!("{:.1}", top / 2.0),
+                format!("{:.1}ms", top),
+            ]));
+        frame.render_widget(chart, area);
+    }
+
+    fn render_stats(&self, frame: &mut Frame, area: Rect) {
+        let stats = &self.stats;
+        let loss = stats.loss_percent();
+        let rows = [
+            Row::new([Cell::from("Status"), status_cell(self.last.as_ref())]),
+            Row::new([
+                Cell::from("Last"),
+                Cell::from(ms(self.last.as_ref().and_then(|r| r.time_ms()))),
+            ]),
+            Row::new(["Minimum".to_string(), ms(stats.min())]),
+            Row::new(["Average".to_string(), ms(stats.average())]),
+            Row::new(["Maximum".to_string(), ms(stats.max())]),
+            Row::new(["Attempted".to_string(), stats.attempted.to_string()]),
+            Row::new(["Connected".to_string(), stats.connected.to_string()]),
+            Row::new([
+                Cell::from("Loss"),
+                Cell::from(format!("{:.1}%", loss))
+                    .style(Style::new().fg(loss_color(loss, self.loss_thresholds))),
+            ]),
+        ];
+        let table = Table::new(rows, [Constraint::Length(10), Constraint::Min(0)])
+            .block(Block::bordered().title(" Statistics "));
+        frame.render_widget(table, area);
+    }
+
+    /// One line of figures and a sparkline of as many probes as fit.
+    fn render_small(&self, frame: &mut Frame, area: Rect) {
+        let times: Vec<Option<f64>> = self.history.iter().map(|&(_, time)| time).collect();
+        let fit = times.len().saturating_sub(area.width as usize);
+        let summary = format!(
+            "{}  last {}  avg {}  loss {:.1}%",
+            self.name,
+            ms(self.last.as_ref().and_then(|r| r.time_ms())),
+            ms(self.stats.average()),
+            self.stats.loss_percent()
+        );
+        let lines = vec![Line::from(summary), Line::from(sparkline(&times[fit..]))];
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+}
+
+impl Screen for Graph {
+    fn record(&mut self, _: usize, result: PingResult) {
+        self.stats.record(&result);
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history
+            .push_back((self.stats.attempted, result.time_ms()));
+        self.last = Some(result);
+    }
+
+    fn render(&self, frame: &mut Frame, stopping: bool) {
+        let [main, help_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let (min_width, min_height) = GRAPH_MIN_SIZE;
+        if main.width < min_width || main.height < min_height {
+            self.render_small(frame, main);
+        } else {
+            let [chart_area, stats_area] =
+                Layout::horizontal([Constraint::Min(0), Constraint::Length(24)]).areas(main);
+            self.render_chart(frame, chart_area);
+            self.render_stats(frame, stats_area);
+        }
+        frame.render_widget(help_line(stopping, ""), help_area);
+    }
+}
+
+/// The connection times as a bar per probe, scaled from the fastest to the
+/// slowest shown; failed probes are marked with a cross.
+fn sparkline(recent: &[Option<f64>]) -> String {
+    let (levels, failed): (&[char], char) = if paping::style::is_ascii() {
+        (&['_', '.', '-', '=', '+', '*', '#', '@'], 'x')
+    } else {
+        (&['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'], '×')
+    };
+    let times = recent.iter().flatten();
+    let min = times.clone().copied().fold(f64::INFINITY, f64::min);
+    let max = times.copied().fold(0.0, f64::max);
+    recent
+        .iter()
+        .map(|time| match time {
+            None => failed,
+            Some(_) if max <= min => levels[0],
+            Some(ms) => {
+                let level = (ms - min) / (max - min) * (levels.len() - 1) as f64;
+                levels[level.round() as usize]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paping::pinger::FailureKind;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    #[test]
+    fn sparkline_scales_from_fastest_to_slowest() {
+        let recent = [Some(1.0), Some(8.0), None, Some(4.5), Some(1.0)];
+        assert_eq!(sparkline(&recent), "▁█×▅▁");
+        assert_eq!(sparkline(&[Some(3.0), Some(3.0)]), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    fn graph_of(results: &[Option<u64>]) -> Graph {
+        let mut graph = Graph {
+            name: "example.com:443".to_string(),
+            stats: PingStats::default(),
+            last: None,
+            history: VecDeque::new(),
+            loss_thresholds: (5.0, 20.0),
+        };
+        for &ms in results {
+            let result = match ms {
+                Some(ms) => PingResult::connected(Duration::from_millis(ms)),
+                None => PingResult::failed(FailureKind::Timeout, "timed out"),
+            };
+            graph.record(0, result);
+        }
+        graph
+    }
+
+    fn draw(graph: &Graph, width: u16, height: u16) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| graph.render(frame, false)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer.content().iter().map(|cell| cell.symbol()).collect()
+    }
+
+    #[test]
+    fn graph_fits_the_terminal() {
+        let graph = graph_of(&[Some(10), Some(12), None, Some(11)]);
+        assert_eq!(graph.stats.attempted, 4);
+        assert_eq!(graph.history.back(), Some(&(4, Some(11.0))));
+
+        let full = draw(&graph, 100, 20);
+        assert!(full.contains("example.com:443"));
+        assert!(full.contains("Statistics") && full.contains("25.0%"));
+        assert!(full.contains("14.4ms"), "the y-axis tops 20% above the slowest");
+
+        let small = draw(&graph, 58, 6);
+        assert!(!small.contains("Statistics"));
+        assert!(small.contains("loss 25.0%") && small.contains("▁█×▅"));
+    }
+}
@@ -5,6 +5,7 @@ use std::io::Read;
 const REPO_OWNER: &str = "yutho-o";
 const REPO_NAME: &str = "paping";
 const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+const DEFAULT_USER_AGENT: &str = "paping-updater";
 
 #[derive(Deserialize)]
 struct Release {
@@ -21,22 +22,42 @@ struct Asset {
 
 pub fn run_update() {
     println!("Checking for updates...");
+    // The build details and path make an old binary earlier in PATH obvious
     println!(
         "Current version: {}",
-        format!("v{}", CURRENT_VERSION).green()
+        crate::version::BUILD.short().green()
     );
+    if let Ok(exe) = std::env::current_exe() {
+        println!("Running from: {}", exe.display());
+    }
 
     let url = format!(
         "https://api.github.com/repos/{}/{}/releases/latest",
         REPO_OWNER, REPO_NAME
     );
 
-    let response = match ureq::get(&url).set("User-Agent", "paping-updater").call() {
+    let response = match github_request(&url).call() {
         Ok(resp) => resp,
         Err(ureq::Error::Status(404, _)) => {
             println!("{}", "No releases found. You are on the latest version.".green());
             return;
         }
+        Err(ureq::Error::Status(code @ (403 | 429), resp))
+            if code == 429 || resp.header("X-RateLimit-Remaining") == Some("0") =>
+        {
+            let reset = resp
+                .header("X-RateLimit-Reset")
+                .and_then(|r| r.trim().parse::<u64>().ok());
+            match reset {
+                Some(epoch) => eprintln!(
+                    "GitHub rate limit reached, try again after {}",
+                    format_reset_time(epoch).yellow()
+                ),
+                None => eprintln!("GitHub rate limit reached, try again later"),
+            }
+            eprintln!("Hint: set PAPING_GITHUB_TOKEN to a GitHub token to raise the limit.");
+            return;
+        }
         Err(e) => {
             eprintln!("Error checking for updates: {}", e);
             return;
@@ -72,7 +93,7 @@ pub fn run_update() {
         .find(|a| a.name.to_lowercase().contains(&target_name))
     {
         println!("Downloading {}...", asset.name.green());
-        match download_and_replace(&asset.browser_download_url) {
+        match download_and_replace(&asset.browser_download_url, &asset.name) {
             Ok(_) => println!("{}", "Update successful! Restart paping to use the new version.".green()),
             Err(e) => {
                 eprintln!("Auto-update failed: {}", e);
@@ -94,6 +115,46 @@ pub fn run_update() {
     }
 }
 
+/// Builds a GET request with the headers expected by the GitHub API.
+/// The User-Agent can be overridden with `PAPING_UPDATE_UA` (some API gateways
+/// filter on it), and `PAPING_GITHUB_TOKEN` adds an `Authorization: token ...`
+/// header, which lifts the low rate limit applied to anonymous requests.
+fn github_request(url: &str) -> ureq::Request {
+    let user_agent = std::env::var("PAPING_UPDATE_UA")
+        .ok()
+        .filter(|ua| !ua.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string());
+
+    let mut request = ureq::get(url).set("User-Agent", &user_agent);
+
+    if let Some(token) = std::env::var("PAPING_GITHUB_TOKEN")
+        .ok()
+        .filter(|t| !t.trim().is_empty())
+    {
+        request = request.set("Authorization", &format!("token {}", token.trim()));
+    }
+
+    request
+}
+
+/// Formats the `X-RateLimit-Reset` epoch as a UTC time of day plus the
+/// remaining wait, e.g. "14:05:09 UTC (in 12 min)".
+fn format_reset_time(reset_epoch: u64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let secs_of_day = reset_epoch % 86_400;
+    let wait_min = reset_epoch.saturating_sub(now).div_ceil(60);
+    format!(
+        "{:02}:{:02}:{:02} UTC (in {} min)",
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60,
+        wait_min
+    )
+}
+
 fn get_target_asset_name() -> String {
     let os = if cfg!(target_os = "windows") {
         "win"
@@ -118,13 +179,14 @@ fn get_target_asset_name() -> String {
     format!("paping-{}-{}", os, arch)
 }
 
-fn download_and_replace(url: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let response = ureq::get(url)
-        .set("User-Agent", "paping-updater")
-        .call()?;
+fn download_and_replace(url: &str, asset_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let response = github_request(url).call()?;
+
+    let mut downloaded = Vec::new();
+    response.into_reader().read_to_end(&mut downloaded)?;
 
-    let mut bytes = Vec::new();
-    response.into_reader().read_to_end(&mut bytes)?;
+    // Releases may ship the raw executable or an archive containing it
+    let bytes = extract_executable(asset_name, downloaded)?;
 
     let current_exe = std::env::current_exe()?;
 
@@ -173,12 +235,10 @@ Move-Item -Force -LiteralPath '{staged_ps}' -Destination '{current_ps}';"
         std::fs::rename(&current_exe, &backup)?;
         std::fs::write(&current_exe, &bytes)?;
 
-        // Make the new binary executable
+        // Carry the original's mode, ownership and extended attributes over
+        // to the new binary instead of forcing 0o755
         #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            std::fs::set_permissions(&current_exe, std::fs::Permissions::from_mode(0o755))?;
-        }
+        copy_unix_metadata(&backup, &current_exe)?;
 
         // Remove the old backup file (no big deal if it fails)
         let _ = std::fs::remove_file(&backup);
@@ -187,8 +247,201 @@ Move-Item -Force -LiteralPath '{staged_ps}' -Destination '{current_ps}';"
     Ok(())
 }
 
+/// Reapplies the mode, owner/group and extended attributes of `from` to `to`.
+/// The mode is mandatory (with the execute bits forced on so the update can
+/// run); ownership and xattrs are best-effort since changing them usually
+/// needs privileges. Linux file capabilities (`security.capability`) and
+/// SELinux labels survive this way when we're allowed to set them.
+///
+/// On macOS, `com.apple.quarantine` is deliberately not copied: the new file
+/// was written by us rather than a browser, so Gatekeeper doesn't assess it,
+/// and copying the flag over would trigger the "downloaded from the Internet"
+/// prompt again on the next launch.
+#[cfg(unix)]
+fn copy_unix_metadata(from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let meta = std::fs::metadata(from)?;
+    let mode = (meta.mode() & 0o7777) | 0o111;
+    std::fs::set_permissions(to, std::fs::Permissions::from_mode(mode))?;
+
+    if let Err(e) = std::os::unix::fs::chown(to, Some(meta.uid()), Some(meta.gid())) {
+        eprintln!("Warning: could not restore ownership on the new binary: {}", e);
+    }
+
+    if let Ok(names) = xattr::list(from) {
+        for name in names {
+            if name == "com.apple.quarantine" {
+                continue;
+            }
+            if let Ok(Some(value)) = xattr::get(from, &name) {
+                if let Err(e) = xattr::set(to, &name, &value) {
+                    eprintln!(
+                        "Warning: could not restore attribute {} on the new binary: {}",
+                        name.to_string_lossy(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    // chown clears the setuid/setgid bits, so apply the mode once more
+    std::fs::set_permissions(to, std::fs::Permissions::from_mode(mode))
+}
+
+/// Name of the executable inside release archives.
+const EXE_NAME: &str = if cfg!(windows) { "paping.exe" } else { "paping" };
+
+/// Returns the executable bytes for a downloaded asset. Raw binaries are
+/// returned as-is; for `.tar.gz`/`.tgz` and `.zip` archives, the single
+/// `paping`/`paping.exe` entry is extracted (wherever it sits in the archive).
+fn extract_executable(asset_name: &str, bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    let lower = asset_name.to_lowercase();
+    let found = if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+        extract_from_tar_gz(&bytes)?
+    } else if lower.ends_with(".zip") {
+        extract_from_zip(&bytes)?
+    } else {
+        return Ok(bytes);
+    };
+
+    match found.len() {
+        1 => Ok(found.into_iter().next().unwrap()),
+        0 => Err(format!("no '{}' executable found in {}", EXE_NAME, asset_name)),
+        n => Err(format!(
+            "{} entries named '{}' found in {}, refusing to guess",
+            n, EXE_NAME, asset_name
+        )),
+    }
+}
+
+fn is_exe_entry(path: &str) -> bool {
+    path.rsplit(['/', '\\']).next() == Some(EXE_NAME)
+}
+
+fn extract_from_tar_gz(bytes: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let decoder = flate2::read::GzDecoder::new(bytes);
+    let mut archive = tar::Archive::new(decoder);
+    let mut found = Vec::new();
+
+    let entries = archive
+        .entries()
+        .map_err(|e| format!("invalid tar.gz archive: {}", e))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("invalid tar.gz archive: {}", e))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry
+            .path()
+            .map_err(|e| format!("invalid tar.gz entry: {}", e))?
+            .to_string_lossy()
+            .into_owned();
+        if is_exe_entry(&path) {
+            let mut data = Vec::new();
+            entry
+                .read_to_end(&mut data)
+                .map_err(|e| format!("failed to extract '{}': {}", path, e))?;
+            found.push(data);
+        }
+    }
+
+    Ok(found)
+}
+
+fn extract_from_zip(bytes: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+        .map_err(|e| format!("invalid zip archive: {}", e))?;
+    let mut found = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut file = archive
+            .by_index(i)
+            .map_err(|e| format!("invalid zip archive: {}", e))?;
+        let name = file
+            .name()
+            .map_err(|e| format!("invalid zip entry: {}", e))?
+            .into_owned();
+        if !file.is_file() || !is_exe_entry(&name) {
+            continue;
+        }
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .map_err(|e| format!("failed to extract '{}': {}", name, e))?;
+        found.push(data);
+    }
+
+    Ok(found)
+}
+
 #[cfg(windows)]
 fn ps_escape_single_quoted(s: &str) -> String {
     // In PowerShell single-quoted strings, escape a single quote by doubling it.
     s.replace('\'', "''")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn tar_gz(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for (path, data) in entries {
+            writer.start_file(*path, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn raw_asset_is_passed_through() {
+        let bytes = extract_executable("paping-linux-amd64", b"ELF".to_vec()).unwrap();
+        assert_eq!(bytes, b"ELF");
+    }
+
+    #[test]
+    fn extracts_from_tar_gz() {
+        let archive = tar_gz(&[
+            ("README.md", b"docs"),
+            (&format!("paping-v2/{}", EXE_NAME), b"binary"),
+        ]);
+        let bytes = extract_executable("paping-linux-amd64.tar.gz", archive).unwrap();
+        assert_eq!(bytes, b"binary");
+    }
+
+    #[test]
+    fn extracts_from_zip() {
+        let archive = zip(&[(EXE_NAME, b"binary"), ("LICENSE", b"MIT")]);
+        let bytes = extract_executable("paping-win-amd64.zip", archive).unwrap();
+        assert_eq!(bytes, b"binary");
+    }
+
+    #[test]
+    fn archive_must_contain_exactly_one_executable() {
+        let none = tar_gz(&[("README.md", b"docs")]);
+        assert!(extract_executable("paping.tgz", none).is_err());
+
+        let two = zip(&[
+            (&format!("a/{}", EXE_NAME), b"1"),
+            (&format!("b/{}", EXE_NAME), b"2"),
+        ]);
+        assert!(extract_executable("paping.zip", two).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Build metadata embedded by build.rs, for `paping version` and
+//! `paping --version --verbose`.
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Build metadata; empty strings when build.rs couldn't find them out.
+pub struct BuildInfo {
+    pub commit: &'static str,
+    /// "true", "false", or empty when unknown
+    pub dirty: &'static str,
+    pub date: &'static str,
+    pub target: &'static str,
+    /// Comma-separated cargo features
+    pub features: &'static str,
+}
+
+pub const BUILD: BuildInfo = BuildInfo {
+    commit: env!("PAPING_GIT_COMMIT"),
+    dirty: env!("PAPING_GIT_DIRTY"),
+    date: env!("PAPING_BUILD_DATE"),
+    target: env!("PAPING_TARGET"),
+    features: env!("PAPING_FEATURES"),
+};
+
+impl BuildInfo {
+    /// The commit, with " (dirty)" for a build with uncommitted changes.
+    pub fn commit(&self) -> String {
+        match (self.commit, self.dirty) {
+            ("", _) => "unknown".to_string(),
+            (commit, "true") => format!("{} (dirty)", commit),
+            (commit, _) => commit.to_string(),
+        }
+    }
+
+    /// The `paping version` block.
+    pub fn render(&self) -> String {
+        let or_unknown = |s: &'static str| if s.is_empty() { "unknown" } else { s };
+        let features = if self.features.is_empty() {
+            "none".to_string()
+        } else {
+            self.features.replace(',', ", ")
+        };
+        format!(
+            "paping {}\ncommit:   {}\nbuilt:    {}\ntarget:   {}\nfeatures: {}",
+            VERSION,
+            self.commit(),
+            or_unknown(self.date),
+            or_unknown(self.target),
+            features
+        )
+    }
+
+    /// One-line form for the updater: "v1.6.1 (abc1234, 2026-10-15, x86_64-...)".
+    pub fn short(&self) -> String {
+        let mut details = vec![self.commit()];
+        details.extend(
+            [self.date, self.target]
+                .into_iter()
+                .filter(|s| !s.is_empty())
+                .map(str::to_string),
+        );
+        format!("v{} ({})", VERSION, details.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_outside_a_checkout() {
+        let info = BuildInfo {
+            commit: "",
+            dirty: "",
+            date: "",
+            target: "",
+            features: "",
+        };
+        assert_eq!(
+            info.render(),
+            format!(
+                "paping {}\ncommit:   unknown\nbuilt:    unknown\ntarget:   unknown\nfeatures: none",
+                VERSION
+            )
+        );
+        assert_eq!(info.short(), format!("v{} (unknown)", VERSION));
+    }
+
+    #[test]
+    fn marks_dirty_builds() {
+        let info = BuildInfo {
+            commit: "0123456789",
+            dirty: "true",
+            date: "2026-10-15",
+            target: "x86_64-unknown-linux-gnu",
+            features: "async,serde",
+        };
+        assert!(info.render().contains("commit:   0123456789 (dirty)\n"));
+        assert!(info.render().ends_with("features: async, serde"));
+        assert_eq!(
+            info.short(),
+            format!(
+                "v{} (0123456789 (dirty), 2026-10-15, x86_64-unknown-linux-gnu)",
+                VERSION
+            )
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! --webhook: POSTs a JSON notification whenever the target goes down or
+//! comes back up.
+//!
+//! ```text
+//! {"target":"example.com","port":443,"state":"down","previous_state":"up",
+//!  "previous_state_secs":3600.2,"ts":"2026-10-15T09:30:00.123Z",
+//!  "error":"connection refused",
+//!  "recent":{"probes":20,"connected":19,"loss_percent":5.0,"avg_ms":12.5}}
+//! ```
+//!
+//! `error` is the failure that took the target down, `recent` covers the
+//! last probes up to the change. With --webhook-format slack the body is
+//! `{"text": "..."}` instead, as Slack's incoming webhooks expect.
+//!
+//! Notifications are sent by a thread of their own, through a short queue,
+//! so that a slow or unreachable webhook never delays probing: when the
+//! queue is full, notifications are dropped (and counted).
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use colored::Colorize;
+use paping::pinger::PingResult;
+use paping::target::join_host_port;
+use paping::template::{duration_text, rfc3339};
+use serde::Serialize;
+
+/// Notifications waiting to be sent at most.
+const QUEUE: usize = 16;
+/// Probes the `recent` statistics cover at most.
+const RECENT: usize = 20;
+/// Longest a delivery attempt may take.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Pause before the one retry of a failed delivery.
+const RETRY_AFTER: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Slack,
+}
+
+impl Format {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec {
+            "json" => Ok(Format::Json),
+            "slack" => Ok(Format::Slack),
+            _ => Err(format!("unknown webhook format '{}' (json or slack)", spec)),
+        }
+    }
+}
+
+/// Body of a notification.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct Notification {
+    target: String,
+    port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    state: &'static str,
+    previous_state: &'static str,
+    /// How long the previous state lasted, as far as this run saw it
+    previous_state_secs: f64,
+    ts: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    recent: Recent,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct Recent {
+    probes: u32,
+    connected: u32,
+    loss_percent: f64,
+    avg_ms: Option<f64>,
+}
+
+impl Notification {
+    fn body(&self, format: Format) -> String {
+        match format {
+            Format::Json => serde_json::to_string(self).expect("plain data serializes"),
+            Format::Slack => serde_json::json!({ "text": self.slack_text() }).to_string(),
+        }
+    }
+
+    /// ":red_circle: *example.com:443* is down (connection refused) after
+    /// 1h 0m up. Last 20 probes: 5.0% loss, 12.50ms average"
+    fn slack_text(&self) -> String {
+        let (icon, why) = match self.error {
+            Some(ref e) => (":red_circle:", format!(" ({})", e)),
+            None => (":large_green_circle:", String::new()),
+        };
+        let label = match self.label {
+            Some(ref label) => format!(" [{}]", label),
+            None => String::new(),
+        };
+        let average = match self.recent.avg_ms {
+            Some(ms) => format!(", {:.2}ms average", ms),
+            None => String::new(),
+        };
+        format!(
+            "{} *{}*{} is {}{} after {} {}. Last {} probes: {:.1}% loss{}",
+            icon,
+            join_host_port(&self.target, self.port),
+            label,
+            self.state,
+            why,
+            duration_text(self.previous_state_secs),
+            self.previous_state,
+            self.recent.probes,
+            self.recent.loss_percent,
+            average
+        )
+    }
+}
+
+/// Follows the results to tell when the state changes.
+struct Tracker {
+    target: String,
+    port: u16,
+    label: Option<String>,
+    /// Up or down, and since when; unknown before the first result
+    state: Option<(bool, Instant)>,
+    recent: VecDeque<Option<f64>>,
+}
+
+impl Tracker {
+    fn new(target: &str, port: u16, label: Option<&str>) -> Self {
+        Tracker {
+            target: target.to_string(),
+            port,
+            label: label.map(str::to_string),
+            state: None,
+            recent: VecDeque::with_capacity(RECENT),
+        }
+    }
+
+    /// The notification to send, when `result` changes the state. The first
+    /// result only sets it.
+    fn track(&mut self, result: &PingResult, now: Instant) -> Option<Notification> {
+        if self.recent.len() == RECENT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(result.time_ms());
+
+        let up = result.is_success();
+        let since = match self.state {
+            Some((state, since)) if state != up => since,
+            Some(_) => return None,
+            None => {
+                self.state = Some((up, now));
+                return None;
+            }
+        };
+        self.state = Some((up, now));
+        let times: Vec<f64> = self.recent.iter().flatten().copied().collect();
+        let probes = self.recent.len() as u32;
+        Some(Notification {
+            target: self.target.clone(),
+            port: self.port,
+            label: self.label.clone(),
+            state: if up { "up" } else { "down" },
+            previous_state: if up { "down" } else { "up" },
+            previous_state_secs: now.duration_since(since).as_secs_f64(),
+            ts: rfc3339(SystemTime::now()),
+            error: match result {
+                PingResult::Failed(f) => Some(f.message.clone()),
+                PingResult::Connected(_) => None,
+            },
+            recent: Recent {
+                probes,
+                connected: times.len() as u32,
+                loss_percent: (probes - times.len() as u32) as f64 * 100.0 / probes as f64,
+                avg_ms: (!times.is_empty()).then(|| times.iter().sum::<f64>() / times.len() as f64),
+            },
+        })
+    }
+}
+
+/// Handle on the sending thread. Results go in through `observer`, one per
+/// target; `finish` waits for the notifications still queued.
+pub struct Webhook {
+    tx: SyncSender<Notification>,
+    dropped: Arc<AtomicU32>,
+    sender: JoinHandle<()>,
+}
+
+impl Webhook {
+    pub fn start(url: &str, format: Format) -> Self {
+        let (tx, rx) = mpsc::sync_channel(QUEUE);
+        let url = url.to_string();
+        let sender = std::thread::spawn(move || deliver_all(&url, format, rx));
+        Webhook {
+            tx,
+            dropped: Arc::new(AtomicU32::new(0)),
+            sender,
+        }
+    }
+
+    /// Hands the sender thread a notification whenever `target`:`port`
+    /// goes down or comes back up, to be posted to the webhook URL.
+    pub fn observer(
+        &self,
+        target: &str,
+        port: u16,
+        label: Option<&str>,
+    ) -> impl FnMut(&PingResult) + Send + 'static {
+        let mut tracker = Tracker::new(target, port, label);
+        let tx = self.tx.clone();
+        let dropped = Arc::clone(&self.dropped);
+        move |result| {
+            if let Some(notification) = tracker.track(result, Instant::now()) {
+                if let Err(TrySendError::Full(_)) = tx.try_send(notification) {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Sends what is still queued, then stops the thread. The pingers
+    /// (holding the observers) must be dropped first.
+    pub fn finish(self) {
+        drop(self.tx);
+        let _ = self.sender.join();
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            warn(&format!(
+                "{} notification{} dropped, the webhook being too slow",
+                dropped,
+                if dropped == 1 { "" } else { "s" }
+            ));
+        }
+    }
+}
+
+/// Sends the notifications as they come, retrying each once. Only the first
+/// of a series of failed deliveries is reported, and the next success.
+fn deliver_all(url: &str, format: Format, rx: Receiver<Notification>) {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let mut failed = 0u32;
+    for notification in rx {
+        let body = notification.body(format);
+        let result = deliver(&agent, url, &body).or_else(|_| {
+            std::thread::sleep(RETRY_AFTER);
+            deliver(&agent, url, &body)
+        });
+        match result {
+            Ok(()) if failed > 0 => {
+                warn(&format!("webhook delivered again, after {} failed", failed));
+                failed = 0;
+            }
+            Ok(()) => {}
+            Err(e) => {
+                if failed == 0 {
+                    warn(&format!(
+                        "webhook delivery failed: {} (not reported again until one succeeds)",
+                        e
+                    ));
+                }
+                failed += 1;
+            }
+        }
+    }
+}
+
+fn deliver(agent: &ureq::Agent, url: &str, body: &str) -> Result<(), String> {
+    let request = agent.post(url).set("Content-Type", "application/json");
+    match request.send_string(body) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, _)) => Err(format!("HTTP {}", code)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn warn(message: &str) {
+    eprintln!("{}", format!("Warning: {}", message).yellow());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paping::pinger::FailureKind;
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    #[test]
+    fn notifies_state_changes_only() {
+        let mut tracker = Tracker::new("example.com", 443, Some("api"));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let up = |ms| PingResult::connected(Duration::from_millis(ms));
+        let down = PingResult::failed(FailureKind::Refused, "connection refused");
+        assert_eq!(tracker.track(&down, at(0)), None);
+        let back = tracker.track(&up(10), at(30)).unwrap();
+        assert_eq!((back.state, back.previous_state), ("up", "down"));
+        assert_eq!(back.previous_state_secs, 30.0);
+        assert_eq!(back.error, None);
+        assert_eq!(tracker.track(&up(30), at(31)), None);
+
+        let gone = tracker.track(&down, at(3630)).unwrap();
+        assert_eq!((gone.state, gone.previous_state), ("down", "up"));
+        assert_eq!(gone.previous_state_secs, 3600.0);
+        assert_eq!(gone.error.as_deref(), Some("connection refused"));
+        assert_eq!(
+            gone.recent,
+            Recent {
+                probes: 4,
+                connected: 2,
+                loss_percent: 50.0,
+                avg_ms: Some(20.0),
+            }
+        );
+        assert_eq!(
+            gone.slack_text(),
+            ":red_circle: *example.com:443* [api] is down (connection refused) after 1h 0m up. \
+             Last 4 probes: 50.0% loss, 20.00ms average"
+        );
+        let json: serde_json::Value = serde_json::from_str(&gone.body(Format::Json)).unwrap();
+        assert_eq!(json["state"], "down");
+        assert_eq!(json["recent"]["connected"], 2);
+    }
+
+    #[test]
+    fn posts_notifications_in_the_background() {
+        let server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.local_addr().unwrap());
+        let receiver = std::thread::spawn(move || {
+            let (stream, _) = server.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(n) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = n.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0u8; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            String::from_utf8(body).unwrap()
+        });
+
+        let webhook = Webhook::start(&url, Format::Slack);
+        let up = PingResult::connected(Duration::from_millis(5));
+        let down = PingResult::failed(FailureKind::Refused, "connection refused");
+        let mut observer = webhook.observer("example.com", 443, None);
+        observer(&up);
+        observer(&down);
+        drop(observer);
+        webhook.finish();
+        let body: serde_json::Value = serde_json::from_str(&receiver.join().unwrap()).unwrap();
+        assert!(body["text"]
+            .as_str()
+            .unwrap()
+            .starts_with(":red_circle: *example.com:443* is down"));
+    }
+}